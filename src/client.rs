@@ -0,0 +1,154 @@
+//! Helpers for off-chain Rust apps reading the escrow program's accounts over RPC
+use solana_client::{client_error::ClientError, rpc_client::RpcClient};
+use solana_program::{program_error::ProgramError, program_pack::Pack, pubkey::Pubkey};
+use thiserror::Error;
+
+use crate::state::Escrow;
+
+#[derive(Error, Debug)]
+pub enum EscrowClientError {
+    /// The account couldn't be fetched, boxed as the RPC error is much larger than the other variants
+    #[error("Rpc Error: {0}")]
+    Rpc(Box<ClientError>),
+
+    /// No account exists at the escrow's address, e.g. it was closed by an exchange or a cancel
+    #[error("Escrow Account Not Found: {0}")]
+    NotFound(Pubkey),
+
+    /// The account isn't owned by the escrow program, holding the owner
+    #[error("Wrong Escrow Account Owner: {0}")]
+    WrongOwner(Pubkey),
+
+    /// The account was fetched but isn't a valid escrow
+    #[error("Invalid Escrow Account: {0}")]
+    InvalidEscrow(#[from] ProgramError),
+}
+
+impl From<ClientError> for EscrowClientError {
+    fn from(e: ClientError) -> Self {
+        EscrowClientError::Rpc(Box::new(e))
+    }
+}
+
+/// Fetches the escrow account and decodes it, checking it is owned by the escrow program
+pub fn get_escrow(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    escrow_pubkey: &Pubkey,
+) -> Result<Escrow, EscrowClientError> {
+    let account = rpc
+        .get_account_with_commitment(escrow_pubkey, rpc.commitment())?
+        .value
+        .ok_or(EscrowClientError::NotFound(*escrow_pubkey))?;
+    if account.owner != *program_id {
+        return Err(EscrowClientError::WrongOwner(account.owner));
+    }
+    Ok(Escrow::unpack(&account.data)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_matches::assert_matches;
+    use solana_account_decoder::{UiAccount, UiAccountEncoding};
+    use solana_client::{
+        mock_sender::Mocks,
+        rpc_request::RpcRequest,
+        rpc_response::{Response, RpcResponseContext},
+    };
+    use solana_sdk::account::Account;
+
+    fn program_id() -> Pubkey {
+        Pubkey::new_from_array([1; 32])
+    }
+
+    fn escrow_pubkey() -> Pubkey {
+        Pubkey::new_from_array([2; 32])
+    }
+
+    fn escrow() -> Escrow {
+        let mut escrow = Escrow::unpack_unchecked(&[0; Escrow::LEN]).unwrap();
+        escrow.is_initialized = true;
+        escrow.initializer_pubkey = Pubkey::new_from_array([3; 32]);
+        escrow.temp_token_account_pubkey = Pubkey::new_from_array([4; 32]);
+        escrow.expected_amount = 500;
+        escrow.taker_slots = 1;
+        escrow
+    }
+
+    /// An RPC client answering `getAccountInfo` with the given account, or with no account
+    fn mock_rpc(account: Option<Account>) -> RpcClient {
+        let value = account.map(|account| {
+            UiAccount::encode(
+                &escrow_pubkey(),
+                &account,
+                UiAccountEncoding::Base64,
+                None,
+                None,
+            )
+        });
+        let mut mocks = Mocks::default();
+        mocks.insert(
+            RpcRequest::GetAccountInfo,
+            serde_json::to_value(Response {
+                context: RpcResponseContext { slot: 1 },
+                value,
+            })
+            .unwrap(),
+        );
+        RpcClient::new_mock_with_mocks("succeeds".to_string(), mocks)
+    }
+
+    fn escrow_account(owner: Pubkey) -> Account {
+        let mut data = vec![0; Escrow::LEN];
+        Escrow::pack(escrow(), &mut data).unwrap();
+        Account {
+            lamports: 1_000_000,
+            data,
+            owner,
+            executable: false,
+            rent_epoch: 0,
+        }
+    }
+
+    #[test]
+    fn test_get_escrow_decodes_the_account() {
+        let rpc = mock_rpc(Some(escrow_account(program_id())));
+        assert_eq!(
+            get_escrow(&rpc, &program_id(), &escrow_pubkey()).unwrap(),
+            escrow()
+        );
+    }
+
+    #[test]
+    fn test_get_escrow_reports_a_missing_account() {
+        let rpc = mock_rpc(None);
+        assert_matches!(
+            get_escrow(&rpc, &program_id(), &escrow_pubkey()),
+            Err(EscrowClientError::NotFound(pubkey)) if pubkey == escrow_pubkey()
+        );
+    }
+
+    #[test]
+    fn test_get_escrow_reports_an_account_of_another_program() {
+        let owner = Pubkey::new_from_array([5; 32]);
+        let rpc = mock_rpc(Some(escrow_account(owner)));
+        assert_matches!(
+            get_escrow(&rpc, &program_id(), &escrow_pubkey()),
+            Err(EscrowClientError::WrongOwner(account_owner)) if account_owner == owner
+        );
+    }
+
+    #[test]
+    fn test_get_escrow_reports_undecodable_data() {
+        let mut account = escrow_account(program_id());
+        account.data.truncate(10);
+        let rpc = mock_rpc(Some(account));
+        assert_matches!(
+            get_escrow(&rpc, &program_id(), &escrow_pubkey()),
+            Err(EscrowClientError::InvalidEscrow(
+                ProgramError::InvalidAccountData
+            ))
+        );
+    }
+}
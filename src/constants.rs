@@ -0,0 +1,52 @@
+use solana_program::{program::MAX_RETURN_DATA, pubkey::Pubkey};
+
+use crate::state::Escrow;
+
+/// The token program the escrowed token accounts must belong to, `spl_token::id()` on every cluster.
+/// It is spelled out so the owner checks compare against a constant, the `spl_token::instruction`
+/// builders the processor CPIs with only accept `spl_token::id()` anyway
+pub const TOKEN_PROGRAM_ID: Pubkey = Pubkey::new_from_array([
+    6, 221, 246, 225, 215, 101, 161, 147, 217, 203, 225, 70, 206, 235, 121, 172, 28, 180, 133,
+    237, 95, 91, 55, 145, 58, 140, 245, 133, 126, 255, 0, 169,
+]);
+
+/// The most accounts a batch instruction handles, so an oversized batch fails upfront rather than running out of compute midway.
+/// It is as many escrow records of `GetEscrowBatch`, each behind its one byte flag, as fit in the return data
+pub const MAX_BATCH: usize = MAX_RETURN_DATA / (Escrow::BATCH_RECORD_LEN + 1);
+
+/// The `[major, minor, patch]` version of the package this program was built from, written as return data by `Version`
+pub const PROGRAM_VERSION: [u8; 3] = [
+    parse_version_part(env!("CARGO_PKG_VERSION_MAJOR")),
+    parse_version_part(env!("CARGO_PKG_VERSION_MINOR")),
+    parse_version_part(env!("CARGO_PKG_VERSION_PATCH")),
+];
+
+/// Parses a decimal version number at compile time, a part that doesn't fit in a u8 fails the build
+const fn parse_version_part(part: &str) -> u8 {
+    let digits = part.as_bytes();
+    let mut value = 0u8;
+    let mut i = 0;
+    while i < digits.len() {
+        value = value * 10 + (digits[i] - b'0');
+        i += 1;
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_program_id_is_spl_token() {
+        assert_eq!(TOKEN_PROGRAM_ID, spl_token::id());
+    }
+
+    #[test]
+    fn test_max_batch_fits_in_the_return_data() {
+        // Six records of 148 bytes take 888 of the 1024 bytes, a seventh wouldn't fit
+        assert_eq!(MAX_RETURN_DATA, 1024);
+        assert_eq!(Escrow::BATCH_RECORD_LEN + 1, 148);
+        assert_eq!(MAX_BATCH, 6);
+    }
+}
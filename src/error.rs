@@ -1,7 +1,7 @@
 use solana_program::program_error::ProgramError;
 use thiserror::Error;
 
-#[derive(Error, Debug, Clone, Copy)]
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum EscrowError {
     #[error("Amount Overflow")]
     AmountOverflow,
@@ -17,10 +17,156 @@ pub enum EscrowError {
     
     #[error("Not Rent Exempt")]
     NotRentExempt,
+
+    #[error("Insufficient Taker Funds")]
+    InsufficientTakerFunds,
+
+    #[error("Taker Not Allowed")]
+    TakerNotAllowed,
+
+    #[error("Escrow Expired")]
+    EscrowExpired,
+
+    #[error("Account Frozen")]
+    AccountFrozen,
+
+    #[error("Program Paused")]
+    ProgramPaused,
+
+    #[error("Pair Not Allowed")]
+    PairNotAllowed,
+
+    #[error("Pair Registry Full")]
+    PairRegistryFull,
+
+    #[error("Delegation Revoked")]
+    DelegationRevoked,
+
+    #[error("Invalid Pda")]
+    InvalidPda,
+
+    #[error("Reentrancy Detected")]
+    ReentrancyDetected,
+
+    #[error("Invalid Preimage")]
+    InvalidPreimage,
+
+    #[error("Escrow Timelocked")]
+    EscrowTimelocked,
+
+    #[error("Amount Underflow")]
+    AmountUnderflow,
+
+    #[error("Index Full")]
+    IndexFull,
+
+    #[error("Wrong Owner")]
+    WrongOwner,
+
+    #[error("Wrong Mint")]
+    WrongMint,
+
+    #[error("Wrong Authority")]
+    WrongAuthority,
+
+    #[error("Not Initialized")]
+    NotInitialized,
+
+    #[error("Batch Too Large")]
+    BatchTooLarge,
+
+    #[error("Mint Paused")]
+    MintPaused,
+
+    #[error("Paused Mints Full")]
+    PausedMintsFull,
+
+    #[error("Not An NFT")]
+    NotAnNft,
+
+    #[error("Missing Clock")]
+    MissingClock,
+
+    #[error("Deadline Exceeded")]
+    DeadlineExceeded,
+
+    #[error("Transfer Fee Mismatch")]
+    TransferFeeMismatch,
+
+    #[error("Escrow Reserved")]
+    EscrowReserved,
+
+    #[error("Namespace Not Allowed")]
+    NamespaceNotAllowed,
+
+    #[error("Namespaces Full")]
+    NamespacesFull,
 }
 
 impl From<EscrowError> for ProgramError {
     fn from(e: EscrowError) -> Self {
         ProgramError::Custom(e as u32)
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_codes_are_stable() {
+        // Clients match on these codes, a variant must never be reordered or removed in place
+        let codes = [
+            (EscrowError::AmountOverflow, 0),
+            (EscrowError::ExpectedAmountMismatch, 1),
+            (EscrowError::InvalidAmount, 2),
+            (EscrowError::InvalidInstruction, 3),
+            (EscrowError::NotRentExempt, 4),
+            (EscrowError::InsufficientTakerFunds, 5),
+            (EscrowError::TakerNotAllowed, 6),
+            (EscrowError::EscrowExpired, 7),
+            (EscrowError::AccountFrozen, 8),
+            (EscrowError::ProgramPaused, 9),
+            (EscrowError::PairNotAllowed, 10),
+            (EscrowError::PairRegistryFull, 11),
+            (EscrowError::DelegationRevoked, 12),
+            (EscrowError::InvalidPda, 13),
+            (EscrowError::ReentrancyDetected, 14),
+            (EscrowError::InvalidPreimage, 15),
+            (EscrowError::EscrowTimelocked, 16),
+            (EscrowError::AmountUnderflow, 17),
+            (EscrowError::IndexFull, 18),
+            (EscrowError::WrongOwner, 19),
+            (EscrowError::WrongMint, 20),
+            (EscrowError::WrongAuthority, 21),
+            (EscrowError::NotInitialized, 22),
+            (EscrowError::BatchTooLarge, 23),
+            (EscrowError::MintPaused, 24),
+            (EscrowError::PausedMintsFull, 25),
+            (EscrowError::NotAnNft, 26),
+            (EscrowError::MissingClock, 27),
+            (EscrowError::DeadlineExceeded, 28),
+            (EscrowError::TransferFeeMismatch, 29),
+            (EscrowError::EscrowReserved, 30),
+            (EscrowError::NamespaceNotAllowed, 31),
+            (EscrowError::NamespacesFull, 32),
+        ];
+        for (error, code) in codes {
+            assert_eq!(ProgramError::from(error), ProgramError::Custom(code));
+        }
+    }
+
+    #[test]
+    fn test_account_validation_errors_describe_the_failure() {
+        let messages = [
+            (EscrowError::WrongOwner, "Wrong Owner"),
+            (EscrowError::WrongMint, "Wrong Mint"),
+            (EscrowError::WrongAuthority, "Wrong Authority"),
+            (EscrowError::NotInitialized, "Not Initialized"),
+            (EscrowError::AccountFrozen, "Account Frozen"),
+        ];
+        for (error, message) in messages {
+            assert_eq!(error.to_string(), message);
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,20 @@
+//! Log lines emitted by the processor for off-chain indexers, each event is a single `msg!` of space separated `key=value` pairs
+use solana_program::{msg, pubkey::Pubkey};
+
+/// Logs the parameters of a new escrow, in place of the usual `Instruction: InitEscrow` line
+pub fn init_escrow(
+    initializer: &Pubkey,
+    temp_token_account: &Pubkey,
+    token_to_receive_account: &Pubkey,
+    expected_amount: u64,
+    pda: &Pubkey,
+) {
+    msg!(
+        "Instruction: InitEscrow initializer={} temp_token_account={} token_to_receive_account={} expected_amount={} pda={}",
+        initializer,
+        temp_token_account,
+        token_to_receive_account,
+        expected_amount,
+        pda
+    );
+}
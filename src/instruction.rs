@@ -1,8 +1,88 @@
 use std::convert::TryInto;
-use solana_program::program_error::ProgramError;
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_program, sysvar,
+};
 
-use crate::error::EscrowError::InvalidInstruction;
+use crate::{
+    constants::TOKEN_PROGRAM_ID,
+    error::EscrowError::InvalidInstruction,
+    pda::{
+        find_config_address, find_escrow_address, find_escrow_index_address,
+        find_fee_vault_address, find_pair_registry_address, find_stats_address,
+        find_template_address, PdaInfo, NAMESPACE_LEN,
+    },
+    state::MAX_NOTE_LEN,
+};
+
+/// The instruction data format version, written before the tag so data encoded for another format is rejected rather than misparsed.
+/// It is bumped whenever the encoding of an existing instruction changes, version 2 added `taker_deadline_slot` to `Exchange`
+pub const INSTRUCTION_VERSION: u8 = 2;
+
+/// The number of accounts of `Cancel`, followed by the accounts of the new escrow in `Replace`
+pub const CANCEL_ACCOUNTS: usize = 10;
+
+/// The number of accounts of `InitEscrow` without the optional stats account
+pub const INIT_ESCROW_ACCOUNTS: usize = 11;
 
+/// The parameters of a new escrow, shared by `InitEscrow`, `Replace` and `InitEscrowNft` which pack them the same way
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct InitEscrowArgs {
+    /// The amount party A expects to receive of token Y, at least `MIN_EXPECTED_AMOUNT` unless the escrow is priced as a ratio or a payment band
+    pub amount: u64,
+    /// The slot at which the expected amount starts to decay
+    pub decay_start_slot: u64,
+    /// The slot at which the expected amount stops decaying, zero disables the decay schedule
+    pub decay_end_slot: u64,
+    /// The amount party A expects to receive of token Y once the decay schedule is over
+    pub decay_end_amount: u64,
+    /// The nonce the escrow account address is derived with, allowing party A to open several escrows
+    pub nonce: u64,
+    /// The number of distinct takers that each fill an equal share of the escrow, at least 1 and at most `MAX_TAKER_SLOTS`
+    pub taker_slots: u8,
+    /// Whether the escrow expires at `expiry_slot` rather than at `expiry_timestamp`
+    pub use_slot_expiry: bool,
+    /// The unix timestamp from which the escrow can't be exchanged anymore, zero means it never expires
+    pub expiry_timestamp: i64,
+    /// The slot from which the escrow can't be exchanged anymore, zero means it never expires
+    pub expiry_slot: u64,
+    /// Whether the initializer keeps custody of the deposited tokens, having approved the PDA returned by `PdaInfo::find`
+    /// as delegate of the deposit earlier in the same transaction, instead of handing the temp token account over to it
+    pub use_delegation: bool,
+    /// The SHA-256 hash the taker has to reveal the preimage of, all zeros disables the hashlock
+    pub hashlock: [u8; 32],
+    /// The slot closing the hashlock's window: the taker has to exchange before it and the initializer can only cancel from it, zero disables the timelock
+    pub timelock_slot: u64,
+    /// The program invoked after every fill with the filled deposit amount as 8 little endian bytes, the default pubkey disables the callback
+    pub callback_program: Pubkey,
+    /// The number of accounts the exchange passes through to `callback_program`
+    pub callback_accounts_len: u8,
+    /// The account the escrow account rent goes back to on close, e.g. a relayer that paid it, the default pubkey refunds the initializer
+    pub rent_refund_recipient: Pubkey,
+    /// The key allowed to cancel the escrow on the initializer's behalf, the default pubkey allows no one but the initializer
+    pub cancel_delegate: Pubkey,
+    /// The numerator of the price per deposited token, only used with a non zero `price_den`
+    pub price_num: u64,
+    /// The denominator of the price per deposited token, zero prices the escrow at `amount` for the whole deposit instead of as a ratio
+    pub price_den: u64,
+    /// The least the taker may pay for the deposit when `max_amount` is set
+    pub min_amount: u64,
+    /// The most the taker may pay for the deposit, zero disables the payment band and the taker pays exactly `amount`
+    pub max_amount: u64,
+    /// How far the amount the taker asks for may be off the deposit share, e.g. after a transfer fee skimmed the deposit. Zero requires the exact share
+    pub amount_tolerance: u64,
+    /// The program derived address that alone can take the escrow, for off-chain matching settled on-chain. The default pubkey lets any taker exchange
+    pub settlement_authority: Pubkey,
+    /// A short memo stored with the escrow and returned by `GetEscrow`, at most `MAX_NOTE_LEN` bytes
+    pub note: Vec<u8>,
+    /// Whether the escrow is listed in the escrow index, left out by clients predating the flag. An unlisted escrow
+    /// can't be held up by a full index
+    pub list_in_index: bool,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub enum EscrowInstruction {
 
     /// Starts the trade by creating and populating an escrow account and transferring ownership of the given temp token account to the PDA
@@ -10,59 +90,2588 @@ pub enum EscrowInstruction {
     ///
     /// Accounts expected:
     ///
-    /// 0. `[signer]` The account of the person initializing the escrow
-    /// 1. `[writable]` Temporary token account that should be created prior to this instruction and owned by the initializer
-    /// 2. `[]` The initializer's token account for the token they will receive should the trade go through
-    /// 3. `[writable]` The escrow account, it will hold all necessary info about the trade.
+    /// 0. `[signer, writable]` The account of the person initializing the escrow, paying the init fee
+    /// 1. `[writable]` Temporary token account that should be created prior to this instruction and owned by the initializer, or with `use_delegation` the initializer's token account the PDA is the delegate of
+    /// 2. `[]` The initializer's token account for the token they will receive should the trade go through. Its owner is never checked nor asked to sign, so it can be a PDA of another program receiving the tokens on the initializer's behalf
+    /// 3. `[writable]` The escrow account, it will hold all necessary info about the trade. It is created by the program, paid by the initializer, at the PDA returned by `pda::find_escrow_address` for the given nonce
     /// 4. `[]` The rent sysvar
     /// 5. `[]` The token program
+    /// 6. `[]` The pair registry account at the address returned by `pda::find_pair_registry_address`, every pair is allowed while it isn't created
+    /// 7. `[]` The config account at the address returned by `pda::find_config_address`, it may not have been created
+    /// 8. `[writable]` The config's treasury, receiving the init fee from the initializer. Any account when the config charges no init fee
+    /// 9. `[]` The system program, creating the escrow account and transferring the init fee
+    /// 10. `[writable]` The escrow index account at the address returned by `pda::find_escrow_index_address`, the escrow is listed in it once it is created with `list_in_index`
+    /// 11. `[writable]` (optional) The stats account, updated when it is passed
     InitEscrow {
-        /// The amount party A expects to receive of token Y
-        amount: u64,
+        /// The parameters of the new escrow
+        args: InitEscrowArgs,
     },
 
-    /// Accepts a trade
+    /// Accepts a trade, or the taker's share of it when the escrow is split across several takers.
+    /// The escrow is closed once every share has been filled
     ///
     ///
     /// Accounts expected:
     ///
-    /// 0. `[signer]` The account of the person taking the trade
+    /// 0. `[signer]` The account of the person taking the trade, also writable when it pays for the creation of the receiving token account
     /// 1. `[writable]` The taker's token account for the token they send 
-    /// 2. `[writable]` The taker's token account for the token they will receive should the trade go through
-    /// 3. `[writable]` The PDA's temp token account to get tokens from and eventually close
+    /// 2. `[writable]` The taker's token account for the token they will receive should the trade go through, if it doesn't exist yet it is created as the taker's associated token account
+    /// 3. `[writable]` The PDA's temp token account to get tokens from and eventually close, or the initializer's delegated token account
     /// 4. `[writable]` The initializer's main account to send their rent fees to
     /// 5. `[writable]` The initializer's token account that will receive tokens
     /// 6. `[writable]` The escrow account holding the escrow info
     /// 7. `[]` The token program
     /// 8. `[]` The PDA account
+    /// 9. `[]` The config account at the address returned by `pda::find_config_address`, it may not have been created
+    /// 10. `[writable]` The escrow index account at the address returned by `pda::find_escrow_index_address`, the escrow is removed from it once it closes
+    /// 11. `[]` The mint of the deposited token, both transfers are `transfer_checked` against the decimals of their mint
+    /// 12. `[]` The mint of the token the initializer expects
+    /// 13. `[writable]` The escrow's rent refund recipient, receiving the escrow account rent once the escrow closes
+    /// 14. `[writable]` A token account of the expected mint owned by its fee vault, the PDA returned by `pda::find_fee_vault_address`,
+    ///     receiving the config's exchange fee out of the payment. Any account when the config charges no exchange fee
+    ///
+    /// Only when the taker's receiving token account doesn't exist yet:
+    ///
+    /// 15. `[]` The associated token program
+    /// 16. `[]` The system program
+    /// 17. `[]` The rent sysvar
+    ///
+    /// Only when the escrow was opened with `InitEscrowWithSol`, in place of the accounts above. The PDA account is then writable,
+    /// the temp token account is closed into it and it pays the taker's receiving account, any account, the deposit in lamports:
+    ///
+    /// 15. `[]` The system program
+    ///
+    /// Only when the escrow was opened from a template, after all the accounts above:
+    ///
+    /// - `[]` The template account
+    ///
+    /// Only when the escrow has a callback program, after all the accounts above:
+    ///
+    /// - `[]` The callback program
+    /// - The `callback_accounts_len` accounts passed through to the callback program, never as signers
+    ///
+    /// Only when the escrow has a settlement authority, after all the accounts above:
+    ///
+    /// - `[signer]` The settlement authority, signing as a PDA of the settlement program
+    ///
+    /// Optionally, after all the accounts above:
+    ///
+    /// - `[writable]` The stats account, updated when it is passed
     Exchange {
-        /// the amount the taker expects to be paid in the other token, as a u64 because that's the max possible supply of a token
+        /// the amount the taker expects to be paid in the other token for their share, as a u64 because that's the max possible supply of a token
+        amount: u64,
+        /// The amount the taker pays for an escrow with a payment band, within its `min_amount` and `max_amount`. Ignored otherwise
+        payment: u64,
+        /// The last slot the taker accepts the exchange to run at, zero disables the deadline
+        taker_deadline_slot: u64,
+        /// The preimage of the escrow's hashlock, only needed when the escrow has one
+        preimage: Option<[u8; 32]>,
+    },
+
+    /// Cancels the escrow, sending the deposited tokens back to the initializer and closing both the PDA's temp token account and the escrow account.
+    /// A delegated escrow instead revokes the PDA's delegation and only closes the escrow account
+    /// An escrow with a timelock can only be cancelled from its `timelock_slot`
+    /// The escrow's cancel delegate can sign in place of the initializer, the tokens then have to go back to a token account the initializer owns
+    /// An escrow opened with `InitEscrowWithSol` closes its temp token account into the initializer's account, unwrapping the deposit,
+    /// its initializer's token account is then left unused
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The account of the person who initialized the escrow, not a signer when the cancel delegate signs
+    /// 1. `[writable]` The PDA's temp token account to get tokens from and eventually close
+    /// 2. `[writable]` The initializer's token account that will receive the deposited tokens back
+    /// 3. `[writable]` The escrow account holding the escrow info
+    /// 4. `[]` The token program
+    /// 5. `[]` The PDA account
+    /// 6. `[]` The config account at the address returned by `pda::find_config_address`, it may not have been created
+    /// 7. `[writable]` The config's treasury, receiving the cancel fee out of the escrow account rent refund. Any account when the config charges no cancel fee
+    /// 8. `[writable]` The escrow index account at the address returned by `pda::find_escrow_index_address`, the escrow is removed from it
+    /// 9. `[writable]` The escrow's rent refund recipient, receiving the escrow account rent
+    /// 10. `[signer]` (optional) The escrow's cancel delegate, only when the initializer doesn't sign
+    Cancel,
+
+    /// Withdraws part of the deposited tokens back to the initializer while leaving the escrow open, not supported by delegated escrows
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The account of the person who initialized the escrow
+    /// 1. `[writable]` The PDA's temp token account to get tokens from
+    /// 2. `[writable]` The initializer's token account that will receive the withdrawn tokens
+    /// 3. `[]` The escrow account holding the escrow info
+    /// 4. `[]` The token program
+    /// 5. `[]` The PDA account
+    PartialWithdraw {
+        /// The amount of deposited tokens to withdraw, it must be less than the PDA's temp token account balance
         amount: u64,
+    },
+
+    /// Writes the packed escrow state into the return data without modifying it
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[]` The escrow account holding the escrow info
+    GetEscrow,
+
+    /// Creates the stats account that keeps protocol wide counters, it only has to run once per deployment
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[writable, signer]` The account paying for the stats account rent
+    /// 1. `[writable]` The stats account, at the address returned by `pda::find_stats_address`
+    /// 2. `[]` The system program
+    InitStats,
+
+    /// Creates the config account holding the program wide settings, it only has to run once per deployment.
+    /// The signing admin is the only one allowed to change the settings afterwards, so it should run right after deploying
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[writable, signer]` The account paying for the config account rent
+    /// 1. `[signer]` The admin of the config
+    /// 2. `[writable]` The config account, at the address returned by `pda::find_config_address`
+    /// 3. `[]` The system program
+    InitConfig,
+
+    /// Pauses or resumes every exchange
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The admin of the config
+    /// 1. `[writable]` The config account
+    SetPaused {
+        /// Whether exchanges are rejected
+        paused: bool,
+    },
+
+    /// Creates the pair registry, once it exists escrows can only be created for the pairs it allows
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[writable, signer]` The account paying for the pair registry rent
+    /// 1. `[signer]` The admin of the config
+    /// 2. `[]` The config account
+    /// 3. `[writable]` The pair registry account, at the address returned by `pda::find_pair_registry_address`
+    /// 4. `[]` The system program
+    InitPairRegistry,
+
+    /// Allows or disallows creating escrows for a pair of mints
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The admin of the config
+    /// 1. `[]` The config account
+    /// 2. `[writable]` The pair registry account
+    SetPairAllowed {
+        /// The mint deposited by the initializer
+        deposit_mint: Pubkey,
+        /// The mint the initializer expects to receive
+        expected_mint: Pubkey,
+        /// Whether escrows can be created for the pair
+        allowed: bool,
+    },
+
+    /// Derives the PDA owning the escrow's temp token account and writes its 32 byte key followed by its bump seed as return data, nothing is modified
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[]` The escrow account
+    DeriveAuthority,
+
+    /// Moves the whole balance of a token account owned by an escrow's PDA that the escrow doesn't track,
+    /// recovering tokens sent to the PDA outside of the normal flow. The escrow's own temp token account can't be swept
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The admin of the config
+    /// 1. `[]` The config account
+    /// 2. `[writable]` The stray token account owned by the PDA
+    /// 3. `[writable]` The token account receiving the stray tokens
+    /// 4. `[]` The escrow account the PDA is derived from, it may already be closed
+    /// 5. `[]` The token program
+    /// 6. `[]` The PDA account
+    SweepStrayTokens,
+
+    /// Evaluates the conditions an exchange checks and writes a single `state::FillStatus` byte as return data, nothing is modified
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[]` The escrow account
+    /// 1. `[]` The PDA's temp token account, or the initializer's delegated token account
+    /// 2. `[]` The config account at the address returned by `pda::find_config_address`, it may not have been created
+    /// 3. `[]` The initializer's token account to receive, its mint is checked against the paused mints
+    /// 4. `[]` (optional) The taker, checked against the takers that already filled a share and the escrow's reservation
+    CheckFillable,
+
+    /// Cancels an escrow and initializes a new one in its place, so the initializer can reprice without a window holding no order.
+    /// The new escrow needs a new nonce as the cancelled escrow account is closed
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. - 9. The accounts of `Cancel` for the escrow being replaced, `CANCEL_ACCOUNTS` of them
+    /// 10. - 21. The accounts of `InitEscrow` for the new escrow, the optional stats account included. The initializer has to be the same
+    Replace {
+        /// The parameters of the new escrow
+        args: InitEscrowArgs,
+    },
+
+    /// Sets the treasury receiving the protocol fees
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The admin of the config
+    /// 1. `[writable]` The config account
+    SetTreasury {
+        /// The new treasury
+        treasury: Pubkey,
+    },
+
+    /// Sets the lamports charged out of the escrow account rent refund on `Cancel`
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The admin of the config
+    /// 1. `[writable]` The config account
+    SetCancelFee {
+        /// The new cancel fee, zero disables it
+        cancel_fee: u64,
+    },
+
+    /// Sets the lamports the initializer pays to the treasury on `InitEscrow`
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The admin of the config
+    /// 1. `[writable]` The config account
+    SetInitFee {
+        /// The new init fee, zero disables it
+        init_fee: u64,
+    },
+
+    /// Writes the states of several escrows as return data, each as a one byte initialized flag followed, when the flag is set,
+    /// by the first `Escrow::BATCH_RECORD_LEN` bytes of the packed escrow. The rest, note included, is read with `GetEscrow`.
+    /// Fails when they don't fit in `program::MAX_RETURN_DATA`, nothing is modified
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. .. `[]` The escrow accounts, in the order their states are written, at most `constants::MAX_BATCH`
+    GetEscrowBatch,
+
+    /// Points the escrow at another token account of the initializer to receive the taker's payment in.
+    /// The new account has to hold the same mint as the current one
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The account of the person who initialized the escrow
+    /// 1. `[writable]` The escrow account holding the escrow info
+    /// 2. `[]` The token account the escrow currently sends the payment to
+    /// 3. `[]` The token account the escrow should send the payment to from now on
+    SetReceiveAccount,
+
+    /// Creates the escrow index, once it exists every escrow opened is listed in it until it closes
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[writable, signer]` The account paying for the escrow index rent
+    /// 1. `[signer]` The admin of the config
+    /// 2. `[]` The config account
+    /// 3. `[writable]` The escrow index account, at the address returned by `pda::find_escrow_index_address`
+    /// 4. `[]` The system program
+    InitEscrowIndex,
+
+    /// Writes the `[major, minor, patch]` version of the deployed build as return data, nothing is modified
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// None
+    Version,
+
+    /// Runs the account checks of `Exchange` on the account list an exchange would be sent with: the keys, owners, mints,
+    /// the expiry and whether the taker already filled a share. Neither balances nor the preimage are checked, nothing is modified
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// The accounts of `Exchange`
+    ValidateExchangeAccounts,
+
+    /// Closes an escrow whose PDA token account is in a state `Cancel` can't handle, e.g. closed, frozen or no longer
+    /// owned by the PDA. Hands the token account back to the initializer, or revokes the PDA's delegation of a delegated
+    /// escrow, only when the token program would allow it and skips that step otherwise. The escrow account is closed either way.
+    /// Like `Cancel` it is only possible from the escrow's `timelock_slot` and charges the cancel fee
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The account of the person who initialized the escrow
+    /// 1. `[writable]` The PDA's temp token account, or the initializer's delegated token account, in whatever state it is
+    /// 2. `[writable]` The escrow account holding the escrow info
+    /// 3. `[]` The token program
+    /// 4. `[]` The PDA account
+    /// 5. `[]` The config account at the address returned by `pda::find_config_address`, it may not have been created
+    /// 6. `[writable]` The config's treasury, receiving the cancel fee out of the escrow account rent refund. Any account when the config charges no cancel fee
+    /// 7. `[writable]` The escrow index account at the address returned by `pda::find_escrow_index_address`, the escrow is removed from it
+    /// 8. `[writable]` The escrow's rent refund recipient, receiving the escrow account rent
+    ForceReclaim,
+
+    /// Writes the time left until the escrow expires as a little endian i64 return data, nothing is modified.
+    /// It is in seconds for an escrow expiring at a timestamp and in slots for one expiring at a slot, zero once
+    /// expired and -1 when the escrow never expires
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[]` The escrow account
+    TimeRemaining,
+
+    /// Transfers the whole balance of a fee token account, one owned by the fee vault PDA of its mint returned by
+    /// `pda::find_fee_vault_address`, to a token account of the admin's choosing
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The admin of the config
+    /// 1. `[]` The config account
+    /// 2. `[writable]` The fee token account
+    /// 3. `[writable]` The token account receiving the fees
+    /// 4. `[]` The token program
+    /// 5. `[]` The fee vault PDA of the fee token account's mint
+    WithdrawFees,
+
+    /// Pauses or resumes the exchanges of every escrow depositing or expecting the given mint
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The admin of the config
+    /// 1. `[writable]` The config account
+    SetMintPaused {
+        /// The mint to pause or resume
+        mint: Pubkey,
+        /// Whether exchanges involving the mint are rejected
+        paused: bool,
+    },
+
+    /// Starts the trade like `InitEscrow` for a single NFT: the deposit has to be exactly 1 unit of a mint with 0 decimals
+    /// and a supply of 1, and the escrow can only have one taker paying a fixed amount, neither a price ratio nor a payment band
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[]` The mint of the NFT
+    /// 1. - 12. The accounts of `InitEscrow`, the optional stats account included
+    InitEscrowNft {
+        /// The parameters of the new escrow, `amount` is the price of the NFT
+        args: InitEscrowArgs,
+    },
+
+    /// Adds lamports to an escrow account, e.g. when it fell below rent exemption after a change of the rent parameters.
+    /// Anyone can top it up, the escrow account has to be rent exempt afterwards
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The account paying the lamports
+    /// 1. `[writable]` The escrow account
+    /// 2. `[]` The system program
+    TopUpRent {
+        /// The lamports to add to the escrow account
+        lamports: u64,
+    },
+
+    /// Writes the status of an escrow as a single `state::Status` byte to the return data.
+    /// The escrow account is closed once the escrow is filled or cancelled, the status is then `Closed`
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[]` The escrow account
+    GetStatus,
+
+    /// Pushes back the expiry of an escrow, charging the initializer the config's extension fee
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The account of the person who initialized the escrow, paying the extension fee
+    /// 1. `[writable]` The escrow account holding the escrow info
+    /// 2. `[]` The config account at the address returned by `pda::find_config_address`, it may not have been created
+    /// 3. `[writable]` The config's treasury, receiving the extension fee. Any account when the config charges no extension fee
+    /// 4. `[]` The system program
+    ExtendExpiry {
+        /// The new expiry slot, or unix timestamp when the escrow doesn't use `use_slot_expiry`. It has to be later
+        /// than the current one, zero is rejected
+        expiry: u64,
+    },
+
+    /// Sets the lamports the initializer pays to the treasury on `ExtendExpiry`
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The admin of the config
+    /// 1. `[writable]` The config account
+    SetExtensionFee {
+        /// The new extension fee, zero disables it
+        extension_fee: u64,
+    },
+
+    /// Settles two mirrored escrows against each other: each deposit goes to the other initializer's receiving token account
+    /// and both escrows are closed. Each deposit has to cover what the other initializer expects. Only escrows with a single
+    /// taker and a fixed expected amount can be matched, without a hashlock, a callback, a settlement authority or delegation
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[writable]` The first escrow account
+    /// 1. `[writable]` The first escrow's temp token account, closed once its deposit is sent
+    /// 2. `[writable]` The first escrow's initializer, receiving the temp token account rent
+    /// 3. `[writable]` The first initializer's token account receiving the second deposit
+    /// 4. `[writable]` The first escrow's rent refund recipient
+    /// 5. `[]` The first escrow's PDA
+    /// 6. - 11. The same accounts for the second escrow
+    /// 12. `[]` The token program
+    /// 13. `[]` The config account at the address returned by `pda::find_config_address`, it may not have been created
+    /// 14. `[writable]` The escrow index account at the address returned by `pda::find_escrow_index_address`, both escrows are removed from it
+    /// 15. `[]` The mint of the first deposit
+    /// 16. `[]` The mint of the second deposit
+    MatchEscrows,
+
+    /// Sets the share of each exchange's payment that goes to the fee vault of the expected mint
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The admin of the config
+    /// 1. `[writable]` The config account
+    SetExchangeFee {
+        /// The new exchange fee in basis points, at most `state::MAX_EXCHANGE_FEE_BPS`, zero disables it
+        exchange_fee_bps: u16,
+    },
+
+    /// Reserves the escrow for the taker until the given slot: until then no other taker can exchange it and the initializer
+    /// can't cancel it. A taker can extend their own reservation, not take over another taker's one before it is over
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The taker reserving the escrow
+    /// 1. `[writable]` The escrow account holding the escrow info
+    Reserve {
+        /// The slot the reservation ends at, later than the current slot and at most `state::MAX_RESERVATION_SLOTS` ahead of it
+        reserved_until_slot: u64,
+    },
+
+    /// Sets the number of fills of each new escrow that the exchange fee is waived for
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The admin of the config
+    /// 1. `[writable]` The config account
+    SetFreeFills {
+        /// The new number of free fills, escrows already open keep the fills they were initialized with
+        free_fills: u16,
+    },
+
+    /// Starts the trade like `InitEscrow` with native SOL as the deposit: the lamports are wrapped into the temp token account,
+    /// an empty token account of the native mint, and the taker is paid them unwrapped. The escrow can only have one taker
+    /// paying a fixed amount, it can't be delegated
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. - 11. The accounts of `InitEscrow`, the optional stats account included
+    InitEscrowWithSol {
+        /// The lamports the initializer deposits
+        lamports: u64,
+        /// The parameters of the new escrow
+        args: InitEscrowArgs,
+    },
+
+    /// Initializes a template of the parameters a maker shares across many escrows, at the address returned by
+    /// `pda::find_template_address`. The escrows opened from it with `InitEscrowFromTemplate` only store a reference to it
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The maker, paying for the template account
+    /// 1. `[writable]` The template account, created unless it was already allocated to the program
+    /// 2. `[]` The mint every escrow opened from the template deposits
+    /// 3. `[]` The mint every escrow opened from the template expects
+    /// 4. `[]` The system program
+    InitTemplate {
+        /// Tells the maker's templates apart, part of the template account's seeds
+        template_id: u64,
+        /// How far the amount a taker asks for may be off its deposit share, zero requires the exact share
+        amount_tolerance: u64,
+        /// The program derived address that has to sign every exchange, the default pubkey lets any taker exchange
+        settlement_authority: Pubkey,
+    },
+
+    /// Starts the trade like `InitEscrow` with the shared parameters of a template: the deposit and the expected mint
+    /// have to be the template's, its amount tolerance and settlement authority apply. The exchange reads them from the template
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[]` The template account, initialized by the initializer
+    /// 1. - 12. The accounts of `InitEscrow`, the optional stats account included
+    InitEscrowFromTemplate {
+        /// The parameters of the new escrow, `amount_tolerance` and `settlement_authority` are left to the template
+        args: InitEscrowArgs,
+    },
+
+    /// Starts the trade like `InitEscrow` in a namespace the config allows. The namespace is a seed of the escrow's PDA,
+    /// so the escrows of each namespace are grouped under authorities no other namespace derives
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. - 11. The accounts of `InitEscrow`, the PDA of the escrow is the one `pda::PdaInfo::find_in_namespace` returns
+    InitEscrowInNamespace {
+        /// The namespace, any other than `pda::DEFAULT_NAMESPACE`
+        namespace: [u8; NAMESPACE_LEN],
+        /// The parameters of the new escrow
+        args: InitEscrowArgs,
+    },
+
+    /// Allows or disallows opening escrows in the given namespace, the escrows already open in it are left as they are
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The admin of the config
+    /// 1. `[writable]` The config account
+    SetNamespaceAllowed {
+        /// The namespace to allow or disallow
+        namespace: [u8; NAMESPACE_LEN],
+        /// Whether escrows can be opened in the namespace
+        allowed: bool,
+    },
+}
+
+impl InitEscrowArgs {
+    /// The number of bytes the parameters take without the bytes of the note
+    const PACKED_LEN_WITHOUT_NOTE: usize = 8
+        + 8
+        + 8
+        + 8
+        + 8
+        + 1
+        + 1
+        + 8
+        + 8
+        + 1
+        + 32
+        + 8
+        + 32
+        + 1
+        + 32
+        + 32
+        + 8
+        + 8
+        + 8
+        + 8
+        + 8
+        + 32
+        + 1
+        + 1;
+
+    /// The number of bytes `unpack` reads for these parameters
+    fn packed_len(&self) -> usize {
+        Self::PACKED_LEN_WITHOUT_NOTE + self.note.len()
+    }
+
+    fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+        let (amount, rest) = EscrowInstruction::unpack_u64(input)?;
+        let (decay_start_slot, rest) = EscrowInstruction::unpack_u64(rest)?;
+        let (decay_end_slot, rest) = EscrowInstruction::unpack_u64(rest)?;
+        let (decay_end_amount, rest) = EscrowInstruction::unpack_u64(rest)?;
+        let (nonce, rest) = EscrowInstruction::unpack_u64(rest)?;
+        let (taker_slots, rest) = EscrowInstruction::unpack_u8(rest)?;
+        let (use_slot_expiry, rest) = EscrowInstruction::unpack_bool(rest)?;
+        let (expiry_timestamp, rest) = EscrowInstruction::unpack_u64(rest)?;
+        let (expiry_slot, rest) = EscrowInstruction::unpack_u64(rest)?;
+        let (use_delegation, rest) = EscrowInstruction::unpack_bool(rest)?;
+        let (hashlock, rest) = EscrowInstruction::unpack_bytes32(rest)?;
+        let (timelock_slot, rest) = EscrowInstruction::unpack_u64(rest)?;
+        let (callback_program, rest) = EscrowInstruction::unpack_pubkey(rest)?;
+        let (callback_accounts_len, rest) = EscrowInstruction::unpack_u8(rest)?;
+        let (rent_refund_recipient, rest) = EscrowInstruction::unpack_pubkey(rest)?;
+        let (cancel_delegate, rest) = EscrowInstruction::unpack_pubkey(rest)?;
+        let (price_num, rest) = EscrowInstruction::unpack_u64(rest)?;
+        let (price_den, rest) = EscrowInstruction::unpack_u64(rest)?;
+        let (min_amount, rest) = EscrowInstruction::unpack_u64(rest)?;
+        let (max_amount, rest) = EscrowInstruction::unpack_u64(rest)?;
+        let (amount_tolerance, rest) = EscrowInstruction::unpack_u64(rest)?;
+        let (settlement_authority, rest) = EscrowInstruction::unpack_pubkey(rest)?;
+        let (note, rest) = EscrowInstruction::unpack_note(rest)?;
+        let list_in_index = !rest.is_empty() && EscrowInstruction::unpack_bool(rest)?.0;
+
+        Ok(Self {
+            amount,
+            decay_start_slot,
+            decay_end_slot,
+            decay_end_amount,
+            nonce,
+            taker_slots,
+            use_slot_expiry,
+            expiry_timestamp: expiry_timestamp as i64,
+            expiry_slot,
+            use_delegation,
+            hashlock,
+            timelock_slot,
+            callback_program,
+            callback_accounts_len,
+            rent_refund_recipient,
+            cancel_delegate,
+            price_num,
+            price_den,
+            min_amount,
+            max_amount,
+            amount_tolerance,
+            settlement_authority,
+            note,
+            list_in_index,
+        })
+    }
+
+    fn pack_into(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.amount.to_le_bytes());
+        buf.extend_from_slice(&self.decay_start_slot.to_le_bytes());
+        buf.extend_from_slice(&self.decay_end_slot.to_le_bytes());
+        buf.extend_from_slice(&self.decay_end_amount.to_le_bytes());
+        buf.extend_from_slice(&self.nonce.to_le_bytes());
+        buf.push(self.taker_slots);
+        buf.push(self.use_slot_expiry as u8);
+        buf.extend_from_slice(&self.expiry_timestamp.to_le_bytes());
+        buf.extend_from_slice(&self.expiry_slot.to_le_bytes());
+        buf.push(self.use_delegation as u8);
+        buf.extend_from_slice(&self.hashlock);
+        buf.extend_from_slice(&self.timelock_slot.to_le_bytes());
+        buf.extend_from_slice(self.callback_program.as_ref());
+        buf.push(self.callback_accounts_len);
+        buf.extend_from_slice(self.rent_refund_recipient.as_ref());
+        buf.extend_from_slice(self.cancel_delegate.as_ref());
+        buf.extend_from_slice(&self.price_num.to_le_bytes());
+        buf.extend_from_slice(&self.price_den.to_le_bytes());
+        buf.extend_from_slice(&self.min_amount.to_le_bytes());
+        buf.extend_from_slice(&self.max_amount.to_le_bytes());
+        buf.extend_from_slice(&self.amount_tolerance.to_le_bytes());
+        buf.extend_from_slice(self.settlement_authority.as_ref());
+        buf.push(self.note.len() as u8);
+        buf.extend_from_slice(&self.note);
+        buf.push(self.list_in_index as u8);
     }
 }
 
 impl EscrowInstruction {
     /// Unpacks a byte buffer into a [EscrowInstruction](enum.EscrowInstruction.html).
     pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
-        let (tag, rest) = input.split_first().ok_or(InvalidInstruction)?;
+        let (version, rest) = input.split_first().ok_or(InvalidInstruction)?;
+        if *version != INSTRUCTION_VERSION {
+            return Err(InvalidInstruction.into());
+        }
+        let (tag, rest) = rest.split_first().ok_or(InvalidInstruction)?;
 
         Ok(match tag {
             0 => Self::InitEscrow {
-                amount: Self::unpack_amount(rest)?,
+                args: InitEscrowArgs::unpack(rest)?,
+            },
+            13 => Self::Replace {
+                args: InitEscrowArgs::unpack(rest)?,
             },
-            1 => Self::Exchange {
+            26 => Self::InitEscrowNft {
+                args: InitEscrowArgs::unpack(rest)?,
+            },
+            1 => {
+                let (amount, rest) = Self::unpack_u64(rest)?;
+                let (payment, rest) = Self::unpack_u64(rest)?;
+                let (taker_deadline_slot, rest) = Self::unpack_u64(rest)?;
+                let preimage = if rest.is_empty() {
+                    None
+                } else {
+                    Some(Self::unpack_bytes32(rest)?.0)
+                };
+                Self::Exchange {
+                    amount,
+                    payment,
+                    taker_deadline_slot,
+                    preimage,
+                }
+            }
+            2 => Self::Cancel,
+            3 => Self::PartialWithdraw {
                 amount: Self::unpack_amount(rest)?,
             },
+            4 => Self::GetEscrow,
+            5 => Self::InitStats,
+            6 => Self::InitConfig,
+            7 => {
+                let (paused, _rest) = Self::unpack_bool(rest)?;
+                Self::SetPaused { paused }
+            }
+            8 => Self::InitPairRegistry,
+            9 => {
+                let (deposit_mint, rest) = Self::unpack_pubkey(rest)?;
+                let (expected_mint, rest) = Self::unpack_pubkey(rest)?;
+                let (allowed, _rest) = Self::unpack_bool(rest)?;
+                Self::SetPairAllowed {
+                    deposit_mint,
+                    expected_mint,
+                    allowed,
+                }
+            }
+            10 => Self::DeriveAuthority,
+            11 => Self::SweepStrayTokens,
+            12 => Self::CheckFillable,
+            14 => {
+                let (treasury, _rest) = Self::unpack_pubkey(rest)?;
+                Self::SetTreasury { treasury }
+            }
+            15 => Self::SetCancelFee {
+                cancel_fee: Self::unpack_amount(rest)?,
+            },
+            16 => Self::GetEscrowBatch,
+            17 => Self::SetReceiveAccount,
+            18 => Self::SetInitFee {
+                init_fee: Self::unpack_amount(rest)?,
+            },
+            19 => Self::InitEscrowIndex,
+            20 => Self::Version,
+            21 => Self::ValidateExchangeAccounts,
+            22 => Self::ForceReclaim,
+            23 => Self::TimeRemaining,
+            24 => Self::WithdrawFees,
+            25 => {
+                let (mint, rest) = Self::unpack_pubkey(rest)?;
+                let (paused, _rest) = Self::unpack_bool(rest)?;
+                Self::SetMintPaused { mint, paused }
+            }
+            27 => Self::TopUpRent {
+                lamports: Self::unpack_amount(rest)?,
+            },
+            28 => Self::GetStatus,
+            29 => Self::ExtendExpiry {
+                expiry: Self::unpack_amount(rest)?,
+            },
+            30 => Self::SetExtensionFee {
+                extension_fee: Self::unpack_amount(rest)?,
+            },
+            31 => Self::MatchEscrows,
+            32 => {
+                let (exchange_fee_bps, _rest) = Self::unpack_u16(rest)?;
+                Self::SetExchangeFee { exchange_fee_bps }
+            }
+            33 => Self::Reserve {
+                reserved_until_slot: Self::unpack_amount(rest)?,
+            },
+            34 => {
+                let (free_fills, _rest) = Self::unpack_u16(rest)?;
+                Self::SetFreeFills { free_fills }
+            }
+            35 => {
+                let (lamports, rest) = Self::unpack_u64(rest)?;
+                Self::InitEscrowWithSol {
+                    lamports,
+                    args: InitEscrowArgs::unpack(rest)?,
+                }
+            }
+            36 => {
+                let (template_id, rest) = Self::unpack_u64(rest)?;
+                let (amount_tolerance, rest) = Self::unpack_u64(rest)?;
+                let (settlement_authority, _rest) = Self::unpack_pubkey(rest)?;
+                Self::InitTemplate {
+                    template_id,
+                    amount_tolerance,
+                    settlement_authority,
+                }
+            }
+            37 => Self::InitEscrowFromTemplate {
+                args: InitEscrowArgs::unpack(rest)?,
+            },
+            38 => {
+                let (namespace, rest) = Self::unpack_namespace(rest)?;
+                Self::InitEscrowInNamespace {
+                    namespace,
+                    args: InitEscrowArgs::unpack(rest)?,
+                }
+            }
+            39 => {
+                let (namespace, rest) = Self::unpack_namespace(rest)?;
+                let (allowed, _rest) = Self::unpack_bool(rest)?;
+                Self::SetNamespaceAllowed { namespace, allowed }
+            }
             _ => return Err(InvalidInstruction.into()),
         })
     }
 
+    /// Packs a [EscrowInstruction](enum.EscrowInstruction.html) into a byte buffer.
+    pub fn pack(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.packed_len());
+        buf.push(INSTRUCTION_VERSION);
+        match self {
+            Self::InitEscrow { args } => {
+                buf.push(0);
+                args.pack_into(&mut buf);
+            }
+            Self::Replace { args } => {
+                buf.push(13);
+                args.pack_into(&mut buf);
+            }
+            Self::InitEscrowNft { args } => {
+                buf.push(26);
+                args.pack_into(&mut buf);
+            }
+            Self::Exchange {
+                amount,
+                payment,
+                taker_deadline_slot,
+                preimage,
+            } => {
+                buf.push(1);
+                buf.extend_from_slice(&amount.to_le_bytes());
+                buf.extend_from_slice(&payment.to_le_bytes());
+                buf.extend_from_slice(&taker_deadline_slot.to_le_bytes());
+                if let Some(preimage) = preimage {
+                    buf.extend_from_slice(preimage);
+                }
+            }
+            Self::Cancel => buf.push(2),
+            Self::PartialWithdraw { amount } => {
+                buf.push(3);
+                buf.extend_from_slice(&amount.to_le_bytes());
+            }
+            Self::GetEscrow => buf.push(4),
+            Self::InitStats => buf.push(5),
+            Self::InitConfig => buf.push(6),
+            Self::SetPaused { paused } => {
+                buf.push(7);
+                buf.push(*paused as u8);
+            }
+            Self::InitPairRegistry => buf.push(8),
+            Self::SetPairAllowed {
+                deposit_mint,
+                expected_mint,
+                allowed,
+            } => {
+                buf.push(9);
+                buf.extend_from_slice(deposit_mint.as_ref());
+                buf.extend_from_slice(expected_mint.as_ref());
+                buf.push(*allowed as u8);
+            }
+            Self::DeriveAuthority => buf.push(10),
+            Self::SweepStrayTokens => buf.push(11),
+            Self::CheckFillable => buf.push(12),
+            Self::SetTreasury { treasury } => {
+                buf.push(14);
+                buf.extend_from_slice(treasury.as_ref());
+            }
+            Self::SetCancelFee { cancel_fee } => {
+                buf.push(15);
+                buf.extend_from_slice(&cancel_fee.to_le_bytes());
+            }
+            Self::GetEscrowBatch => buf.push(16),
+            Self::SetReceiveAccount => buf.push(17),
+            Self::SetInitFee { init_fee } => {
+                buf.push(18);
+                buf.extend_from_slice(&init_fee.to_le_bytes());
+            }
+            Self::InitEscrowIndex => buf.push(19),
+            Self::Version => buf.push(20),
+            Self::ValidateExchangeAccounts => buf.push(21),
+            Self::ForceReclaim => buf.push(22),
+            Self::TimeRemaining => buf.push(23),
+            Self::WithdrawFees => buf.push(24),
+            Self::SetMintPaused { mint, paused } => {
+                buf.push(25);
+                buf.extend_from_slice(mint.as_ref());
+                buf.push(*paused as u8);
+            }
+            Self::TopUpRent { lamports } => {
+                buf.push(27);
+                buf.extend_from_slice(&lamports.to_le_bytes());
+            }
+            Self::GetStatus => buf.push(28),
+            Self::ExtendExpiry { expiry } => {
+                buf.push(29);
+                buf.extend_from_slice(&expiry.to_le_bytes());
+            }
+            Self::SetExtensionFee { extension_fee } => {
+                buf.push(30);
+                buf.extend_from_slice(&extension_fee.to_le_bytes());
+            }
+            Self::MatchEscrows => buf.push(31),
+            Self::SetExchangeFee { exchange_fee_bps } => {
+                buf.push(32);
+                buf.extend_from_slice(&exchange_fee_bps.to_le_bytes());
+            }
+            Self::Reserve {
+                reserved_until_slot,
+            } => {
+                buf.push(33);
+                buf.extend_from_slice(&reserved_until_slot.to_le_bytes());
+            }
+            Self::SetFreeFills { free_fills } => {
+                buf.push(34);
+                buf.extend_from_slice(&free_fills.to_le_bytes());
+            }
+            Self::InitEscrowWithSol { lamports, args } => {
+                buf.push(35);
+                buf.extend_from_slice(&lamports.to_le_bytes());
+                args.pack_into(&mut buf);
+            }
+            Self::InitTemplate {
+                template_id,
+                amount_tolerance,
+                settlement_authority,
+            } => {
+                buf.push(36);
+                buf.extend_from_slice(&template_id.to_le_bytes());
+                buf.extend_from_slice(&amount_tolerance.to_le_bytes());
+                buf.extend_from_slice(settlement_authority.as_ref());
+            }
+            Self::InitEscrowFromTemplate { args } => {
+                buf.push(37);
+                args.pack_into(&mut buf);
+            }
+            Self::InitEscrowInNamespace { namespace, args } => {
+                buf.push(38);
+                buf.extend_from_slice(namespace);
+                args.pack_into(&mut buf);
+            }
+            Self::SetNamespaceAllowed { namespace, allowed } => {
+                buf.push(39);
+                buf.extend_from_slice(namespace);
+                buf.push(*allowed as u8);
+            }
+        }
+        // `unpack` reads the fields at fixed offsets, a length mismatch means a field was packed with the wrong width
+        debug_assert_eq!(buf.len(), self.packed_len());
+        buf
+    }
+
+    /// The number of bytes `unpack` reads for this instruction, version and tag included
+    fn packed_len(&self) -> usize {
+        1 + match self {
+            Self::InitEscrow { args }
+            | Self::Replace { args }
+            | Self::InitEscrowNft { args }
+            | Self::InitEscrowFromTemplate { args } => 1 + args.packed_len(),
+            Self::InitEscrowWithSol { args, .. } => 1 + 8 + args.packed_len(),
+            Self::InitEscrowInNamespace { args, .. } => 1 + NAMESPACE_LEN + args.packed_len(),
+            Self::Exchange { preimage, .. } => 1 + 8 + 8 + 8 + preimage.map_or(0, |_| 32),
+            Self::PartialWithdraw { .. }
+            | Self::SetCancelFee { .. }
+            | Self::SetInitFee { .. }
+            | Self::TopUpRent { .. }
+            | Self::ExtendExpiry { .. }
+            | Self::SetExtensionFee { .. }
+            | Self::Reserve { .. } => 1 + 8,
+            Self::SetTreasury { .. } => 1 + 32,
+            Self::InitTemplate { .. } => 1 + 8 + 8 + 32,
+            Self::SetPaused { .. } => 1 + 1,
+            Self::SetExchangeFee { .. } | Self::SetFreeFills { .. } => 1 + 2,
+            Self::SetPairAllowed { .. } => 1 + 32 + 32 + 1,
+            Self::SetMintPaused { .. } => 1 + 32 + 1,
+            Self::SetNamespaceAllowed { .. } => 1 + NAMESPACE_LEN + 1,
+            Self::Cancel
+            | Self::GetEscrow
+            | Self::InitStats
+            | Self::InitConfig
+            | Self::InitPairRegistry
+            | Self::DeriveAuthority
+            | Self::SweepStrayTokens
+            | Self::CheckFillable
+            | Self::GetEscrowBatch
+            | Self::SetReceiveAccount
+            | Self::InitEscrowIndex
+            | Self::Version
+            | Self::ValidateExchangeAccounts
+            | Self::ForceReclaim
+            | Self::TimeRemaining
+            | Self::GetStatus
+            | Self::MatchEscrows
+            | Self::WithdrawFees => 1,
+        }
+    }
+
+    /// The number of accounts the instruction can't do without, the optional trailing accounts left out
+    pub fn min_accounts(&self) -> usize {
+        match self {
+            Self::MatchEscrows => 17,
+            Self::Exchange { .. } | Self::ValidateExchangeAccounts => 15,
+            Self::InitEscrow { .. }
+            | Self::InitEscrowWithSol { .. }
+            | Self::InitEscrowInNamespace { .. } => INIT_ESCROW_ACCOUNTS,
+            Self::InitEscrowNft { .. } | Self::InitEscrowFromTemplate { .. } => {
+                1 + INIT_ESCROW_ACCOUNTS
+            }
+            Self::Replace { .. } => CANCEL_ACCOUNTS + INIT_ESCROW_ACCOUNTS,
+            Self::Cancel => CANCEL_ACCOUNTS,
+            Self::ForceReclaim => 9,
+            Self::SweepStrayTokens => 7,
+            Self::PartialWithdraw { .. } | Self::WithdrawFees => 6,
+            Self::ExtendExpiry { .. } | Self::InitTemplate { .. } => 5,
+            Self::InitPairRegistry | Self::InitEscrowIndex => 5,
+            Self::InitConfig | Self::SetReceiveAccount | Self::CheckFillable => 4,
+            Self::InitStats | Self::SetPairAllowed { .. } | Self::TopUpRent { .. } => 3,
+            Self::SetPaused { .. }
+            | Self::SetMintPaused { .. }
+            | Self::SetTreasury { .. }
+            | Self::SetCancelFee { .. }
+            | Self::SetInitFee { .. }
+            | Self::SetExtensionFee { .. }
+            | Self::SetExchangeFee { .. }
+            | Self::SetFreeFills { .. }
+            | Self::SetNamespaceAllowed { .. }
+            | Self::Reserve { .. } => 2,
+            Self::GetEscrow | Self::GetStatus | Self::DeriveAuthority | Self::TimeRemaining => 1,
+            Self::GetEscrowBatch | Self::Version => 0,
+        }
+    }
+
     pub fn unpack_amount(input: &[u8]) -> Result<u64, ProgramError> {
-        let amount = input
+        let (amount, _rest) = Self::unpack_u64(input)?;
+        Ok(amount)
+    }
+
+    fn unpack_pubkey(input: &[u8]) -> Result<(Pubkey, &[u8]), ProgramError> {
+        let key = input.get(..32).map(Pubkey::new).ok_or(InvalidInstruction)?;
+        Ok((key, &input[32..]))
+    }
+
+    fn unpack_bytes32(input: &[u8]) -> Result<([u8; 32], &[u8]), ProgramError> {
+        let bytes = input
+            .get(..32)
+            .and_then(|slice| slice.try_into().ok())
+            .ok_or(InvalidInstruction)?;
+        Ok((bytes, &input[32..]))
+    }
+
+    fn unpack_namespace(input: &[u8]) -> Result<([u8; NAMESPACE_LEN], &[u8]), ProgramError> {
+        let namespace = input
+            .get(..NAMESPACE_LEN)
+            .and_then(|slice| slice.try_into().ok())
+            .ok_or(InvalidInstruction)?;
+        Ok((namespace, &input[NAMESPACE_LEN..]))
+    }
+
+    fn unpack_note(input: &[u8]) -> Result<(Vec<u8>, &[u8]), ProgramError> {
+        let (len, rest) = Self::unpack_u8(input)?;
+        // The length byte goes up to 255, a longer note wouldn't fit in the escrow account
+        if len as usize > MAX_NOTE_LEN {
+            return Err(InvalidInstruction.into());
+        }
+        let note = rest.get(..len as usize).ok_or(InvalidInstruction)?;
+        Ok((note.to_vec(), &rest[len as usize..]))
+    }
+
+    fn unpack_u8(input: &[u8]) -> Result<(u8, &[u8]), ProgramError> {
+        let (value, rest) = input.split_first().ok_or(InvalidInstruction)?;
+        Ok((*value, rest))
+    }
+
+    fn unpack_bool(input: &[u8]) -> Result<(bool, &[u8]), ProgramError> {
+        let (value, rest) = Self::unpack_u8(input)?;
+        match value {
+            0 => Ok((false, rest)),
+            1 => Ok((true, rest)),
+            _ => Err(InvalidInstruction.into()),
+        }
+    }
+
+    fn unpack_u16(input: &[u8]) -> Result<(u16, &[u8]), ProgramError> {
+        let value = input
+            .get(..2)
+            .and_then(|slice| slice.try_into().ok())
+            .map(u16::from_le_bytes)
+            .ok_or(InvalidInstruction)?;
+        Ok((value, &input[2..]))
+    }
+
+    fn unpack_u64(input: &[u8]) -> Result<(u64, &[u8]), ProgramError> {
+        let value = input
             .get(..8)
             .and_then(|slice| slice.try_into().ok())
             .map(u64::from_le_bytes)
             .ok_or(InvalidInstruction)?;
-        Ok(amount)
+        Ok((value, &input[8..]))
+    }
+}
+
+/// Creates an `InitEscrow` instruction, the escrow account address is derived from the initializer and the nonce
+pub fn init_escrow(
+    program_id: &Pubkey,
+    initializer: &Pubkey,
+    temp_token_account: &Pubkey,
+    token_to_receive_account: &Pubkey,
+    treasury: &Pubkey,
+    args: InitEscrowArgs,
+    update_stats: bool,
+) -> Result<Instruction, ProgramError> {
+    // The note's length is packed as a single byte
+    if args.note.len() > MAX_NOTE_LEN {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let (escrow, _) = find_escrow_address(initializer, args.nonce, program_id);
+    let data = EscrowInstruction::InitEscrow { args }.pack();
+
+    let mut accounts = vec![
+        AccountMeta::new(*initializer, true),
+        AccountMeta::new(*temp_token_account, false),
+        AccountMeta::new_readonly(*token_to_receive_account, false),
+        AccountMeta::new(escrow, false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+        AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+        AccountMeta::new_readonly(find_pair_registry_address(program_id).0, false),
+        AccountMeta::new_readonly(find_config_address(program_id).0, false),
+        AccountMeta::new(*treasury, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new(find_escrow_index_address(program_id).0, false),
+    ];
+    if update_stats {
+        accounts.push(AccountMeta::new(find_stats_address(program_id).0, false));
+    }
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates an `InitEscrowNft` instruction, the temp token account has to hold the NFT of `nft_mint`
+#[allow(clippy::too_many_arguments)]
+pub fn init_escrow_nft(
+    program_id: &Pubkey,
+    nft_mint: &Pubkey,
+    initializer: &Pubkey,
+    temp_token_account: &Pubkey,
+    token_to_receive_account: &Pubkey,
+    treasury: &Pubkey,
+    args: InitEscrowArgs,
+    update_stats: bool,
+) -> Result<Instruction, ProgramError> {
+    let mut accounts = vec![AccountMeta::new_readonly(*nft_mint, false)];
+    let init_escrow_ix = init_escrow(
+        program_id,
+        initializer,
+        temp_token_account,
+        token_to_receive_account,
+        treasury,
+        args.clone(),
+        update_stats,
+    )?;
+    accounts.extend(init_escrow_ix.accounts);
+
+    let data = EscrowInstruction::InitEscrowNft { args }.pack();
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates an `InitEscrowWithSol` instruction, the temp token account has to be an empty token account of the native mint
+#[allow(clippy::too_many_arguments)]
+pub fn init_escrow_with_sol(
+    program_id: &Pubkey,
+    initializer: &Pubkey,
+    temp_token_account: &Pubkey,
+    token_to_receive_account: &Pubkey,
+    treasury: &Pubkey,
+    lamports: u64,
+    args: InitEscrowArgs,
+    update_stats: bool,
+) -> Result<Instruction, ProgramError> {
+    let init_escrow_ix = init_escrow(
+        program_id,
+        initializer,
+        temp_token_account,
+        token_to_receive_account,
+        treasury,
+        args.clone(),
+        update_stats,
+    )?;
+
+    let data = EscrowInstruction::InitEscrowWithSol { lamports, args }.pack();
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts: init_escrow_ix.accounts,
+        data,
+    })
+}
+
+/// Creates an `InitEscrowInNamespace` instruction. The instructions built for the escrow afterwards have to be
+/// passed through `with_namespace`, they otherwise name the PDA of the default namespace
+#[allow(clippy::too_many_arguments)]
+pub fn init_escrow_in_namespace(
+    program_id: &Pubkey,
+    initializer: &Pubkey,
+    temp_token_account: &Pubkey,
+    token_to_receive_account: &Pubkey,
+    treasury: &Pubkey,
+    namespace: &[u8; NAMESPACE_LEN],
+    args: InitEscrowArgs,
+    update_stats: bool,
+) -> Result<Instruction, ProgramError> {
+    let init_escrow_ix = init_escrow(
+        program_id,
+        initializer,
+        temp_token_account,
+        token_to_receive_account,
+        treasury,
+        args.clone(),
+        update_stats,
+    )?;
+
+    let data = EscrowInstruction::InitEscrowInNamespace {
+        namespace: *namespace,
+        args,
+    }
+    .pack();
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts: init_escrow_ix.accounts,
+        data,
+    })
+}
+
+/// Replaces the PDA of the default namespace among the accounts of an instruction built for `escrow`
+/// with the PDA of the namespace the escrow was opened in
+pub fn with_namespace(
+    mut instruction: Instruction,
+    escrow: &Pubkey,
+    namespace: &[u8; NAMESPACE_LEN],
+) -> Instruction {
+    let default_pda = PdaInfo::find(&instruction.program_id, escrow).key;
+    let namespaced_pda = PdaInfo::find_in_namespace(&instruction.program_id, namespace, escrow).key;
+    for account in instruction.accounts.iter_mut() {
+        if account.pubkey == default_pda {
+            account.pubkey = namespaced_pda;
+        }
+    }
+    instruction
+}
+
+/// Creates an `InitTemplate` instruction, the template account address is derived from the maker and the template id
+pub fn init_template(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    deposit_mint: &Pubkey,
+    expected_mint: &Pubkey,
+    template_id: u64,
+    amount_tolerance: u64,
+    settlement_authority: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::InitTemplate {
+        template_id,
+        amount_tolerance,
+        settlement_authority: *settlement_authority,
+    }
+    .pack();
+
+    let accounts = vec![
+        AccountMeta::new(*authority, true),
+        AccountMeta::new(
+            find_template_address(authority, template_id, program_id).0,
+            false,
+        ),
+        AccountMeta::new_readonly(*deposit_mint, false),
+        AccountMeta::new_readonly(*expected_mint, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates an `InitEscrowFromTemplate` instruction opening an escrow from the initializer's `template`
+#[allow(clippy::too_many_arguments)]
+pub fn init_escrow_from_template(
+    program_id: &Pubkey,
+    template: &Pubkey,
+    initializer: &Pubkey,
+    temp_token_account: &Pubkey,
+    token_to_receive_account: &Pubkey,
+    treasury: &Pubkey,
+    args: InitEscrowArgs,
+    update_stats: bool,
+) -> Result<Instruction, ProgramError> {
+    let mut accounts = vec![AccountMeta::new_readonly(*template, false)];
+    let init_escrow_ix = init_escrow(
+        program_id,
+        initializer,
+        temp_token_account,
+        token_to_receive_account,
+        treasury,
+        args.clone(),
+        update_stats,
+    )?;
+    accounts.extend(init_escrow_ix.accounts);
+
+    let data = EscrowInstruction::InitEscrowFromTemplate { args }.pack();
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates an `Exchange` instruction. With `create_receive_account` the taker's receiving token account
+/// doesn't exist yet, it is then created as the taker's associated token account of the deposit mint.
+/// The `callback_accounts` are the escrow's callback program followed by the accounts passed to it, empty without a callback.
+/// The `template` is the one the escrow was opened from, `None` without a template.
+/// The `settlement_authority` is the escrow's one, or its template's, `None` without a settlement authority
+#[allow(clippy::too_many_arguments)]
+pub fn exchange(
+    program_id: &Pubkey,
+    taker: &Pubkey,
+    taker_token_to_send_account: &Pubkey,
+    taker_token_to_receive_account: &Pubkey,
+    pda_token_account: &Pubkey,
+    initializer: &Pubkey,
+    initializer_token_to_receive_account: &Pubkey,
+    escrow: &Pubkey,
+    deposit_mint: &Pubkey,
+    expected_mint: &Pubkey,
+    rent_refund_recipient: &Pubkey,
+    fee_token_account: &Pubkey,
+    create_receive_account: bool,
+    amount: u64,
+    payment: u64,
+    taker_deadline_slot: u64,
+    preimage: Option<[u8; 32]>,
+    template: Option<&Pubkey>,
+    callback_accounts: &[AccountMeta],
+    settlement_authority: Option<&Pubkey>,
+    update_stats: bool,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::Exchange {
+        amount,
+        payment,
+        taker_deadline_slot,
+        preimage,
+    }
+    .pack();
+
+    let mut accounts = vec![
+        AccountMeta::new(*taker, true),
+        AccountMeta::new(*taker_token_to_send_account, false),
+        AccountMeta::new(*taker_token_to_receive_account, false),
+        AccountMeta::new(*pda_token_account, false),
+        AccountMeta::new(*initializer, false),
+        AccountMeta::new(*initializer_token_to_receive_account, false),
+        AccountMeta::new(*escrow, false),
+        AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+        AccountMeta::new_readonly(PdaInfo::find(program_id, escrow).key, false),
+        AccountMeta::new_readonly(find_config_address(program_id).0, false),
+        AccountMeta::new(find_escrow_index_address(program_id).0, false),
+        AccountMeta::new_readonly(*deposit_mint, false),
+        AccountMeta::new_readonly(*expected_mint, false),
+        AccountMeta::new(*rent_refund_recipient, false),
+        AccountMeta::new(*fee_token_account, false),
+    ];
+    if create_receive_account {
+        accounts.push(AccountMeta::new_readonly(
+            spl_associated_token_account::id(),
+            false,
+        ));
+        accounts.push(AccountMeta::new_readonly(system_program::id(), false));
+        accounts.push(AccountMeta::new_readonly(sysvar::rent::id(), false));
+    }
+    if let Some(template) = template {
+        accounts.push(AccountMeta::new_readonly(*template, false));
+    }
+    accounts.extend_from_slice(callback_accounts);
+    if let Some(settlement_authority) = settlement_authority {
+        accounts.push(AccountMeta::new_readonly(*settlement_authority, true));
+    }
+    if update_stats {
+        accounts.push(AccountMeta::new(find_stats_address(program_id).0, false));
+    }
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates an `Exchange` instruction for an escrow opened with `InitEscrowWithSol`, the taker is paid the deposit
+/// in lamports into `taker_lamports_account`
+#[allow(clippy::too_many_arguments)]
+pub fn exchange_sol_deposit(
+    program_id: &Pubkey,
+    taker: &Pubkey,
+    taker_token_to_send_account: &Pubkey,
+    taker_lamports_account: &Pubkey,
+    pda_token_account: &Pubkey,
+    initializer: &Pubkey,
+    initializer_token_to_receive_account: &Pubkey,
+    escrow: &Pubkey,
+    expected_mint: &Pubkey,
+    rent_refund_recipient: &Pubkey,
+    fee_token_account: &Pubkey,
+    amount: u64,
+    payment: u64,
+    taker_deadline_slot: u64,
+    preimage: Option<[u8; 32]>,
+    template: Option<&Pubkey>,
+    callback_accounts: &[AccountMeta],
+    settlement_authority: Option<&Pubkey>,
+    update_stats: bool,
+) -> Result<Instruction, ProgramError> {
+    let mut exchange_ix = exchange(
+        program_id,
+        taker,
+        taker_token_to_send_account,
+        taker_lamports_account,
+        pda_token_account,
+        initializer,
+        initializer_token_to_receive_account,
+        escrow,
+        &spl_token::native_mint::id(),
+        expected_mint,
+        rent_refund_recipient,
+        fee_token_account,
+        false,
+        amount,
+        payment,
+        taker_deadline_slot,
+        preimage,
+        template,
+        callback_accounts,
+        settlement_authority,
+        update_stats,
+    )?;
+    exchange_ix.accounts[8].is_writable = true;
+    exchange_ix
+        .accounts
+        .insert(15, AccountMeta::new_readonly(system_program::id(), false));
+    Ok(exchange_ix)
+}
+
+/// Creates a `MatchEscrows` instruction settling `escrow_a` and `escrow_b` against each other
+#[allow(clippy::too_many_arguments)]
+pub fn match_escrows(
+    program_id: &Pubkey,
+    escrow_a: &Pubkey,
+    pda_token_account_a: &Pubkey,
+    initializer_a: &Pubkey,
+    initializer_token_to_receive_account_a: &Pubkey,
+    rent_refund_recipient_a: &Pubkey,
+    deposit_mint_a: &Pubkey,
+    escrow_b: &Pubkey,
+    pda_token_account_b: &Pubkey,
+    initializer_b: &Pubkey,
+    initializer_token_to_receive_account_b: &Pubkey,
+    rent_refund_recipient_b: &Pubkey,
+    deposit_mint_b: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new(*escrow_a, false),
+        AccountMeta::new(*pda_token_account_a, false),
+        AccountMeta::new(*initializer_a, false),
+        AccountMeta::new(*initializer_token_to_receive_account_a, false),
+        AccountMeta::new(*rent_refund_recipient_a, false),
+        AccountMeta::new_readonly(PdaInfo::find(program_id, escrow_a).key, false),
+        AccountMeta::new(*escrow_b, false),
+        AccountMeta::new(*pda_token_account_b, false),
+        AccountMeta::new(*initializer_b, false),
+        AccountMeta::new(*initializer_token_to_receive_account_b, false),
+        AccountMeta::new(*rent_refund_recipient_b, false),
+        AccountMeta::new_readonly(PdaInfo::find(program_id, escrow_b).key, false),
+        AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+        AccountMeta::new_readonly(find_config_address(program_id).0, false),
+        AccountMeta::new(find_escrow_index_address(program_id).0, false),
+        AccountMeta::new_readonly(*deposit_mint_a, false),
+        AccountMeta::new_readonly(*deposit_mint_b, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::MatchEscrows.pack(),
+    })
+}
+
+/// Creates a `ValidateExchangeAccounts` instruction with the accounts `exchange` would be given
+#[allow(clippy::too_many_arguments)]
+pub fn validate_exchange_accounts(
+    program_id: &Pubkey,
+    taker: &Pubkey,
+    taker_token_to_send_account: &Pubkey,
+    taker_token_to_receive_account: &Pubkey,
+    pda_token_account: &Pubkey,
+    initializer: &Pubkey,
+    initializer_token_to_receive_account: &Pubkey,
+    escrow: &Pubkey,
+    deposit_mint: &Pubkey,
+    expected_mint: &Pubkey,
+    rent_refund_recipient: &Pubkey,
+    fee_token_account: &Pubkey,
+    create_receive_account: bool,
+    template: Option<&Pubkey>,
+    callback_accounts: &[AccountMeta],
+    settlement_authority: Option<&Pubkey>,
+    update_stats: bool,
+) -> Result<Instruction, ProgramError> {
+    let accounts = exchange(
+        program_id,
+        taker,
+        taker_token_to_send_account,
+        taker_token_to_receive_account,
+        pda_token_account,
+        initializer,
+        initializer_token_to_receive_account,
+        escrow,
+        deposit_mint,
+        expected_mint,
+        rent_refund_recipient,
+        fee_token_account,
+        create_receive_account,
+        0,
+        0,
+        0,
+        None,
+        template,
+        callback_accounts,
+        settlement_authority,
+        update_stats,
+    )?
+    .accounts;
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::ValidateExchangeAccounts.pack(),
+    })
+}
+
+/// Creates a `ForceReclaim` instruction
+pub fn force_reclaim(
+    program_id: &Pubkey,
+    initializer: &Pubkey,
+    pda_token_account: &Pubkey,
+    escrow: &Pubkey,
+    treasury: &Pubkey,
+    rent_refund_recipient: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new(*initializer, true),
+        AccountMeta::new(*pda_token_account, false),
+        AccountMeta::new(*escrow, false),
+        AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+        AccountMeta::new_readonly(PdaInfo::find(program_id, escrow).key, false),
+        AccountMeta::new_readonly(find_config_address(program_id).0, false),
+        AccountMeta::new(*treasury, false),
+        AccountMeta::new(find_escrow_index_address(program_id).0, false),
+        AccountMeta::new(*rent_refund_recipient, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::ForceReclaim.pack(),
+    })
+}
+
+/// Creates a `Cancel` instruction signed by the escrow's cancel delegate rather than the initializer
+#[allow(clippy::too_many_arguments)]
+pub fn cancel_by_delegate(
+    program_id: &Pubkey,
+    cancel_delegate: &Pubkey,
+    initializer: &Pubkey,
+    pda_token_account: &Pubkey,
+    initializer_token_account: &Pubkey,
+    escrow: &Pubkey,
+    treasury: &Pubkey,
+    rent_refund_recipient: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let mut instruction = cancel(
+        program_id,
+        initializer,
+        pda_token_account,
+        initializer_token_account,
+        escrow,
+        treasury,
+        rent_refund_recipient,
+    )?;
+    instruction.accounts[0].is_signer = false;
+    instruction
+        .accounts
+        .push(AccountMeta::new_readonly(*cancel_delegate, true));
+
+    Ok(instruction)
+}
+
+/// Creates a `Cancel` instruction
+pub fn cancel(
+    program_id: &Pubkey,
+    initializer: &Pubkey,
+    pda_token_account: &Pubkey,
+    initializer_token_account: &Pubkey,
+    escrow: &Pubkey,
+    treasury: &Pubkey,
+    rent_refund_recipient: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::Cancel.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*initializer, true),
+        AccountMeta::new(*pda_token_account, false),
+        AccountMeta::new(*initializer_token_account, false),
+        AccountMeta::new(*escrow, false),
+        AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+        AccountMeta::new_readonly(PdaInfo::find(program_id, escrow).key, false),
+        AccountMeta::new_readonly(find_config_address(program_id).0, false),
+        AccountMeta::new(*treasury, false),
+        AccountMeta::new(find_escrow_index_address(program_id).0, false),
+        AccountMeta::new(*rent_refund_recipient, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `PartialWithdraw` instruction
+pub fn partial_withdraw(
+    program_id: &Pubkey,
+    initializer: &Pubkey,
+    pda_token_account: &Pubkey,
+    initializer_token_account: &Pubkey,
+    escrow: &Pubkey,
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::PartialWithdraw { amount }.pack();
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*initializer, true),
+        AccountMeta::new(*pda_token_account, false),
+        AccountMeta::new(*initializer_token_account, false),
+        AccountMeta::new_readonly(*escrow, false),
+        AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+        AccountMeta::new_readonly(PdaInfo::find(program_id, escrow).key, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `SetReceiveAccount` instruction
+pub fn set_receive_account(
+    program_id: &Pubkey,
+    initializer: &Pubkey,
+    escrow: &Pubkey,
+    current_receive_account: &Pubkey,
+    new_receive_account: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new_readonly(*initializer, true),
+        AccountMeta::new(*escrow, false),
+        AccountMeta::new_readonly(*current_receive_account, false),
+        AccountMeta::new_readonly(*new_receive_account, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::SetReceiveAccount.pack(),
+    })
+}
+
+/// Creates a `GetEscrow` instruction
+pub fn get_escrow(program_id: &Pubkey, escrow: &Pubkey) -> Result<Instruction, ProgramError> {
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts: vec![AccountMeta::new_readonly(*escrow, false)],
+        data: EscrowInstruction::GetEscrow.pack(),
+    })
+}
+
+/// Creates a `TimeRemaining` instruction
+pub fn time_remaining(program_id: &Pubkey, escrow: &Pubkey) -> Result<Instruction, ProgramError> {
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts: vec![AccountMeta::new_readonly(*escrow, false)],
+        data: EscrowInstruction::TimeRemaining.pack(),
+    })
+}
+
+/// Creates a `TopUpRent` instruction
+pub fn top_up_rent(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    escrow: &Pubkey,
+    lamports: u64,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new(*payer, true),
+        AccountMeta::new(*escrow, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::TopUpRent { lamports }.pack(),
+    })
+}
+
+/// Creates a `GetEscrowBatch` instruction
+pub fn get_escrow_batch(
+    program_id: &Pubkey,
+    escrows: &[Pubkey],
+) -> Result<Instruction, ProgramError> {
+    let accounts = escrows
+        .iter()
+        .map(|escrow| AccountMeta::new_readonly(*escrow, false))
+        .collect();
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::GetEscrowBatch.pack(),
+    })
+}
+
+/// Creates an `InitStats` instruction
+pub fn init_stats(program_id: &Pubkey, payer: &Pubkey) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new(*payer, true),
+        AccountMeta::new(find_stats_address(program_id).0, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::InitStats.pack(),
+    })
+}
+
+/// Creates an `InitConfig` instruction
+pub fn init_config(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    admin: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new(*payer, true),
+        AccountMeta::new_readonly(*admin, true),
+        AccountMeta::new(find_config_address(program_id).0, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::InitConfig.pack(),
+    })
+}
+
+/// Creates a `SetPaused` instruction
+pub fn set_paused(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    paused: bool,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new_readonly(*admin, true),
+        AccountMeta::new(find_config_address(program_id).0, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::SetPaused { paused }.pack(),
+    })
+}
+
+/// Creates a `SetMintPaused` instruction
+pub fn set_mint_paused(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    mint: &Pubkey,
+    paused: bool,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new_readonly(*admin, true),
+        AccountMeta::new(find_config_address(program_id).0, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::SetMintPaused {
+            mint: *mint,
+            paused,
+        }
+        .pack(),
+    })
+}
+
+/// Creates a `SetNamespaceAllowed` instruction
+pub fn set_namespace_allowed(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    namespace: &[u8; NAMESPACE_LEN],
+    allowed: bool,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new_readonly(*admin, true),
+        AccountMeta::new(find_config_address(program_id).0, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::SetNamespaceAllowed {
+            namespace: *namespace,
+            allowed,
+        }
+        .pack(),
+    })
+}
+
+/// Creates a `SetTreasury` instruction
+pub fn set_treasury(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    treasury: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new_readonly(*admin, true),
+        AccountMeta::new(find_config_address(program_id).0, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::SetTreasury {
+            treasury: *treasury,
+        }
+        .pack(),
+    })
+}
+
+/// Creates a `SetCancelFee` instruction
+pub fn set_cancel_fee(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    cancel_fee: u64,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new_readonly(*admin, true),
+        AccountMeta::new(find_config_address(program_id).0, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::SetCancelFee { cancel_fee }.pack(),
+    })
+}
+
+/// Creates a `SetInitFee` instruction
+pub fn set_init_fee(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    init_fee: u64,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new_readonly(*admin, true),
+        AccountMeta::new(find_config_address(program_id).0, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::SetInitFee { init_fee }.pack(),
+    })
+}
+
+/// Creates a `SetExtensionFee` instruction
+pub fn set_extension_fee(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    extension_fee: u64,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new_readonly(*admin, true),
+        AccountMeta::new(find_config_address(program_id).0, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::SetExtensionFee { extension_fee }.pack(),
+    })
+}
+
+/// Creates a `SetExchangeFee` instruction
+pub fn set_exchange_fee(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    exchange_fee_bps: u16,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new_readonly(*admin, true),
+        AccountMeta::new(find_config_address(program_id).0, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::SetExchangeFee { exchange_fee_bps }.pack(),
+    })
+}
+
+/// Creates a `SetFreeFills` instruction
+pub fn set_free_fills(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    free_fills: u16,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new_readonly(*admin, true),
+        AccountMeta::new(find_config_address(program_id).0, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::SetFreeFills { free_fills }.pack(),
+    })
+}
+
+/// Creates an `ExtendExpiry` instruction, the treasury is only checked when the config charges an extension fee
+pub fn extend_expiry(
+    program_id: &Pubkey,
+    initializer: &Pubkey,
+    escrow: &Pubkey,
+    treasury: &Pubkey,
+    expiry: u64,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new(*initializer, true),
+        AccountMeta::new(*escrow, false),
+        AccountMeta::new_readonly(find_config_address(program_id).0, false),
+        AccountMeta::new(*treasury, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::ExtendExpiry { expiry }.pack(),
+    })
+}
+
+/// Creates a `Reserve` instruction
+pub fn reserve(
+    program_id: &Pubkey,
+    taker: &Pubkey,
+    escrow: &Pubkey,
+    reserved_until_slot: u64,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new_readonly(*taker, true),
+        AccountMeta::new(*escrow, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::Reserve {
+            reserved_until_slot,
+        }
+        .pack(),
+    })
+}
+
+/// Creates an `InitPairRegistry` instruction
+pub fn init_pair_registry(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    admin: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new(*payer, true),
+        AccountMeta::new_readonly(*admin, true),
+        AccountMeta::new_readonly(find_config_address(program_id).0, false),
+        AccountMeta::new(find_pair_registry_address(program_id).0, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::InitPairRegistry.pack(),
+    })
+}
+
+/// Creates an `InitEscrowIndex` instruction
+pub fn init_escrow_index(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    admin: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new(*payer, true),
+        AccountMeta::new_readonly(*admin, true),
+        AccountMeta::new_readonly(find_config_address(program_id).0, false),
+        AccountMeta::new(find_escrow_index_address(program_id).0, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::InitEscrowIndex.pack(),
+    })
+}
+
+/// Creates a `SetPairAllowed` instruction
+pub fn set_pair_allowed(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    deposit_mint: &Pubkey,
+    expected_mint: &Pubkey,
+    allowed: bool,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::SetPairAllowed {
+        deposit_mint: *deposit_mint,
+        expected_mint: *expected_mint,
+        allowed,
+    }
+    .pack();
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*admin, true),
+        AccountMeta::new_readonly(find_config_address(program_id).0, false),
+        AccountMeta::new(find_pair_registry_address(program_id).0, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `DeriveAuthority` instruction
+pub fn derive_authority(program_id: &Pubkey, escrow: &Pubkey) -> Result<Instruction, ProgramError> {
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts: vec![AccountMeta::new_readonly(*escrow, false)],
+        data: EscrowInstruction::DeriveAuthority.pack(),
+    })
+}
+
+/// Creates a `Version` instruction
+pub fn version(program_id: &Pubkey) -> Result<Instruction, ProgramError> {
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts: vec![],
+        data: EscrowInstruction::Version.pack(),
+    })
+}
+
+/// Creates a `WithdrawFees` instruction
+pub fn withdraw_fees(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    fee_token_account: &Pubkey,
+    destination_token_account: &Pubkey,
+    mint: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new_readonly(*admin, true),
+        AccountMeta::new_readonly(find_config_address(program_id).0, false),
+        AccountMeta::new(*fee_token_account, false),
+        AccountMeta::new(*destination_token_account, false),
+        AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+        AccountMeta::new_readonly(find_fee_vault_address(mint, program_id).0, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::WithdrawFees.pack(),
+    })
+}
+
+/// Creates a `SweepStrayTokens` instruction
+pub fn sweep_stray_tokens(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    stray_token_account: &Pubkey,
+    destination_token_account: &Pubkey,
+    escrow: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new_readonly(*admin, true),
+        AccountMeta::new_readonly(find_config_address(program_id).0, false),
+        AccountMeta::new(*stray_token_account, false),
+        AccountMeta::new(*destination_token_account, false),
+        AccountMeta::new_readonly(*escrow, false),
+        AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+        AccountMeta::new_readonly(PdaInfo::find(program_id, escrow).key, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::SweepStrayTokens.pack(),
+    })
+}
+
+/// Creates a `GetStatus` instruction
+pub fn get_status(program_id: &Pubkey, escrow: &Pubkey) -> Result<Instruction, ProgramError> {
+    let accounts = vec![AccountMeta::new_readonly(*escrow, false)];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::GetStatus.pack(),
+    })
+}
+
+/// Creates a `CheckFillable` instruction
+pub fn check_fillable(
+    program_id: &Pubkey,
+    escrow: &Pubkey,
+    pda_token_account: &Pubkey,
+    initializer_token_to_receive_account: &Pubkey,
+    taker: Option<&Pubkey>,
+) -> Result<Instruction, ProgramError> {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*escrow, false),
+        AccountMeta::new_readonly(*pda_token_account, false),
+        AccountMeta::new_readonly(find_config_address(program_id).0, false),
+        AccountMeta::new_readonly(*initializer_token_to_receive_account, false),
+    ];
+    if let Some(taker) = taker {
+        accounts.push(AccountMeta::new_readonly(*taker, false));
+    }
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::CheckFillable.pack(),
+    })
+}
+
+/// Creates a `Replace` instruction cancelling `old_escrow` and initializing a new escrow with the nonce of `args`
+#[allow(clippy::too_many_arguments)]
+pub fn replace(
+    program_id: &Pubkey,
+    initializer: &Pubkey,
+    old_pda_token_account: &Pubkey,
+    initializer_token_account: &Pubkey,
+    old_escrow: &Pubkey,
+    treasury: &Pubkey,
+    old_rent_refund_recipient: &Pubkey,
+    temp_token_account: &Pubkey,
+    token_to_receive_account: &Pubkey,
+    args: InitEscrowArgs,
+    update_stats: bool,
+) -> Result<Instruction, ProgramError> {
+    let mut accounts = cancel(
+        program_id,
+        initializer,
+        old_pda_token_account,
+        initializer_token_account,
+        old_escrow,
+        treasury,
+        old_rent_refund_recipient,
+    )?
+    .accounts;
+    let init_escrow_ix = init_escrow(
+        program_id,
+        initializer,
+        temp_token_account,
+        token_to_receive_account,
+        treasury,
+        args.clone(),
+        update_stats,
+    )?;
+    accounts.extend(init_escrow_ix.accounts);
+
+    let data = EscrowInstruction::Replace { args }.pack();
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::Processor;
+    use solana_program::account_info::AccountInfo;
+
+    /// Parameters with every field set away from its default, so a field packed at the wrong offset shows up
+    fn init_escrow_args() -> InitEscrowArgs {
+        InitEscrowArgs {
+            amount: 1,
+            decay_start_slot: 2,
+            decay_end_slot: 3,
+            decay_end_amount: 4,
+            nonce: u64::MAX,
+            taker_slots: 5,
+            use_slot_expiry: true,
+            expiry_timestamp: -6,
+            expiry_slot: 7,
+            use_delegation: true,
+            hashlock: [8; 32],
+            timelock_slot: 9,
+            callback_program: Pubkey::new_from_array([10; 32]),
+            callback_accounts_len: 11,
+            rent_refund_recipient: Pubkey::new_from_array([12; 32]),
+            cancel_delegate: Pubkey::new_from_array([13; 32]),
+            price_num: 14,
+            price_den: 15,
+            min_amount: 16,
+            max_amount: 17,
+            amount_tolerance: 18,
+            settlement_authority: Pubkey::new_from_array([19; 32]),
+            note: vec![20; MAX_NOTE_LEN],
+            list_in_index: true,
+        }
     }
-}
\ No newline at end of file
+
+    /// Every instruction, each with its fields set away from their defaults
+    fn every_instruction() -> Vec<EscrowInstruction> {
+        vec![
+            EscrowInstruction::InitEscrow {
+                args: init_escrow_args(),
+            },
+            EscrowInstruction::Exchange {
+                amount: 1,
+                payment: 2,
+                taker_deadline_slot: 3,
+                preimage: Some([4; 32]),
+            },
+            EscrowInstruction::Exchange {
+                amount: u64::MAX,
+                payment: 0,
+                taker_deadline_slot: 0,
+                preimage: None,
+            },
+            EscrowInstruction::Cancel,
+            EscrowInstruction::PartialWithdraw { amount: 5 },
+            EscrowInstruction::GetEscrow,
+            EscrowInstruction::InitStats,
+            EscrowInstruction::InitConfig,
+            EscrowInstruction::SetPaused { paused: true },
+            EscrowInstruction::InitPairRegistry,
+            EscrowInstruction::SetPairAllowed {
+                deposit_mint: Pubkey::new_from_array([6; 32]),
+                expected_mint: Pubkey::new_from_array([7; 32]),
+                allowed: true,
+            },
+            EscrowInstruction::DeriveAuthority,
+            EscrowInstruction::SweepStrayTokens,
+            EscrowInstruction::CheckFillable,
+            EscrowInstruction::Replace {
+                args: InitEscrowArgs {
+                    note: vec![],
+                    list_in_index: false,
+                    ..init_escrow_args()
+                },
+            },
+            EscrowInstruction::SetTreasury {
+                treasury: Pubkey::new_from_array([8; 32]),
+            },
+            EscrowInstruction::SetCancelFee { cancel_fee: 9 },
+            EscrowInstruction::GetEscrowBatch,
+            EscrowInstruction::SetReceiveAccount,
+            EscrowInstruction::SetInitFee { init_fee: 10 },
+            EscrowInstruction::InitEscrowIndex,
+            EscrowInstruction::Version,
+            EscrowInstruction::ValidateExchangeAccounts,
+            EscrowInstruction::ForceReclaim,
+            EscrowInstruction::TimeRemaining,
+            EscrowInstruction::WithdrawFees,
+            EscrowInstruction::SetMintPaused {
+                mint: Pubkey::new_from_array([11; 32]),
+                paused: true,
+            },
+            EscrowInstruction::InitEscrowNft {
+                args: init_escrow_args(),
+            },
+            EscrowInstruction::TopUpRent { lamports: 12 },
+            EscrowInstruction::GetStatus,
+            EscrowInstruction::ExtendExpiry { expiry: 13 },
+            EscrowInstruction::SetExtensionFee { extension_fee: 14 },
+            EscrowInstruction::MatchEscrows,
+            EscrowInstruction::SetExchangeFee {
+                exchange_fee_bps: 15,
+            },
+            EscrowInstruction::Reserve {
+                reserved_until_slot: 16,
+            },
+            EscrowInstruction::SetFreeFills { free_fills: 17 },
+            EscrowInstruction::InitEscrowWithSol {
+                lamports: 18,
+                args: init_escrow_args(),
+            },
+            EscrowInstruction::InitTemplate {
+                template_id: 19,
+                amount_tolerance: 20,
+                settlement_authority: Pubkey::new_from_array([21; 32]),
+            },
+            EscrowInstruction::InitEscrowFromTemplate {
+                args: init_escrow_args(),
+            },
+            EscrowInstruction::InitEscrowInNamespace {
+                namespace: [22; NAMESPACE_LEN],
+                args: init_escrow_args(),
+            },
+            EscrowInstruction::SetNamespaceAllowed {
+                namespace: [23; NAMESPACE_LEN],
+                allowed: true,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_pack_unpack_round_trip_of_every_instruction() {
+        let mut tags = vec![];
+        for instruction in every_instruction() {
+            let data = instruction.pack();
+            assert_eq!(data[0], INSTRUCTION_VERSION);
+            tags.push(data[1]);
+            assert_eq!(EscrowInstruction::unpack(&data), Ok(instruction));
+        }
+
+        // Every tag is covered, a new instruction has to be added above
+        tags.sort_unstable();
+        tags.dedup();
+        assert_eq!(tags, (0..40).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn test_dispatch_rejects_fewer_accounts_than_min_accounts() {
+        let program_id = Pubkey::new_from_array([7; 32]);
+        let owner = system_program::id();
+        for instruction in every_instruction() {
+            let min_accounts = instruction.min_accounts();
+            if min_accounts == 0 {
+                continue;
+            }
+
+            // Without any account, or one short, whatever the accounts are the handler is never reached
+            for accounts_len in [0, min_accounts - 1] {
+                let keys: Vec<Pubkey> = (0..accounts_len).map(|_| Pubkey::new_unique()).collect();
+                let mut lamports = vec![0; accounts_len];
+                let mut data = vec![vec![]; accounts_len];
+                let accounts: Vec<AccountInfo> = keys
+                    .iter()
+                    .zip(lamports.iter_mut())
+                    .zip(data.iter_mut())
+                    .map(|((key, lamports), data)| {
+                        AccountInfo::new(key, true, true, lamports, data, &owner, false, 0)
+                    })
+                    .collect();
+                assert_eq!(
+                    Processor::processor(&program_id, &accounts, &instruction.pack()),
+                    Err(ProgramError::NotEnoughAccountKeys),
+                    "{:?} with {} accounts",
+                    instruction,
+                    accounts_len
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_builders_round_trip_u64_max_amounts() {
+        let args = InitEscrowArgs {
+            amount: u64::MAX,
+            decay_start_slot: u64::MAX,
+            decay_end_slot: u64::MAX,
+            decay_end_amount: u64::MAX,
+            nonce: u64::MAX,
+            expiry_slot: u64::MAX,
+            timelock_slot: u64::MAX,
+            price_num: u64::MAX,
+            price_den: u64::MAX,
+            min_amount: u64::MAX,
+            max_amount: u64::MAX,
+            amount_tolerance: u64::MAX,
+            ..init_escrow_args()
+        };
+        let key = Pubkey::new_from_array([1; 32]);
+        let ix = init_escrow(&key, &key, &key, &key, &key, args.clone(), false).unwrap();
+        assert_eq!(
+            ix.data.len(),
+            EscrowInstruction::InitEscrow { args: args.clone() }.packed_len()
+        );
+        assert_eq!(
+            EscrowInstruction::unpack(&ix.data),
+            Ok(EscrowInstruction::InitEscrow { args })
+        );
+
+        let ix = exchange(
+            &key,
+            &key,
+            &key,
+            &key,
+            &key,
+            &key,
+            &key,
+            &key,
+            &key,
+            &key,
+            &key,
+            &key,
+            false,
+            u64::MAX,
+            u64::MAX,
+            u64::MAX,
+            None,
+            None,
+            &[],
+            None,
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            EscrowInstruction::unpack(&ix.data),
+            Ok(EscrowInstruction::Exchange {
+                amount: u64::MAX,
+                payment: u64::MAX,
+                taker_deadline_slot: u64::MAX,
+                preimage: None,
+            })
+        );
+
+        let ix = partial_withdraw(&key, &key, &key, &key, &key, u64::MAX).unwrap();
+        assert_eq!(
+            EscrowInstruction::unpack(&ix.data),
+            Ok(EscrowInstruction::PartialWithdraw { amount: u64::MAX })
+        );
+    }
+
+    #[test]
+    fn test_init_escrow_payment_band_offsets() {
+        // The band follows the price ratio, moving it changes the encoding and needs a new `INSTRUCTION_VERSION`
+        let data = EscrowInstruction::InitEscrow {
+            args: InitEscrowArgs {
+                note: vec![],
+                ..init_escrow_args()
+            },
+        }
+        .pack();
+        assert_eq!(&data[..2], &[INSTRUCTION_VERSION, 0]);
+        assert_eq!(&data[2 + 212..2 + 220], &16u64.to_le_bytes());
+        assert_eq!(&data[2 + 220..2 + 228], &17u64.to_le_bytes());
+        assert_eq!(data.len(), 2 + 268 + 1 + 1);
+
+        // Data cut anywhere in the band is rejected rather than read as a smaller band
+        for len in 2 + 212..2 + 228 {
+            assert_eq!(
+                EscrowInstruction::unpack(&data[..len]),
+                Err(InvalidInstruction.into())
+            );
+        }
+    }
+
+    #[test]
+    fn test_unpack_note_up_to_max_note_len() {
+        let data = EscrowInstruction::InitEscrow {
+            args: InitEscrowArgs {
+                note: vec![1; MAX_NOTE_LEN],
+                ..init_escrow_args()
+            },
+        }
+        .pack();
+        let note_len_offset = 2 + 268;
+        assert_eq!(data[note_len_offset], 128);
+        assert!(EscrowInstruction::unpack(&data).is_ok());
+
+        // The same data with one note byte more
+        let mut data = data;
+        data[note_len_offset] = 129;
+        data.insert(note_len_offset + 1, 1);
+        assert_eq!(
+            EscrowInstruction::unpack(&data),
+            Err(InvalidInstruction.into())
+        );
+    }
+
+    #[test]
+    fn test_unpack_rejects_unknown_tags() {
+        for tag in 40..=u8::MAX {
+            assert_eq!(
+                EscrowInstruction::unpack(&[INSTRUCTION_VERSION, tag]),
+                Err(InvalidInstruction.into())
+            );
+        }
+    }
+
+    #[test]
+    fn test_unpack_rejects_unknown_versions() {
+        let mut data = EscrowInstruction::Cancel.pack();
+        assert_eq!(
+            EscrowInstruction::unpack(&data),
+            Ok(EscrowInstruction::Cancel)
+        );
+
+        for version in (0..=u8::MAX).filter(|version| *version != INSTRUCTION_VERSION) {
+            data[0] = version;
+            assert_eq!(
+                EscrowInstruction::unpack(&data),
+                Err(InvalidInstruction.into())
+            );
+        }
+        assert_eq!(
+            EscrowInstruction::unpack(&[]),
+            Err(InvalidInstruction.into())
+        );
+        assert_eq!(
+            EscrowInstruction::unpack(&[INSTRUCTION_VERSION]),
+            Err(InvalidInstruction.into())
+        );
+    }
+}
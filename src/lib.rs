@@ -1,5 +1,14 @@
+pub mod constants;
 pub mod entrypoint;
 pub mod instruction;
 pub mod error;
+pub mod event;
+pub mod logging;
+pub mod math;
+pub mod pda;
 pub mod processor;
-pub mod state;
\ No newline at end of file
+pub mod state;
+pub mod util;
+pub mod validation;
+#[cfg(feature = "client")]
+pub mod client;
\ No newline at end of file
@@ -0,0 +1,32 @@
+/// Logs like `msg!`, but only when the program is built with the `verbose` feature. Use it for debugging
+/// diagnostics, the essential instruction and event logs stay on `msg!` so production builds don't pay for the rest
+#[cfg(feature = "verbose")]
+#[macro_export]
+macro_rules! verbose_msg {
+    ($($arg:tt)*) => {
+        solana_program::msg!($($arg)*);
+    };
+}
+
+/// Without the `verbose` feature nothing is logged nor evaluated. The arguments are still type checked
+/// inside a closure that is never called, so no binding goes unused
+#[cfg(not(feature = "verbose"))]
+#[macro_export]
+macro_rules! verbose_msg {
+    ($($arg:tt)*) => {
+        let _ = || {
+            let _ = format_args!($($arg)*);
+        };
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    // Both builds have to compile, run the tests with and without `--features verbose`
+    #[test]
+    fn test_verbose_msg_uses_its_arguments() {
+        let only_logged = 3;
+        verbose_msg!("A message without arguments");
+        verbose_msg!("A message with {} argument", only_logged);
+    }
+}
@@ -0,0 +1,94 @@
+use crate::error::EscrowError;
+
+/// Returns `a + b`, failing with `AmountOverflow` when it doesn't fit in a u64
+pub fn add(a: u64, b: u64) -> Result<u64, EscrowError> {
+    a.checked_add(b).ok_or(EscrowError::AmountOverflow)
+}
+
+/// Returns `a - b`, failing with `AmountUnderflow` when `b` is bigger than `a`
+pub fn sub(a: u64, b: u64) -> Result<u64, EscrowError> {
+    a.checked_sub(b).ok_or(EscrowError::AmountUnderflow)
+}
+
+/// Returns `a * b / c` rounded down. The product is computed in u128 so it can't overflow on the way,
+/// fails with `AmountOverflow` when `c` is zero or the result doesn't fit in a u64
+pub fn mul_div(a: u64, b: u64, c: u64) -> Result<u64, EscrowError> {
+    let result = (a as u128)
+        .checked_mul(b as u128)
+        .and_then(|product| product.checked_div(c as u128))
+        .ok_or(EscrowError::AmountOverflow)?;
+
+    if result > u64::MAX as u128 {
+        return Err(EscrowError::AmountOverflow);
+    }
+    Ok(result as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_boundaries() {
+        assert_eq!(add(0, 0), Ok(0));
+        assert_eq!(add(u64::MAX, 0), Ok(u64::MAX));
+        assert_eq!(add(u64::MAX - 1, 1), Ok(u64::MAX));
+        assert_eq!(add(u64::MAX, 1), Err(EscrowError::AmountOverflow));
+        assert_eq!(add(1, u64::MAX), Err(EscrowError::AmountOverflow));
+        assert_eq!(add(u64::MAX, u64::MAX), Err(EscrowError::AmountOverflow));
+    }
+
+    #[test]
+    fn test_sub_boundaries() {
+        assert_eq!(sub(0, 0), Ok(0));
+        assert_eq!(sub(u64::MAX, u64::MAX), Ok(0));
+        assert_eq!(sub(u64::MAX, 0), Ok(u64::MAX));
+        assert_eq!(sub(1, 1), Ok(0));
+        assert_eq!(sub(0, 1), Err(EscrowError::AmountUnderflow));
+        assert_eq!(
+            sub(u64::MAX - 1, u64::MAX),
+            Err(EscrowError::AmountUnderflow)
+        );
+    }
+
+    #[test]
+    fn test_mul_div_rounds_down() {
+        assert_eq!(mul_div(0, 0, 1), Ok(0));
+        assert_eq!(mul_div(1, 1, 2), Ok(0));
+        assert_eq!(mul_div(2, 1, 3), Ok(0));
+        assert_eq!(mul_div(5, 2, 3), Ok(3));
+        assert_eq!(mul_div(10, 3, 4), Ok(7));
+    }
+
+    #[test]
+    fn test_mul_div_boundaries() {
+        // The product is computed in u128, so it may exceed u64 as long as the quotient fits
+        assert_eq!(mul_div(u64::MAX, u64::MAX, u64::MAX), Ok(u64::MAX));
+        assert_eq!(mul_div(u64::MAX, 2, 2), Ok(u64::MAX));
+        assert_eq!(mul_div(u64::MAX, u64::MAX - 1, u64::MAX), Ok(u64::MAX - 1));
+        assert_eq!(mul_div(u64::MAX, 1, 1), Ok(u64::MAX));
+        assert_eq!(mul_div(u64::MAX, 0, 1), Ok(0));
+        assert_eq!(mul_div(u64::MAX, 1, u64::MAX), Ok(1));
+        assert_eq!(mul_div(u64::MAX - 1, 1, u64::MAX), Ok(0));
+
+        assert_eq!(mul_div(u64::MAX, 2, 1), Err(EscrowError::AmountOverflow));
+        assert_eq!(
+            mul_div(u64::MAX, u64::MAX, u64::MAX - 1),
+            Err(EscrowError::AmountOverflow)
+        );
+        assert_eq!(
+            mul_div(u64::MAX, u64::MAX, 1),
+            Err(EscrowError::AmountOverflow)
+        );
+    }
+
+    #[test]
+    fn test_mul_div_by_zero() {
+        assert_eq!(mul_div(0, 0, 0), Err(EscrowError::AmountOverflow));
+        assert_eq!(mul_div(1, 1, 0), Err(EscrowError::AmountOverflow));
+        assert_eq!(
+            mul_div(u64::MAX, u64::MAX, 0),
+            Err(EscrowError::AmountOverflow)
+        );
+    }
+}
@@ -0,0 +1,235 @@
+use std::slice;
+
+use solana_program::pubkey::{Pubkey, PubkeyError};
+
+/// Seed prefix of the PDA owning the temp token account of an escrow
+pub const AUTHORITY_SEED: &[u8] = b"escrow";
+
+/// The length of the namespaces escrows can be grouped under, each namespace derives authorities of its own
+pub const NAMESPACE_LEN: usize = 16;
+
+/// The namespace of escrows that weren't opened in one, its authorities are derived without a namespace seed
+pub const DEFAULT_NAMESPACE: [u8; NAMESPACE_LEN] = [0; NAMESPACE_LEN];
+
+/// Seed prefix of the PDA holding an escrow account, followed by the initializer and the nonce. It is the
+/// authority's prefix too, the two derivations can't collide as they hash a different number of seed bytes
+pub const ESCROW_SEED: &[u8] = b"escrow";
+
+/// Seed of the PDA holding the protocol wide `EscrowStats`
+pub const STATS_SEED: &[u8] = b"escrow-stats";
+
+/// Seed of the PDA holding the program wide `EscrowConfig`
+pub const CONFIG_SEED: &[u8] = b"escrow-config";
+
+/// Seed of the PDA holding the `PairRegistry`
+pub const PAIR_REGISTRY_SEED: &[u8] = b"escrow-pair-registry";
+
+/// Seed of the PDA holding the `EscrowIndex`
+pub const ESCROW_INDEX_SEED: &[u8] = b"escrow-index";
+
+/// Seed prefix of the PDA owning the token accounts the exchange fees of one mint are collected in
+pub const FEE_VAULT_SEED: &[u8] = b"fee-vault";
+
+/// Seed prefix of the PDA holding an `EscrowTemplate`, followed by the maker and the template id
+pub const TEMPLATE_SEED: &[u8] = b"escrow-template";
+
+/// Derives the address and bump seed of the PDA holding the escrow an initializer opens with the given nonce.
+///
+/// `InitEscrow` creates the account at this address, so a maker can run several escrows with the
+/// same parameters side by side without keeping track of extra keypairs.
+pub fn find_escrow_address(initializer: &Pubkey, nonce: u64, program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[ESCROW_SEED, initializer.as_ref(), &nonce.to_le_bytes()],
+        program_id,
+    )
+}
+
+/// Derives the address and bump seed of the PDA holding the protocol wide `EscrowStats`
+pub fn find_stats_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[STATS_SEED], program_id)
+}
+
+/// Derives the address and bump seed of the PDA holding the program wide `EscrowConfig`
+pub fn find_config_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[CONFIG_SEED], program_id)
+}
+
+/// Derives the address and bump seed of the PDA holding the `PairRegistry`
+pub fn find_pair_registry_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[PAIR_REGISTRY_SEED], program_id)
+}
+
+/// Derives the address and bump seed of the PDA holding the `EscrowIndex`
+pub fn find_escrow_index_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[ESCROW_INDEX_SEED], program_id)
+}
+
+/// Derives the address and bump seed of the fee vault PDA owning the fee token accounts of the given mint
+pub fn find_fee_vault_address(mint: &Pubkey, program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[FEE_VAULT_SEED, mint.as_ref()], program_id)
+}
+
+/// Derives the address and bump seed of the PDA holding the template a maker initializes with the given id
+pub fn find_template_address(
+    authority: &Pubkey,
+    template_id: u64,
+    program_id: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            TEMPLATE_SEED,
+            authority.as_ref(),
+            &template_id.to_le_bytes(),
+        ],
+        program_id,
+    )
+}
+
+/// Returns the seed a namespace adds to the authority derivation. The default namespace adds an empty seed, which
+/// hashes like no seed at all, so escrows opened without a namespace keep their authority
+fn namespace_seed(namespace: &[u8; NAMESPACE_LEN]) -> &[u8] {
+    if *namespace == DEFAULT_NAMESPACE {
+        &[]
+    } else {
+        namespace
+    }
+}
+
+/// The PDA owning the temp token account of an escrow, derived from the escrow's namespace and account key
+pub struct PdaInfo {
+    pub key: Pubkey,
+    pub bump: u8,
+    namespace: [u8; NAMESPACE_LEN],
+    escrow_key: Pubkey,
+}
+
+impl PdaInfo {
+    /// Derives the PDA of the given escrow account in the default namespace
+    pub fn find(program_id: &Pubkey, escrow_key: &Pubkey) -> Self {
+        Self::find_in_namespace(program_id, &DEFAULT_NAMESPACE, escrow_key)
+    }
+
+    /// Derives the PDA of the given escrow account opened in `namespace`
+    pub fn find_in_namespace(
+        program_id: &Pubkey,
+        namespace: &[u8; NAMESPACE_LEN],
+        escrow_key: &Pubkey,
+    ) -> Self {
+        let (key, bump) = Pubkey::find_program_address(
+            &[
+                AUTHORITY_SEED,
+                namespace_seed(namespace),
+                escrow_key.as_ref(),
+            ],
+            program_id,
+        );
+        PdaInfo {
+            key,
+            bump,
+            namespace: *namespace,
+            escrow_key: *escrow_key,
+        }
+    }
+
+    /// Recreates the PDA of the given escrow account from a stored bump seed, without searching for the canonical one
+    pub fn from_bump(
+        program_id: &Pubkey,
+        namespace: &[u8; NAMESPACE_LEN],
+        escrow_key: &Pubkey,
+        bump: u8,
+    ) -> Result<Self, PubkeyError> {
+        let key = Pubkey::create_program_address(
+            &[
+                AUTHORITY_SEED,
+                namespace_seed(namespace),
+                escrow_key.as_ref(),
+                slice::from_ref(&bump),
+            ],
+            program_id,
+        )?;
+        Ok(PdaInfo {
+            key,
+            bump,
+            namespace: *namespace,
+            escrow_key: *escrow_key,
+        })
+    }
+
+    /// Returns the seeds to sign for the PDA with `invoke_signed`
+    pub fn signer_seeds(&self) -> [&[u8]; 4] {
+        [
+            AUTHORITY_SEED,
+            namespace_seed(&self.namespace),
+            self.escrow_key.as_ref(),
+            slice::from_ref(&self.bump),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn program_id() -> Pubkey {
+        Pubkey::new_from_array([1; 32])
+    }
+
+    #[test]
+    fn test_from_bump_recreates_the_found_pda() {
+        let escrow_key = Pubkey::new_from_array([7; 32]);
+        let namespace = [3; NAMESPACE_LEN];
+        for namespace in [DEFAULT_NAMESPACE, namespace] {
+            let found = PdaInfo::find_in_namespace(&program_id(), &namespace, &escrow_key);
+            let recreated =
+                PdaInfo::from_bump(&program_id(), &namespace, &escrow_key, found.bump).unwrap();
+            assert_eq!(recreated.key, found.key);
+            assert_eq!(recreated.bump, found.bump);
+            assert_eq!(recreated.signer_seeds(), found.signer_seeds());
+        }
+    }
+
+    #[test]
+    fn test_default_namespace_derives_without_a_namespace_seed() {
+        let escrow_key = Pubkey::new_from_array([7; 32]);
+        let pda = PdaInfo::find(&program_id(), &escrow_key);
+        assert_eq!(
+            (pda.key, pda.bump),
+            Pubkey::find_program_address(&[AUTHORITY_SEED, escrow_key.as_ref()], &program_id())
+        );
+        assert_eq!(pda.signer_seeds()[1], &[] as &[u8]);
+        assert_eq!(
+            Pubkey::create_program_address(&pda.signer_seeds(), &program_id()),
+            Ok(pda.key)
+        );
+
+        // Any other namespace derives an authority of its own
+        let namespaced =
+            PdaInfo::find_in_namespace(&program_id(), &[3; NAMESPACE_LEN], &escrow_key);
+        assert_ne!(namespaced.key, pda.key);
+        assert_eq!(namespaced.signer_seeds()[1], &[3; NAMESPACE_LEN][..]);
+        assert_eq!(
+            Pubkey::create_program_address(&namespaced.signer_seeds(), &program_id()),
+            Ok(namespaced.key)
+        );
+    }
+
+    #[test]
+    fn test_from_bump_with_another_bump_is_another_address() {
+        let escrow_key = Pubkey::new_from_array([7; 32]);
+        let found = PdaInfo::find(&program_id(), &escrow_key);
+        // The bumps above the canonical one are all on the curve, below it the address differs or doesn't exist
+        for bump in found.bump.saturating_add(1)..=u8::MAX {
+            assert_eq!(
+                PdaInfo::from_bump(&program_id(), &DEFAULT_NAMESPACE, &escrow_key, bump).err(),
+                Some(PubkeyError::InvalidSeeds)
+            );
+        }
+        for bump in 0..found.bump {
+            if let Ok(pda) =
+                PdaInfo::from_bump(&program_id(), &DEFAULT_NAMESPACE, &escrow_key, bump)
+            {
+                assert_ne!(pda.key, found.key);
+            }
+        }
+    }
+}
@@ -1,17 +1,171 @@
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     entrypoint::ProgramResult,
+    hash::hash,
+    instruction::{AccountMeta, Instruction},
     msg,
-    program::{invoke, invoke_signed},
+    program::{invoke, invoke_signed, set_return_data, MAX_RETURN_DATA},
     program_error::ProgramError,
+    program_option::COption,
     program_pack::{IsInitialized, Pack},
     pubkey::Pubkey,
-    sysvar::{rent::Rent, Sysvar},
+    system_instruction, system_program,
+    sysvar::{self, clock::Clock, rent::Rent, Sysvar},
 };
 
-use spl_token::state::Account as TokenAccount;
+use spl_token::state::{Account as TokenAccount, Mint};
+use std::slice::Iter;
 
-use crate::{error::EscrowError, instruction::EscrowInstruction, state::Escrow};
+use crate::{
+    constants::{MAX_BATCH, PROGRAM_VERSION, TOKEN_PROGRAM_ID},
+    error::EscrowError,
+    event,
+    instruction::{EscrowInstruction, InitEscrowArgs, CANCEL_ACCOUNTS},
+    math,
+    pda::{
+        find_config_address, find_escrow_address, find_escrow_index_address,
+        find_fee_vault_address, find_pair_registry_address, find_stats_address,
+        find_template_address, PdaInfo, CONFIG_SEED, DEFAULT_NAMESPACE, ESCROW_INDEX_SEED,
+        ESCROW_SEED, FEE_VAULT_SEED, NAMESPACE_LEN, PAIR_REGISTRY_SEED, STATS_SEED, TEMPLATE_SEED,
+    },
+    state::{
+        Escrow, EscrowConfig, EscrowIndex, EscrowStats, EscrowTemplate, FillStatus, PairRegistry,
+        Status, MAX_ALLOWED_PAIRS, MAX_EXCHANGE_FEE_BPS, MAX_INDEXED_ESCROWS, MAX_NAMESPACES,
+        MAX_NOTE_LEN, MAX_PAUSED_MINTS, MAX_RESERVATION_SLOTS, MAX_TAKER_SLOTS,
+        MIN_EXPECTED_AMOUNT,
+    },
+    util::compute_taker_payment,
+    validation::{require_owned_by_token_program, TokenAccountExt},
+    verbose_msg,
+};
+
+// The fixed positions of the exchange accounts, read by `validate_exchange_accounts`
+const EXCHANGE_TAKER_IDX: usize = 0;
+const EXCHANGE_TAKER_SEND_IDX: usize = 1;
+const EXCHANGE_TAKER_RECEIVE_IDX: usize = 2;
+const EXCHANGE_PDA_TOKEN_IDX: usize = 3;
+const EXCHANGE_INITIALIZER_IDX: usize = 4;
+const EXCHANGE_INITIALIZER_RECEIVE_IDX: usize = 5;
+const EXCHANGE_ESCROW_IDX: usize = 6;
+const EXCHANGE_TOKEN_PROGRAM_IDX: usize = 7;
+const EXCHANGE_PDA_IDX: usize = 8;
+const EXCHANGE_CONFIG_IDX: usize = 9;
+const EXCHANGE_ESCROW_INDEX_IDX: usize = 10;
+const EXCHANGE_DEPOSIT_MINT_IDX: usize = 11;
+const EXCHANGE_EXPECTED_MINT_IDX: usize = 12;
+const EXCHANGE_RENT_REFUND_IDX: usize = 13;
+const EXCHANGE_FEE_TOKEN_IDX: usize = 14;
+/// The optional accounts of an exchange are still read in order after the fixed ones
+const EXCHANGE_FIXED_ACCOUNTS: usize = 15;
+/// The position of the cancel delegate signing `Cancel` on the initializer's behalf, after the accounts of `Cancel`
+const CANCEL_DELEGATE_IDX: usize = CANCEL_ACCOUNTS;
+
+/// The state shared by the handlers of one instruction, built once in `Processor::processor`
+pub struct ProcessorContext<'a, 'b> {
+    pub program_id: &'b Pubkey,
+    /// All the accounts of the instruction, for the handlers that don't only read them in order
+    pub accounts: &'b [AccountInfo<'a>],
+    /// The accounts not read yet
+    pub account_info_iter: Iter<'b, AccountInfo<'a>>,
+    clock: Option<Clock>,
+}
+
+impl<'a, 'b> ProcessorContext<'a, 'b> {
+    pub fn new(program_id: &'b Pubkey, accounts: &'b [AccountInfo<'a>]) -> Self {
+        Self {
+            program_id,
+            accounts,
+            account_info_iter: accounts.iter(),
+            clock: None,
+        }
+    }
+
+    /// Returns the next account, failing with `NotEnoughAccountKeys` once they run out
+    pub fn next_account(&mut self) -> Result<&'b AccountInfo<'a>, ProgramError> {
+        next_account_info(&mut self.account_info_iter)
+    }
+
+    /// Returns the clock sysvar, only fetched the first time a handler asks for it.
+    /// A runtime without the sysvar call falls back to a clock sysvar account passed anywhere in the instruction
+    pub fn clock(&mut self) -> Result<Clock, ProgramError> {
+        if let Some(clock) = &self.clock {
+            return Ok(clock.clone());
+        }
+        let clock = match Clock::get() {
+            Ok(clock) => clock,
+            Err(_) => self
+                .accounts
+                .iter()
+                .find(|account| sysvar::clock::check_id(account.key))
+                .map(Clock::from_account_info)
+                .transpose()?
+                .ok_or(EscrowError::MissingClock)?,
+        };
+        self.clock = Some(clock.clone());
+        Ok(clock)
+    }
+
+    /// Returns the account at a fixed position, logging its role when the list is too short
+    pub fn account(&self, index: usize, role: &str) -> Result<&'b AccountInfo<'a>, ProgramError> {
+        self.accounts.get(index).ok_or_else(|| {
+            msg!("Missing the {} account at index {}", role, index);
+            ProgramError::NotEnoughAccountKeys
+        })
+    }
+
+    /// Moves the sequential reads of `next_account` to the given position
+    pub fn skip_to(&mut self, index: usize) {
+        self.account_info_iter = self.accounts.get(index..).unwrap_or(&[]).iter();
+    }
+}
+
+/// One of the two escrows of `MatchEscrows`, with its accounts checked against its state
+struct MatchedEscrow<'a, 'b> {
+    escrow_account: &'b AccountInfo<'a>,
+    pda_token_account: &'b AccountInfo<'a>,
+    initializer_account: &'b AccountInfo<'a>,
+    initializer_token_to_receive_account: &'b AccountInfo<'a>,
+    rent_refund_account: &'b AccountInfo<'a>,
+    pda_account: &'b AccountInfo<'a>,
+    escrow_info: Escrow,
+    pda: PdaInfo,
+    deposit_balance: u64,
+    receive_mint: Pubkey,
+}
+
+/// The accounts of `Exchange` once `validate_exchange_accounts` checked them, with the state read along the way
+struct ExchangeAccounts<'a, 'b> {
+    taker_account: &'b AccountInfo<'a>,
+    taker_token_to_send_account: &'b AccountInfo<'a>,
+    taker_token_to_receive_account: &'b AccountInfo<'a>,
+    pda_token_account: &'b AccountInfo<'a>,
+    initializer_account: &'b AccountInfo<'a>,
+    initializer_token_to_receive_account: &'b AccountInfo<'a>,
+    escrow_account: &'b AccountInfo<'a>,
+    token_program: &'b AccountInfo<'a>,
+    pda_account: &'b AccountInfo<'a>,
+    escrow_index_account: &'b AccountInfo<'a>,
+    deposit_mint: &'b AccountInfo<'a>,
+    expected_mint: &'b AccountInfo<'a>,
+    rent_refund_account: &'b AccountInfo<'a>,
+    fee_token_account: &'b AccountInfo<'a>,
+    escrow_info: Escrow,
+    pda: PdaInfo,
+    deposit_balance: u64,
+    taker_token_to_send_account_state: TokenAccount,
+    /// The share of each payment going to the fee vault, in basis points
+    exchange_fee_bps: u16,
+    escrow_index: Option<EscrowIndex>,
+    deposit_decimals: u8,
+    expected_decimals: u8,
+    /// The accounts creating the taker's associated token account, when it doesn't exist yet
+    create_taker_account_infos: Option<[AccountInfo<'a>; 7]>,
+    /// The system program paying the taker in lamports, when the escrow's deposit is native SOL
+    system_program: Option<&'b AccountInfo<'a>>,
+    /// The callback accounts followed by the callback program, when the escrow has one
+    callback_account_infos: Option<Vec<AccountInfo<'a>>>,
+    stats: Option<(&'b AccountInfo<'a>, EscrowStats)>,
+}
 
 pub struct Processor;
 impl Processor {
@@ -21,26 +175,285 @@ impl Processor {
         instruction_data: &[u8],
     ) -> ProgramResult {
         let instruction = EscrowInstruction::unpack(instruction_data)?;
+        let mut ctx = ProcessorContext::new(program_id, accounts);
+
+        // Fail upfront with a clear log rather than on whichever account a handler happens to read first
+        let min_accounts = instruction.min_accounts();
+        if accounts.len() < min_accounts {
+            msg!(
+                "Instruction expects at least {} accounts but got {}",
+                min_accounts,
+                accounts.len()
+            );
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
 
         match instruction {
-            EscrowInstruction::InitEscrow { amount } => {
-                msg!("Instruction: InitEscrow");
-                Self::processor_init_escrow(accounts, amount, program_id)
+            EscrowInstruction::InitEscrow { args } => {
+                Self::processor_init_escrow(&mut ctx, args, false, &DEFAULT_NAMESPACE)
             }
-            EscrowInstruction::Exchange { amount } => {
+            EscrowInstruction::Exchange {
+                amount,
+                payment,
+                taker_deadline_slot,
+                preimage,
+            } => {
                 msg!("Instruction: Exchange");
-                Self::processor_exchange(accounts, amount, program_id)
+                Self::processor_exchange(&mut ctx, amount, payment, taker_deadline_slot, preimage)
+            }
+            EscrowInstruction::Cancel => {
+                msg!("Instruction: Cancel");
+                Self::processor_cancel(&mut ctx)
+            }
+            EscrowInstruction::PartialWithdraw { amount } => {
+                msg!("Instruction: PartialWithdraw");
+                Self::processor_partial_withdraw(&mut ctx, amount)
+            }
+            EscrowInstruction::GetEscrow => {
+                msg!("Instruction: GetEscrow");
+                Self::processor_get_escrow(&mut ctx)
+            }
+            EscrowInstruction::InitStats => {
+                msg!("Instruction: InitStats");
+                Self::processor_init_stats(&mut ctx)
+            }
+            EscrowInstruction::InitConfig => {
+                msg!("Instruction: InitConfig");
+                Self::processor_init_config(&mut ctx)
+            }
+            EscrowInstruction::SetPaused { paused } => {
+                msg!("Instruction: SetPaused");
+                Self::processor_set_paused(&mut ctx, paused)
+            }
+            EscrowInstruction::InitPairRegistry => {
+                msg!("Instruction: InitPairRegistry");
+                Self::processor_init_pair_registry(&mut ctx)
+            }
+            EscrowInstruction::SetPairAllowed {
+                deposit_mint,
+                expected_mint,
+                allowed,
+            } => {
+                msg!("Instruction: SetPairAllowed");
+                Self::processor_set_pair_allowed(&mut ctx, &deposit_mint, &expected_mint, allowed)
+            }
+            EscrowInstruction::DeriveAuthority => {
+                msg!("Instruction: DeriveAuthority");
+                Self::processor_derive_authority(&mut ctx)
+            }
+            EscrowInstruction::SweepStrayTokens => {
+                msg!("Instruction: SweepStrayTokens");
+                Self::processor_sweep_stray_tokens(&mut ctx)
+            }
+            EscrowInstruction::Replace { args } => {
+                msg!("Instruction: Replace");
+                Self::processor_replace(&mut ctx, args)
+            }
+            EscrowInstruction::InitEscrowNft { args } => {
+                msg!("Instruction: InitEscrowNft");
+                Self::processor_init_escrow_nft(&mut ctx, args)
+            }
+            EscrowInstruction::InitEscrowWithSol { lamports, args } => {
+                msg!("Instruction: InitEscrowWithSol");
+                Self::processor_init_escrow_with_sol(&mut ctx, lamports, args)
+            }
+            EscrowInstruction::InitTemplate {
+                template_id,
+                amount_tolerance,
+                settlement_authority,
+            } => {
+                msg!("Instruction: InitTemplate");
+                Self::processor_init_template(
+                    &mut ctx,
+                    template_id,
+                    amount_tolerance,
+                    &settlement_authority,
+                )
+            }
+            EscrowInstruction::InitEscrowFromTemplate { args } => {
+                msg!("Instruction: InitEscrowFromTemplate");
+                Self::processor_init_escrow_from_template(&mut ctx, args)
+            }
+            EscrowInstruction::InitEscrowInNamespace { namespace, args } => {
+                msg!("Instruction: InitEscrowInNamespace");
+                // Escrows opened without a namespace already derive the default namespace's authorities
+                if namespace == DEFAULT_NAMESPACE {
+                    return Err(ProgramError::InvalidArgument);
+                }
+                Self::processor_init_escrow(&mut ctx, args, false, &namespace)
+            }
+            EscrowInstruction::SetNamespaceAllowed { namespace, allowed } => {
+                msg!("Instruction: SetNamespaceAllowed");
+                Self::processor_set_namespace_allowed(&mut ctx, &namespace, allowed)
+            }
+            EscrowInstruction::SetTreasury { treasury } => {
+                msg!("Instruction: SetTreasury");
+                Self::processor_set_treasury(&mut ctx, &treasury)
+            }
+            EscrowInstruction::SetCancelFee { cancel_fee } => {
+                msg!("Instruction: SetCancelFee");
+                Self::processor_set_cancel_fee(&mut ctx, cancel_fee)
+            }
+            EscrowInstruction::SetInitFee { init_fee } => {
+                msg!("Instruction: SetInitFee");
+                Self::processor_set_init_fee(&mut ctx, init_fee)
+            }
+            EscrowInstruction::InitEscrowIndex => {
+                msg!("Instruction: InitEscrowIndex");
+                Self::processor_init_escrow_index(&mut ctx)
+            }
+            EscrowInstruction::Version => {
+                msg!("Instruction: Version");
+                Self::processor_version()
+            }
+            EscrowInstruction::ValidateExchangeAccounts => {
+                msg!("Instruction: ValidateExchangeAccounts");
+                Self::processor_validate_exchange_accounts(&mut ctx)
+            }
+            EscrowInstruction::ForceReclaim => {
+                msg!("Instruction: ForceReclaim");
+                Self::processor_force_reclaim(&mut ctx)
+            }
+            EscrowInstruction::TimeRemaining => {
+                msg!("Instruction: TimeRemaining");
+                Self::processor_time_remaining(&mut ctx)
+            }
+            EscrowInstruction::WithdrawFees => {
+                msg!("Instruction: WithdrawFees");
+                Self::processor_withdraw_fees(&mut ctx)
+            }
+            EscrowInstruction::SetMintPaused { mint, paused } => {
+                msg!("Instruction: SetMintPaused");
+                Self::processor_set_mint_paused(&mut ctx, &mint, paused)
+            }
+            EscrowInstruction::TopUpRent { lamports } => {
+                msg!("Instruction: TopUpRent");
+                Self::processor_top_up_rent(&mut ctx, lamports)
+            }
+            EscrowInstruction::GetStatus => {
+                msg!("Instruction: GetStatus");
+                Self::processor_get_status(&mut ctx)
+            }
+            EscrowInstruction::ExtendExpiry { expiry } => {
+                msg!("Instruction: ExtendExpiry");
+                Self::processor_extend_expiry(&mut ctx, expiry)
+            }
+            EscrowInstruction::SetExtensionFee { extension_fee } => {
+                msg!("Instruction: SetExtensionFee");
+                Self::processor_set_extension_fee(&mut ctx, extension_fee)
+            }
+            EscrowInstruction::MatchEscrows => {
+                msg!("Instruction: MatchEscrows");
+                Self::processor_match_escrows(&mut ctx)
+            }
+            EscrowInstruction::SetExchangeFee { exchange_fee_bps } => {
+                msg!("Instruction: SetExchangeFee");
+                Self::processor_set_exchange_fee(&mut ctx, exchange_fee_bps)
+            }
+            EscrowInstruction::Reserve {
+                reserved_until_slot,
+            } => {
+                msg!("Instruction: Reserve");
+                Self::processor_reserve(&mut ctx, reserved_until_slot)
+            }
+            EscrowInstruction::SetFreeFills { free_fills } => {
+                msg!("Instruction: SetFreeFills");
+                Self::processor_set_free_fills(&mut ctx, free_fills)
+            }
+            EscrowInstruction::GetEscrowBatch => {
+                msg!("Instruction: GetEscrowBatch");
+                Self::processor_get_escrow_batch(&mut ctx)
+            }
+            EscrowInstruction::CheckFillable => {
+                msg!("Instruction: CheckFillable");
+                Self::processor_check_fillable(&mut ctx)
+            }
+            EscrowInstruction::SetReceiveAccount => {
+                msg!("Instruction: SetReceiveAccount");
+                Self::processor_set_receive_account(&mut ctx)
             }
         }
     }
 
     pub fn processor_init_escrow(
-        accounts: &[AccountInfo],
-        amount: u64,
-        program_id: &Pubkey,
+        ctx: &mut ProcessorContext,
+        args: InitEscrowArgs,
+        is_nft: bool,
+        namespace: &[u8; NAMESPACE_LEN],
     ) -> ProgramResult {
-        let account_info_iter = &mut accounts.iter();
-        let initializer = next_account_info(account_info_iter)?;
+        let InitEscrowArgs {
+            amount,
+            decay_start_slot,
+            decay_end_slot,
+            decay_end_amount,
+            nonce,
+            taker_slots,
+            use_slot_expiry,
+            expiry_timestamp,
+            expiry_slot,
+            use_delegation,
+            hashlock,
+            timelock_slot,
+            callback_program,
+            callback_accounts_len,
+            rent_refund_recipient,
+            cancel_delegate,
+            price_num,
+            price_den,
+            min_amount,
+            max_amount,
+            amount_tolerance,
+            settlement_authority,
+            note,
+            list_in_index,
+        } = args;
+
+        // With a price ratio every fill is priced on its own, neither the expected amount nor a decay schedule of it apply
+        if price_den != 0 && (price_num == 0 || decay_end_slot != 0) {
+            return Err(EscrowError::InvalidAmount.into());
+        }
+
+        // With a payment band the single taker picks the payment, it can't be combined with another way of pricing the escrow
+        if max_amount != 0 {
+            if min_amount < MIN_EXPECTED_AMOUNT
+                || min_amount > max_amount
+                || taker_slots != 1
+                || decay_end_slot != 0
+                || price_den != 0
+            {
+                return Err(EscrowError::InvalidAmount.into());
+            }
+        } else if price_den == 0 && amount < MIN_EXPECTED_AMOUNT {
+            return Err(EscrowError::InvalidAmount.into());
+        }
+
+        // A decay schedule has to end after it starts and can only lower the expected amount
+        if decay_end_slot != 0 && (decay_end_slot <= decay_start_slot || decay_end_amount > amount)
+        {
+            return Err(EscrowError::InvalidAmount.into());
+        }
+
+        if taker_slots == 0 || taker_slots as usize > MAX_TAKER_SLOTS {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        // A single unit can't be split across takers, nor priced per fill or within a payment band
+        if is_nft && (taker_slots != 1 || max_amount != 0 || price_den != 0) {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        if note.len() > MAX_NOTE_LEN {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        // Callback accounts without a callback program would only be required and never used
+        if (callback_program == Pubkey::default() && callback_accounts_len != 0)
+            || callback_program == *ctx.program_id
+        {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let initializer = ctx.next_account()?;
 
         if !initializer.is_signer {
             return Err(ProgramError::MissingRequiredSignature);
@@ -48,26 +461,117 @@ impl Processor {
 
         // This token account should be writeable, but no need to check it
         // because the transaction will fail when the program try to write it
-        let temp_token_account = next_account_info(account_info_iter)?;
+        let temp_token_account = ctx.next_account()?;
         let temp_token_account_state =
             TokenAccount::unpack(&temp_token_account.try_borrow_data()?)?;
+        temp_token_account_state.require_authority(initializer.key)?;
+        // A wrapped SOL account that wasn't synced after being funded would escrow a different amount than its lamports suggest
+        temp_token_account_state.require_synced_native(temp_token_account)?;
+        // An empty deposit would list an escrow that offers nothing
+        if temp_token_account_state.amount == 0 {
+            return Err(EscrowError::InvalidAmount.into());
+        }
 
         // You might ask yourself, "why do we check that the token_to_receive_account is actually owned by the token program but don't do the same for the temp_token_account?".
         // The answer is that later on in the function we will ask the token program to transfer ownership of the temp_token_account to the PDA.
         // This transfer will fail if the temp_token_account is not owned by the token program, because - as I'm sure you remember - only programs that own accounts may change accounts.
         // Hence, there is no need for us to add another check here.
-        let token_to_receive_account = next_account_info(account_info_iter)?;
-        if *token_to_receive_account.owner != spl_token::id() {
-            return Err(ProgramError::IncorrectProgramId);
+        // The owner of the receive account is deliberately left unchecked: it only ever receives tokens,
+        // so it can be owned by another program's PDA for program to program trades
+        let token_to_receive_account = ctx.next_account()?;
+        // The temp token account is handed to the PDA, exchanges would pay the initializer into the account they take from
+        if token_to_receive_account.key == temp_token_account.key {
+            return Err(ProgramError::InvalidArgument);
+        }
+        require_owned_by_token_program(token_to_receive_account)?;
+        let token_to_receive_account_state =
+            TokenAccount::unpack(&token_to_receive_account.try_borrow_data()?)?;
+        token_to_receive_account_state.require_synced_native(token_to_receive_account)?;
+
+        let escrow_account = ctx.next_account()?;
+        let (escrow_address, escrow_bump_seed) =
+            find_escrow_address(initializer.key, nonce, ctx.program_id);
+        if *escrow_account.key != escrow_address {
+            return Err(ProgramError::InvalidSeeds);
         }
 
-        let escrow_account = next_account_info(account_info_iter)?;
-        let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+        let rent = &Rent::from_account_info(ctx.next_account()?)?;
 
-        if !rent.is_exempt(escrow_account.lamports(), escrow_account.data_len()) {
+        // An empty escrow account is created once the system program is read, one that was already
+        // allocated to the program, e.g. loaded into a test validator, only has to be rent exempt
+        if !escrow_account.data_is_empty()
+            && !rent.is_exempt(escrow_account.lamports(), escrow_account.data_len())
+        {
+            let minimum_balance = rent.minimum_balance(escrow_account.data_len());
+            msg!(
+                "Escrow account needs {} lamports to be rent exempt but holds {}, {} short",
+                minimum_balance,
+                escrow_account.lamports(),
+                minimum_balance.saturating_sub(escrow_account.lamports())
+            );
             return Err(EscrowError::NotRentExempt.into());
         }
 
+        let token_program = ctx.next_account()?;
+        if *token_program.key != TOKEN_PROGRAM_ID {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+
+        let pair_registry_account = ctx.next_account()?;
+        if let Some(pair_registry) =
+            Self::load_pair_registry(pair_registry_account, ctx.program_id)?
+        {
+            if !pair_registry.is_allowed(
+                &temp_token_account_state.mint,
+                &token_to_receive_account_state.mint,
+            ) {
+                return Err(EscrowError::PairNotAllowed.into());
+            }
+        }
+
+        let config_account = ctx.next_account()?;
+        let treasury_account = ctx.next_account()?;
+        let init_fee =
+            Self::treasury_fee(config_account, treasury_account, ctx.program_id, |config| {
+                config.init_fee
+            })?;
+        let config = Self::load_config(config_account, ctx.program_id)?;
+        // Only the namespaces the config whitelists group escrows under authorities of their own
+        if *namespace != DEFAULT_NAMESPACE
+            && !matches!(&config, Some(config) if config.is_namespace_allowed(namespace))
+        {
+            return Err(EscrowError::NamespaceNotAllowed.into());
+        }
+        let free_fills = config.map_or(0, |config| config.free_fills);
+        let system_program = ctx.next_account()?;
+
+        if escrow_account.data_is_empty() {
+            Self::create_pda_account(
+                initializer,
+                escrow_account,
+                system_program,
+                Escrow::LEN,
+                &[
+                    ESCROW_SEED,
+                    initializer.key.as_ref(),
+                    &nonce.to_le_bytes(),
+                    &[escrow_bump_seed],
+                ],
+                ctx.program_id,
+            )?;
+        }
+
+        // Listing is opted into per escrow, an escrow that isn't listed can't be held up by a full index
+        let escrow_index_account = ctx.next_account()?;
+        let escrow_index = Self::load_escrow_index(escrow_index_account, ctx.program_id)?;
+        if let Some(mut escrow_index) = escrow_index.filter(|_| list_in_index) {
+            escrow_index.add(escrow_account.key)?;
+            EscrowIndex::pack(
+                escrow_index,
+                &mut escrow_index_account.try_borrow_mut_data()?,
+            )?;
+        }
+
         let mut escrow_info = Escrow::unpack_unchecked(&escrow_account.try_borrow_data()?)?;
         if escrow_info.is_initialized() {
             return Err(ProgramError::AccountAlreadyInitialized);
@@ -76,149 +580,4148 @@ impl Processor {
         escrow_info.is_initialized = true;
         escrow_info.initializer_pubkey = *initializer.key;
         escrow_info.temp_token_account_pubkey = *temp_token_account.key;
+        escrow_info.deposit_mint = temp_token_account_state.mint;
         escrow_info.initializer_token_to_receive_account_pubkey = *token_to_receive_account.key;
         escrow_info.expected_amount = amount;
+        escrow_info.decay_start_slot = decay_start_slot;
+        escrow_info.decay_end_slot = decay_end_slot;
+        escrow_info.decay_end_amount = decay_end_amount;
+        escrow_info.nonce = nonce;
+        escrow_info.created_at_slot = ctx.clock()?.slot;
+        escrow_info.taker_slots = taker_slots;
+        escrow_info.filled_takers_count = 0;
+        escrow_info.filled_takers = [Pubkey::default(); MAX_TAKER_SLOTS];
+        escrow_info.use_slot_expiry = use_slot_expiry;
+        escrow_info.expiry_timestamp = expiry_timestamp;
+        escrow_info.expiry_slot = expiry_slot;
+        escrow_info.is_delegated = use_delegation;
+        escrow_info.hashlock = hashlock;
+        escrow_info.timelock_slot = timelock_slot;
+        escrow_info.rent_refund_recipient = if rent_refund_recipient == Pubkey::default() {
+            *initializer.key
+        } else {
+            rent_refund_recipient
+        };
+        escrow_info.callback_program = callback_program;
+        escrow_info.callback_accounts_len = callback_accounts_len;
+        escrow_info.cancel_delegate = cancel_delegate;
+        escrow_info.price_num = price_num;
+        escrow_info.price_den = price_den;
+        escrow_info.min_amount = min_amount;
+        escrow_info.max_amount = max_amount;
+        escrow_info.amount_tolerance = amount_tolerance;
+        escrow_info.settlement_authority = settlement_authority;
+        escrow_info.note_len = note.len() as u8;
+        escrow_info.note = [0; MAX_NOTE_LEN];
+        escrow_info.note[..note.len()].copy_from_slice(&note);
+        escrow_info.is_nft = is_nft;
+        escrow_info.fills_remaining_free = free_fills;
+        escrow_info.namespace = *namespace;
 
-        Escrow::pack(escrow_info, &mut escrow_account.try_borrow_mut_data()?)?;
+        let pda = PdaInfo::find_in_namespace(ctx.program_id, namespace, escrow_account.key);
+        escrow_info.pda_bump = pda.bump;
 
-        let (pda, _bump_seed) = Pubkey::find_program_address(&[b"escrow"], program_id);
+        Escrow::pack(escrow_info, &mut escrow_account.try_borrow_mut_data()?)?;
 
-        let token_program = next_account_info(account_info_iter)?;
-        let owner_change_ix = spl_token::instruction::set_authority(
-            token_program.key,
-            temp_token_account.key,
-            Some(&pda),
-            spl_token::instruction::AuthorityType::AccountOwner,
+        event::init_escrow(
             initializer.key,
-            &[initializer.key],
-        )?;
+            temp_token_account.key,
+            token_to_receive_account.key,
+            amount,
+            &pda.key,
+        );
 
-        msg!("Calling the token program to transfer token account ownership...");
-        invoke(
-            &owner_change_ix,
-            &[
-                temp_token_account.clone(),
-                initializer.clone(),
-                token_program.clone(),
-            ],
-        )?;
+        if use_delegation {
+            // The PDA isn't among the accounts of this instruction, so the initializer approves it in a prior instruction
+            if temp_token_account_state.delegated_amount_to(&pda.key) == 0 {
+                return Err(EscrowError::WrongAuthority.into());
+            }
+        } else {
+            let owner_change_ix = spl_token::instruction::set_authority(
+                token_program.key,
+                temp_token_account.key,
+                Some(&pda.key),
+                spl_token::instruction::AuthorityType::AccountOwner,
+                initializer.key,
+                &[initializer.key],
+            )?;
+
+            verbose_msg!("Calling the token program to transfer token account ownership...");
+            invoke(
+                &owner_change_ix,
+                &[
+                    temp_token_account.clone(),
+                    initializer.clone(),
+                    token_program.clone(),
+                ],
+            )?;
+        }
+
+        // The fee comes out of the initializer's own lamports, the escrow account is rent exempt already
+        if init_fee != 0 {
+            let ix_init_fee =
+                system_instruction::transfer(initializer.key, treasury_account.key, init_fee);
+            verbose_msg!("Calling the system program to transfer the init fee to the treasury...");
+            invoke(
+                &ix_init_fee,
+                &[
+                    initializer.clone(),
+                    treasury_account.clone(),
+                    system_program.clone(),
+                ],
+            )?;
+        }
+
+        if let Some(stats_account) = ctx.account_info_iter.next() {
+            Self::update_stats(stats_account, ctx.program_id, |stats| {
+                stats.total_created = math::add(stats.total_created, 1)?;
+                Ok(())
+            })?;
+        }
 
         Ok(())
     }
 
     pub fn processor_exchange(
-        accounts: &[AccountInfo],
+        ctx: &mut ProcessorContext,
         amount: u64,
-        program_id: &Pubkey,
+        payment: u64,
+        taker_deadline_slot: u64,
+        preimage: Option<[u8; 32]>,
     ) -> ProgramResult {
-        let account_info_iter = &mut accounts.iter();
-        let taker_account = next_account_info(account_info_iter)?;
+        let ExchangeAccounts {
+            taker_account,
+            taker_token_to_send_account,
+            taker_token_to_receive_account,
+            pda_token_account,
+            initializer_account,
+            initializer_token_to_receive_account,
+            escrow_account,
+            token_program,
+            pda_account,
+            escrow_index_account,
+            deposit_mint,
+            expected_mint,
+            rent_refund_account,
+            fee_token_account,
+            mut escrow_info,
+            pda,
+            deposit_balance,
+            taker_token_to_send_account_state,
+            exchange_fee_bps,
+            escrow_index,
+            deposit_decimals,
+            expected_decimals,
+            create_taker_account_infos,
+            system_program,
+            callback_account_infos,
+            stats,
+        } = Self::validate_exchange_accounts(ctx)?;
 
-        if !taker_account.is_signer {
-            return Err(ProgramError::MissingRequiredSignature);
+        // The taker bounds how long a pending transaction stays valid, the escrow may have changed in the meantime
+        let clock = ctx.clock()?;
+        if taker_deadline_slot != 0 && clock.slot > taker_deadline_slot {
+            return Err(EscrowError::DeadlineExceeded.into());
+        }
+
+        if escrow_info.hashlock != [0; 32] {
+            let revealed_hash = preimage.map(|preimage| hash(&preimage).to_bytes());
+            if revealed_hash != Some(escrow_info.hashlock) {
+                return Err(EscrowError::InvalidPreimage.into());
+            }
         }
 
-        let taker_token_to_send_account = next_account_info(account_info_iter)?;
+        let required_amount =
+            compute_taker_payment(&escrow_info, deposit_balance, amount, payment, clock.slot)?;
+        // Within the tolerance the taker receives the share the PDA actually holds rather than the amount asked for
+        let deposit_amount = escrow_info.fill_deposit_amount(deposit_balance)?;
+        // More units could have been minted to the PDA since, an NFT escrow only ever hands over the one it was opened with
+        if escrow_info.is_nft && deposit_amount != 1 {
+            return Err(EscrowError::NotAnNft.into());
+        }
+        verbose_msg!(
+            "Taker pays {} for {} of the {} deposited tokens",
+            required_amount,
+            deposit_amount,
+            deposit_balance
+        );
+        // The fee comes out of the payment, the taker pays the same and the initializer receives the rest.
+        // The escrow's first fills are free, each fill the fee is waived for uses one of them up
+        let fee = if exchange_fee_bps != 0 && escrow_info.fills_remaining_free > 0 {
+            escrow_info.fills_remaining_free -= 1;
+            0
+        } else {
+            math::mul_div(
+                required_amount,
+                u64::from(exchange_fee_bps),
+                u64::from(MAX_EXCHANGE_FEE_BPS),
+            )?
+        };
 
-        let taker_token_to_receive_account = next_account_info(account_info_iter)?;
+        // Check the balance upfront so an underfunded taker gets a clear error before any transfer happens
+        if taker_token_to_send_account_state.amount < required_amount {
+            return Err(EscrowError::InsufficientTakerFunds.into());
+        }
 
-        let pda_token_account = next_account_info(account_info_iter)?;
-        let pda_token_account_state = TokenAccount::unpack(&pda_token_account.try_borrow_data()?)?;
-        let (pda, bump_seed) = Pubkey::find_program_address(&[b"escrow"], program_id);
+        let stats = stats
+            .map(|(stats_account, mut stats_info)| {
+                stats_info.total_filled = math::add(stats_info.total_filled, 1)?;
+                stats_info.total_volume = math::add(stats_info.total_volume, deposit_amount)?;
+                Ok::<_, ProgramError>((stats_account, stats_info))
+            })
+            .transpose()?;
+
+        // Flag the exchange as running before the first CPI, it is cleared once the escrow is updated or closed
+        escrow_info.in_progress = true;
+        escrow_info.pack_into_slice(&mut escrow_account.try_borrow_mut_data()?);
+        escrow_info.in_progress = false;
+
+        if let Some(create_taker_account_infos) = create_taker_account_infos {
+            let ix_create_taker_account =
+                spl_associated_token_account::create_associated_token_account(
+                    taker_account.key,
+                    taker_account.key,
+                    &escrow_info.deposit_mint,
+                );
+            verbose_msg!(
+                "Calling the associated token program to create the taker's token account..."
+            );
+            invoke(&ix_create_taker_account, &create_taker_account_infos)?;
+        }
+
+        // The token program looks the accounts of each CPI up by key,
+        // so they are cloned once and the same slice is shared by every CPI below
+        let token_cpi_accounts = [
+            token_program.clone(),
+            taker_account.clone(),
+            taker_token_to_send_account.clone(),
+            taker_token_to_receive_account.clone(),
+            pda_token_account.clone(),
+            initializer_account.clone(),
+            initializer_token_to_receive_account.clone(),
+            pda_account.clone(),
+            deposit_mint.clone(),
+            expected_mint.clone(),
+            fee_token_account.clone(),
+        ];
+
+        let ix_transfer_to_initializer = spl_token::instruction::transfer_checked(
+            token_program.key,
+            taker_token_to_send_account.key,
+            expected_mint.key,
+            initializer_token_to_receive_account.key,
+            taker_account.key,
+            &[taker_account.key],
+            math::sub(required_amount, fee)?,
+            expected_decimals,
+        )?;
+        verbose_msg!(
+            "Calling the token program to transfer the taker's tokens to the initializer..."
+        );
+        invoke(&ix_transfer_to_initializer, &token_cpi_accounts)?;
+
+        if fee != 0 {
+            let ix_transfer_fee = spl_token::instruction::transfer_checked(
+                token_program.key,
+                taker_token_to_send_account.key,
+                expected_mint.key,
+                fee_token_account.key,
+                taker_account.key,
+                &[taker_account.key],
+                fee,
+                expected_decimals,
+            )?;
+            verbose_msg!(
+                "Calling the token program to transfer the exchange fee to the fee vault..."
+            );
+            invoke(&ix_transfer_fee, &token_cpi_accounts)?;
+        }
+
+        if let Some(system_program) = system_program {
+            // The temp token account is closed into the PDA, unwrapping the deposit. The PDA pays the taker
+            // and hands the rest, the token account rent, to the initializer, it is left without lamports
+            // and never has to be rent exempt
+            let ix_unwrap = spl_token::instruction::close_account(
+                token_program.key,
+                pda_token_account.key,
+                &pda.key,
+                &pda.key,
+                &[&pda.key],
+            )?;
+            verbose_msg!("Calling the token program to unwrap the deposited lamports...");
+            invoke_signed(&ix_unwrap, &token_cpi_accounts, &[&pda.signer_seeds()])?;
+
+            let system_cpi_accounts = [
+                system_program.clone(),
+                pda_account.clone(),
+                taker_token_to_receive_account.clone(),
+                initializer_account.clone(),
+            ];
+            let ix_transfer_to_taker = system_instruction::transfer(
+                &pda.key,
+                taker_token_to_receive_account.key,
+                deposit_amount,
+            );
+            verbose_msg!(
+                "Calling the system program to transfer the deposited lamports to the taker..."
+            );
+            invoke_signed(
+                &ix_transfer_to_taker,
+                &system_cpi_accounts,
+                &[&pda.signer_seeds()],
+            )?;
+            let ix_refund_rent = system_instruction::transfer(
+                &pda.key,
+                initializer_account.key,
+                pda_account.lamports(),
+            );
+            invoke_signed(
+                &ix_refund_rent,
+                &system_cpi_accounts,
+                &[&pda.signer_seeds()],
+            )?;
+        } else {
+            let ix_transfer_to_taker = spl_token::instruction::transfer_checked(
+                token_program.key,
+                pda_token_account.key,
+                deposit_mint.key,
+                taker_token_to_receive_account.key,
+                &pda.key,
+                &[&pda.key],
+                deposit_amount,
+                deposit_decimals,
+            )?;
+            verbose_msg!(
+                "Calling the token program to transfer the deposited tokens to the taker..."
+            );
+            invoke_signed(
+                &ix_transfer_to_taker,
+                &token_cpi_accounts,
+                &[&pda.signer_seeds()],
+            )?;
+        }
+
+        if let Some((stats_account, stats_info)) = stats {
+            EscrowStats::pack(stats_info, &mut stats_account.try_borrow_mut_data()?)?;
+        }
+
+        if let Some(callback_account_infos) = callback_account_infos {
+            // The maker picks the callback program, so none of the taker's signatures is passed on to it.
+            // The escrow is still marked in progress, a callback exchanging it again is rejected
+            let (callback_program, callback_accounts) = callback_account_infos
+                .split_last()
+                .ok_or(ProgramError::NotEnoughAccountKeys)?;
+            let ix_callback = Instruction {
+                program_id: *callback_program.key,
+                accounts: callback_accounts
+                    .iter()
+                    .map(|account| {
+                        if account.is_writable {
+                            AccountMeta::new(*account.key, false)
+                        } else {
+                            AccountMeta::new_readonly(*account.key, false)
+                        }
+                    })
+                    .collect(),
+                data: deposit_amount.to_le_bytes().to_vec(),
+            };
+            verbose_msg!("Calling the escrow's callback program...");
+            invoke(&ix_callback, &callback_account_infos)?;
+        }
+
+        // The reservation was for this fill, the next share of a split escrow is open to every taker again
+        if escrow_info.reserved_taker == *taker_account.key {
+            escrow_info.reserved_taker = Pubkey::default();
+            escrow_info.reserved_until_slot = 0;
+        }
+
+        if escrow_info.remaining_taker_slots() > 1 {
+            escrow_info.filled_takers[escrow_info.filled_takers_count as usize] =
+                *taker_account.key;
+            escrow_info.filled_takers_count += 1;
+            Escrow::pack(escrow_info, &mut escrow_account.try_borrow_mut_data()?)?;
+            return Ok(());
+        }
+
+        // A delegated escrow's token account belongs to the initializer, it stays open. A native deposit's one was closed already
+        if !escrow_info.is_delegated && !escrow_info.is_native_deposit {
+            let ix_close_pda_account = spl_token::instruction::close_account(
+                token_program.key,
+                pda_token_account.key,
+                initializer_account.key,
+                &pda.key,
+                &[&pda.key],
+            )?;
+            invoke_signed(
+                &ix_close_pda_account,
+                &token_cpi_accounts,
+                &[&pda.signer_seeds()],
+            )?;
+        }
+
+        if let Some(mut escrow_index) = escrow_index {
+            escrow_index.remove(escrow_account.key);
+            EscrowIndex::pack(
+                escrow_index,
+                &mut escrow_index_account.try_borrow_mut_data()?,
+            )?;
+        }
+
+        Self::close_escrow_account(escrow_account, rent_refund_account)
+    }
+
+    pub fn processor_match_escrows(ctx: &mut ProcessorContext) -> ProgramResult {
+        let a = Self::load_matched_escrow(ctx)?;
+        let b = Self::load_matched_escrow(ctx)?;
+
+        // Matching an escrow with itself would send its deposit back into the account it came from
+        if a.escrow_account.key == b.escrow_account.key
+            || a.initializer_token_to_receive_account.key == b.pda_token_account.key
+            || b.initializer_token_to_receive_account.key == a.pda_token_account.key
+        {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let token_program = ctx.next_account()?;
+        if *token_program.key != TOKEN_PROGRAM_ID {
+            return Err(ProgramError::IncorrectProgramId);
+        }
 
-        if amount != pda_token_account_state.amount {
+        let config_account = ctx.next_account()?;
+        let config = Self::load_config(config_account, ctx.program_id)?;
+        if matches!(&config, Some(config) if config.paused) {
+            return Err(EscrowError::ProgramPaused.into());
+        }
+
+        let escrow_index_account = ctx.next_account()?;
+        let escrow_index = Self::load_escrow_index(escrow_index_account, ctx.program_id)?;
+
+        let deposit_mint_a = ctx.next_account()?;
+        let deposit_mint_b = ctx.next_account()?;
+        if *deposit_mint_a.key != a.escrow_info.deposit_mint
+            || *deposit_mint_b.key != b.escrow_info.deposit_mint
+        {
+            return Err(EscrowError::WrongMint.into());
+        }
+        // Each initializer has to receive the token the other one deposited
+        if a.receive_mint != b.escrow_info.deposit_mint
+            || b.receive_mint != a.escrow_info.deposit_mint
+        {
+            return Err(EscrowError::WrongMint.into());
+        }
+        if let Some(config) = &config {
+            if config.is_mint_paused(deposit_mint_a.key)
+                || config.is_mint_paused(deposit_mint_b.key)
+            {
+                return Err(EscrowError::MintPaused.into());
+            }
+        }
+        require_owned_by_token_program(deposit_mint_a)?;
+        let deposit_decimals_a = Mint::unpack(&deposit_mint_a.try_borrow_data()?)?.decimals;
+        require_owned_by_token_program(deposit_mint_b)?;
+        let deposit_decimals_b = Mint::unpack(&deposit_mint_b.try_borrow_data()?)?.decimals;
+
+        let clock = ctx.clock()?;
+        if a.escrow_info.is_expired(&clock) || b.escrow_info.is_expired(&clock) {
+            return Err(EscrowError::EscrowExpired.into());
+        }
+
+        // Each deposit has to cover what the other initializer expects, any surplus goes to them as well
+        if b.deposit_balance < a.escrow_info.required_amount(clock.slot)?
+            || a.deposit_balance < b.escrow_info.required_amount(clock.slot)?
+        {
             return Err(EscrowError::ExpectedAmountMismatch.into());
         }
+        verbose_msg!(
+            "Matching {} deposited tokens against {} deposited tokens",
+            a.deposit_balance,
+            b.deposit_balance
+        );
+
+        let token_cpi_accounts = [
+            token_program.clone(),
+            a.pda_token_account.clone(),
+            a.initializer_account.clone(),
+            a.initializer_token_to_receive_account.clone(),
+            a.pda_account.clone(),
+            b.pda_token_account.clone(),
+            b.initializer_account.clone(),
+            b.initializer_token_to_receive_account.clone(),
+            b.pda_account.clone(),
+            deposit_mint_a.clone(),
+            deposit_mint_b.clone(),
+        ];
+
+        for (from, to, deposit_mint, deposit_decimals) in [
+            (&a, &b, deposit_mint_a, deposit_decimals_a),
+            (&b, &a, deposit_mint_b, deposit_decimals_b),
+        ] {
+            let ix_transfer = spl_token::instruction::transfer_checked(
+                token_program.key,
+                from.pda_token_account.key,
+                deposit_mint.key,
+                to.initializer_token_to_receive_account.key,
+                &from.pda.key,
+                &[&from.pda.key],
+                from.deposit_balance,
+                deposit_decimals,
+            )?;
+            verbose_msg!(
+                "Calling the token program to transfer a deposit to the other initializer..."
+            );
+            invoke_signed(
+                &ix_transfer,
+                &token_cpi_accounts,
+                &[&from.pda.signer_seeds()],
+            )?;
+
+            let ix_close_pda_account = spl_token::instruction::close_account(
+                token_program.key,
+                from.pda_token_account.key,
+                from.initializer_account.key,
+                &from.pda.key,
+                &[&from.pda.key],
+            )?;
+            invoke_signed(
+                &ix_close_pda_account,
+                &token_cpi_accounts,
+                &[&from.pda.signer_seeds()],
+            )?;
+        }
 
-        let initializer_account = next_account_info(account_info_iter)?;
+        if let Some(mut escrow_index) = escrow_index {
+            escrow_index.remove(a.escrow_account.key);
+            escrow_index.remove(b.escrow_account.key);
+            EscrowIndex::pack(
+                escrow_index,
+                &mut escrow_index_account.try_borrow_mut_data()?,
+            )?;
+        }
 
-        let initializer_token_to_receive_account = next_account_info(account_info_iter)?;
+        Self::close_escrow_account(a.escrow_account, a.rent_refund_account)?;
+        Self::close_escrow_account(b.escrow_account, b.rent_refund_account)
+    }
 
-        let escrow_account = next_account_info(account_info_iter)?;
+    /// Reads the accounts of one side of `MatchEscrows` and checks them against its escrow
+    fn load_matched_escrow<'a, 'b>(
+        ctx: &mut ProcessorContext<'a, 'b>,
+    ) -> Result<MatchedEscrow<'a, 'b>, ProgramError> {
+        let escrow_account = ctx.next_account()?;
+        if escrow_account.owner != ctx.program_id {
+            return Err(EscrowError::WrongOwner.into());
+        }
         let escrow_info = Escrow::unpack(&escrow_account.try_borrow_data()?)?;
 
+        if escrow_info.in_progress {
+            return Err(EscrowError::ReentrancyDetected.into());
+        }
+
+        // Only an escrow that is filled in one go at a fixed amount has a single price to match against
+        if escrow_info.taker_slots != 1
+            || escrow_info.hashlock != [0; 32]
+            || escrow_info.callback_program != Pubkey::default()
+            || escrow_info.settlement_authority != Pubkey::default()
+            || escrow_info.template != Pubkey::default()
+            || escrow_info.price_den != 0
+            || escrow_info.max_amount != 0
+            || escrow_info.is_delegated
+        {
+            msg!("Escrow {} can't be matched", escrow_account.key);
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let pda_token_account = ctx.next_account()?;
         if *pda_token_account.key != escrow_info.temp_token_account_pubkey {
             return Err(ProgramError::InvalidAccountData);
         }
+        let pda_token_account_state = TokenAccount::unpack(&pda_token_account.try_borrow_data()?)?;
+        if pda_token_account_state.is_frozen() {
+            return Err(EscrowError::AccountFrozen.into());
+        }
+        pda_token_account_state.require_mint(&escrow_info.deposit_mint)?;
 
+        let initializer_account = ctx.next_account()?;
         if escrow_info.initializer_pubkey != *initializer_account.key {
             return Err(ProgramError::InvalidAccountData);
         }
 
+        let initializer_token_to_receive_account = ctx.next_account()?;
         if escrow_info.initializer_token_to_receive_account_pubkey
             != *initializer_token_to_receive_account.key
         {
             return Err(ProgramError::InvalidAccountData);
         }
+        require_owned_by_token_program(initializer_token_to_receive_account)?;
+        let receive_mint =
+            TokenAccount::unpack(&initializer_token_to_receive_account.try_borrow_data()?)?.mint;
 
-        let token_program = next_account_info(account_info_iter)?;
+        let rent_refund_account = ctx.next_account()?;
+        if *rent_refund_account.key != escrow_info.rent_refund_recipient {
+            return Err(ProgramError::InvalidAccountData);
+        }
 
-        let ix_transfer_to_initializer = spl_token::instruction::transfer(
-            token_program.key,
-            taker_token_to_send_account.key,
-            initializer_token_to_receive_account.key,
-            taker_account.key,
-            &[taker_account.key],
-            amount,
-        )?;
-        invoke(
-            &ix_transfer_to_initializer,
-            &[
-                token_program.clone(),
-                taker_token_to_send_account.clone(),
-                initializer_token_to_receive_account.clone(),
-                taker_account.clone(),
-            ],
+        let pda = Self::stored_pda(
+            ctx.program_id,
+            escrow_account.key,
+            &escrow_info,
+            &pda_token_account_state,
         )?;
+        let pda_account = ctx.next_account()?;
+        if *pda_account.key != pda.key {
+            return Err(EscrowError::InvalidPda.into());
+        }
+        let deposit_balance = Self::deposit_balance(&escrow_info, &pda_token_account_state, &pda)?;
 
-        let pda_account = next_account_info(account_info_iter)?;
+        Ok(MatchedEscrow {
+            escrow_account,
+            pda_token_account,
+            initializer_account,
+            initializer_token_to_receive_account,
+            rent_refund_account,
+            pda_account,
+            escrow_info,
+            pda,
+            deposit_balance,
+            receive_mint,
+        })
+    }
 
-        let ix_transfer_to_taker = spl_token::instruction::transfer(
-            token_program.key,
-            pda_token_account.key,
-            taker_token_to_receive_account.key,
-            &pda,
-            &[&pda],
-            pda_token_account_state.amount,
-        )?;
-        invoke_signed(
-            &ix_transfer_to_taker,
-            &[
-                token_program.clone(),
-                pda_token_account.clone(),
-                taker_token_to_receive_account.clone(),
-                pda_account.clone(),
-            ],
-            &[&[&b"escrow"[..], &[bump_seed]]],
-        )?;
+    pub fn processor_validate_exchange_accounts(ctx: &mut ProcessorContext) -> ProgramResult {
+        Self::validate_exchange_accounts(ctx).map(|_| ())
+    }
 
-        let ix_close_pda_account = spl_token::instruction::close_account(
-            token_program.key,
-            pda_token_account.key,
-            initializer_account.key,
-            &pda,
-            &[&pda],
-        )?;
-        invoke_signed(
-            &ix_close_pda_account,
-            &[
-                token_program.clone(),
-                pda_token_account.clone(),
-                initializer_account.clone(),
-                pda_account.clone(),
-            ],
-            &[&[&b"escrow"[..], &[bump_seed]]],
+    /// Reads the accounts of `Exchange` and checks them against its escrow. `ValidateExchangeAccounts` runs the
+    /// same checks, so both fail the same way on the same accounts. Only the amounts are left to the exchange
+    fn validate_exchange_accounts<'a, 'b>(
+        ctx: &mut ProcessorContext<'a, 'b>,
+    ) -> Result<ExchangeAccounts<'a, 'b>, ProgramError> {
+        let taker_account = ctx.account(EXCHANGE_TAKER_IDX, "taker")?;
+
+        if !taker_account.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let taker_token_to_send_account =
+            ctx.account(EXCHANGE_TAKER_SEND_IDX, "taker token to send")?;
+        let taker_token_to_receive_account =
+            ctx.account(EXCHANGE_TAKER_RECEIVE_IDX, "taker token to receive")?;
+        let pda_token_account = ctx.account(EXCHANGE_PDA_TOKEN_IDX, "PDA token")?;
+        let initializer_account = ctx.account(EXCHANGE_INITIALIZER_IDX, "initializer")?;
+        let initializer_token_to_receive_account = ctx.account(
+            EXCHANGE_INITIALIZER_RECEIVE_IDX,
+            "initializer token to receive",
         )?;
 
-        // gave back the lamports that was used for renting the escrow account space
-        **initializer_account.lamports.borrow_mut() = initializer_account
-            .lamports()
-            .checked_add(escrow_account.lamports())
-            .ok_or(EscrowError::AmountOverflow)?;
-        // always clear the value inside the account after closing the account
-        **escrow_account.lamports.borrow_mut() = 0;
-        *escrow_account.try_borrow_mut_data()? = &mut [];
+        let escrow_account = ctx.account(EXCHANGE_ESCROW_IDX, "escrow")?;
+        // A closed escrow has been settled already, reject stale exchanges before touching anything else
+        if escrow_account.lamports() == 0 || escrow_account.data_is_empty() {
+            msg!("Escrow account is closed, the escrow was already settled");
+            return Err(EscrowError::NotInitialized.into());
+        }
 
-        Ok(())
+        let pda_token_account_state = TokenAccount::unpack(&pda_token_account.try_borrow_data()?)?;
+        // The token program can't move tokens out of a frozen account, fail with a clear error instead
+        if pda_token_account_state.is_frozen() {
+            return Err(EscrowError::AccountFrozen.into());
+        }
+
+        let mut escrow_info = Escrow::unpack(&escrow_account.try_borrow_data()?)?;
+
+        if escrow_info.in_progress {
+            return Err(EscrowError::ReentrancyDetected.into());
+        }
+
+        if *pda_token_account.key != escrow_info.temp_token_account_pubkey {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        pda_token_account_state.require_mint(&escrow_info.deposit_mint)?;
+
+        let pda = Self::stored_pda(
+            ctx.program_id,
+            escrow_account.key,
+            &escrow_info,
+            &pda_token_account_state,
+        )?;
+        let deposit_balance = Self::deposit_balance(&escrow_info, &pda_token_account_state, &pda)?;
+
+        // The temp token account rent goes back to the initializer once the escrow is filled
+        if escrow_info.initializer_pubkey != *initializer_account.key {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        // The rent is credited to the initializer by hand, fail clearly rather than on the lamport write
+        if !initializer_account.is_writable {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        if escrow_info.initializer_token_to_receive_account_pubkey
+            != *initializer_token_to_receive_account.key
+        {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        // Passing one token account in two roles would make the transfers net out or move the wrong funds
+        if taker_token_to_send_account.key == taker_token_to_receive_account.key
+            || taker_token_to_send_account.key == initializer_token_to_receive_account.key
+            || taker_token_to_send_account.key == pda_token_account.key
+            || taker_token_to_receive_account.key == pda_token_account.key
+            || taker_token_to_receive_account.key == initializer_token_to_receive_account.key
+        {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        if escrow_info.is_expired(&ctx.clock()?) {
+            return Err(EscrowError::EscrowExpired.into());
+        }
+
+        // Every share of a split escrow has to be filled by a different taker
+        if escrow_info.has_filled(taker_account.key) {
+            return Err(EscrowError::TakerNotAllowed.into());
+        }
+
+        if escrow_info.is_reserved_for_other(taker_account.key, ctx.clock()?.slot) {
+            return Err(EscrowError::EscrowReserved.into());
+        }
+
+        // Its balance depends on the amounts, the exchange checks it once they are known
+        require_owned_by_token_program(taker_token_to_send_account)?;
+        let taker_token_to_send_account_state =
+            TokenAccount::unpack(&taker_token_to_send_account.try_borrow_data()?)?;
+
+        // The PDA signs the CPIs by its seeds and the token program is invoked by key, neither would be checked otherwise
+        let token_program = ctx.account(EXCHANGE_TOKEN_PROGRAM_IDX, "token program")?;
+        if *token_program.key != TOKEN_PROGRAM_ID {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let pda_account = ctx.account(EXCHANGE_PDA_IDX, "PDA")?;
+        if *pda_account.key != pda.key {
+            return Err(EscrowError::InvalidPda.into());
+        }
+
+        let config_account = ctx.account(EXCHANGE_CONFIG_IDX, "config")?;
+        let config = Self::load_config(config_account, ctx.program_id)?;
+        if matches!(&config, Some(config) if config.paused) {
+            return Err(EscrowError::ProgramPaused.into());
+        }
+
+        let escrow_index_account = ctx.account(EXCHANGE_ESCROW_INDEX_IDX, "escrow index")?;
+        let escrow_index = Self::load_escrow_index(escrow_index_account, ctx.program_id)?;
+
+        // The token program checks each mint against the token accounts of its transfer
+        let deposit_mint = ctx.account(EXCHANGE_DEPOSIT_MINT_IDX, "deposit mint")?;
+        if *deposit_mint.key != escrow_info.deposit_mint {
+            return Err(EscrowError::WrongMint.into());
+        }
+        require_owned_by_token_program(deposit_mint)?;
+        let deposit_decimals = Mint::unpack(&deposit_mint.try_borrow_data()?)?.decimals;
+        let expected_mint = ctx.account(EXCHANGE_EXPECTED_MINT_IDX, "expected mint")?;
+        require_owned_by_token_program(expected_mint)?;
+        let expected_decimals = Mint::unpack(&expected_mint.try_borrow_data()?)?.decimals;
+        if let Some(config) = &config {
+            if config.is_mint_paused(deposit_mint.key) || config.is_mint_paused(expected_mint.key) {
+                return Err(EscrowError::MintPaused.into());
+            }
+        }
+        taker_token_to_send_account_state.require_mint(expected_mint.key)?;
+        // A wrapped SOL payment has to come out of a native token account whose balance matches its lamports
+        if *expected_mint.key == spl_token::native_mint::id() {
+            if !taker_token_to_send_account_state.is_native() {
+                return Err(EscrowError::InvalidAmount.into());
+            }
+            taker_token_to_send_account_state.require_synced_native(taker_token_to_send_account)?;
+        }
+
+        let rent_refund_account = ctx.account(EXCHANGE_RENT_REFUND_IDX, "rent refund")?;
+        if *rent_refund_account.key != escrow_info.rent_refund_recipient {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        // The fee is paid in the expected mint, into a token account the fee vault of that mint owns
+        let fee_token_account = ctx.account(EXCHANGE_FEE_TOKEN_IDX, "fee token")?;
+        let exchange_fee_bps = config.as_ref().map_or(0, |config| config.exchange_fee_bps);
+        if exchange_fee_bps != 0 {
+            require_owned_by_token_program(fee_token_account)?;
+            let fee_token_account_state =
+                TokenAccount::unpack(&fee_token_account.try_borrow_data()?)?;
+            fee_token_account_state.require_mint(expected_mint.key)?;
+            fee_token_account_state
+                .require_authority(&find_fee_vault_address(expected_mint.key, ctx.program_id).0)?;
+        }
+        ctx.skip_to(EXCHANGE_FIXED_ACCOUNTS);
+
+        // A native deposit is paid to the taker in lamports by the PDA, so any account can receive it
+        let mut system_program = None;
+        let create_taker_account_infos = if escrow_info.is_native_deposit {
+            let system_program_account = ctx.next_account()?;
+            if *system_program_account.key != system_program::id() {
+                return Err(ProgramError::IncorrectProgramId);
+            }
+            if !pda_account.is_writable {
+                return Err(ProgramError::InvalidArgument);
+            }
+            system_program = Some(system_program_account);
+            None
+        } else if taker_token_to_receive_account.data_is_empty() {
+            let associated_token_program = ctx.next_account()?;
+            if *associated_token_program.key != spl_associated_token_account::id() {
+                return Err(ProgramError::IncorrectProgramId);
+            }
+            let system_program = ctx.next_account()?;
+            let rent_sysvar = ctx.next_account()?;
+
+            // The associated token account of the deposit mint is the only account the program creates for the taker
+            if *taker_token_to_receive_account.key
+                != spl_associated_token_account::get_associated_token_address(
+                    taker_account.key,
+                    &escrow_info.deposit_mint,
+                )
+            {
+                return Err(ProgramError::InvalidAccountData);
+            }
+
+            Some([
+                associated_token_program.clone(),
+                taker_account.clone(),
+                taker_token_to_receive_account.clone(),
+                deposit_mint.clone(),
+                system_program.clone(),
+                token_program.clone(),
+                rent_sysvar.clone(),
+            ])
+        } else {
+            require_owned_by_token_program(taker_token_to_receive_account)?;
+            TokenAccount::unpack(&taker_token_to_receive_account.try_borrow_data()?)?
+                .require_mint(&escrow_info.deposit_mint)?;
+            None
+        };
+
+        // An escrow opened from a template takes the parameters it shares from there, they apply to this exchange
+        // like the escrow's own. A split escrow writes them back with its fill, a template can't change anyway
+        if escrow_info.template != Pubkey::default() {
+            let template_account = ctx.next_account()?;
+            if *template_account.key != escrow_info.template {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            let template = Self::load_template(template_account, ctx.program_id)?;
+            escrow_info.amount_tolerance = template.amount_tolerance;
+            escrow_info.settlement_authority = template.settlement_authority;
+        }
+
+        // The number of callback accounts is fixed at init, so the optional stats account can still follow them
+        let callback_account_infos = if escrow_info.callback_program != Pubkey::default() {
+            let callback_program = ctx.next_account()?;
+            if *callback_program.key != escrow_info.callback_program {
+                return Err(ProgramError::IncorrectProgramId);
+            }
+            let mut callback_account_infos = (0..escrow_info.callback_accounts_len)
+                .map(|_| ctx.next_account().cloned())
+                .collect::<Result<Vec<_>, _>>()?;
+            callback_account_infos.push(callback_program.clone());
+            Some(callback_account_infos)
+        } else {
+            None
+        };
+
+        Self::require_settlement_authority(ctx, &escrow_info)?;
+
+        let stats = ctx
+            .account_info_iter
+            .next()
+            .map(|stats_account| {
+                Ok::<_, ProgramError>((
+                    stats_account,
+                    Self::load_stats(stats_account, ctx.program_id)?,
+                ))
+            })
+            .transpose()?;
+
+        Ok(ExchangeAccounts {
+            taker_account,
+            taker_token_to_send_account,
+            taker_token_to_receive_account,
+            pda_token_account,
+            initializer_account,
+            initializer_token_to_receive_account,
+            escrow_account,
+            token_program,
+            pda_account,
+            escrow_index_account,
+            deposit_mint,
+            expected_mint,
+            rent_refund_account,
+            fee_token_account,
+            escrow_info,
+            pda,
+            deposit_balance,
+            taker_token_to_send_account_state,
+            exchange_fee_bps,
+            escrow_index,
+            deposit_decimals,
+            expected_decimals,
+            create_taker_account_infos,
+            system_program,
+            callback_account_infos,
+            stats,
+        })
+    }
+
+    pub fn processor_cancel(ctx: &mut ProcessorContext) -> ProgramResult {
+        let initializer = ctx.next_account()?;
+
+        let pda_token_account = ctx.next_account()?;
+        let pda_token_account_state = TokenAccount::unpack(&pda_token_account.try_borrow_data()?)?;
+        // The token program can't move tokens out of a frozen account, fail with a clear error instead
+        if pda_token_account_state.is_frozen() {
+            return Err(EscrowError::AccountFrozen.into());
+        }
+
+        let initializer_token_account = ctx.next_account()?;
+
+        let escrow_account = ctx.next_account()?;
+        // Only an escrow account of this program can vouch for the initializer and the cancel delegate
+        if escrow_account.owner != ctx.program_id {
+            return Err(EscrowError::WrongOwner.into());
+        }
+        let escrow_info = Escrow::unpack(&escrow_account.try_borrow_data()?)?;
+
+        // A native deposit is unwrapped into the initializer's account, no token account receives it
+        let initializer_token_account_state = if escrow_info.is_native_deposit {
+            None
+        } else {
+            let initializer_token_account_state =
+                TokenAccount::unpack(&initializer_token_account.try_borrow_data()?)?;
+            initializer_token_account_state.require_mint(&pda_token_account_state.mint)?;
+            Some(initializer_token_account_state)
+        };
+
+        if escrow_info.in_progress {
+            return Err(EscrowError::ReentrancyDetected.into());
+        }
+
+        if escrow_info.initializer_pubkey != *initializer.key {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        // Without the initializer's signature the escrow's cancel delegate has to sign, passed after the other accounts
+        if !initializer.is_signer {
+            let cancel_delegate = ctx
+                .accounts
+                .get(CANCEL_DELEGATE_IDX)
+                .ok_or(ProgramError::MissingRequiredSignature)?;
+            if escrow_info.cancel_delegate == Pubkey::default()
+                || *cancel_delegate.key != escrow_info.cancel_delegate
+            {
+                return Err(EscrowError::WrongAuthority.into());
+            }
+            if !cancel_delegate.is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            // Revoking the PDA's delegation takes the token account owner's signature
+            if escrow_info.is_delegated {
+                msg!("A delegated escrow can only be cancelled by its initializer");
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            // The delegate only gets to cancel, the tokens go back to an account of the initializer
+            if let Some(initializer_token_account_state) = &initializer_token_account_state {
+                initializer_token_account_state.require_authority(initializer.key)?;
+            }
+        }
+
+        // The taker may already have revealed the preimage elsewhere, the deposit stays theirs to take until the timelock is over
+        if escrow_info.is_timelocked(ctx.clock()?.slot) {
+            return Err(EscrowError::EscrowTimelocked.into());
+        }
+
+        // A taker who reserved the escrow counts on the deposit being there until the reservation is over
+        if escrow_info.is_reserved(ctx.clock()?.slot) {
+            return Err(EscrowError::EscrowReserved.into());
+        }
+
+        if *pda_token_account.key != escrow_info.temp_token_account_pubkey {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let token_program = ctx.next_account()?;
+        if *token_program.key != TOKEN_PROGRAM_ID {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let pda_account = ctx.next_account()?;
+        let pda =
+            PdaInfo::find_in_namespace(ctx.program_id, &escrow_info.namespace, escrow_account.key);
+        if *pda_account.key != pda.key {
+            return Err(EscrowError::InvalidPda.into());
+        }
+
+        let config_account = ctx.next_account()?;
+        let treasury_account = ctx.next_account()?;
+        let cancel_fee =
+            Self::treasury_fee(config_account, treasury_account, ctx.program_id, |config| {
+                config.cancel_fee
+            })?;
+        verbose_msg!("Cancel fee is {} lamports", cancel_fee);
+
+        // Both ways below close the escrow, so it leaves the index upfront
+        let escrow_index_account = ctx.next_account()?;
+        if let Some(mut escrow_index) =
+            Self::load_escrow_index(escrow_index_account, ctx.program_id)?
+        {
+            escrow_index.remove(escrow_account.key);
+            EscrowIndex::pack(
+                escrow_index,
+                &mut escrow_index_account.try_borrow_mut_data()?,
+            )?;
+        }
+
+        let rent_refund_account = ctx.next_account()?;
+        if *rent_refund_account.key != escrow_info.rent_refund_recipient {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        if escrow_info.is_delegated {
+            // The initializer may have revoked or replaced the delegation already, only revoke the PDA's own
+            if pda_token_account_state.delegated_amount_to(&pda.key) != 0 {
+                let ix_revoke = spl_token::instruction::revoke(
+                    token_program.key,
+                    pda_token_account.key,
+                    initializer.key,
+                    &[initializer.key],
+                )?;
+                invoke(
+                    &ix_revoke,
+                    &[
+                        token_program.clone(),
+                        pda_token_account.clone(),
+                        initializer.clone(),
+                    ],
+                )?;
+            }
+
+            Self::charge_fee(escrow_account, treasury_account, cancel_fee)?;
+            return Self::close_escrow_account(escrow_account, rent_refund_account);
+        }
+
+        pda_token_account_state.require_authority(&pda.key)?;
+
+        let token_cpi_accounts = [
+            token_program.clone(),
+            initializer.clone(),
+            pda_token_account.clone(),
+            initializer_token_account.clone(),
+            pda_account.clone(),
+        ];
+
+        // Closing a native deposit's token account alone hands the initializer the deposit with the rent
+        if initializer_token_account_state.is_some() {
+            let ix_transfer_to_initializer = spl_token::instruction::transfer(
+                token_program.key,
+                pda_token_account.key,
+                initializer_token_account.key,
+                &pda.key,
+                &[&pda.key],
+                pda_token_account_state.amount,
+            )?;
+            verbose_msg!(
+                "Calling the token program to return {} tokens to the initializer...",
+                pda_token_account_state.amount
+            );
+            invoke_signed(
+                &ix_transfer_to_initializer,
+                &token_cpi_accounts,
+                &[&pda.signer_seeds()],
+            )?;
+        }
+
+        let ix_close_pda_account = spl_token::instruction::close_account(
+            token_program.key,
+            pda_token_account.key,
+            initializer.key,
+            &pda.key,
+            &[&pda.key],
+        )?;
+        invoke_signed(
+            &ix_close_pda_account,
+            &token_cpi_accounts,
+            &[&pda.signer_seeds()],
+        )?;
+
+        Self::charge_fee(escrow_account, treasury_account, cancel_fee)?;
+        Self::close_escrow_account(escrow_account, rent_refund_account)
+    }
+
+    pub fn processor_force_reclaim(ctx: &mut ProcessorContext) -> ProgramResult {
+        let initializer = ctx.next_account()?;
+
+        if !initializer.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let pda_token_account = ctx.next_account()?;
+
+        let escrow_account = ctx.next_account()?;
+        if escrow_account.owner != ctx.program_id {
+            return Err(EscrowError::WrongOwner.into());
+        }
+        let escrow_info = Escrow::unpack(&escrow_account.try_borrow_data()?)?;
+
+        if escrow_info.in_progress {
+            return Err(EscrowError::ReentrancyDetected.into());
+        }
+
+        if escrow_info.initializer_pubkey != *initializer.key {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        if escrow_info.is_timelocked(ctx.clock()?.slot) {
+            return Err(EscrowError::EscrowTimelocked.into());
+        }
+
+        // Reclaiming cancels a frozen escrow, the reservation holds it all the same
+        if escrow_info.is_reserved(ctx.clock()?.slot) {
+            return Err(EscrowError::EscrowReserved.into());
+        }
+
+        if *pda_token_account.key != escrow_info.temp_token_account_pubkey {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let token_program = ctx.next_account()?;
+        if *token_program.key != TOKEN_PROGRAM_ID {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let pda_account = ctx.next_account()?;
+        let pda =
+            PdaInfo::find_in_namespace(ctx.program_id, &escrow_info.namespace, escrow_account.key);
+        if *pda_account.key != pda.key {
+            return Err(EscrowError::InvalidPda.into());
+        }
+
+        let config_account = ctx.next_account()?;
+        let treasury_account = ctx.next_account()?;
+        let cancel_fee =
+            Self::treasury_fee(config_account, treasury_account, ctx.program_id, |config| {
+                config.cancel_fee
+            })?;
+
+        let escrow_index_account = ctx.next_account()?;
+        if let Some(mut escrow_index) =
+            Self::load_escrow_index(escrow_index_account, ctx.program_id)?
+        {
+            escrow_index.remove(escrow_account.key);
+            EscrowIndex::pack(
+                escrow_index,
+                &mut escrow_index_account.try_borrow_mut_data()?,
+            )?;
+        }
+
+        let rent_refund_account = ctx.next_account()?;
+        if *rent_refund_account.key != escrow_info.rent_refund_recipient {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        // A failing CPI would fail the whole instruction, so each step only runs when the token program would accept it
+        let pda_token_account_state = if *pda_token_account.owner == TOKEN_PROGRAM_ID {
+            TokenAccount::unpack(&pda_token_account.try_borrow_data()?).ok()
+        } else {
+            None
+        };
+        match pda_token_account_state {
+            Some(state) if state.is_frozen() => {
+                msg!("Token account is frozen, leaving it as it is");
+            }
+            Some(state) if escrow_info.is_delegated => {
+                if state.owner == *initializer.key && state.delegated_amount_to(&pda.key) != 0 {
+                    let ix_revoke = spl_token::instruction::revoke(
+                        token_program.key,
+                        pda_token_account.key,
+                        initializer.key,
+                        &[initializer.key],
+                    )?;
+                    invoke(
+                        &ix_revoke,
+                        &[
+                            token_program.clone(),
+                            pda_token_account.clone(),
+                            initializer.clone(),
+                        ],
+                    )?;
+                }
+            }
+            Some(state) => {
+                if state.owner == pda.key {
+                    let ix_owner_change = spl_token::instruction::set_authority(
+                        token_program.key,
+                        pda_token_account.key,
+                        Some(initializer.key),
+                        spl_token::instruction::AuthorityType::AccountOwner,
+                        &pda.key,
+                        &[&pda.key],
+                    )?;
+                    verbose_msg!("Calling the token program to hand the token account back to the initializer...");
+                    invoke_signed(
+                        &ix_owner_change,
+                        &[
+                            token_program.clone(),
+                            pda_token_account.clone(),
+                            pda_account.clone(),
+                        ],
+                        &[&pda.signer_seeds()],
+                    )?;
+                } else {
+                    msg!("Token account isn't owned by the PDA, leaving it as it is");
+                }
+            }
+            None => {
+                msg!("Token account doesn't hold a token account state, leaving it as it is");
+            }
+        }
+
+        Self::charge_fee(escrow_account, treasury_account, cancel_fee)?;
+        Self::close_escrow_account(escrow_account, rent_refund_account)
+    }
+
+    pub fn processor_replace(ctx: &mut ProcessorContext, args: InitEscrowArgs) -> ProgramResult {
+        if ctx.accounts.len() <= CANCEL_ACCOUNTS {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+        let (cancel_accounts, init_accounts) = ctx.accounts.split_at(CANCEL_ACCOUNTS);
+
+        // Both escrows belong to the same initializer, the new one can't be opened on someone else's behalf
+        if cancel_accounts[0].key != init_accounts[0].key {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        // Each half reads its accounts in order from the start
+        Self::processor_cancel(&mut ProcessorContext::new(ctx.program_id, cancel_accounts))?;
+        Self::processor_init_escrow(
+            &mut ProcessorContext::new(ctx.program_id, init_accounts),
+            args,
+            false,
+            &DEFAULT_NAMESPACE,
+        )
+    }
+
+    /// Checks the deposit is a single NFT of the given mint, then initializes the escrow with the remaining accounts
+    pub fn processor_init_escrow_nft(
+        ctx: &mut ProcessorContext,
+        args: InitEscrowArgs,
+    ) -> ProgramResult {
+        let nft_mint = ctx.next_account()?;
+        require_owned_by_token_program(nft_mint)?;
+        let nft_mint_state = Mint::unpack(&nft_mint.try_borrow_data()?)?;
+        if nft_mint_state.decimals != 0 || nft_mint_state.supply != 1 {
+            return Err(EscrowError::NotAnNft.into());
+        }
+
+        // The temp token account is the second account of `InitEscrow`, after the initializer
+        let temp_token_account = ctx.account(2, "temp token")?;
+        let temp_token_account_state =
+            TokenAccount::unpack(&temp_token_account.try_borrow_data()?)?;
+        if temp_token_account_state.mint != *nft_mint.key || temp_token_account_state.amount != 1 {
+            return Err(EscrowError::NotAnNft.into());
+        }
+
+        Self::processor_init_escrow(
+            &mut ProcessorContext::new(ctx.program_id, &ctx.accounts[1..]),
+            args,
+            true,
+            &DEFAULT_NAMESPACE,
+        )
+    }
+
+    pub fn processor_init_escrow_with_sol(
+        ctx: &mut ProcessorContext,
+        lamports: u64,
+        args: InitEscrowArgs,
+    ) -> ProgramResult {
+        // The exchange pays the whole deposit out in lamports, a share of it or a delegated deposit can't be unwrapped
+        if lamports == 0 || args.taker_slots != 1 || args.price_den != 0 || args.use_delegation {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        // The accounts are the ones of `InitEscrow`, which reads them again once the lamports are wrapped
+        let initializer = ctx.account(0, "initializer")?;
+        let temp_token_account = ctx.account(1, "temp token")?;
+        let token_program = ctx.account(5, "token program")?;
+        if *token_program.key != TOKEN_PROGRAM_ID {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let system_program = ctx.account(9, "system program")?;
+        if *system_program.key != system_program::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+
+        // Tokens already in the account would be paid out as part of the deposit
+        require_owned_by_token_program(temp_token_account)?;
+        let temp_token_account_state =
+            TokenAccount::unpack(&temp_token_account.try_borrow_data()?)?;
+        temp_token_account_state.require_mint(&spl_token::native_mint::id())?;
+        if temp_token_account_state.amount != 0 {
+            return Err(EscrowError::InvalidAmount.into());
+        }
+
+        let ix_deposit =
+            system_instruction::transfer(initializer.key, temp_token_account.key, lamports);
+        verbose_msg!("Calling the system program to deposit the lamports...");
+        invoke(
+            &ix_deposit,
+            &[
+                initializer.clone(),
+                temp_token_account.clone(),
+                system_program.clone(),
+            ],
+        )?;
+        let ix_sync_native =
+            spl_token::instruction::sync_native(token_program.key, temp_token_account.key)?;
+        verbose_msg!("Calling the token program to wrap the deposited lamports...");
+        invoke(
+            &ix_sync_native,
+            &[temp_token_account.clone(), token_program.clone()],
+        )?;
+
+        Self::processor_init_escrow(ctx, args, false, &DEFAULT_NAMESPACE)?;
+
+        let escrow_account = ctx.account(3, "escrow")?;
+        let mut escrow_info = Escrow::unpack(&escrow_account.try_borrow_data()?)?;
+        escrow_info.is_native_deposit = true;
+        Escrow::pack(escrow_info, &mut escrow_account.try_borrow_mut_data()?)
+    }
+
+    pub fn processor_init_template(
+        ctx: &mut ProcessorContext,
+        template_id: u64,
+        amount_tolerance: u64,
+        settlement_authority: &Pubkey,
+    ) -> ProgramResult {
+        let authority = ctx.next_account()?;
+
+        if !authority.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let template_account = ctx.next_account()?;
+        let (template_address, template_bump_seed) =
+            find_template_address(authority.key, template_id, ctx.program_id);
+        if *template_account.key != template_address {
+            return Err(ProgramError::InvalidSeeds);
+        }
+
+        let deposit_mint = ctx.next_account()?;
+        require_owned_by_token_program(deposit_mint)?;
+        Mint::unpack(&deposit_mint.try_borrow_data()?)?;
+        let expected_mint = ctx.next_account()?;
+        require_owned_by_token_program(expected_mint)?;
+        Mint::unpack(&expected_mint.try_borrow_data()?)?;
+
+        let system_program = ctx.next_account()?;
+
+        // Like an escrow account, a template account that was already allocated to the program is only initialized
+        if template_account.data_is_empty() {
+            Self::create_pda_account(
+                authority,
+                template_account,
+                system_program,
+                EscrowTemplate::LEN,
+                &[
+                    TEMPLATE_SEED,
+                    authority.key.as_ref(),
+                    &template_id.to_le_bytes(),
+                    &[template_bump_seed],
+                ],
+                ctx.program_id,
+            )?;
+        } else if template_account.owner != ctx.program_id {
+            return Err(EscrowError::WrongOwner.into());
+        }
+
+        let template = EscrowTemplate::unpack_unchecked(&template_account.try_borrow_data()?)?;
+        if template.is_initialized() {
+            return Err(ProgramError::AccountAlreadyInitialized);
+        }
+
+        let template = EscrowTemplate {
+            is_initialized: true,
+            authority: *authority.key,
+            deposit_mint: *deposit_mint.key,
+            expected_mint: *expected_mint.key,
+            amount_tolerance,
+            settlement_authority: *settlement_authority,
+        };
+        EscrowTemplate::pack(template, &mut template_account.try_borrow_mut_data()?)?;
+
+        Ok(())
+    }
+
+    pub fn processor_init_escrow_from_template(
+        ctx: &mut ProcessorContext,
+        args: InitEscrowArgs,
+    ) -> ProgramResult {
+        // The template's parameters can't be overridden per escrow, the exchange only reads the template's
+        if args.amount_tolerance != 0 || args.settlement_authority != Pubkey::default() {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let template_account = ctx.next_account()?;
+        let template = Self::load_template(template_account, ctx.program_id)?;
+
+        // The accounts of `InitEscrow` follow the template account, the initializer first
+        let initializer = ctx.account(1, "initializer")?;
+        if template.authority != *initializer.key {
+            return Err(EscrowError::WrongAuthority.into());
+        }
+        let temp_token_account = ctx.account(2, "temp token")?;
+        TokenAccount::unpack(&temp_token_account.try_borrow_data()?)?
+            .require_mint(&template.deposit_mint)?;
+        let token_to_receive_account = ctx.account(3, "token to receive")?;
+        TokenAccount::unpack(&token_to_receive_account.try_borrow_data()?)?
+            .require_mint(&template.expected_mint)?;
+
+        Self::processor_init_escrow(
+            &mut ProcessorContext::new(ctx.program_id, &ctx.accounts[1..]),
+            args,
+            false,
+            &DEFAULT_NAMESPACE,
+        )?;
+
+        let escrow_account = ctx.account(4, "escrow")?;
+        let mut escrow_info = Escrow::unpack(&escrow_account.try_borrow_data()?)?;
+        escrow_info.template = *template_account.key;
+        Escrow::pack(escrow_info, &mut escrow_account.try_borrow_mut_data()?)
+    }
+
+    pub fn processor_partial_withdraw(ctx: &mut ProcessorContext, amount: u64) -> ProgramResult {
+        let initializer = ctx.next_account()?;
+
+        if !initializer.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let pda_token_account = ctx.next_account()?;
+        let pda_token_account_state = TokenAccount::unpack(&pda_token_account.try_borrow_data()?)?;
+        // The token program can't move tokens out of a frozen account, fail with a clear error instead
+        if pda_token_account_state.is_frozen() {
+            return Err(EscrowError::AccountFrozen.into());
+        }
+
+        // Withdrawing everything would leave an escrow that offers nothing,
+        // the initializer should cancel the escrow instead
+        if amount == 0 || amount >= pda_token_account_state.amount {
+            return Err(EscrowError::InvalidAmount.into());
+        }
+
+        let initializer_token_account = ctx.next_account()?;
+        TokenAccount::unpack(&initializer_token_account.try_borrow_data()?)?
+            .require_mint(&pda_token_account_state.mint)?;
+
+        let escrow_account = ctx.next_account()?;
+        // Only an escrow account of this program can vouch for the initializer and the PDA's token account
+        if escrow_account.owner != ctx.program_id {
+            return Err(EscrowError::WrongOwner.into());
+        }
+        let escrow_info = Escrow::unpack(&escrow_account.try_borrow_data()?)?;
+
+        if escrow_info.in_progress {
+            return Err(EscrowError::ReentrancyDetected.into());
+        }
+
+        // The initializer of a delegated escrow lowers the delegation with the token program directly
+        if escrow_info.is_delegated {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        if escrow_info.initializer_pubkey != *initializer.key {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        // The reserving taker expects the whole deposit, not what is left of it
+        if escrow_info.is_reserved(ctx.clock()?.slot) {
+            return Err(EscrowError::EscrowReserved.into());
+        }
+
+        if *pda_token_account.key != escrow_info.temp_token_account_pubkey {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let token_program = ctx.next_account()?;
+        if *token_program.key != TOKEN_PROGRAM_ID {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let pda_account = ctx.next_account()?;
+        let pda =
+            PdaInfo::find_in_namespace(ctx.program_id, &escrow_info.namespace, escrow_account.key);
+        if *pda_account.key != pda.key {
+            return Err(EscrowError::InvalidPda.into());
+        }
+        pda_token_account_state.require_authority(&pda.key)?;
+
+        let ix_transfer_to_initializer = spl_token::instruction::transfer(
+            token_program.key,
+            pda_token_account.key,
+            initializer_token_account.key,
+            &pda.key,
+            &[&pda.key],
+            amount,
+        )?;
+        invoke_signed(
+            &ix_transfer_to_initializer,
+            &[
+                token_program.clone(),
+                pda_token_account.clone(),
+                initializer_token_account.clone(),
+                pda_account.clone(),
+            ],
+            &[&pda.signer_seeds()],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn processor_set_receive_account(ctx: &mut ProcessorContext) -> ProgramResult {
+        let initializer = ctx.next_account()?;
+
+        if !initializer.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let escrow_account = ctx.next_account()?;
+        if escrow_account.owner != ctx.program_id {
+            return Err(EscrowError::WrongOwner.into());
+        }
+        let mut escrow_info = Escrow::unpack(&escrow_account.try_borrow_data()?)?;
+
+        if escrow_info.in_progress {
+            return Err(EscrowError::ReentrancyDetected.into());
+        }
+
+        if escrow_info.initializer_pubkey != *initializer.key {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        // The escrow doesn't store the expected mint, the current receive account holds it
+        let current_receive_account = ctx.next_account()?;
+        if escrow_info.initializer_token_to_receive_account_pubkey != *current_receive_account.key {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        require_owned_by_token_program(current_receive_account)?;
+        let expected_mint = TokenAccount::unpack(&current_receive_account.try_borrow_data()?)?.mint;
+
+        // Same checks as the receive account gets on init, its owner is left unchecked there too
+        let new_receive_account = ctx.next_account()?;
+        if *new_receive_account.key == escrow_info.temp_token_account_pubkey {
+            return Err(ProgramError::InvalidArgument);
+        }
+        require_owned_by_token_program(new_receive_account)?;
+        let new_receive_account_state =
+            TokenAccount::unpack(&new_receive_account.try_borrow_data()?)?;
+        new_receive_account_state.require_mint(&expected_mint)?;
+        new_receive_account_state.require_synced_native(new_receive_account)?;
+
+        escrow_info.initializer_token_to_receive_account_pubkey = *new_receive_account.key;
+        Escrow::pack(escrow_info, &mut escrow_account.try_borrow_mut_data()?)?;
+
+        Ok(())
+    }
+
+    pub fn processor_get_escrow(ctx: &mut ProcessorContext) -> ProgramResult {
+        let escrow_account = ctx.next_account()?;
+
+        if escrow_account.owner != ctx.program_id {
+            return Err(EscrowError::WrongOwner.into());
+        }
+
+        let escrow_info = Escrow::unpack(&escrow_account.try_borrow_data()?)?;
+
+        let mut escrow_data = [0u8; Escrow::LEN];
+        Escrow::pack(escrow_info, &mut escrow_data)?;
+        set_return_data(&escrow_data);
+
+        Ok(())
+    }
+
+    pub fn processor_time_remaining(ctx: &mut ProcessorContext) -> ProgramResult {
+        let escrow_account = ctx.next_account()?;
+
+        if escrow_account.owner != ctx.program_id {
+            return Err(EscrowError::WrongOwner.into());
+        }
+
+        let escrow_info = Escrow::unpack(&escrow_account.try_borrow_data()?)?;
+
+        let clock = ctx.clock()?;
+        let time_remaining = if escrow_info.use_slot_expiry {
+            if escrow_info.expiry_slot == 0 {
+                -1
+            } else {
+                escrow_info
+                    .expiry_slot
+                    .saturating_sub(clock.slot)
+                    .min(i64::MAX as u64) as i64
+            }
+        } else if escrow_info.expiry_timestamp == 0 {
+            -1
+        } else {
+            escrow_info
+                .expiry_timestamp
+                .saturating_sub(clock.unix_timestamp)
+                .max(0)
+        };
+        set_return_data(&time_remaining.to_le_bytes());
+
+        Ok(())
+    }
+
+    pub fn processor_top_up_rent(ctx: &mut ProcessorContext, lamports: u64) -> ProgramResult {
+        let payer = ctx.next_account()?;
+
+        if !payer.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let escrow_account = ctx.next_account()?;
+        if escrow_account.owner != ctx.program_id {
+            return Err(EscrowError::WrongOwner.into());
+        }
+        // Only a live escrow is worth keeping alive
+        Escrow::unpack(&escrow_account.try_borrow_data()?)?;
+
+        let system_program = ctx.next_account()?;
+
+        let ix_top_up = system_instruction::transfer(payer.key, escrow_account.key, lamports);
+        verbose_msg!("Calling the system program to top up the escrow account rent...");
+        invoke(
+            &ix_top_up,
+            &[
+                payer.clone(),
+                escrow_account.clone(),
+                system_program.clone(),
+            ],
+        )?;
+
+        let rent = Rent::get()?;
+        if !rent.is_exempt(escrow_account.lamports(), escrow_account.data_len()) {
+            let minimum_balance = rent.minimum_balance(escrow_account.data_len());
+            msg!(
+                "Escrow account needs {} lamports to be rent exempt but holds {}, {} short",
+                minimum_balance,
+                escrow_account.lamports(),
+                minimum_balance.saturating_sub(escrow_account.lamports())
+            );
+            return Err(EscrowError::NotRentExempt.into());
+        }
+
+        Ok(())
+    }
+
+    pub fn processor_reserve(
+        ctx: &mut ProcessorContext,
+        reserved_until_slot: u64,
+    ) -> ProgramResult {
+        let taker = ctx.next_account()?;
+
+        if !taker.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let escrow_account = ctx.next_account()?;
+        if escrow_account.owner != ctx.program_id {
+            return Err(EscrowError::WrongOwner.into());
+        }
+
+        let mut escrow_info = Escrow::unpack(&escrow_account.try_borrow_data()?)?;
+
+        if escrow_info.in_progress {
+            return Err(EscrowError::ReentrancyDetected.into());
+        }
+
+        let clock = ctx.clock()?;
+        if escrow_info.is_expired(&clock) {
+            return Err(EscrowError::EscrowExpired.into());
+        }
+
+        // A taker who filled their share already has nothing left to reserve
+        if escrow_info.has_filled(taker.key) {
+            return Err(EscrowError::TakerNotAllowed.into());
+        }
+
+        // Only the taker holding the reservation can extend it before it is over
+        if escrow_info.is_reserved_for_other(taker.key, clock.slot) {
+            return Err(EscrowError::EscrowReserved.into());
+        }
+
+        if reserved_until_slot <= clock.slot
+            || reserved_until_slot > math::add(clock.slot, MAX_RESERVATION_SLOTS)?
+        {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        escrow_info.reserved_taker = *taker.key;
+        escrow_info.reserved_until_slot = reserved_until_slot;
+        Escrow::pack(escrow_info, &mut escrow_account.try_borrow_mut_data()?)?;
+
+        Ok(())
+    }
+
+    pub fn processor_extend_expiry(ctx: &mut ProcessorContext, expiry: u64) -> ProgramResult {
+        let initializer = ctx.next_account()?;
+
+        if !initializer.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let escrow_account = ctx.next_account()?;
+        if escrow_account.owner != ctx.program_id {
+            return Err(EscrowError::WrongOwner.into());
+        }
+
+        let mut escrow_info = Escrow::unpack(&escrow_account.try_borrow_data()?)?;
+
+        if escrow_info.in_progress {
+            return Err(EscrowError::ReentrancyDetected.into());
+        }
+
+        if escrow_info.initializer_pubkey != *initializer.key {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        // The expiry can only move later, an escrow that never expires has nothing to extend
+        // and extending can't lift the expiry altogether
+        let current_expiry = if escrow_info.use_slot_expiry {
+            escrow_info.expiry_slot
+        } else {
+            escrow_info.expiry_timestamp as u64
+        };
+        if current_expiry == 0
+            || expiry == 0
+            || expiry <= current_expiry
+            || (!escrow_info.use_slot_expiry && expiry > i64::MAX as u64)
+        {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let config_account = ctx.next_account()?;
+        let treasury_account = ctx.next_account()?;
+        let extension_fee =
+            Self::treasury_fee(config_account, treasury_account, ctx.program_id, |config| {
+                config.extension_fee
+            })?;
+        let system_program = ctx.next_account()?;
+
+        if escrow_info.use_slot_expiry {
+            escrow_info.expiry_slot = expiry;
+        } else {
+            escrow_info.expiry_timestamp = expiry as i64;
+        }
+        Escrow::pack(escrow_info, &mut escrow_account.try_borrow_mut_data()?)?;
+
+        if extension_fee != 0 {
+            let ix_extension_fee =
+                system_instruction::transfer(initializer.key, treasury_account.key, extension_fee);
+            verbose_msg!(
+                "Calling the system program to transfer the extension fee to the treasury..."
+            );
+            invoke(
+                &ix_extension_fee,
+                &[
+                    initializer.clone(),
+                    treasury_account.clone(),
+                    system_program.clone(),
+                ],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn processor_get_escrow_batch(ctx: &mut ProcessorContext) -> ProgramResult {
+        if ctx.accounts.len() > MAX_BATCH {
+            msg!(
+                "Batch of {} escrows is over the limit of {}",
+                ctx.accounts.len(),
+                MAX_BATCH
+            );
+            return Err(EscrowError::BatchTooLarge.into());
+        }
+
+        let mut batch_data = Vec::with_capacity(MAX_RETURN_DATA);
+        for escrow_account in ctx.accounts {
+            if escrow_account.owner != ctx.program_id {
+                return Err(EscrowError::WrongOwner.into());
+            }
+
+            // A closed escrow account has its data zeroed, or emptied before escrows were closed that way,
+            // it is flagged like an uninitialized one
+            if escrow_account.data_is_empty() {
+                batch_data.push(0);
+                continue;
+            }
+
+            let escrow_info = Escrow::unpack_unchecked(&escrow_account.try_borrow_data()?)?;
+            if !escrow_info.is_initialized() {
+                batch_data.push(0);
+                continue;
+            }
+
+            let mut escrow_data = [0u8; Escrow::LEN];
+            Escrow::pack(escrow_info, &mut escrow_data)?;
+            batch_data.push(1);
+            batch_data.extend_from_slice(&escrow_data[..Escrow::BATCH_RECORD_LEN]);
+        }
+
+        if batch_data.len() > MAX_RETURN_DATA {
+            msg!(
+                "{} bytes of escrow states don't fit in the {} bytes of return data",
+                batch_data.len(),
+                MAX_RETURN_DATA
+            );
+            return Err(ProgramError::InvalidArgument);
+        }
+        set_return_data(&batch_data);
+
+        Ok(())
+    }
+
+    pub fn processor_derive_authority(ctx: &mut ProcessorContext) -> ProgramResult {
+        let escrow_account = ctx.next_account()?;
+
+        let namespace = Self::escrow_namespace(escrow_account, ctx.program_id)?;
+        let pda = PdaInfo::find_in_namespace(ctx.program_id, &namespace, escrow_account.key);
+
+        let mut authority_data = [0u8; 33];
+        authority_data[..32].copy_from_slice(pda.key.as_ref());
+        authority_data[32] = pda.bump;
+        set_return_data(&authority_data);
+
+        Ok(())
+    }
+
+    pub fn processor_version() -> ProgramResult {
+        set_return_data(&PROGRAM_VERSION);
+
+        Ok(())
+    }
+
+    pub fn processor_sweep_stray_tokens(ctx: &mut ProcessorContext) -> ProgramResult {
+        let admin = ctx.next_account()?;
+        let config_account = ctx.next_account()?;
+        Self::require_admin(admin, config_account, ctx.program_id)?;
+
+        let stray_token_account = ctx.next_account()?;
+        let stray_token_account_state =
+            TokenAccount::unpack(&stray_token_account.try_borrow_data()?)?;
+        if stray_token_account_state.is_frozen() {
+            return Err(EscrowError::AccountFrozen.into());
+        }
+
+        let destination_token_account = ctx.next_account()?;
+        if destination_token_account.key == stray_token_account.key {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        // A live escrow's temp token account holds the deposit takers are paying for, it must never be swept
+        let escrow_account = ctx.next_account()?;
+        if escrow_account.owner == ctx.program_id && !escrow_account.data_is_empty() {
+            let escrow_info = Escrow::unpack_unchecked(&escrow_account.try_borrow_data()?)?;
+            if escrow_info.is_initialized()
+                && escrow_info.temp_token_account_pubkey == *stray_token_account.key
+            {
+                return Err(ProgramError::InvalidArgument);
+            }
+        }
+
+        let namespace = Self::escrow_namespace(escrow_account, ctx.program_id)?;
+        let pda = PdaInfo::find_in_namespace(ctx.program_id, &namespace, escrow_account.key);
+        stray_token_account_state.require_authority(&pda.key)?;
+
+        let token_program = ctx.next_account()?;
+        if *token_program.key != TOKEN_PROGRAM_ID {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let pda_account = ctx.next_account()?;
+        if *pda_account.key != pda.key {
+            return Err(EscrowError::InvalidPda.into());
+        }
+
+        let ix_sweep = spl_token::instruction::transfer(
+            token_program.key,
+            stray_token_account.key,
+            destination_token_account.key,
+            &pda.key,
+            &[&pda.key],
+            stray_token_account_state.amount,
+        )?;
+        verbose_msg!("Calling the token program to sweep the stray tokens...");
+        invoke_signed(
+            &ix_sweep,
+            &[
+                token_program.clone(),
+                stray_token_account.clone(),
+                destination_token_account.clone(),
+                pda_account.clone(),
+            ],
+            &[&pda.signer_seeds()],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn processor_check_fillable(ctx: &mut ProcessorContext) -> ProgramResult {
+        let escrow_account = ctx.next_account()?;
+
+        if escrow_account.owner != ctx.program_id {
+            return Err(EscrowError::WrongOwner.into());
+        }
+
+        let escrow_info = Escrow::unpack(&escrow_account.try_borrow_data()?)?;
+
+        let pda_token_account = ctx.next_account()?;
+        if *pda_token_account.key != escrow_info.temp_token_account_pubkey {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let pda_token_account_state = TokenAccount::unpack(&pda_token_account.try_borrow_data()?)?;
+
+        let config_account = ctx.next_account()?;
+        let config = Self::load_config(config_account, ctx.program_id)?;
+
+        // The expected mint isn't stored with the escrow, it is the one of the initializer's receiving token account
+        let initializer_token_to_receive_account = ctx.next_account()?;
+        if *initializer_token_to_receive_account.key
+            != escrow_info.initializer_token_to_receive_account_pubkey
+        {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let expected_mint =
+            TokenAccount::unpack(&initializer_token_to_receive_account.try_borrow_data()?)?.mint;
+
+        let taker_account = ctx.account_info_iter.next();
+
+        let pda =
+            PdaInfo::find_in_namespace(ctx.program_id, &escrow_info.namespace, escrow_account.key);
+
+        let status = Self::fill_status(
+            &escrow_info,
+            &pda_token_account_state,
+            &pda,
+            config.as_ref(),
+            &expected_mint,
+            taker_account.map(|taker| taker.key),
+            &ctx.clock()?,
+        )?;
+        set_return_data(&[status as u8]);
+
+        Ok(())
+    }
+
+    /// Returns whether the escrow account was closed: drained of its lamports, emptied, or zeroed out
+    fn is_closed_escrow(escrow_account: &AccountInfo) -> Result<bool, ProgramError> {
+        if escrow_account.lamports() == 0 || escrow_account.data_is_empty() {
+            return Ok(true);
+        }
+        Ok(matches!(
+            Escrow::unpack_unchecked(&escrow_account.try_borrow_data()?),
+            Ok(escrow_info) if !escrow_info.is_initialized
+        ))
+    }
+
+    /// Returns whether the escrow can be exchanged, or the reason an exchange would fail.
+    /// The checks run in the same order as in `processor_exchange`
+    fn fill_status(
+        escrow_info: &Escrow,
+        pda_token_account_state: &TokenAccount,
+        pda: &PdaInfo,
+        config: Option<&EscrowConfig>,
+        expected_mint: &Pubkey,
+        taker: Option<&Pubkey>,
+        clock: &Clock,
+    ) -> Result<FillStatus, ProgramError> {
+        Ok(if escrow_info.is_expired(clock) {
+            FillStatus::Expired
+        } else if matches!(config, Some(config) if config.paused) {
+            FillStatus::Paused
+        } else if matches!(config, Some(config) if config.is_mint_paused(&escrow_info.deposit_mint)
+            || config.is_mint_paused(expected_mint))
+        {
+            FillStatus::MintPaused
+        } else if pda_token_account_state.is_frozen() {
+            FillStatus::Frozen
+        } else if escrow_info.is_delegated
+            && pda_token_account_state.delegated_amount_to(&pda.key) == 0
+        {
+            FillStatus::DelegationRevoked
+        } else if Self::deposit_balance(escrow_info, pda_token_account_state, pda)? == 0 {
+            FillStatus::Empty
+        } else if matches!(taker, Some(taker) if escrow_info.has_filled(taker)) {
+            FillStatus::AlreadyFilled
+        } else if taker.map_or(escrow_info.is_reserved(clock.slot), |taker| {
+            escrow_info.is_reserved_for_other(taker, clock.slot)
+        }) {
+            FillStatus::Reserved
+        } else {
+            FillStatus::Fillable
+        })
+    }
+
+    pub fn processor_get_status(ctx: &mut ProcessorContext) -> ProgramResult {
+        let escrow_account = ctx.next_account()?;
+
+        // A closed account may have been handed back to the system program, check it before its owner
+        if Self::is_closed_escrow(escrow_account)? {
+            set_return_data(&[Status::Closed as u8]);
+            return Ok(());
+        }
+
+        if escrow_account.owner != ctx.program_id {
+            return Err(EscrowError::WrongOwner.into());
+        }
+
+        let escrow_info = Escrow::unpack(&escrow_account.try_borrow_data()?)?;
+
+        let status = escrow_info.status(&ctx.clock()?);
+        set_return_data(&[status as u8]);
+
+        Ok(())
+    }
+
+    pub fn processor_init_stats(ctx: &mut ProcessorContext) -> ProgramResult {
+        let payer = ctx.next_account()?;
+
+        if !payer.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let stats_account = ctx.next_account()?;
+        let (stats_address, stats_bump_seed) = find_stats_address(ctx.program_id);
+        if *stats_account.key != stats_address {
+            return Err(ProgramError::InvalidSeeds);
+        }
+
+        if !stats_account.data_is_empty() {
+            return Err(ProgramError::AccountAlreadyInitialized);
+        }
+
+        let system_program = ctx.next_account()?;
+
+        Self::create_pda_account(
+            payer,
+            stats_account,
+            system_program,
+            EscrowStats::LEN,
+            &[STATS_SEED, &[stats_bump_seed]],
+            ctx.program_id,
+        )?;
+
+        let stats = EscrowStats {
+            is_initialized: true,
+            total_created: 0,
+            total_filled: 0,
+            total_volume: 0,
+        };
+        EscrowStats::pack(stats, &mut stats_account.try_borrow_mut_data()?)?;
+
+        Ok(())
+    }
+
+    pub fn processor_init_config(ctx: &mut ProcessorContext) -> ProgramResult {
+        let payer = ctx.next_account()?;
+
+        if !payer.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let admin = ctx.next_account()?;
+
+        if !admin.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let config_account = ctx.next_account()?;
+        let (config_address, config_bump_seed) = find_config_address(ctx.program_id);
+        if *config_account.key != config_address {
+            return Err(ProgramError::InvalidSeeds);
+        }
+
+        if !config_account.data_is_empty() {
+            return Err(ProgramError::AccountAlreadyInitialized);
+        }
+
+        let system_program = ctx.next_account()?;
+
+        Self::create_pda_account(
+            payer,
+            config_account,
+            system_program,
+            EscrowConfig::LEN,
+            &[CONFIG_SEED, &[config_bump_seed]],
+            ctx.program_id,
+        )?;
+
+        let config = EscrowConfig {
+            is_initialized: true,
+            admin: *admin.key,
+            paused: false,
+            treasury: Pubkey::default(),
+            cancel_fee: 0,
+            init_fee: 0,
+            extension_fee: 0,
+            paused_mints_count: 0,
+            paused_mints: [Pubkey::default(); MAX_PAUSED_MINTS],
+            exchange_fee_bps: 0,
+            free_fills: 0,
+            namespaces_count: 0,
+            namespaces: [[0; NAMESPACE_LEN]; MAX_NAMESPACES],
+        };
+        EscrowConfig::pack(config, &mut config_account.try_borrow_mut_data()?)?;
+
+        Ok(())
+    }
+
+    pub fn processor_set_paused(ctx: &mut ProcessorContext, paused: bool) -> ProgramResult {
+        let admin = ctx.next_account()?;
+        let config_account = ctx.next_account()?;
+        let mut config = Self::require_admin(admin, config_account, ctx.program_id)?;
+
+        config.paused = paused;
+        EscrowConfig::pack(config, &mut config_account.try_borrow_mut_data()?)?;
+
+        Ok(())
+    }
+
+    pub fn processor_set_mint_paused(
+        ctx: &mut ProcessorContext,
+        mint: &Pubkey,
+        paused: bool,
+    ) -> ProgramResult {
+        let admin = ctx.next_account()?;
+        let config_account = ctx.next_account()?;
+        let mut config = Self::require_admin(admin, config_account, ctx.program_id)?;
+
+        if paused {
+            config.pause_mint(mint)?;
+        } else {
+            config.resume_mint(mint);
+        }
+        EscrowConfig::pack(config, &mut config_account.try_borrow_mut_data()?)?;
+
+        Ok(())
+    }
+
+    pub fn processor_set_namespace_allowed(
+        ctx: &mut ProcessorContext,
+        namespace: &[u8; NAMESPACE_LEN],
+        allowed: bool,
+    ) -> ProgramResult {
+        let admin = ctx.next_account()?;
+        let config_account = ctx.next_account()?;
+        let mut config = Self::require_admin(admin, config_account, ctx.program_id)?;
+
+        if allowed {
+            config.allow_namespace(namespace)?;
+        } else {
+            config.disallow_namespace(namespace);
+        }
+        EscrowConfig::pack(config, &mut config_account.try_borrow_mut_data()?)?;
+
+        Ok(())
+    }
+
+    pub fn processor_set_treasury(ctx: &mut ProcessorContext, treasury: &Pubkey) -> ProgramResult {
+        let admin = ctx.next_account()?;
+        let config_account = ctx.next_account()?;
+        let mut config = Self::require_admin(admin, config_account, ctx.program_id)?;
+
+        config.treasury = *treasury;
+        EscrowConfig::pack(config, &mut config_account.try_borrow_mut_data()?)?;
+
+        Ok(())
+    }
+
+    pub fn processor_set_cancel_fee(ctx: &mut ProcessorContext, cancel_fee: u64) -> ProgramResult {
+        let admin = ctx.next_account()?;
+        let config_account = ctx.next_account()?;
+        let mut config = Self::require_admin(admin, config_account, ctx.program_id)?;
+
+        config.cancel_fee = cancel_fee;
+        EscrowConfig::pack(config, &mut config_account.try_borrow_mut_data()?)?;
+
+        Ok(())
+    }
+
+    pub fn processor_set_init_fee(ctx: &mut ProcessorContext, init_fee: u64) -> ProgramResult {
+        let admin = ctx.next_account()?;
+        let config_account = ctx.next_account()?;
+        let mut config = Self::require_admin(admin, config_account, ctx.program_id)?;
+
+        config.init_fee = init_fee;
+        EscrowConfig::pack(config, &mut config_account.try_borrow_mut_data()?)?;
+
+        Ok(())
+    }
+
+    pub fn processor_set_extension_fee(
+        ctx: &mut ProcessorContext,
+        extension_fee: u64,
+    ) -> ProgramResult {
+        let admin = ctx.next_account()?;
+        let config_account = ctx.next_account()?;
+        let mut config = Self::require_admin(admin, config_account, ctx.program_id)?;
+
+        config.extension_fee = extension_fee;
+        EscrowConfig::pack(config, &mut config_account.try_borrow_mut_data()?)?;
+
+        Ok(())
+    }
+
+    pub fn processor_set_exchange_fee(
+        ctx: &mut ProcessorContext,
+        exchange_fee_bps: u16,
+    ) -> ProgramResult {
+        let admin = ctx.next_account()?;
+        let config_account = ctx.next_account()?;
+        let mut config = Self::require_admin(admin, config_account, ctx.program_id)?;
+
+        // The fee is a share of the payment, it can't take more than the whole of it
+        if exchange_fee_bps > MAX_EXCHANGE_FEE_BPS {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        config.exchange_fee_bps = exchange_fee_bps;
+        EscrowConfig::pack(config, &mut config_account.try_borrow_mut_data()?)?;
+
+        Ok(())
+    }
+
+    pub fn processor_set_free_fills(ctx: &mut ProcessorContext, free_fills: u16) -> ProgramResult {
+        let admin = ctx.next_account()?;
+        let config_account = ctx.next_account()?;
+        let mut config = Self::require_admin(admin, config_account, ctx.program_id)?;
+
+        config.free_fills = free_fills;
+        EscrowConfig::pack(config, &mut config_account.try_borrow_mut_data()?)?;
+
+        Ok(())
+    }
+
+    pub fn processor_withdraw_fees(ctx: &mut ProcessorContext) -> ProgramResult {
+        let admin = ctx.next_account()?;
+        let config_account = ctx.next_account()?;
+        Self::require_admin(admin, config_account, ctx.program_id)?;
+
+        let fee_token_account = ctx.next_account()?;
+        require_owned_by_token_program(fee_token_account)?;
+        let fee_token_account_state = TokenAccount::unpack(&fee_token_account.try_borrow_data()?)?;
+        if fee_token_account_state.is_frozen() {
+            return Err(EscrowError::AccountFrozen.into());
+        }
+
+        let destination_token_account = ctx.next_account()?;
+        if destination_token_account.key == fee_token_account.key {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        // Only the fees collected by the vault of the account's mint can be withdrawn
+        let (fee_vault, fee_vault_bump) =
+            find_fee_vault_address(&fee_token_account_state.mint, ctx.program_id);
+        fee_token_account_state.require_authority(&fee_vault)?;
+
+        let token_program = ctx.next_account()?;
+        if *token_program.key != TOKEN_PROGRAM_ID {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let fee_vault_account = ctx.next_account()?;
+        if *fee_vault_account.key != fee_vault {
+            return Err(EscrowError::InvalidPda.into());
+        }
+
+        let ix_withdraw = spl_token::instruction::transfer(
+            token_program.key,
+            fee_token_account.key,
+            destination_token_account.key,
+            &fee_vault,
+            &[&fee_vault],
+            fee_token_account_state.amount,
+        )?;
+        verbose_msg!("Calling the token program to withdraw the fees...");
+        invoke_signed(
+            &ix_withdraw,
+            &[
+                token_program.clone(),
+                fee_token_account.clone(),
+                destination_token_account.clone(),
+                fee_vault_account.clone(),
+            ],
+            &[&[
+                FEE_VAULT_SEED,
+                fee_token_account_state.mint.as_ref(),
+                &[fee_vault_bump],
+            ]],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn processor_init_pair_registry(ctx: &mut ProcessorContext) -> ProgramResult {
+        let payer = ctx.next_account()?;
+
+        if !payer.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let admin = ctx.next_account()?;
+        let config_account = ctx.next_account()?;
+        Self::require_admin(admin, config_account, ctx.program_id)?;
+
+        let pair_registry_account = ctx.next_account()?;
+        let (pair_registry_address, pair_registry_bump_seed) =
+            find_pair_registry_address(ctx.program_id);
+        if *pair_registry_account.key != pair_registry_address {
+            return Err(ProgramError::InvalidSeeds);
+        }
+
+        if !pair_registry_account.data_is_empty() {
+            return Err(ProgramError::AccountAlreadyInitialized);
+        }
+
+        let system_program = ctx.next_account()?;
+
+        Self::create_pda_account(
+            payer,
+            pair_registry_account,
+            system_program,
+            PairRegistry::LEN,
+            &[PAIR_REGISTRY_SEED, &[pair_registry_bump_seed]],
+            ctx.program_id,
+        )?;
+
+        let pair_registry = PairRegistry {
+            is_initialized: true,
+            pairs_count: 0,
+            pairs: [[0; 32]; MAX_ALLOWED_PAIRS],
+        };
+        PairRegistry::pack(
+            pair_registry,
+            &mut pair_registry_account.try_borrow_mut_data()?,
+        )?;
+
+        Ok(())
+    }
+
+    pub fn processor_init_escrow_index(ctx: &mut ProcessorContext) -> ProgramResult {
+        let payer = ctx.next_account()?;
+
+        if !payer.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let admin = ctx.next_account()?;
+        let config_account = ctx.next_account()?;
+        Self::require_admin(admin, config_account, ctx.program_id)?;
+
+        let escrow_index_account = ctx.next_account()?;
+        let (escrow_index_address, escrow_index_bump_seed) =
+            find_escrow_index_address(ctx.program_id);
+        if *escrow_index_account.key != escrow_index_address {
+            return Err(ProgramError::InvalidSeeds);
+        }
+
+        if !escrow_index_account.data_is_empty() {
+            return Err(ProgramError::AccountAlreadyInitialized);
+        }
+
+        let system_program = ctx.next_account()?;
+
+        Self::create_pda_account(
+            payer,
+            escrow_index_account,
+            system_program,
+            EscrowIndex::LEN,
+            &[ESCROW_INDEX_SEED, &[escrow_index_bump_seed]],
+            ctx.program_id,
+        )?;
+
+        let escrow_index = EscrowIndex {
+            is_initialized: true,
+            escrows_count: 0,
+            escrows: [Pubkey::default(); MAX_INDEXED_ESCROWS],
+        };
+        EscrowIndex::pack(
+            escrow_index,
+            &mut escrow_index_account.try_borrow_mut_data()?,
+        )?;
+
+        Ok(())
+    }
+
+    pub fn processor_set_pair_allowed(
+        ctx: &mut ProcessorContext,
+        deposit_mint: &Pubkey,
+        expected_mint: &Pubkey,
+        allowed: bool,
+    ) -> ProgramResult {
+        let admin = ctx.next_account()?;
+        let config_account = ctx.next_account()?;
+        Self::require_admin(admin, config_account, ctx.program_id)?;
+
+        let pair_registry_account = ctx.next_account()?;
+        let mut pair_registry = Self::load_pair_registry(pair_registry_account, ctx.program_id)?
+            .ok_or(EscrowError::NotInitialized)?;
+
+        if allowed {
+            pair_registry.allow(deposit_mint, expected_mint)?;
+        } else {
+            pair_registry.disallow(deposit_mint, expected_mint);
+        }
+        PairRegistry::pack(
+            pair_registry,
+            &mut pair_registry_account.try_borrow_mut_data()?,
+        )?;
+
+        Ok(())
+    }
+
+    /// Fails unless the admin signed and is the one stored in the config, returning the config
+    fn require_admin(
+        admin: &AccountInfo,
+        config_account: &AccountInfo,
+        program_id: &Pubkey,
+    ) -> Result<EscrowConfig, ProgramError> {
+        if !admin.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let config =
+            Self::load_config(config_account, program_id)?.ok_or(EscrowError::NotInitialized)?;
+
+        if config.admin != *admin.key {
+            return Err(EscrowError::WrongAuthority.into());
+        }
+
+        Ok(config)
+    }
+
+    /// Loads the escrow index, or returns `None` when the escrow index account hasn't been created yet
+    fn load_escrow_index(
+        escrow_index_account: &AccountInfo,
+        program_id: &Pubkey,
+    ) -> Result<Option<EscrowIndex>, ProgramError> {
+        if *escrow_index_account.key != find_escrow_index_address(program_id).0 {
+            return Err(ProgramError::InvalidSeeds);
+        }
+
+        if escrow_index_account.data_is_empty() {
+            return Ok(None);
+        }
+
+        if escrow_index_account.owner != program_id {
+            return Err(EscrowError::WrongOwner.into());
+        }
+
+        Ok(Some(EscrowIndex::unpack(
+            &escrow_index_account.try_borrow_data()?,
+        )?))
+    }
+
+    /// Loads the pair registry, or returns `None` when the pair registry account hasn't been created yet
+    fn load_pair_registry(
+        pair_registry_account: &AccountInfo,
+        program_id: &Pubkey,
+    ) -> Result<Option<PairRegistry>, ProgramError> {
+        if *pair_registry_account.key != find_pair_registry_address(program_id).0 {
+            return Err(ProgramError::InvalidSeeds);
+        }
+
+        if pair_registry_account.data_is_empty() {
+            return Ok(None);
+        }
+
+        if pair_registry_account.owner != program_id {
+            return Err(EscrowError::WrongOwner.into());
+        }
+
+        Ok(Some(PairRegistry::unpack(
+            &pair_registry_account.try_borrow_data()?,
+        )?))
+    }
+
+    /// Loads the config, or returns `None` when the config account hasn't been created yet
+    fn load_config(
+        config_account: &AccountInfo,
+        program_id: &Pubkey,
+    ) -> Result<Option<EscrowConfig>, ProgramError> {
+        if *config_account.key != find_config_address(program_id).0 {
+            return Err(ProgramError::InvalidSeeds);
+        }
+
+        if config_account.data_is_empty() {
+            return Ok(None);
+        }
+
+        if config_account.owner != program_id {
+            return Err(EscrowError::WrongOwner.into());
+        }
+
+        Ok(Some(EscrowConfig::unpack(
+            &config_account.try_borrow_data()?,
+        )?))
+    }
+
+    /// Returns the namespace of the escrow the account holds, the default namespace for an account that
+    /// doesn't hold an initialized escrow of this program yet
+    fn escrow_namespace(
+        escrow_account: &AccountInfo,
+        program_id: &Pubkey,
+    ) -> Result<[u8; NAMESPACE_LEN], ProgramError> {
+        if escrow_account.owner != program_id || escrow_account.data_len() != Escrow::LEN {
+            return Ok(DEFAULT_NAMESPACE);
+        }
+
+        let escrow_info = Escrow::unpack_unchecked(&escrow_account.try_borrow_data()?)?;
+        Ok(if escrow_info.is_initialized() {
+            escrow_info.namespace
+        } else {
+            DEFAULT_NAMESPACE
+        })
+    }
+
+    /// Reads the settlement authority account of an escrow that has one and checks it signed the exchange
+    fn require_settlement_authority(
+        ctx: &mut ProcessorContext,
+        escrow_info: &Escrow,
+    ) -> ProgramResult {
+        if escrow_info.settlement_authority == Pubkey::default() {
+            return Ok(());
+        }
+
+        let settlement_authority = ctx.next_account()?;
+        if *settlement_authority.key != escrow_info.settlement_authority {
+            return Err(EscrowError::WrongAuthority.into());
+        }
+        if !settlement_authority.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        Ok(())
+    }
+
+    /// Recreates the escrow's PDA from its stored bump. A stored bump that doesn't give the authority of the
+    /// escrow's token account falls back to the canonical bump, failing with `InvalidPda` if that doesn't either.
+    /// A delegated deposit has neither as its delegate once the initializer revoked the delegation, that fails with
+    /// `DelegationRevoked`
+    fn stored_pda(
+        program_id: &Pubkey,
+        escrow_key: &Pubkey,
+        escrow_info: &Escrow,
+        token_account_state: &TokenAccount,
+    ) -> Result<PdaInfo, ProgramError> {
+        let is_authority = |pda: &PdaInfo| {
+            if escrow_info.is_delegated {
+                token_account_state.delegate == COption::Some(pda.key)
+            } else {
+                token_account_state.owner == pda.key
+            }
+        };
+
+        if let Ok(pda) = PdaInfo::from_bump(
+            program_id,
+            &escrow_info.namespace,
+            escrow_key,
+            escrow_info.pda_bump,
+        ) {
+            if is_authority(&pda) {
+                return Ok(pda);
+            }
+        }
+
+        let pda = PdaInfo::find_in_namespace(program_id, &escrow_info.namespace, escrow_key);
+        if !is_authority(&pda) {
+            if escrow_info.is_delegated {
+                return Err(EscrowError::DelegationRevoked.into());
+            }
+            return Err(EscrowError::InvalidPda.into());
+        }
+        msg!("Warning: the stored PDA bump seed isn't the escrow's, falling back to the canonical one");
+        Ok(pda)
+    }
+
+    /// Returns the amount of deposited tokens the PDA can move out of the escrow's token account,
+    /// the balance of the temp token account it owns or what is left of its delegation over the initializer's token account.
+    /// The deposit is read live from the token account every time and never cached in the escrow state, this is the
+    /// authoritative value: tokens sent to the temp token account after init are part of what the takers receive
+    fn deposit_balance(
+        escrow_info: &Escrow,
+        token_account_state: &TokenAccount,
+        pda: &PdaInfo,
+    ) -> Result<u64, ProgramError> {
+        if !escrow_info.is_delegated {
+            token_account_state.require_authority(&pda.key)?;
+            return Ok(token_account_state.amount);
+        }
+
+        let delegated_amount = token_account_state.delegated_amount_to(&pda.key);
+        if delegated_amount == 0 {
+            return Err(EscrowError::DelegationRevoked.into());
+        }
+
+        // The initializer keeps custody, so the balance may have dropped below the delegation
+        Ok(delegated_amount.min(token_account_state.amount))
+    }
+
+    /// Loads the stats after checking the stats account is the program's stats PDA
+    /// Reads an initialized template, which only the program can have written
+    fn load_template(
+        template_account: &AccountInfo,
+        program_id: &Pubkey,
+    ) -> Result<EscrowTemplate, ProgramError> {
+        if template_account.owner != program_id {
+            return Err(EscrowError::WrongOwner.into());
+        }
+
+        EscrowTemplate::unpack(&template_account.try_borrow_data()?)
+    }
+
+    fn load_stats(
+        stats_account: &AccountInfo,
+        program_id: &Pubkey,
+    ) -> Result<EscrowStats, ProgramError> {
+        if *stats_account.key != find_stats_address(program_id).0 {
+            return Err(ProgramError::InvalidSeeds);
+        }
+
+        if stats_account.owner != program_id {
+            return Err(EscrowError::WrongOwner.into());
+        }
+
+        EscrowStats::unpack(&stats_account.try_borrow_data()?)
+    }
+
+    fn update_stats<F>(stats_account: &AccountInfo, program_id: &Pubkey, update: F) -> ProgramResult
+    where
+        F: FnOnce(&mut EscrowStats) -> Result<(), EscrowError>,
+    {
+        let mut stats = Self::load_stats(stats_account, program_id)?;
+        update(&mut stats)?;
+        EscrowStats::pack(stats, &mut stats_account.try_borrow_mut_data()?)?;
+
+        Ok(())
+    }
+
+    fn create_pda_account<'a>(
+        payer: &AccountInfo<'a>,
+        pda_account: &AccountInfo<'a>,
+        system_program: &AccountInfo<'a>,
+        space: usize,
+        signer_seeds: &[&[u8]],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let ix_create_pda_account = system_instruction::create_account(
+            payer.key,
+            pda_account.key,
+            Rent::get()?.minimum_balance(space),
+            space as u64,
+            program_id,
+        );
+        invoke_signed(
+            &ix_create_pda_account,
+            &[payer.clone(), pda_account.clone(), system_program.clone()],
+            &[signer_seeds],
+        )
+    }
+
+    /// Returns the fee the config charges, checking the treasury account is the config's when the fee isn't zero.
+    /// Nothing is charged while the config account hasn't been created
+    fn treasury_fee<F>(
+        config_account: &AccountInfo,
+        treasury_account: &AccountInfo,
+        program_id: &Pubkey,
+        fee: F,
+    ) -> Result<u64, ProgramError>
+    where
+        F: FnOnce(&EscrowConfig) -> u64,
+    {
+        let config = match Self::load_config(config_account, program_id)? {
+            Some(config) => config,
+            None => return Ok(0),
+        };
+
+        let fee = fee(&config);
+        if fee != 0 && config.treasury != *treasury_account.key {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(fee)
+    }
+
+    /// Moves a lamport fee out of an account owned by the program into the treasury,
+    /// taking whatever is left when the account holds less than the fee
+    fn charge_fee(
+        program_account: &AccountInfo,
+        treasury_account: &AccountInfo,
+        fee: u64,
+    ) -> ProgramResult {
+        if fee == 0 {
+            return Ok(());
+        }
+
+        if treasury_account.key == program_account.key {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let fee = fee.min(program_account.lamports());
+        **treasury_account.lamports.borrow_mut() = math::add(treasury_account.lamports(), fee)?;
+        **program_account.lamports.borrow_mut() = math::sub(program_account.lamports(), fee)?;
+
+        Ok(())
+    }
+
+    fn close_escrow_account(
+        escrow_account: &AccountInfo,
+        destination_account: &AccountInfo,
+    ) -> ProgramResult {
+        // Sending the lamports to the escrow account itself would zero them right after
+        if destination_account.key == escrow_account.key {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let escrow_lamports_before = escrow_account.lamports();
+        let destination_lamports_before = destination_account.lamports();
+        let total_before = math::add(escrow_lamports_before, destination_lamports_before)?;
+
+        // gave back the lamports that was used for renting the escrow account space
+        **destination_account.lamports.borrow_mut() =
+            math::add(destination_lamports_before, escrow_lamports_before)?;
+        // always clear the value inside the account after closing the account, stale reads then see an uninitialized escrow
+        **escrow_account.lamports.borrow_mut() = 0;
+        Escrow::pack(
+            Escrow::UNINITIALIZED,
+            &mut escrow_account.try_borrow_mut_data()?,
+        )?;
+
+        // The destination only ever gains lamports so it stays rent exempt, what has to hold is that none were lost or made up
+        let total_after = math::add(escrow_account.lamports(), destination_account.lamports())?;
+        if escrow_account.lamports() != 0 || total_after != total_before {
+            msg!(
+                "Closing the escrow account changed the lamports of both accounts from {} to {}",
+                total_before,
+                total_after
+            );
+            return Err(EscrowError::AmountOverflow.into());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{convert::TryInto, str::FromStr};
+
+    fn program_id() -> Pubkey {
+        Pubkey::new_from_array([7; 32])
+    }
+
+    fn escrow_key() -> Pubkey {
+        Pubkey::new_from_array([1; 32])
+    }
+
+    fn pda() -> PdaInfo {
+        PdaInfo::find(&program_id(), &escrow_key())
+    }
+
+    thread_local! {
+        static RETURN_DATA: std::cell::RefCell<Option<(Pubkey, Vec<u8>)>> =
+            const { std::cell::RefCell::new(None) };
+        static LOGS: std::cell::RefCell<Vec<String>> = const { std::cell::RefCell::new(vec![]) };
+        static CPIS: std::cell::RefCell<Vec<RecordedCpi>> = const { std::cell::RefCell::new(vec![]) };
+        static CLOCK: std::cell::RefCell<Option<Clock>> = const { std::cell::RefCell::new(None) };
+    }
+
+    /// A CPI a handler made, with the keys of the accounts it passed and the PDAs its seeds sign for
+    struct RecordedCpi {
+        instruction: Instruction,
+        account_keys: Vec<Pubkey>,
+        signers: Vec<Pubkey>,
+    }
+
+    /// Syscall stubs keeping the return data, the logs and the CPIs of a handler, the default ones drop the
+    /// return data and the CPIs and print the logs. The clock sysvar call only succeeds once a test sets
+    /// the clock. The stubs are shared by every test thread, so all of it is kept per thread
+    struct RecordingStubs;
+
+    impl solana_program::program_stubs::SyscallStubs for RecordingStubs {
+        fn sol_log(&self, message: &str) {
+            LOGS.with(|logs| logs.borrow_mut().push(message.to_string()));
+        }
+
+        fn sol_invoke_signed(
+            &self,
+            instruction: &Instruction,
+            account_infos: &[AccountInfo],
+            signers_seeds: &[&[&[u8]]],
+        ) -> ProgramResult {
+            let signers = signers_seeds
+                .iter()
+                .map(|seeds| Pubkey::create_program_address(seeds, &program_id()))
+                .collect::<Result<_, _>>()?;
+            CPIS.with(|cpis| {
+                cpis.borrow_mut().push(RecordedCpi {
+                    instruction: instruction.clone(),
+                    account_keys: account_infos.iter().map(|account| *account.key).collect(),
+                    signers,
+                })
+            });
+            Ok(())
+        }
+
+        fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+            match CLOCK.with(|clock| clock.borrow().clone()) {
+                Some(clock) => {
+                    unsafe { *(var_addr as *mut Clock) = clock };
+                    solana_program::entrypoint::SUCCESS
+                }
+                None => solana_program::program_error::UNSUPPORTED_SYSVAR,
+            }
+        }
+
+        fn sol_get_return_data(&self) -> Option<(Pubkey, Vec<u8>)> {
+            RETURN_DATA.with(|return_data| return_data.borrow().clone())
+        }
+
+        fn sol_set_return_data(&mut self, data: &[u8]) {
+            RETURN_DATA.with(|return_data| {
+                *return_data.borrow_mut() = Some((program_id(), data.to_vec()))
+            });
+        }
+    }
+
+    /// Installs the `RecordingStubs` and clears what the thread recorded so far
+    fn start_recording() {
+        static INSTALL_STUBS: std::sync::Once = std::sync::Once::new();
+        INSTALL_STUBS.call_once(|| {
+            solana_program::program_stubs::set_syscall_stubs(Box::new(RecordingStubs));
+        });
+        RETURN_DATA.with(|return_data| *return_data.borrow_mut() = None);
+        LOGS.with(|logs| logs.borrow_mut().clear());
+        CPIS.with(|cpis| cpis.borrow_mut().clear());
+        CLOCK.with(|clock| *clock.borrow_mut() = None);
+    }
+
+    fn recorded_logs() -> Vec<String> {
+        LOGS.with(|logs| logs.borrow().clone())
+    }
+
+    /// An account of a unit test, lent to the handler under test as an `AccountInfo`
+    struct TestAccount {
+        key: Pubkey,
+        is_signer: bool,
+        is_writable: bool,
+        lamports: u64,
+        data: Vec<u8>,
+        owner: Pubkey,
+    }
+
+    impl TestAccount {
+        fn new(key: Pubkey, owner: Pubkey, data: Vec<u8>) -> Self {
+            Self {
+                key,
+                is_signer: false,
+                is_writable: false,
+                lamports: Rent::default().minimum_balance(data.len()),
+                data,
+                owner,
+            }
+        }
+
+        /// An escrow account of the program holding the packed escrow
+        fn escrow(key: Pubkey, escrow_info: Escrow) -> Self {
+            let mut data = vec![0; Escrow::LEN];
+            Escrow::pack(escrow_info, &mut data).unwrap();
+            Self::new(key, program_id(), data)
+        }
+
+        /// A token account of the token program holding the packed account state
+        fn token_account(key: Pubkey, token_account_state: TokenAccount) -> Self {
+            let mut data = vec![0; TokenAccount::LEN];
+            TokenAccount::pack(token_account_state, &mut data).unwrap();
+            Self::new(key, TOKEN_PROGRAM_ID, data)
+        }
+
+        /// An initialized token account holding `amount` tokens of the mint
+        fn holding(key: Pubkey, mint: Pubkey, owner: Pubkey, amount: u64) -> Self {
+            Self::token_account(
+                key,
+                TokenAccount {
+                    mint,
+                    owner,
+                    amount,
+                    state: spl_token::state::AccountState::Initialized,
+                    ..TokenAccount::default()
+                },
+            )
+        }
+
+        /// A mint of the token program with the given decimals
+        fn mint(key: Pubkey, decimals: u8) -> Self {
+            let mut data = vec![0; Mint::LEN];
+            Mint::pack(
+                Mint {
+                    decimals,
+                    is_initialized: true,
+                    ..Mint::default()
+                },
+                &mut data,
+            )
+            .unwrap();
+            Self::new(key, TOKEN_PROGRAM_ID, data)
+        }
+
+        /// The clock sysvar account the handlers fall back to, the test runtime has no clock sysvar call
+        fn clock(clock: &Clock) -> Self {
+            let mut account =
+                Self::new(sysvar::clock::id(), sysvar::id(), vec![0; Clock::size_of()]);
+            clock.to_account_info(&mut account.info()).unwrap();
+            account
+        }
+
+        /// The rent sysvar account `InitEscrow` reads the rent from
+        fn rent() -> Self {
+            let mut account = Self::new(sysvar::rent::id(), sysvar::id(), vec![0; Rent::size_of()]);
+            Rent::default()
+                .to_account_info(&mut account.info())
+                .unwrap();
+            account
+        }
+
+        fn info(&mut self) -> AccountInfo<'_> {
+            AccountInfo::new(
+                &self.key,
+                self.is_signer,
+                self.is_writable,
+                &mut self.lamports,
+                &mut self.data,
+                &self.owner,
+                false,
+                0,
+            )
+        }
+    }
+
+    /// Processes the instruction and returns the return data it set
+    fn process_return_data(
+        accounts: &mut [TestAccount],
+        instruction: EscrowInstruction,
+    ) -> Result<Vec<u8>, ProgramError> {
+        start_recording();
+        let account_infos: Vec<AccountInfo> = accounts.iter_mut().map(TestAccount::info).collect();
+        Processor::processor(&program_id(), &account_infos, &instruction.pack())?;
+        let (return_program_id, return_data) = solana_program::program::get_return_data().unwrap();
+        assert_eq!(return_program_id, program_id());
+        Ok(return_data)
+    }
+
+    #[test]
+    fn test_derive_authority_returns_the_pda_and_its_bump() {
+        let authority_data = |pda: &PdaInfo| {
+            let mut authority_data = pda.key.to_bytes().to_vec();
+            authority_data.push(pda.bump);
+            authority_data
+        };
+
+        // An escrow account that isn't initialized yet gets the authority of the default namespace
+        let mut accounts = [TestAccount::new(escrow_key(), system_program::id(), vec![])];
+        assert_eq!(
+            process_return_data(&mut accounts, EscrowInstruction::DeriveAuthority),
+            Ok(authority_data(&pda()))
+        );
+
+        let namespace = *b"namespace-a\0\0\0\0\0";
+        let mut accounts = [TestAccount::escrow(
+            escrow_key(),
+            Escrow {
+                is_initialized: true,
+                namespace,
+                ..Escrow::UNINITIALIZED
+            },
+        )];
+        assert_eq!(
+            process_return_data(&mut accounts, EscrowInstruction::DeriveAuthority),
+            Ok(authority_data(&PdaInfo::find_in_namespace(
+                &program_id(),
+                &namespace,
+                &escrow_key()
+            )))
+        );
+    }
+
+    #[test]
+    fn test_check_fillable_returns_the_fill_status() {
+        let pda_token_account = Pubkey::new_from_array([2; 32]);
+        let initializer_token_to_receive_account = Pubkey::new_from_array([3; 32]);
+        let expected_mint = Pubkey::new_from_array([5; 32]);
+        let taker = Pubkey::new_from_array([6; 32]);
+        let escrow_info = Escrow {
+            is_initialized: true,
+            temp_token_account_pubkey: pda_token_account,
+            initializer_token_to_receive_account_pubkey: initializer_token_to_receive_account,
+            taker_slots: 2,
+            expiry_timestamp: 100,
+            ..Escrow::UNINITIALIZED
+        };
+        let check_fillable =
+            |escrow_info: &Escrow, amount: u64, taker: Option<Pubkey>, unix_timestamp| {
+                let mut accounts = vec![
+                    TestAccount::escrow(escrow_key(), escrow_info.clone()),
+                    TestAccount::token_account(
+                        pda_token_account,
+                        TokenAccount {
+                            owner: pda().key,
+                            amount,
+                            state: spl_token::state::AccountState::Initialized,
+                            ..TokenAccount::default()
+                        },
+                    ),
+                    // Without a config the program's defaults apply
+                    TestAccount::new(
+                        find_config_address(&program_id()).0,
+                        system_program::id(),
+                        vec![],
+                    ),
+                    TestAccount::token_account(
+                        initializer_token_to_receive_account,
+                        TokenAccount {
+                            mint: expected_mint,
+                            state: spl_token::state::AccountState::Initialized,
+                            ..TokenAccount::default()
+                        },
+                    ),
+                    TestAccount::clock(&Clock {
+                        unix_timestamp,
+                        ..Clock::default()
+                    }),
+                ];
+                if let Some(taker) = taker {
+                    accounts.insert(4, TestAccount::new(taker, system_program::id(), vec![]));
+                }
+                process_return_data(&mut accounts, EscrowInstruction::CheckFillable)
+            };
+
+        assert_eq!(
+            check_fillable(&escrow_info, 1_000, Some(taker), 50),
+            Ok(vec![FillStatus::Fillable as u8])
+        );
+        assert_eq!(
+            check_fillable(&escrow_info, 1_000, Some(taker), 100),
+            Ok(vec![FillStatus::Expired as u8])
+        );
+        assert_eq!(
+            check_fillable(&escrow_info, 0, Some(taker), 50),
+            Ok(vec![FillStatus::Empty as u8])
+        );
+
+        let mut filled_escrow_info = escrow_info.clone();
+        filled_escrow_info.filled_takers[0] = taker;
+        filled_escrow_info.filled_takers_count = 1;
+        assert_eq!(
+            check_fillable(&filled_escrow_info, 500, Some(taker), 50),
+            Ok(vec![FillStatus::AlreadyFilled as u8])
+        );
+
+        // A reservation keeps out anyone when no taker is given
+        let reserved_escrow_info = Escrow {
+            reserved_taker: taker,
+            reserved_until_slot: 10,
+            ..escrow_info.clone()
+        };
+        assert_eq!(
+            check_fillable(&reserved_escrow_info, 1_000, None, 50),
+            Ok(vec![FillStatus::Reserved as u8])
+        );
+        assert_eq!(
+            check_fillable(&reserved_escrow_info, 1_000, Some(taker), 50),
+            Ok(vec![FillStatus::Fillable as u8])
+        );
+    }
+
+    #[test]
+    fn test_get_escrow_batch_returns_a_record_per_escrow() {
+        let escrow_info = |nonce| Escrow {
+            is_initialized: true,
+            initializer_pubkey: Pubkey::new_from_array([2; 32]),
+            expected_amount: 500,
+            nonce,
+            taker_slots: 1,
+            ..Escrow::UNINITIALIZED
+        };
+        let escrow_account = |index: u8, nonce| {
+            TestAccount::escrow(Pubkey::new_from_array([20 + index; 32]), escrow_info(nonce))
+        };
+        let record = |escrow_info: Escrow| {
+            let mut escrow_data = [0; Escrow::LEN];
+            Escrow::pack(escrow_info, &mut escrow_data).unwrap();
+            let mut record = vec![1];
+            record.extend_from_slice(&escrow_data[..Escrow::BATCH_RECORD_LEN]);
+            record
+        };
+
+        // A zeroed or emptied escrow account is flagged as closed
+        let mut accounts = [
+            escrow_account(0, 1),
+            TestAccount::new(
+                Pubkey::new_from_array([21; 32]),
+                program_id(),
+                vec![0; Escrow::LEN],
+            ),
+            TestAccount::new(Pubkey::new_from_array([22; 32]), program_id(), vec![]),
+            escrow_account(3, 2),
+        ];
+        let mut expected_data = record(escrow_info(1));
+        expected_data.extend_from_slice(&[0, 0]);
+        expected_data.extend_from_slice(&record(escrow_info(2)));
+        assert_eq!(
+            process_return_data(&mut accounts, EscrowInstruction::GetEscrowBatch),
+            Ok(expected_data)
+        );
+
+        // A full batch is the most `MAX_RETURN_DATA` holds, so the return data limit can't be hit below `MAX_BATCH`
+        let mut accounts: Vec<TestAccount> = (0..MAX_BATCH as u8)
+            .map(|index| escrow_account(index, index as u64))
+            .collect();
+        let batch_data =
+            process_return_data(&mut accounts, EscrowInstruction::GetEscrowBatch).unwrap();
+        assert_eq!(batch_data.len(), MAX_BATCH * (Escrow::BATCH_RECORD_LEN + 1));
+        assert!(batch_data.len() <= MAX_RETURN_DATA);
+
+        accounts.push(escrow_account(MAX_BATCH as u8, 0));
+        assert_eq!(
+            process_return_data(&mut accounts, EscrowInstruction::GetEscrowBatch),
+            Err(EscrowError::BatchTooLarge.into())
+        );
+
+        let mut accounts = [
+            escrow_account(0, 1),
+            TestAccount::new(
+                Pubkey::new_from_array([21; 32]),
+                system_program::id(),
+                vec![],
+            ),
+        ];
+        assert_eq!(
+            process_return_data(&mut accounts, EscrowInstruction::GetEscrowBatch),
+            Err(EscrowError::WrongOwner.into())
+        );
+    }
+
+    #[test]
+    fn test_version_returns_the_program_version() {
+        let package_version: Vec<u8> = env!("CARGO_PKG_VERSION")
+            .split('.')
+            .map(|part| part.parse().unwrap())
+            .collect();
+        assert_eq!(
+            process_return_data(&mut [], EscrowInstruction::Version),
+            Ok(package_version)
+        );
+    }
+
+    #[test]
+    fn test_time_remaining_returns_the_time_until_expiry() {
+        let clock = Clock {
+            slot: 40,
+            unix_timestamp: 1_000,
+            ..Clock::default()
+        };
+        let time_remaining = |escrow_info: Escrow| {
+            let mut accounts = [
+                TestAccount::escrow(escrow_key(), escrow_info),
+                TestAccount::clock(&clock),
+            ];
+            process_return_data(&mut accounts, EscrowInstruction::TimeRemaining)
+                .map(|data| i64::from_le_bytes(data.as_slice().try_into().unwrap()))
+        };
+        let escrow_info = Escrow {
+            is_initialized: true,
+            taker_slots: 1,
+            ..Escrow::UNINITIALIZED
+        };
+
+        // An escrow without an expiry never runs out of time
+        assert_eq!(time_remaining(escrow_info.clone()), Ok(-1));
+        assert_eq!(
+            time_remaining(Escrow {
+                use_slot_expiry: true,
+                ..escrow_info.clone()
+            }),
+            Ok(-1)
+        );
+
+        assert_eq!(
+            time_remaining(Escrow {
+                expiry_timestamp: 1_250,
+                ..escrow_info.clone()
+            }),
+            Ok(250)
+        );
+        assert_eq!(
+            time_remaining(Escrow {
+                use_slot_expiry: true,
+                expiry_slot: 100,
+                ..escrow_info.clone()
+            }),
+            Ok(60)
+        );
+
+        // An expired escrow has no time left rather than a negative one
+        assert_eq!(
+            time_remaining(Escrow {
+                expiry_timestamp: 900,
+                ..escrow_info.clone()
+            }),
+            Ok(0)
+        );
+        assert_eq!(
+            time_remaining(Escrow {
+                use_slot_expiry: true,
+                expiry_slot: 30,
+                ..escrow_info.clone()
+            }),
+            Ok(0)
+        );
+
+        // Without the clock sysvar account there is no clock to count from
+        let mut accounts = [TestAccount::escrow(escrow_key(), escrow_info)];
+        assert_eq!(
+            process_return_data(&mut accounts, EscrowInstruction::TimeRemaining),
+            Err(EscrowError::MissingClock.into())
+        );
+    }
+
+    #[test]
+    fn test_get_status_returns_the_escrow_status() {
+        let clock = Clock {
+            unix_timestamp: 1_000,
+            ..Clock::default()
+        };
+        let get_status = |escrow_account: TestAccount| {
+            let mut accounts = [escrow_account, TestAccount::clock(&clock)];
+            process_return_data(&mut accounts, EscrowInstruction::GetStatus)
+        };
+        let escrow_info = Escrow {
+            is_initialized: true,
+            taker_slots: 2,
+            expiry_timestamp: 1_500,
+            ..Escrow::UNINITIALIZED
+        };
+
+        assert_eq!(
+            get_status(TestAccount::escrow(escrow_key(), escrow_info.clone())),
+            Ok(vec![Status::Open as u8])
+        );
+        assert_eq!(
+            get_status(TestAccount::escrow(
+                escrow_key(),
+                Escrow {
+                    filled_takers_count: 1,
+                    ..escrow_info.clone()
+                }
+            )),
+            Ok(vec![Status::PartiallyFilled as u8])
+        );
+        assert_eq!(
+            get_status(TestAccount::escrow(
+                escrow_key(),
+                Escrow {
+                    expiry_timestamp: 1_000,
+                    ..escrow_info.clone()
+                }
+            )),
+            Ok(vec![Status::Expired as u8])
+        );
+
+        // A closed escrow account is zeroed, emptied or handed back to the system program
+        for closed_account in [
+            TestAccount::new(escrow_key(), program_id(), vec![0; Escrow::LEN]),
+            TestAccount::new(escrow_key(), program_id(), vec![]),
+            TestAccount::new(escrow_key(), system_program::id(), vec![]),
+        ] {
+            assert_eq!(get_status(closed_account), Ok(vec![Status::Closed as u8]));
+        }
+
+        let mut foreign_account = TestAccount::escrow(escrow_key(), escrow_info);
+        foreign_account.owner = system_program::id();
+        assert_eq!(
+            get_status(foreign_account),
+            Err(EscrowError::WrongOwner.into())
+        );
+    }
+
+    #[test]
+    fn test_clock_falls_back_to_a_clock_sysvar_account() {
+        // The unit tests have no sysvar call, as on a runtime without it
+        let clock = Clock {
+            slot: 40,
+            unix_timestamp: 1_000,
+            ..Clock::default()
+        };
+        let mut accounts = [
+            TestAccount::new(escrow_key(), program_id(), vec![]),
+            TestAccount::clock(&clock),
+        ];
+        let program_id = program_id();
+        let account_infos: Vec<AccountInfo> = accounts.iter_mut().map(TestAccount::info).collect();
+        let mut ctx = ProcessorContext::new(&program_id, &account_infos);
+        assert_eq!(ctx.clock(), Ok(clock.clone()));
+
+        // The clock is read once, a handler asking again gets the same one
+        account_infos[1].try_borrow_mut_data().unwrap().fill(0);
+        assert_eq!(ctx.clock(), Ok(clock));
+
+        let mut ctx = ProcessorContext::new(&program_id, &account_infos[..1]);
+        assert_eq!(ctx.clock(), Err(EscrowError::MissingClock.into()));
+    }
+
+    #[test]
+    fn test_account_validation_errors_log_their_context() {
+        let program_id = program_id();
+        let escrow_key = escrow_key();
+        let exchange = EscrowInstruction::Exchange {
+            amount: 1_000,
+            payment: 0,
+            taker_deadline_slot: 0,
+            preimage: None,
+        }
+        .pack();
+
+        // An Exchange of a closed escrow account, the taker signs and every other account is left empty
+        let mut accounts: Vec<TestAccount> = (0..15)
+            .map(|_| TestAccount::new(Pubkey::new_unique(), system_program::id(), vec![]))
+            .collect();
+        accounts[EXCHANGE_TAKER_IDX].is_signer = true;
+        accounts[EXCHANGE_ESCROW_IDX] = TestAccount::new(escrow_key, program_id, vec![]);
+        let account_infos: Vec<AccountInfo> = accounts.iter_mut().map(TestAccount::info).collect();
+
+        start_recording();
+        assert_eq!(
+            Processor::processor(&program_id, &account_infos, &exchange),
+            Err(EscrowError::NotInitialized.into())
+        );
+        assert_eq!(
+            recorded_logs(),
+            [
+                "Instruction: Exchange",
+                "Escrow account is closed, the escrow was already settled"
+            ]
+        );
+
+        start_recording();
+        assert_eq!(
+            Processor::processor(&program_id, &account_infos[..3], &exchange),
+            Err(ProgramError::NotEnoughAccountKeys)
+        );
+        assert_eq!(
+            recorded_logs(),
+            ["Instruction expects at least 15 accounts but got 3"]
+        );
+
+        // An account read by its position names its role when it is missing
+        start_recording();
+        let ctx = ProcessorContext::new(&program_id, &account_infos);
+        assert_eq!(
+            ctx.account(15, "stats").unwrap_err(),
+            ProgramError::NotEnoughAccountKeys
+        );
+        assert_eq!(recorded_logs(), ["Missing the stats account at index 15"]);
+    }
+
+    /// The position of the escrow account among the accounts of `InitEscrow`
+    const INIT_ESCROW_ESCROW_IDX: usize = 3;
+
+    /// The accounts of an `InitEscrow` of nonce 0 depositing 1000 tokens, in the order the instruction expects them
+    fn init_escrow_accounts() -> Vec<TestAccount> {
+        let initializer = Pubkey::new_from_array([2; 32]);
+        let deposit_mint = Pubkey::new_from_array([6; 32]);
+        let expected_mint = Pubkey::new_from_array([8; 32]);
+        let escrow = find_escrow_address(&initializer, 0, &program_id()).0;
+
+        let mut accounts = vec![
+            TestAccount::new(initializer, system_program::id(), vec![]),
+            TestAccount::holding(
+                Pubkey::new_from_array([4; 32]),
+                deposit_mint,
+                initializer,
+                1_000,
+            ),
+            TestAccount::holding(
+                Pubkey::new_from_array([5; 32]),
+                expected_mint,
+                initializer,
+                0,
+            ),
+            TestAccount::new(escrow, program_id(), vec![0; Escrow::LEN]),
+            TestAccount::rent(),
+            TestAccount::new(TOKEN_PROGRAM_ID, sysvar::id(), vec![]),
+            TestAccount::new(
+                find_pair_registry_address(&program_id()).0,
+                system_program::id(),
+                vec![],
+            ),
+            TestAccount::new(
+                find_config_address(&program_id()).0,
+                system_program::id(),
+                vec![],
+            ),
+            TestAccount::new(initializer, system_program::id(), vec![]),
+            TestAccount::new(system_program::id(), sysvar::id(), vec![]),
+            TestAccount::new(
+                find_escrow_index_address(&program_id()).0,
+                system_program::id(),
+                vec![],
+            ),
+        ];
+        accounts[0].is_signer = true;
+        for account in &mut accounts[..4] {
+            account.is_writable = true;
+        }
+        accounts
+    }
+
+    /// Processes an `InitEscrow` expecting 500 tokens
+    fn process_init_escrow(accounts: &mut [TestAccount]) -> ProgramResult {
+        start_recording();
+        CLOCK.with(|clock| *clock.borrow_mut() = Some(Clock::default()));
+        let init_escrow = EscrowInstruction::InitEscrow {
+            args: InitEscrowArgs {
+                amount: 500,
+                taker_slots: 1,
+                ..InitEscrowArgs::default()
+            },
+        };
+        let account_infos: Vec<AccountInfo> = accounts.iter_mut().map(TestAccount::info).collect();
+        Processor::processor(&program_id(), &account_infos, &init_escrow.pack())
+    }
+
+    #[test]
+    fn test_init_escrow_logs_its_parameters() {
+        let mut accounts = init_escrow_accounts();
+        process_init_escrow(&mut accounts).unwrap();
+
+        let pda = PdaInfo::find(&program_id(), &accounts[INIT_ESCROW_ESCROW_IDX].key);
+        let expected_log = format!(
+            "Instruction: InitEscrow initializer={} temp_token_account={} token_to_receive_account={} expected_amount=500 pda={}",
+            accounts[0].key, accounts[1].key, accounts[2].key, pda.key
+        );
+        // The event is the first line, only the verbose feature logs the CPIs after it
+        assert_eq!(recorded_logs().first(), Some(&expected_log));
+    }
+
+    #[test]
+    fn test_init_escrow_logs_the_rent_shortfall() {
+        let mut accounts = init_escrow_accounts();
+        let minimum_balance = Rent::default().minimum_balance(Escrow::LEN);
+        accounts[INIT_ESCROW_ESCROW_IDX].lamports = minimum_balance - 100;
+        assert_eq!(
+            process_init_escrow(&mut accounts),
+            Err(EscrowError::NotRentExempt.into())
+        );
+        assert_eq!(
+            recorded_logs(),
+            [format!(
+                "Escrow account needs {} lamports to be rent exempt but holds {}, 100 short",
+                minimum_balance,
+                minimum_balance - 100
+            )]
+        );
+    }
+
+    /// The escrow of the unit test `Exchange`s, offering 1000 deposit tokens for 500 expected tokens
+    fn exchange_escrow() -> Escrow {
+        let initializer = Pubkey::new_from_array([3; 32]);
+        Escrow {
+            is_initialized: true,
+            initializer_pubkey: initializer,
+            temp_token_account_pubkey: Pubkey::new_from_array([4; 32]),
+            initializer_token_to_receive_account_pubkey: Pubkey::new_from_array([5; 32]),
+            expected_amount: 500,
+            taker_slots: 1,
+            pda_bump: pda().bump,
+            deposit_mint: Pubkey::new_from_array([6; 32]),
+            rent_refund_recipient: initializer,
+            ..Escrow::UNINITIALIZED
+        }
+    }
+
+    /// The accounts of an `Exchange` of the escrow with mints of the given decimals, in the order the instruction expects them
+    fn exchange_accounts(
+        escrow_info: Escrow,
+        deposit_decimals: u8,
+        expected_decimals: u8,
+    ) -> Vec<TestAccount> {
+        let taker = Pubkey::new_from_array([2; 32]);
+        let expected_mint = Pubkey::new_from_array([8; 32]);
+        let mut accounts = vec![
+            TestAccount::new(taker, system_program::id(), vec![]),
+            TestAccount::holding(Pubkey::new_from_array([9; 32]), expected_mint, taker, 500),
+            TestAccount::holding(
+                Pubkey::new_from_array([10; 32]),
+                escrow_info.deposit_mint,
+                taker,
+                0,
+            ),
+            TestAccount::holding(
+                escrow_info.temp_token_account_pubkey,
+                escrow_info.deposit_mint,
+                pda().key,
+                1_000,
+            ),
+            TestAccount::new(escrow_info.initializer_pubkey, system_program::id(), vec![]),
+            TestAccount::holding(
+                escrow_info.initializer_token_to_receive_account_pubkey,
+                expected_mint,
+                escrow_info.initializer_pubkey,
+                0,
+            ),
+            TestAccount::escrow(escrow_key(), escrow_info.clone()),
+            TestAccount::new(TOKEN_PROGRAM_ID, sysvar::id(), vec![]),
+            TestAccount::new(pda().key, system_program::id(), vec![]),
+            TestAccount::new(
+                find_config_address(&program_id()).0,
+                system_program::id(),
+                vec![],
+            ),
+            TestAccount::new(
+                find_escrow_index_address(&program_id()).0,
+                system_program::id(),
+                vec![],
+            ),
+            TestAccount::mint(escrow_info.deposit_mint, deposit_decimals),
+            TestAccount::mint(expected_mint, expected_decimals),
+            TestAccount::new(
+                escrow_info.rent_refund_recipient,
+                system_program::id(),
+                vec![],
+            ),
+            TestAccount::new(Pubkey::new_unique(), system_program::id(), vec![]),
+        ];
+        accounts[EXCHANGE_TAKER_IDX].is_signer = true;
+        for index in [
+            EXCHANGE_INITIALIZER_IDX,
+            EXCHANGE_ESCROW_IDX,
+            EXCHANGE_RENT_REFUND_IDX,
+        ] {
+            accounts[index].is_writable = true;
+        }
+        accounts
+    }
+
+    /// Processes an `Exchange` of the whole deposit and returns the CPIs it made
+    fn process_exchange(accounts: &mut [TestAccount]) -> Result<Vec<RecordedCpi>, ProgramError> {
+        start_recording();
+        CLOCK.with(|clock| *clock.borrow_mut() = Some(Clock::default()));
+        let exchange = EscrowInstruction::Exchange {
+            amount: 1_000,
+            payment: 0,
+            taker_deadline_slot: 0,
+            preimage: None,
+        };
+        let account_infos: Vec<AccountInfo> = accounts.iter_mut().map(TestAccount::info).collect();
+        Processor::processor(&program_id(), &account_infos, &exchange.pack())?;
+        Ok(CPIS.with(|cpis| cpis.take()))
+    }
+
+    #[test]
+    fn test_exchange_cpis_share_one_account_slice() {
+        let mut accounts = exchange_accounts(exchange_escrow(), 6, 6);
+        let key = |index: usize| accounts[index].key;
+        let (taker, pda_token_account, initializer) = (
+            key(EXCHANGE_TAKER_IDX),
+            key(EXCHANGE_PDA_TOKEN_IDX),
+            key(EXCHANGE_INITIALIZER_IDX),
+        );
+        let expected_instructions = [
+            spl_token::instruction::transfer_checked(
+                &TOKEN_PROGRAM_ID,
+                &key(EXCHANGE_TAKER_SEND_IDX),
+                &key(EXCHANGE_EXPECTED_MINT_IDX),
+                &key(EXCHANGE_INITIALIZER_RECEIVE_IDX),
+                &taker,
+                &[&taker],
+                500,
+                6,
+            )
+            .unwrap(),
+            spl_token::instruction::transfer_checked(
+                &TOKEN_PROGRAM_ID,
+                &pda_token_account,
+                &key(EXCHANGE_DEPOSIT_MINT_IDX),
+                &key(EXCHANGE_TAKER_RECEIVE_IDX),
+                &pda().key,
+                &[&pda().key],
+                1_000,
+                6,
+            )
+            .unwrap(),
+            spl_token::instruction::close_account(
+                &TOKEN_PROGRAM_ID,
+                &pda_token_account,
+                &initializer,
+                &pda().key,
+                &[&pda().key],
+            )
+            .unwrap(),
+        ];
+        let escrow_rent = accounts[EXCHANGE_ESCROW_IDX].lamports;
+        let refund_lamports = accounts[EXCHANGE_RENT_REFUND_IDX].lamports;
+
+        let cpis = process_exchange(&mut accounts).unwrap();
+        assert_eq!(cpis.len(), expected_instructions.len());
+        for ((cpi, expected_instruction), signers) in cpis
+            .iter()
+            .zip(expected_instructions.iter())
+            .zip([vec![], vec![pda().key], vec![pda().key]])
+        {
+            assert_eq!(cpi.instruction, *expected_instruction);
+            assert_eq!(cpi.signers, signers);
+            // The CPIs are all passed the same slice, holding every account any of them lists
+            assert_eq!(cpi.account_keys, cpis[0].account_keys);
+            for account_meta in &cpi.instruction.accounts {
+                assert!(cpi.account_keys.contains(&account_meta.pubkey));
+            }
+        }
+        assert!(cpis[0].account_keys.contains(&TOKEN_PROGRAM_ID));
+
+        // The escrow account's rent is refunded by hand once the CPIs are done
+        assert_eq!(accounts[EXCHANGE_ESCROW_IDX].lamports, 0);
+        assert_eq!(
+            accounts[EXCHANGE_RENT_REFUND_IDX].lamports,
+            refund_lamports + escrow_rent
+        );
+    }
+
+    #[test]
+    fn test_exchange_falls_back_to_the_canonical_bump() {
+        // A bump below the canonical one derives another address or none at all, neither owns the token account
+        let mut accounts = exchange_accounts(
+            Escrow {
+                pda_bump: pda().bump - 1,
+                ..exchange_escrow()
+            },
+            6,
+            6,
+        );
+        let cpis = process_exchange(&mut accounts).unwrap();
+        assert!(recorded_logs().contains(
+            &"Warning: the stored PDA bump seed isn't the escrow's, falling back to the canonical one"
+                .to_string()
+        ));
+        assert_eq!(cpis[1].signers, [pda().key]);
+
+        // Without the PDA owning the token account no bump is the escrow's
+        let mut accounts = exchange_accounts(exchange_escrow(), 6, 6);
+        let pda_token_account = &mut accounts[EXCHANGE_PDA_TOKEN_IDX];
+        *pda_token_account = TestAccount::holding(
+            pda_token_account.key,
+            exchange_escrow().deposit_mint,
+            Pubkey::new_unique(),
+            1_000,
+        );
+        assert_eq!(
+            process_exchange(&mut accounts).err(),
+            Some(EscrowError::InvalidPda.into())
+        );
+        assert!(CPIS.with(|cpis| cpis.borrow().is_empty()));
+    }
+
+    #[test]
+    fn test_exchange_rejects_a_pda_token_account_of_another_mint() {
+        // The token account has the key the escrow recorded, but not the mint its deposit was made in
+        let mut accounts = exchange_accounts(exchange_escrow(), 6, 6);
+        let pda_token_account = &mut accounts[EXCHANGE_PDA_TOKEN_IDX];
+        *pda_token_account = TestAccount::holding(
+            pda_token_account.key,
+            Pubkey::new_unique(),
+            pda().key,
+            1_000,
+        );
+        assert_eq!(
+            process_exchange(&mut accounts).err(),
+            Some(EscrowError::WrongMint.into())
+        );
+        assert!(CPIS.with(|cpis| cpis.borrow().is_empty()));
+
+        // Nor can the mint account passed for the transfer be another one
+        let mut accounts = exchange_accounts(exchange_escrow(), 6, 6);
+        accounts[EXCHANGE_DEPOSIT_MINT_IDX] = TestAccount::mint(Pubkey::new_unique(), 6);
+        assert_eq!(
+            process_exchange(&mut accounts).err(),
+            Some(EscrowError::WrongMint.into())
+        );
+        assert!(CPIS.with(|cpis| cpis.borrow().is_empty()));
+    }
+
+    #[test]
+    fn test_exchange_transfers_are_checked_against_the_decimals_of_their_mint() {
+        let mut accounts = exchange_accounts(exchange_escrow(), 9, 2);
+        let cpis = process_exchange(&mut accounts).unwrap();
+        let decimals = |cpi: &RecordedCpi| match spl_token::instruction::TokenInstruction::unpack(
+            &cpi.instruction.data,
+        )
+        .unwrap()
+        {
+            spl_token::instruction::TokenInstruction::TransferChecked { decimals, .. } => decimals,
+            _ => panic!("Not a transfer_checked"),
+        };
+        assert_eq!(decimals(&cpis[0]), 2);
+        assert_eq!(decimals(&cpis[1]), 9);
+
+        // The token program rejects the payment against a mint of other decimals, and accepts it against its own
+        let transfer_to_initializer = &cpis[0].instruction;
+        let process_transfer = |accounts: &mut [TestAccount]| {
+            let account_infos: Vec<AccountInfo> =
+                accounts.iter_mut().map(TestAccount::info).collect();
+            let transfer_infos: Vec<AccountInfo> = transfer_to_initializer
+                .accounts
+                .iter()
+                .map(|account_meta| {
+                    account_infos
+                        .iter()
+                        .find(|account_info| *account_info.key == account_meta.pubkey)
+                        .unwrap()
+                        .clone()
+                })
+                .collect();
+            spl_token::processor::Processor::process(
+                &TOKEN_PROGRAM_ID,
+                &transfer_infos,
+                &transfer_to_initializer.data,
+            )
+        };
+        let expected_mint = accounts[EXCHANGE_EXPECTED_MINT_IDX].key;
+        accounts[EXCHANGE_EXPECTED_MINT_IDX] = TestAccount::mint(expected_mint, 9);
+        assert_eq!(
+            process_transfer(&mut accounts),
+            Err(spl_token::error::TokenError::MintDecimalsMismatch.into())
+        );
+        accounts[EXCHANGE_EXPECTED_MINT_IDX] = TestAccount::mint(expected_mint, 2);
+        process_transfer(&mut accounts).unwrap();
+        let initializer_token_to_receive_account =
+            TokenAccount::unpack(&accounts[EXCHANGE_INITIALIZER_RECEIVE_IDX].data).unwrap();
+        assert_eq!(initializer_token_to_receive_account.amount, 500);
+    }
+
+    #[test]
+    fn test_close_escrow_account_conserves_lamports() {
+        let owner = program_id();
+        let escrow_key = escrow_key();
+        let destination_key = Pubkey::new_from_array([3; 32]);
+        let mut escrow_lamports = 2_000_000;
+        let mut destination_lamports = 5_000;
+        let mut escrow_data = vec![0; Escrow::LEN];
+        Escrow::pack(
+            Escrow {
+                is_initialized: true,
+                ..Escrow::UNINITIALIZED
+            },
+            &mut escrow_data,
+        )
+        .unwrap();
+        let mut destination_data = vec![];
+        let escrow_account = AccountInfo::new(
+            &escrow_key,
+            false,
+            true,
+            &mut escrow_lamports,
+            &mut escrow_data,
+            &owner,
+            false,
+            0,
+        );
+        let destination_account = AccountInfo::new(
+            &destination_key,
+            false,
+            true,
+            &mut destination_lamports,
+            &mut destination_data,
+            &owner,
+            false,
+            0,
+        );
+
+        Processor::close_escrow_account(&escrow_account, &destination_account).unwrap();
+
+        assert_eq!(escrow_account.lamports(), 0);
+        assert_eq!(destination_account.lamports(), 2_005_000);
+        assert_eq!(
+            Escrow::unpack_unchecked(&escrow_account.try_borrow_data().unwrap()).unwrap(),
+            Escrow::UNINITIALIZED
+        );
+    }
+
+    #[test]
+    fn test_close_escrow_account_overflowing_destination() {
+        let owner = program_id();
+        let escrow_key = escrow_key();
+        let destination_key = Pubkey::new_from_array([3; 32]);
+        let mut escrow_lamports = 2;
+        let mut destination_lamports = u64::MAX - 1;
+        let mut escrow_data = vec![0; Escrow::LEN];
+        let mut destination_data = vec![];
+        let escrow_account = AccountInfo::new(
+            &escrow_key,
+            false,
+            true,
+            &mut escrow_lamports,
+            &mut escrow_data,
+            &owner,
+            false,
+            0,
+        );
+        let destination_account = AccountInfo::new(
+            &destination_key,
+            false,
+            true,
+            &mut destination_lamports,
+            &mut destination_data,
+            &owner,
+            false,
+            0,
+        );
+
+        assert_eq!(
+            Processor::close_escrow_account(&escrow_account, &destination_account),
+            Err(EscrowError::AmountOverflow.into())
+        );
+        assert_eq!(escrow_account.lamports(), 2);
+        assert_eq!(destination_account.lamports(), u64::MAX - 1);
+    }
+
+    // The CPIs below are built exactly like the processor builds them, their bytes are pinned so a refactor
+    // can't silently change the authority type, the signers or the seeds the PDA signs with.
+    // The authority is passed as its own signer too, so the token program lists it a second time as a signer
+
+    #[test]
+    fn test_pda_signer_seeds() {
+        let pda = pda();
+        assert_eq!(
+            pda.key,
+            Pubkey::from_str("FuMx76fFzELn7ao53DPneypCWh56TjV7kTgMzNVPC98H").unwrap()
+        );
+        assert_eq!(pda.bump, 253);
+
+        let seeds = pda.signer_seeds();
+        assert_eq!(seeds, [&b"escrow"[..], &[][..], &[1; 32][..], &[253][..]]);
+        assert_eq!(
+            Pubkey::create_program_address(&seeds, &program_id()),
+            Ok(pda.key)
+        );
+    }
+
+    #[test]
+    fn test_namespaced_pda_signer_seeds() {
+        let namespace = [9; NAMESPACE_LEN];
+        let pda = PdaInfo::find_in_namespace(&program_id(), &namespace, &escrow_key());
+        assert_ne!(pda.key, self::pda().key);
+
+        let seeds = pda.signer_seeds();
+        assert_eq!(
+            seeds,
+            [
+                &b"escrow"[..],
+                &namespace[..],
+                &[1; 32][..],
+                &[pda.bump][..]
+            ]
+        );
+        assert_eq!(
+            Pubkey::create_program_address(&seeds, &program_id()),
+            Ok(pda.key)
+        );
+        // The stored bump seed recreates the same PDA
+        assert_eq!(
+            PdaInfo::from_bump(&program_id(), &namespace, &escrow_key(), pda.bump)
+                .unwrap()
+                .key,
+            pda.key
+        );
+    }
+
+    #[test]
+    fn test_init_set_authority_cpi() {
+        let temp_token_account = Pubkey::new_from_array([2; 32]);
+        let initializer = Pubkey::new_from_array([3; 32]);
+        let pda = pda();
+
+        let owner_change_ix = spl_token::instruction::set_authority(
+            &TOKEN_PROGRAM_ID,
+            &temp_token_account,
+            Some(&pda.key),
+            spl_token::instruction::AuthorityType::AccountOwner,
+            &initializer,
+            &[&initializer],
+        )
+        .unwrap();
+
+        assert_eq!(owner_change_ix.program_id, spl_token::id());
+        let mut data = vec![6, 2, 1];
+        data.extend_from_slice(pda.key.as_ref());
+        assert_eq!(owner_change_ix.data, data);
+        assert_eq!(
+            owner_change_ix.accounts,
+            vec![
+                AccountMeta::new(temp_token_account, false),
+                AccountMeta::new_readonly(initializer, false),
+                AccountMeta::new_readonly(initializer, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_exchange_transfer_checked_cpis() {
+        let taker = Pubkey::new_from_array([3; 32]);
+        let taker_token_to_send_account = Pubkey::new_from_array([4; 32]);
+        let taker_token_to_receive_account = Pubkey::new_from_array([5; 32]);
+        let pda_token_account = Pubkey::new_from_array([6; 32]);
+        let initializer_token_to_receive_account = Pubkey::new_from_array([8; 32]);
+        let deposit_mint = Pubkey::new_from_array([9; 32]);
+        let expected_mint = Pubkey::new_from_array([10; 32]);
+        let pda = pda();
+
+        let ix_transfer_to_initializer = spl_token::instruction::transfer_checked(
+            &TOKEN_PROGRAM_ID,
+            &taker_token_to_send_account,
+            &expected_mint,
+            &initializer_token_to_receive_account,
+            &taker,
+            &[&taker],
+            500,
+            9,
+        )
+        .unwrap();
+        assert_eq!(ix_transfer_to_initializer.program_id, spl_token::id());
+        assert_eq!(
+            ix_transfer_to_initializer.data,
+            vec![12, 0xf4, 0x01, 0, 0, 0, 0, 0, 0, 9]
+        );
+        assert_eq!(
+            ix_transfer_to_initializer.accounts,
+            vec![
+                AccountMeta::new(taker_token_to_send_account, false),
+                AccountMeta::new_readonly(expected_mint, false),
+                AccountMeta::new(initializer_token_to_receive_account, false),
+                AccountMeta::new_readonly(taker, false),
+                AccountMeta::new_readonly(taker, true),
+            ]
+        );
+
+        let ix_transfer_to_taker = spl_token::instruction::transfer_checked(
+            &TOKEN_PROGRAM_ID,
+            &pda_token_account,
+            &deposit_mint,
+            &taker_token_to_receive_account,
+            &pda.key,
+            &[&pda.key],
+            1_000,
+            6,
+        )
+        .unwrap();
+        assert_eq!(
+            ix_transfer_to_taker.data,
+            vec![12, 0xe8, 0x03, 0, 0, 0, 0, 0, 0, 6]
+        );
+        // Only the PDA signs, through the seeds passed to `invoke_signed`
+        assert_eq!(
+            ix_transfer_to_taker.accounts,
+            vec![
+                AccountMeta::new(pda_token_account, false),
+                AccountMeta::new_readonly(deposit_mint, false),
+                AccountMeta::new(taker_token_to_receive_account, false),
+                AccountMeta::new_readonly(pda.key, false),
+                AccountMeta::new_readonly(pda.key, true),
+            ]
+        );
+        assert_eq!(
+            pda.signer_seeds(),
+            [
+                &b"escrow"[..],
+                &[][..],
+                escrow_key().as_ref(),
+                &[pda.bump][..]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_is_closed_escrow() {
+        let owner = program_id();
+        let escrow_key = escrow_key();
+        let is_closed_escrow = |mut lamports: u64, mut data: Vec<u8>| {
+            let escrow_account = AccountInfo::new(
+                &escrow_key,
+                false,
+                false,
+                &mut lamports,
+                &mut data,
+                &owner,
+                false,
+                0,
+            );
+            Processor::is_closed_escrow(&escrow_account).unwrap()
+        };
+        let mut open_data = vec![0; Escrow::LEN];
+        Escrow::pack(
+            Escrow {
+                is_initialized: true,
+                ..Escrow::UNINITIALIZED
+            },
+            &mut open_data,
+        )
+        .unwrap();
+
+        assert!(!is_closed_escrow(2_000_000, open_data.clone()));
+        assert!(is_closed_escrow(0, open_data));
+        assert!(is_closed_escrow(2_000_000, vec![]));
+        assert!(is_closed_escrow(2_000_000, vec![0; Escrow::LEN]));
+
+        // Data that isn't an escrow at all is left to the owner check and `unpack`
+        assert!(!is_closed_escrow(2_000_000, vec![0; 7]));
+    }
+
+    #[test]
+    fn test_fill_status_reports_paused_mints() {
+        let deposit_mint = Pubkey::new_from_array([4; 32]);
+        let expected_mint = Pubkey::new_from_array([5; 32]);
+        let escrow_info = Escrow {
+            is_initialized: true,
+            deposit_mint,
+            taker_slots: 1,
+            ..Escrow::UNINITIALIZED
+        };
+        let pda = pda();
+        let pda_token_account_state = TokenAccount {
+            mint: deposit_mint,
+            owner: pda.key,
+            amount: 1_000,
+            state: spl_token::state::AccountState::Initialized,
+            ..TokenAccount::default()
+        };
+        let config_pausing = |mint: Pubkey| {
+            let mut config = EscrowConfig {
+                is_initialized: true,
+                admin: Pubkey::default(),
+                paused: false,
+                treasury: Pubkey::default(),
+                cancel_fee: 0,
+                init_fee: 0,
+                paused_mints_count: 0,
+                paused_mints: [Pubkey::default(); MAX_PAUSED_MINTS],
+                extension_fee: 0,
+                exchange_fee_bps: 0,
+                free_fills: 0,
+                namespaces_count: 0,
+                namespaces: [[0; NAMESPACE_LEN]; MAX_NAMESPACES],
+            };
+            config.pause_mint(&mint).unwrap();
+            config
+        };
+        let fill_status = |config: Option<&EscrowConfig>| {
+            Processor::fill_status(
+                &escrow_info,
+                &pda_token_account_state,
+                &pda,
+                config,
+                &expected_mint,
+                None,
+                &Clock::default(),
+            )
+            .unwrap()
+        };
+
+        assert_eq!(fill_status(None), FillStatus::Fillable);
+        assert_eq!(
+            fill_status(Some(&config_pausing(Pubkey::new_from_array([9; 32])))),
+            FillStatus::Fillable
+        );
+        assert_eq!(
+            fill_status(Some(&config_pausing(deposit_mint))),
+            FillStatus::MintPaused
+        );
+        assert_eq!(
+            fill_status(Some(&config_pausing(expected_mint))),
+            FillStatus::MintPaused
+        );
+
+        // The global pause is reported first, like `processor_exchange` rejects it first
+        let mut config = config_pausing(expected_mint);
+        config.paused = true;
+        assert_eq!(fill_status(Some(&config)), FillStatus::Paused);
+    }
+
+    #[test]
+    fn test_fill_status_reports_reservations() {
+        let reserved_taker = Pubkey::new_from_array([4; 32]);
+        let escrow_info = Escrow {
+            is_initialized: true,
+            taker_slots: 1,
+            reserved_taker,
+            reserved_until_slot: 10,
+            ..Escrow::UNINITIALIZED
+        };
+        let pda = pda();
+        let pda_token_account_state = TokenAccount {
+            owner: pda.key,
+            amount: 1_000,
+            state: spl_token::state::AccountState::Initialized,
+            ..TokenAccount::default()
+        };
+        let fill_status = |taker: Option<&Pubkey>, slot: u64| {
+            Processor::fill_status(
+                &escrow_info,
+                &pda_token_account_state,
+                &pda,
+                None,
+                &Pubkey::default(),
+                taker,
+                &Clock {
+                    slot,
+                    ..Clock::default()
+                },
+            )
+            .unwrap()
+        };
+
+        let other_taker = Pubkey::new_from_array([5; 32]);
+        assert_eq!(fill_status(Some(&reserved_taker), 9), FillStatus::Fillable);
+        assert_eq!(fill_status(Some(&other_taker), 9), FillStatus::Reserved);
+        assert_eq!(fill_status(None, 9), FillStatus::Reserved);
+
+        // Once the reservation is over every taker can fill again
+        assert_eq!(fill_status(Some(&other_taker), 10), FillStatus::Fillable);
+        assert_eq!(fill_status(None, 10), FillStatus::Fillable);
+    }
+
+    #[test]
+    fn test_cancel_transfer_cpi() {
+        let pda_token_account = Pubkey::new_from_array([6; 32]);
+        let initializer_token_account = Pubkey::new_from_array([11; 32]);
+        let pda = pda();
+
+        let ix_transfer_to_initializer = spl_token::instruction::transfer(
+            &TOKEN_PROGRAM_ID,
+            &pda_token_account,
+            &initializer_token_account,
+            &pda.key,
+            &[&pda.key],
+            u64::MAX,
+        )
+        .unwrap();
+
+        assert_eq!(ix_transfer_to_initializer.program_id, spl_token::id());
+        assert_eq!(
+            ix_transfer_to_initializer.data,
+            vec![3, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]
+        );
+        assert_eq!(
+            ix_transfer_to_initializer.accounts,
+            vec![
+                AccountMeta::new(pda_token_account, false),
+                AccountMeta::new(initializer_token_account, false),
+                AccountMeta::new_readonly(pda.key, false),
+                AccountMeta::new_readonly(pda.key, true),
+            ]
+        );
     }
 }
@@ -1,17 +1,306 @@
 use solana_program::{
+    clock::Clock,
+    hash::hashv,
     program_pack::{IsInitialized, Sealed, Pack}, 
     program_error::ProgramError,
     pubkey::Pubkey};
 
 use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
 
+use crate::{error::EscrowError, math, pda::NAMESPACE_LEN};
 
+/// The maximum number of distinct takers an escrow can be split across
+pub const MAX_TAKER_SLOTS: usize = 4;
+
+/// The maximum number of bytes an escrow's note can hold
+pub const MAX_NOTE_LEN: usize = 128;
+
+/// The maximum number of mints the config can pause exchanges of
+pub const MAX_PAUSED_MINTS: usize = 8;
+
+/// The maximum number of mint pairs the pair registry can allow
+pub const MAX_ALLOWED_PAIRS: usize = 32;
+
+/// The maximum number of open escrows the escrow index can list, the index is unpacked on the stack so it stays small
+pub const MAX_INDEXED_ESCROWS: usize = 32;
+
+/// The most slots ahead a taker can reserve an escrow for, so a reservation never locks the initializer out for long
+pub const MAX_RESERVATION_SLOTS: u64 = 150;
+
+/// The smallest expected amount an escrow can be created with, forks can raise it to keep dust escrows out.
+/// There is deliberately no largest one: the taker payments are split with `math::mul_div` and never add up to
+/// more than the expected amount, so even `u64::MAX` can't overflow, nor can the exchange fee as a share of each payment
+pub const MIN_EXPECTED_AMOUNT: u64 = 1;
+
+/// The basis points of a whole payment, the most `EscrowConfig::exchange_fee_bps` can be
+pub const MAX_EXCHANGE_FEE_BPS: u16 = 10_000;
+
+/// The maximum number of namespaces the config allows escrows to be opened in
+pub const MAX_NAMESPACES: usize = 8;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Escrow {
     pub is_initialized: bool,
     pub initializer_pubkey: Pubkey,
     pub temp_token_account_pubkey: Pubkey,
     pub initializer_token_to_receive_account_pubkey: Pubkey,
+    /// The amount the initializer expects for the whole deposit, unused with a price ratio or a payment band. The deposited amount itself isn't stored,
+    /// exchanges always read it from the PDA's token account
     pub expected_amount: u64,
+    /// The slot at which the expected amount starts to decay
+    pub decay_start_slot: u64,
+    /// The slot at which the expected amount reaches `decay_end_amount`, zero means there is no decay schedule
+    pub decay_end_slot: u64,
+    /// The expected amount once the decay schedule is over
+    pub decay_end_amount: u64,
+    /// The nonce the escrow account address was derived with
+    pub nonce: u64,
+    /// The slot the escrow was initialized at
+    pub created_at_slot: u64,
+    /// The number of distinct takers that have to fill the escrow before it closes
+    pub taker_slots: u8,
+    /// The number of takers that already filled their share of the escrow
+    pub filled_takers_count: u8,
+    /// The takers that already filled their share of the escrow, only the first `filled_takers_count` are set
+    pub filled_takers: [Pubkey; MAX_TAKER_SLOTS],
+    /// Whether the escrow expires at `expiry_slot` rather than at `expiry_timestamp`
+    pub use_slot_expiry: bool,
+    /// The unix timestamp from which the escrow can't be exchanged anymore, zero means it never expires
+    pub expiry_timestamp: i64,
+    /// The slot from which the escrow can't be exchanged anymore, zero means it never expires
+    pub expiry_slot: u64,
+    /// Whether the PDA only holds a delegation over the initializer's token account rather than owning a temp token account
+    pub is_delegated: bool,
+    /// The canonical bump seed of the escrow's PDA, saving the search in `find_program_address` on exchange
+    pub pda_bump: u8,
+    /// Whether an exchange of the escrow is running, set across its CPIs so a nested call can't observe half updated state
+    pub in_progress: bool,
+    /// The SHA-256 hash the taker has to reveal the preimage of to exchange, all zeros disables the hashlock
+    pub hashlock: [u8; 32],
+    /// The slot from which the taker can't exchange anymore and before which the initializer can't cancel, zero disables the timelock
+    pub timelock_slot: u64,
+    /// The program invoked after every fill with the filled amount, the default pubkey disables the callback
+    pub callback_program: Pubkey,
+    /// The number of accounts passed through to `callback_program`
+    pub callback_accounts_len: u8,
+    /// The mint of the deposited tokens, recorded from the temp token account at init
+    pub deposit_mint: Pubkey,
+    /// The account the escrow account rent goes back to once the escrow closes, the initializer unless someone else paid it
+    pub rent_refund_recipient: Pubkey,
+    /// The key that can cancel the escrow on the initializer's behalf, the default pubkey disables it
+    pub cancel_delegate: Pubkey,
+    /// The numerator of the price the taker pays per deposited token
+    pub price_num: u64,
+    /// The denominator of the price the taker pays per deposited token, zero means the escrow is priced at `expected_amount` instead
+    pub price_den: u64,
+    /// The number of bytes of `note` that are set
+    pub note_len: u8,
+    /// A short memo of the initializer, only the first `note_len` bytes are set
+    pub note: [u8; MAX_NOTE_LEN],
+    /// The least the taker may pay for the deposit, only used with a non zero `max_amount`
+    pub min_amount: u64,
+    /// The most the taker may pay for the deposit, zero means there is no payment band
+    pub max_amount: u64,
+    /// Whether the deposit is a single NFT, the escrow was then initialized through `InitEscrowNft`
+    pub is_nft: bool,
+    /// How far the amount a taker asks for may be off its deposit share, zero requires the exact share
+    pub amount_tolerance: u64,
+    /// The program derived address that has to sign every exchange, the default pubkey lets any taker exchange
+    pub settlement_authority: Pubkey,
+    /// The taker holding a reservation of the escrow until `reserved_until_slot`, the default pubkey when nobody reserved it
+    pub reserved_taker: Pubkey,
+    /// The slot from which the reservation is over, before it only `reserved_taker` can exchange and the initializer can't cancel
+    pub reserved_until_slot: u64,
+    /// The number of fills left that the exchange fee is waived for, copied from the config's `free_fills` at init
+    pub fills_remaining_free: u16,
+    /// Whether the deposit is native SOL wrapped by `InitEscrowWithSol`, the taker is then paid it in lamports
+    pub is_native_deposit: bool,
+    /// The template the escrow was opened from with `InitEscrowFromTemplate`, its `amount_tolerance` and `settlement_authority`
+    /// are the template's then. The default pubkey when the escrow has no template
+    pub template: Pubkey,
+    /// The namespace the escrow was opened in with `InitEscrowInNamespace`, part of the seeds of its PDA. `pda::DEFAULT_NAMESPACE`
+    /// when the escrow wasn't opened in one
+    pub namespace: [u8; NAMESPACE_LEN],
+}
+
+impl Escrow {
+    /// The length of the record `GetEscrowBatch` writes for each escrow: the packed escrow up to and including
+    /// `filled_takers_count`, so it leaves out the note and still fits several escrows in the return data
+    pub const BATCH_RECORD_LEN: usize = 1 + 32 + 32 + 32 + 8 + 8 + 8 + 8 + 8 + 8 + 1 + 1;
+
+    /// The all zero escrow a closed escrow account is overwritten with, it packs to `LEN` zero bytes and reads back as uninitialized
+    pub const UNINITIALIZED: Escrow = Escrow {
+        is_initialized: false,
+        initializer_pubkey: Pubkey::new_from_array([0; 32]),
+        temp_token_account_pubkey: Pubkey::new_from_array([0; 32]),
+        initializer_token_to_receive_account_pubkey: Pubkey::new_from_array([0; 32]),
+        expected_amount: 0,
+        decay_start_slot: 0,
+        decay_end_slot: 0,
+        decay_end_amount: 0,
+        nonce: 0,
+        created_at_slot: 0,
+        taker_slots: 0,
+        filled_takers_count: 0,
+        filled_takers: [Pubkey::new_from_array([0; 32]); MAX_TAKER_SLOTS],
+        use_slot_expiry: false,
+        expiry_timestamp: 0,
+        expiry_slot: 0,
+        is_delegated: false,
+        pda_bump: 0,
+        in_progress: false,
+        hashlock: [0; 32],
+        timelock_slot: 0,
+        callback_program: Pubkey::new_from_array([0; 32]),
+        callback_accounts_len: 0,
+        deposit_mint: Pubkey::new_from_array([0; 32]),
+        rent_refund_recipient: Pubkey::new_from_array([0; 32]),
+        cancel_delegate: Pubkey::new_from_array([0; 32]),
+        price_num: 0,
+        price_den: 0,
+        note_len: 0,
+        note: [0; MAX_NOTE_LEN],
+        min_amount: 0,
+        max_amount: 0,
+        is_nft: false,
+        amount_tolerance: 0,
+        settlement_authority: Pubkey::new_from_array([0; 32]),
+        reserved_taker: Pubkey::new_from_array([0; 32]),
+        reserved_until_slot: 0,
+        fills_remaining_free: 0,
+        is_native_deposit: false,
+        template: Pubkey::new_from_array([0; 32]),
+        namespace: [0; NAMESPACE_LEN],
+    };
+
+    /// Returns the amount the taker has to pay at the given slot.
+    ///
+    /// Without a decay schedule this is always `expected_amount`, otherwise it decreases linearly
+    /// from `expected_amount` at `decay_start_slot` to `decay_end_amount` at `decay_end_slot`.
+    pub fn required_amount(&self, slot: u64) -> Result<u64, EscrowError> {
+        if self.decay_end_slot == 0 || slot <= self.decay_start_slot {
+            return Ok(self.expected_amount);
+        }
+        if slot >= self.decay_end_slot {
+            return Ok(self.decay_end_amount);
+        }
+
+        let total_decay = math::sub(self.expected_amount, self.decay_end_amount)?;
+        let elapsed_slots = math::sub(slot, self.decay_start_slot)?;
+        let total_slots = math::sub(self.decay_end_slot, self.decay_start_slot)?;
+        let decayed = math::mul_div(total_decay, elapsed_slots, total_slots)?;
+
+        math::sub(self.expected_amount, decayed)
+    }
+
+    /// Returns whether the escrow can't be exchanged anymore at the given clock
+    pub fn is_expired(&self, clock: &Clock) -> bool {
+        if self.timelock_slot != 0 && clock.slot >= self.timelock_slot {
+            return true;
+        }
+        if self.use_slot_expiry {
+            self.expiry_slot != 0 && clock.slot >= self.expiry_slot
+        } else {
+            self.expiry_timestamp != 0 && clock.unix_timestamp >= self.expiry_timestamp
+        }
+    }
+
+    /// Returns whether the timelock still keeps the initializer from cancelling at the given slot
+    pub fn is_timelocked(&self, slot: u64) -> bool {
+        self.timelock_slot != 0 && slot < self.timelock_slot
+    }
+
+    /// Returns whether a reservation still holds the escrow at the given slot
+    pub fn is_reserved(&self, slot: u64) -> bool {
+        slot < self.reserved_until_slot
+    }
+
+    /// Returns whether another taker's reservation keeps `taker` from exchanging at the given slot
+    pub fn is_reserved_for_other(&self, taker: &Pubkey, slot: u64) -> bool {
+        self.is_reserved(slot) && self.reserved_taker != *taker
+    }
+
+    /// Returns the number of takers that can still fill their share of the escrow
+    pub fn remaining_taker_slots(&self) -> u8 {
+        self.taker_slots.saturating_sub(self.filled_takers_count)
+    }
+
+    /// Returns whether the taker already filled a share of the escrow
+    pub fn has_filled(&self, taker: &Pubkey) -> bool {
+        self.filled_takers[..self.filled_takers_count as usize].contains(taker)
+    }
+
+    /// Returns the amount of deposited tokens the next taker receives out of the PDA's `pda_balance`,
+    /// the last taker receives everything that is left
+    pub fn fill_deposit_amount(&self, pda_balance: u64) -> Result<u64, EscrowError> {
+        pda_balance
+            .checked_div(self.remaining_taker_slots() as u64)
+            .ok_or(EscrowError::AmountOverflow)
+    }
+
+    /// Returns the amount the next taker pays for `fill_amount` deposited tokens at the given slot.
+    ///
+    /// With a price ratio this is `fill_amount * price_num / price_den` rounded down, failing with `InvalidAmount` when that
+    /// rounds down to zero so no fill goes unpaid. Otherwise the required amount is
+    /// split evenly and always rounded in the takers' favor: the n-th taker pays the first n shares minus the first n - 1
+    /// shares, both rounded down, so no taker pays more than one base unit over its exact share and the payments add up
+    /// to the required amount
+    pub fn fill_payment_amount(&self, fill_amount: u64, slot: u64) -> Result<u64, EscrowError> {
+        if self.price_den != 0 {
+            let payment = math::mul_div(fill_amount, self.price_num, self.price_den)?;
+            if payment == 0 {
+                return Err(EscrowError::InvalidAmount);
+            }
+            return Ok(payment);
+        }
+
+        let required_amount = self.required_amount(slot)?;
+        let taker_slots = self.taker_slots as u64;
+        let filled_takers = self.filled_takers_count as u64;
+
+        let paid_before = math::mul_div(required_amount, filled_takers, taker_slots)?;
+        let paid_after = math::mul_div(required_amount, filled_takers + 1, taker_slots)?;
+        math::sub(paid_after, paid_before)
+    }
+
+    /// Returns the status of the open escrow at the given clock, a filled or cancelled escrow has its account closed
+    pub fn status(&self, clock: &Clock) -> Status {
+        if self.is_expired(clock) {
+            Status::Expired
+        } else if self.filled_takers_count > 0 {
+            Status::PartiallyFilled
+        } else {
+            Status::Open
+        }
+    }
+}
+
+/// The status byte `GetStatus` returns
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Status {
+    Open = 0,
+    PartiallyFilled = 1,
+    Expired = 2,
+    /// The escrow account was closed, the escrow was filled or cancelled
+    Closed = 3,
+}
+
+/// The status byte `CheckFillable` returns, anything but `Fillable` is the reason an exchange would currently fail
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FillStatus {
+    Fillable = 0,
+    Expired = 1,
+    Paused = 2,
+    Empty = 3,
+    Frozen = 4,
+    DelegationRevoked = 5,
+    AlreadyFilled = 6,
+    /// The config paused the escrow's deposit mint or expected mint
+    MintPaused = 7,
+    /// Another taker reserved the escrow, or anyone did when no taker is given
+    Reserved = 8,
 }
 
 impl Sealed for Escrow {}
@@ -29,6 +318,42 @@ impl Pack for Escrow {
         + 32 // temp_token_account_pubkey: Pubkey
         + 32 // initializer_token_to_receive_account_pubkey: Pubkey
         + 8 // expected_amount: u64
+        + 8 // decay_start_slot: u64
+        + 8 // decay_end_slot: u64
+        + 8 // decay_end_amount: u64
+        + 8 // nonce: u64
+        + 8 // created_at_slot: u64
+        + 1 // taker_slots: u8
+        + 1 // filled_takers_count: u8
+        + 32 * MAX_TAKER_SLOTS // filled_takers: [Pubkey; MAX_TAKER_SLOTS]
+        + 1 // use_slot_expiry: boolean
+        + 8 // expiry_timestamp: i64
+        + 8 // expiry_slot: u64
+        + 1 // is_delegated: boolean
+        + 1 // pda_bump: u8
+        + 1 // in_progress: boolean
+        + 32 // hashlock: [u8; 32]
+        + 8 // timelock_slot: u64
+        + 32 // callback_program: Pubkey
+        + 1 // callback_accounts_len: u8
+        + 32 // deposit_mint: Pubkey
+        + 32 // rent_refund_recipient: Pubkey
+        + 32 // cancel_delegate: Pubkey
+        + 8 // price_num: u64
+        + 8 // price_den: u64
+        + 1 // note_len: u8
+        + MAX_NOTE_LEN // note: [u8; MAX_NOTE_LEN]
+        + 8 // min_amount: u64
+        + 8 // max_amount: u64
+        + 1 // is_nft: boolean
+        + 8 // amount_tolerance: u64
+        + 32 // settlement_authority: Pubkey
+        + 32 // reserved_taker: Pubkey
+        + 8 // reserved_until_slot: u64
+        + 2 // fills_remaining_free: u16
+        + 1 // is_native_deposit: boolean
+        + 32 // template: Pubkey
+        + NAMESPACE_LEN // namespace: [u8; NAMESPACE_LEN]
     ;
 
     fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
@@ -40,19 +365,91 @@ impl Pack for Escrow {
             temp_token_account_pubkey,
             initializer_token_to_receive_account_pubkey,
             expected_amount,
-        ) = array_refs![src, 1, 32, 32, 32, 8];
-        let is_initialized = match is_initialized {
-            [0] => false,
-            [1] => true,
-            _ => return Err(ProgramError::InvalidAccountData),
-        };
+            decay_start_slot,
+            decay_end_slot,
+            decay_end_amount,
+            nonce,
+            created_at_slot,
+            taker_slots,
+            filled_takers_count,
+            filled_takers_src,
+            use_slot_expiry,
+            expiry_timestamp,
+            expiry_slot,
+            is_delegated,
+            pda_bump,
+            in_progress,
+            hashlock,
+            timelock_slot,
+            callback_program,
+            callback_accounts_len,
+            deposit_mint,
+            rent_refund_recipient,
+            cancel_delegate,
+            price_num,
+            price_den,
+            note_len,
+            note,
+            min_amount,
+            max_amount,
+            is_nft,
+            amount_tolerance,
+            settlement_authority,
+            reserved_taker,
+            reserved_until_slot,
+            fills_remaining_free,
+            is_native_deposit,
+            template,
+            namespace,
+        ) = array_refs![src, 1, 32, 32, 32, 8, 8, 8, 8, 8, 8, 1, 1, 32 * MAX_TAKER_SLOTS, 1, 8, 8, 1, 1, 1, 32, 8, 32, 1, 32, 32, 32, 8, 8, 1, MAX_NOTE_LEN, 8, 8, 1, 8, 32, 32, 8, 2, 1, 32, NAMESPACE_LEN];
+
+        let mut filled_takers = [Pubkey::default(); MAX_TAKER_SLOTS];
+        for (taker, taker_src) in filled_takers.iter_mut().zip(filled_takers_src.chunks_exact(32)) {
+            *taker = Pubkey::new(taker_src);
+        }
 
         Ok(Escrow {
-            is_initialized,
+            is_initialized: unpack_bool(is_initialized)?,
             initializer_pubkey: Pubkey::new_from_array(*initializer_pubkey),
             temp_token_account_pubkey: Pubkey::new_from_array(*temp_token_account_pubkey),
             initializer_token_to_receive_account_pubkey: Pubkey::new_from_array(*initializer_token_to_receive_account_pubkey),
             expected_amount: u64::from_le_bytes(*expected_amount),
+            decay_start_slot: u64::from_le_bytes(*decay_start_slot),
+            decay_end_slot: u64::from_le_bytes(*decay_end_slot),
+            decay_end_amount: u64::from_le_bytes(*decay_end_amount),
+            nonce: u64::from_le_bytes(*nonce),
+            created_at_slot: u64::from_le_bytes(*created_at_slot),
+            taker_slots: taker_slots[0],
+            filled_takers_count: filled_takers_count[0],
+            filled_takers,
+            use_slot_expiry: unpack_bool(use_slot_expiry)?,
+            expiry_timestamp: i64::from_le_bytes(*expiry_timestamp),
+            expiry_slot: u64::from_le_bytes(*expiry_slot),
+            is_delegated: unpack_bool(is_delegated)?,
+            pda_bump: pda_bump[0],
+            in_progress: unpack_bool(in_progress)?,
+            hashlock: *hashlock,
+            timelock_slot: u64::from_le_bytes(*timelock_slot),
+            callback_program: Pubkey::new_from_array(*callback_program),
+            callback_accounts_len: callback_accounts_len[0],
+            deposit_mint: Pubkey::new_from_array(*deposit_mint),
+            rent_refund_recipient: Pubkey::new_from_array(*rent_refund_recipient),
+            cancel_delegate: Pubkey::new_from_array(*cancel_delegate),
+            price_num: u64::from_le_bytes(*price_num),
+            price_den: u64::from_le_bytes(*price_den),
+            note_len: note_len[0],
+            note: *note,
+            min_amount: u64::from_le_bytes(*min_amount),
+            max_amount: u64::from_le_bytes(*max_amount),
+            is_nft: unpack_bool(is_nft)?,
+            amount_tolerance: u64::from_le_bytes(*amount_tolerance),
+            settlement_authority: Pubkey::new_from_array(*settlement_authority),
+            reserved_taker: Pubkey::new_from_array(*reserved_taker),
+            reserved_until_slot: u64::from_le_bytes(*reserved_until_slot),
+            fills_remaining_free: u16::from_le_bytes(*fills_remaining_free),
+            is_native_deposit: unpack_bool(is_native_deposit)?,
+            template: Pubkey::new_from_array(*template),
+            namespace: *namespace,
         })
     }
 
@@ -65,7 +462,43 @@ impl Pack for Escrow {
             temp_token_account_pubkey_dst,
             initializer_token_to_receive_account_pubkey_dst,
             expected_amount_dst,
-        ) = mut_array_refs![dst, 1, 32, 32, 32, 8];
+            decay_start_slot_dst,
+            decay_end_slot_dst,
+            decay_end_amount_dst,
+            nonce_dst,
+            created_at_slot_dst,
+            taker_slots_dst,
+            filled_takers_count_dst,
+            filled_takers_dst,
+            use_slot_expiry_dst,
+            expiry_timestamp_dst,
+            expiry_slot_dst,
+            is_delegated_dst,
+            pda_bump_dst,
+            in_progress_dst,
+            hashlock_dst,
+            timelock_slot_dst,
+            callback_program_dst,
+            callback_accounts_len_dst,
+            deposit_mint_dst,
+            rent_refund_recipient_dst,
+            cancel_delegate_dst,
+            price_num_dst,
+            price_den_dst,
+            note_len_dst,
+            note_dst,
+            min_amount_dst,
+            max_amount_dst,
+            is_nft_dst,
+            amount_tolerance_dst,
+            settlement_authority_dst,
+            reserved_taker_dst,
+            reserved_until_slot_dst,
+            fills_remaining_free_dst,
+            is_native_deposit_dst,
+            template_dst,
+            namespace_dst,
+        ) = mut_array_refs![dst, 1, 32, 32, 32, 8, 8, 8, 8, 8, 8, 1, 1, 32 * MAX_TAKER_SLOTS, 1, 8, 8, 1, 1, 1, 32, 8, 32, 1, 32, 32, 32, 8, 8, 1, MAX_NOTE_LEN, 8, 8, 1, 8, 32, 32, 8, 2, 1, 32, NAMESPACE_LEN];
 
         let Escrow {
             is_initialized,
@@ -73,6 +506,42 @@ impl Pack for Escrow {
             temp_token_account_pubkey,
             initializer_token_to_receive_account_pubkey,
             expected_amount,
+            decay_start_slot,
+            decay_end_slot,
+            decay_end_amount,
+            nonce,
+            created_at_slot,
+            taker_slots,
+            filled_takers_count,
+            filled_takers,
+            use_slot_expiry,
+            expiry_timestamp,
+            expiry_slot,
+            is_delegated,
+            pda_bump,
+            in_progress,
+            hashlock,
+            timelock_slot,
+            callback_program,
+            callback_accounts_len,
+            deposit_mint,
+            rent_refund_recipient,
+            cancel_delegate,
+            price_num,
+            price_den,
+            note_len,
+            note,
+            min_amount,
+            max_amount,
+            is_nft,
+            amount_tolerance,
+            settlement_authority,
+            reserved_taker,
+            reserved_until_slot,
+            fills_remaining_free,
+            is_native_deposit,
+            template,
+            namespace,
         } = self;
 
         // We only use the very first bit to store boolean
@@ -81,5 +550,1111 @@ impl Pack for Escrow {
         temp_token_account_pubkey_dst.copy_from_slice(temp_token_account_pubkey.as_ref());
         initializer_token_to_receive_account_pubkey_dst.copy_from_slice(initializer_token_to_receive_account_pubkey.as_ref());
         *expected_amount_dst = expected_amount.to_le_bytes();
+        *decay_start_slot_dst = decay_start_slot.to_le_bytes();
+        *decay_end_slot_dst = decay_end_slot.to_le_bytes();
+        *decay_end_amount_dst = decay_end_amount.to_le_bytes();
+        *nonce_dst = nonce.to_le_bytes();
+        *created_at_slot_dst = created_at_slot.to_le_bytes();
+        taker_slots_dst[0] = *taker_slots;
+        filled_takers_count_dst[0] = *filled_takers_count;
+        for (taker, taker_dst) in filled_takers.iter().zip(filled_takers_dst.chunks_exact_mut(32)) {
+            taker_dst.copy_from_slice(taker.as_ref());
+        }
+        use_slot_expiry_dst[0] = *use_slot_expiry as u8;
+        *expiry_timestamp_dst = expiry_timestamp.to_le_bytes();
+        *expiry_slot_dst = expiry_slot.to_le_bytes();
+        is_delegated_dst[0] = *is_delegated as u8;
+        pda_bump_dst[0] = *pda_bump;
+        in_progress_dst[0] = *in_progress as u8;
+        hashlock_dst.copy_from_slice(hashlock);
+        *timelock_slot_dst = timelock_slot.to_le_bytes();
+        callback_program_dst.copy_from_slice(callback_program.as_ref());
+        callback_accounts_len_dst[0] = *callback_accounts_len;
+        deposit_mint_dst.copy_from_slice(deposit_mint.as_ref());
+        rent_refund_recipient_dst.copy_from_slice(rent_refund_recipient.as_ref());
+        cancel_delegate_dst.copy_from_slice(cancel_delegate.as_ref());
+        *price_num_dst = price_num.to_le_bytes();
+        *price_den_dst = price_den.to_le_bytes();
+        note_len_dst[0] = *note_len;
+        note_dst.copy_from_slice(note);
+        *min_amount_dst = min_amount.to_le_bytes();
+        *max_amount_dst = max_amount.to_le_bytes();
+        is_nft_dst[0] = *is_nft as u8;
+        *amount_tolerance_dst = amount_tolerance.to_le_bytes();
+        settlement_authority_dst.copy_from_slice(settlement_authority.as_ref());
+        reserved_taker_dst.copy_from_slice(reserved_taker.as_ref());
+        *reserved_until_slot_dst = reserved_until_slot.to_le_bytes();
+        *fills_remaining_free_dst = fills_remaining_free.to_le_bytes();
+        is_native_deposit_dst[0] = *is_native_deposit as u8;
+        template_dst.copy_from_slice(template.as_ref());
+        *namespace_dst = *namespace;
+    }
+}
+
+
+/// Protocol wide counters, kept in the PDA derived from `pda::STATS_SEED`
+pub struct EscrowStats {
+    pub is_initialized: bool,
+    /// The number of escrows initialized since the stats account was created
+    pub total_created: u64,
+    /// The number of exchanges, each filled share of a split escrow counts as one
+    pub total_filled: u64,
+    /// The sum of the deposited tokens sent to takers, across every mint
+    pub total_volume: u64,
+}
+
+impl Sealed for EscrowStats {}
+
+impl IsInitialized for EscrowStats {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for EscrowStats {
+    const LEN: usize =
+        1 // is_initialized: boolean
+        + 8 // total_created: u64
+        + 8 // total_filled: u64
+        + 8 // total_volume: u64
+    ;
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, EscrowStats::LEN];
+
+        let (
+            is_initialized,
+            total_created,
+            total_filled,
+            total_volume,
+        ) = array_refs![src, 1, 8, 8, 8];
+
+        Ok(EscrowStats {
+            is_initialized: unpack_bool(is_initialized)?,
+            total_created: u64::from_le_bytes(*total_created),
+            total_filled: u64::from_le_bytes(*total_filled),
+            total_volume: u64::from_le_bytes(*total_volume),
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, EscrowStats::LEN];
+
+        let (
+            is_initialized_dst,
+            total_created_dst,
+            total_filled_dst,
+            total_volume_dst,
+        ) = mut_array_refs![dst, 1, 8, 8, 8];
+
+        let EscrowStats {
+            is_initialized,
+            total_created,
+            total_filled,
+            total_volume,
+        } = self;
+
+        is_initialized_dst[0] = *is_initialized as u8;
+        *total_created_dst = total_created.to_le_bytes();
+        *total_filled_dst = total_filled.to_le_bytes();
+        *total_volume_dst = total_volume.to_le_bytes();
+    }
+}
+
+
+/// Program wide settings, kept in the PDA derived from `pda::CONFIG_SEED`
+#[derive(Debug, PartialEq)]
+pub struct EscrowConfig {
+    pub is_initialized: bool,
+    /// The only account allowed to change the settings
+    pub admin: Pubkey,
+    /// Whether every exchange is currently rejected
+    pub paused: bool,
+    /// The account receiving the protocol fees
+    pub treasury: Pubkey,
+    /// The lamports taken out of the escrow account rent refund when an escrow is cancelled, zero disables the fee
+    pub cancel_fee: u64,
+    /// The lamports the initializer pays to the treasury on `InitEscrow`, zero disables the fee
+    pub init_fee: u64,
+    /// The number of paused mints, only the first `paused_mints_count` entries of `paused_mints` are set
+    pub paused_mints_count: u8,
+    /// The mints no escrow can be exchanged for or with, on top of the global `paused`
+    pub paused_mints: [Pubkey; MAX_PAUSED_MINTS],
+    /// The lamports the initializer pays to the treasury on `ExtendExpiry`, zero makes extending free
+    pub extension_fee: u64,
+    /// The share of each exchange's payment, in basis points, paid into the fee vault of the expected mint rather than to
+    /// the initializer, zero disables the fee
+    pub exchange_fee_bps: u16,
+    /// The number of fills of each new escrow that the exchange fee is waived for, zero charges every fill
+    pub free_fills: u16,
+    /// The number of allowed namespaces, only the first `namespaces_count` entries of `namespaces` are set
+    pub namespaces_count: u8,
+    /// The namespaces escrows can be opened in with `InitEscrowInNamespace`
+    pub namespaces: [[u8; NAMESPACE_LEN]; MAX_NAMESPACES],
+}
+
+impl EscrowConfig {
+    /// Returns whether exchanges involving the mint are paused
+    pub fn is_mint_paused(&self, mint: &Pubkey) -> bool {
+        self.paused_mints[..self.paused_mints_count as usize].contains(mint)
+    }
+
+    /// Pauses exchanges involving the mint, doing nothing if they already are
+    pub fn pause_mint(&mut self, mint: &Pubkey) -> Result<(), EscrowError> {
+        if self.is_mint_paused(mint) {
+            return Ok(());
+        }
+        if self.paused_mints_count as usize >= MAX_PAUSED_MINTS {
+            return Err(EscrowError::PausedMintsFull);
+        }
+        self.paused_mints[self.paused_mints_count as usize] = *mint;
+        self.paused_mints_count += 1;
+        Ok(())
+    }
+
+    /// Resumes exchanges involving the mint, doing nothing if they aren't paused
+    pub fn resume_mint(&mut self, mint: &Pubkey) {
+        let paused_mints_count = self.paused_mints_count as usize;
+        if let Some(index) = self.paused_mints[..paused_mints_count]
+            .iter()
+            .position(|paused_mint| paused_mint == mint)
+        {
+            // Keep the paused mints contiguous by moving the last one into the freed entry
+            self.paused_mints[index] = self.paused_mints[paused_mints_count - 1];
+            self.paused_mints[paused_mints_count - 1] = Pubkey::default();
+            self.paused_mints_count -= 1;
+        }
+    }
+
+    /// Returns whether escrows can be opened in the namespace
+    pub fn is_namespace_allowed(&self, namespace: &[u8; NAMESPACE_LEN]) -> bool {
+        self.namespaces[..self.namespaces_count as usize].contains(namespace)
+    }
+
+    /// Allows escrows to be opened in the namespace, doing nothing if they already can
+    pub fn allow_namespace(&mut self, namespace: &[u8; NAMESPACE_LEN]) -> Result<(), EscrowError> {
+        if self.is_namespace_allowed(namespace) {
+            return Ok(());
+        }
+        if self.namespaces_count as usize >= MAX_NAMESPACES {
+            return Err(EscrowError::NamespacesFull);
+        }
+        self.namespaces[self.namespaces_count as usize] = *namespace;
+        self.namespaces_count += 1;
+        Ok(())
+    }
+
+    /// Stops new escrows being opened in the namespace, the escrows already open in it keep their authorities
+    pub fn disallow_namespace(&mut self, namespace: &[u8; NAMESPACE_LEN]) {
+        let namespaces_count = self.namespaces_count as usize;
+        if let Some(index) = self.namespaces[..namespaces_count]
+            .iter()
+            .position(|allowed| allowed == namespace)
+        {
+            // Keep the allowed namespaces contiguous by moving the last one into the freed entry
+            self.namespaces[index] = self.namespaces[namespaces_count - 1];
+            self.namespaces[namespaces_count - 1] = [0; NAMESPACE_LEN];
+            self.namespaces_count -= 1;
+        }
+    }
+}
+
+impl Sealed for EscrowConfig {}
+
+impl IsInitialized for EscrowConfig {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for EscrowConfig {
+    const LEN: usize =
+        1 // is_initialized: boolean
+        + 32 // admin: Pubkey
+        + 1 // paused: boolean
+        + 32 // treasury: Pubkey
+        + 8 // cancel_fee: u64
+        + 8 // init_fee: u64
+        + 1 // paused_mints_count: u8
+        + 32 * MAX_PAUSED_MINTS // paused_mints: [Pubkey; MAX_PAUSED_MINTS]
+        + 8 // extension_fee: u64
+        + 2 // exchange_fee_bps: u16
+        + 2 // free_fills: u16
+        + 1 // namespaces_count: u8
+        + NAMESPACE_LEN * MAX_NAMESPACES // namespaces: [[u8; NAMESPACE_LEN]; MAX_NAMESPACES]
+    ;
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, EscrowConfig::LEN];
+
+        let (
+            is_initialized,
+            admin,
+            paused,
+            treasury,
+            cancel_fee,
+            init_fee,
+            paused_mints_count,
+            paused_mints_src,
+            extension_fee,
+            exchange_fee_bps,
+            free_fills,
+            namespaces_count,
+            namespaces_src,
+        ) = array_refs![src, 1, 32, 1, 32, 8, 8, 1, 32 * MAX_PAUSED_MINTS, 8, 2, 2, 1, NAMESPACE_LEN * MAX_NAMESPACES];
+
+        let mut paused_mints = [Pubkey::default(); MAX_PAUSED_MINTS];
+        for (paused_mint, paused_mint_src) in paused_mints
+            .iter_mut()
+            .zip(paused_mints_src.chunks_exact(32))
+        {
+            *paused_mint = Pubkey::new(paused_mint_src);
+        }
+
+        let mut namespaces = [[0; NAMESPACE_LEN]; MAX_NAMESPACES];
+        for (namespace, namespace_src) in namespaces
+            .iter_mut()
+            .zip(namespaces_src.chunks_exact(NAMESPACE_LEN))
+        {
+            namespace.copy_from_slice(namespace_src);
+        }
+
+        Ok(EscrowConfig {
+            is_initialized: unpack_bool(is_initialized)?,
+            admin: Pubkey::new_from_array(*admin),
+            paused: unpack_bool(paused)?,
+            treasury: Pubkey::new_from_array(*treasury),
+            cancel_fee: u64::from_le_bytes(*cancel_fee),
+            init_fee: u64::from_le_bytes(*init_fee),
+            paused_mints_count: paused_mints_count[0],
+            paused_mints,
+            extension_fee: u64::from_le_bytes(*extension_fee),
+            exchange_fee_bps: u16::from_le_bytes(*exchange_fee_bps),
+            free_fills: u16::from_le_bytes(*free_fills),
+            namespaces_count: namespaces_count[0],
+            namespaces,
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, EscrowConfig::LEN];
+
+        let (
+            is_initialized_dst,
+            admin_dst,
+            paused_dst,
+            treasury_dst,
+            cancel_fee_dst,
+            init_fee_dst,
+            paused_mints_count_dst,
+            paused_mints_dst,
+            extension_fee_dst,
+            exchange_fee_bps_dst,
+            free_fills_dst,
+            namespaces_count_dst,
+            namespaces_dst,
+        ) = mut_array_refs![dst, 1, 32, 1, 32, 8, 8, 1, 32 * MAX_PAUSED_MINTS, 8, 2, 2, 1, NAMESPACE_LEN * MAX_NAMESPACES];
+
+        let EscrowConfig {
+            is_initialized,
+            admin,
+            paused,
+            treasury,
+            cancel_fee,
+            init_fee,
+            paused_mints_count,
+            paused_mints,
+            extension_fee,
+            exchange_fee_bps,
+            free_fills,
+            namespaces_count,
+            namespaces,
+        } = self;
+
+        is_initialized_dst[0] = *is_initialized as u8;
+        admin_dst.copy_from_slice(admin.as_ref());
+        paused_dst[0] = *paused as u8;
+        treasury_dst.copy_from_slice(treasury.as_ref());
+        *cancel_fee_dst = cancel_fee.to_le_bytes();
+        *init_fee_dst = init_fee.to_le_bytes();
+        paused_mints_count_dst[0] = *paused_mints_count;
+        for (paused_mint, paused_mint_dst) in paused_mints
+            .iter()
+            .zip(paused_mints_dst.chunks_exact_mut(32))
+        {
+            paused_mint_dst.copy_from_slice(paused_mint.as_ref());
+        }
+        *extension_fee_dst = extension_fee.to_le_bytes();
+        *exchange_fee_bps_dst = exchange_fee_bps.to_le_bytes();
+        *free_fills_dst = free_fills.to_le_bytes();
+        namespaces_count_dst[0] = *namespaces_count;
+        for (namespace, namespace_dst) in namespaces
+            .iter()
+            .zip(namespaces_dst.chunks_exact_mut(NAMESPACE_LEN))
+        {
+            namespace_dst.copy_from_slice(namespace);
+        }
+    }
+}
+
+
+/// The mint pairs escrows can be created for, kept in the PDA derived from `pda::PAIR_REGISTRY_SEED`
+#[derive(Debug, PartialEq)]
+pub struct PairRegistry {
+    pub is_initialized: bool,
+    /// The number of allowed pairs, only the first `pairs_count` entries of `pairs` are set
+    pub pairs_count: u8,
+    /// The hashes of the allowed pairs, see `PairRegistry::pair_hash`
+    pub pairs: [[u8; 32]; MAX_ALLOWED_PAIRS],
+}
+
+impl PairRegistry {
+    /// Hashes a pair of the mint deposited by the initializer and the mint they expect to receive
+    pub fn pair_hash(deposit_mint: &Pubkey, expected_mint: &Pubkey) -> [u8; 32] {
+        hashv(&[deposit_mint.as_ref(), expected_mint.as_ref()]).to_bytes()
+    }
+
+    /// Returns whether escrows can be created for the pair
+    pub fn is_allowed(&self, deposit_mint: &Pubkey, expected_mint: &Pubkey) -> bool {
+        let pair_hash = Self::pair_hash(deposit_mint, expected_mint);
+        self.pairs[..self.pairs_count as usize].contains(&pair_hash)
+    }
+
+    /// Allows the pair, doing nothing if it is already allowed
+    pub fn allow(&mut self, deposit_mint: &Pubkey, expected_mint: &Pubkey) -> Result<(), EscrowError> {
+        if self.is_allowed(deposit_mint, expected_mint) {
+            return Ok(());
+        }
+        if self.pairs_count as usize >= MAX_ALLOWED_PAIRS {
+            return Err(EscrowError::PairRegistryFull);
+        }
+        self.pairs[self.pairs_count as usize] = Self::pair_hash(deposit_mint, expected_mint);
+        self.pairs_count += 1;
+        Ok(())
+    }
+
+    /// Disallows the pair, doing nothing if it isn't allowed
+    pub fn disallow(&mut self, deposit_mint: &Pubkey, expected_mint: &Pubkey) {
+        let pair_hash = Self::pair_hash(deposit_mint, expected_mint);
+        let pairs_count = self.pairs_count as usize;
+        if let Some(index) = self.pairs[..pairs_count].iter().position(|pair| *pair == pair_hash) {
+            // Keep the allowed pairs contiguous by moving the last one into the freed entry
+            self.pairs[index] = self.pairs[pairs_count - 1];
+            self.pairs[pairs_count - 1] = [0; 32];
+            self.pairs_count -= 1;
+        }
+    }
+}
+
+impl Sealed for PairRegistry {}
+
+impl IsInitialized for PairRegistry {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for PairRegistry {
+    const LEN: usize =
+        1 // is_initialized: boolean
+        + 1 // pairs_count: u8
+        + 32 * MAX_ALLOWED_PAIRS // pairs: [[u8; 32]; MAX_ALLOWED_PAIRS]
+    ;
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, PairRegistry::LEN];
+
+        let (
+            is_initialized,
+            pairs_count,
+            pairs_src,
+        ) = array_refs![src, 1, 1, 32 * MAX_ALLOWED_PAIRS];
+
+        let mut pairs = [[0; 32]; MAX_ALLOWED_PAIRS];
+        for (pair, pair_src) in pairs.iter_mut().zip(pairs_src.chunks_exact(32)) {
+            pair.copy_from_slice(pair_src);
+        }
+
+        Ok(PairRegistry {
+            is_initialized: unpack_bool(is_initialized)?,
+            pairs_count: pairs_count[0],
+            pairs,
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, PairRegistry::LEN];
+
+        let (
+            is_initialized_dst,
+            pairs_count_dst,
+            pairs_dst,
+        ) = mut_array_refs![dst, 1, 1, 32 * MAX_ALLOWED_PAIRS];
+
+        let PairRegistry {
+            is_initialized,
+            pairs_count,
+            pairs,
+        } = self;
+
+        is_initialized_dst[0] = *is_initialized as u8;
+        pairs_count_dst[0] = *pairs_count;
+        for (pair, pair_dst) in pairs.iter().zip(pairs_dst.chunks_exact_mut(32)) {
+            pair_dst.copy_from_slice(pair);
+        }
+    }
+}
+
+fn unpack_bool(src: &[u8; 1]) -> Result<bool, ProgramError> {
+    match src {
+        [0] => Ok(false),
+        [1] => Ok(true),
+        _ => Err(ProgramError::InvalidAccountData),
+    }
+}
+
+
+/// The open escrows, kept in the PDA derived from `pda::ESCROW_INDEX_SEED` so clients can list them without `getProgramAccounts`
+#[derive(Debug, PartialEq)]
+pub struct EscrowIndex {
+    pub is_initialized: bool,
+    /// The number of listed escrows, only the first `escrows_count` entries of `escrows` are set
+    pub escrows_count: u16,
+    /// The escrow accounts that are still open
+    pub escrows: [Pubkey; MAX_INDEXED_ESCROWS],
+}
+
+impl EscrowIndex {
+    /// Returns the escrows currently listed
+    pub fn listed(&self) -> &[Pubkey] {
+        &self.escrows[..self.escrows_count as usize]
+    }
+
+    /// Lists the escrow, doing nothing if it is already listed
+    pub fn add(&mut self, escrow: &Pubkey) -> Result<(), EscrowError> {
+        if self.listed().contains(escrow) {
+            return Ok(());
+        }
+        if self.escrows_count as usize >= MAX_INDEXED_ESCROWS {
+            return Err(EscrowError::IndexFull);
+        }
+        self.escrows[self.escrows_count as usize] = *escrow;
+        self.escrows_count += 1;
+        Ok(())
+    }
+
+    /// Removes the escrow, doing nothing if it isn't listed such as when it was opened before the index was created
+    pub fn remove(&mut self, escrow: &Pubkey) {
+        let escrows_count = self.escrows_count as usize;
+        if let Some(index) = self.listed().iter().position(|listed| listed == escrow) {
+            // Keep the listed escrows contiguous by moving the last one into the freed entry
+            self.escrows[index] = self.escrows[escrows_count - 1];
+            self.escrows[escrows_count - 1] = Pubkey::default();
+            self.escrows_count -= 1;
+        }
+    }
+}
+
+impl Sealed for EscrowIndex {}
+
+impl IsInitialized for EscrowIndex {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for EscrowIndex {
+    const LEN: usize =
+        1 // is_initialized: boolean
+        + 2 // escrows_count: u16
+        + 32 * MAX_INDEXED_ESCROWS // escrows: [Pubkey; MAX_INDEXED_ESCROWS]
+    ;
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, EscrowIndex::LEN];
+
+        let (
+            is_initialized,
+            escrows_count,
+            escrows_src,
+        ) = array_refs![src, 1, 2, 32 * MAX_INDEXED_ESCROWS];
+
+        let mut escrows = [Pubkey::default(); MAX_INDEXED_ESCROWS];
+        for (escrow, escrow_src) in escrows.iter_mut().zip(escrows_src.chunks_exact(32)) {
+            *escrow = Pubkey::new(escrow_src);
+        }
+
+        Ok(EscrowIndex {
+            is_initialized: unpack_bool(is_initialized)?,
+            escrows_count: u16::from_le_bytes(*escrows_count),
+            escrows,
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, EscrowIndex::LEN];
+
+        let (
+            is_initialized_dst,
+            escrows_count_dst,
+            escrows_dst,
+        ) = mut_array_refs![dst, 1, 2, 32 * MAX_INDEXED_ESCROWS];
+
+        let EscrowIndex {
+            is_initialized,
+            escrows_count,
+            escrows,
+        } = self;
+
+        is_initialized_dst[0] = *is_initialized as u8;
+        *escrows_count_dst = escrows_count.to_le_bytes();
+        for (escrow, escrow_dst) in escrows.iter().zip(escrows_dst.chunks_exact_mut(32)) {
+            escrow_dst.copy_from_slice(escrow.as_ref());
+        }
+    }
+}
+
+
+/// The parameters a maker shares across the escrows opened from it with `InitEscrowFromTemplate`, kept in the PDA derived
+/// from `pda::TEMPLATE_SEED`. A template can't be changed once initialized, the escrows opened from it rely on its parameters
+#[derive(Debug, PartialEq)]
+pub struct EscrowTemplate {
+    pub is_initialized: bool,
+    /// The maker, the only initializer who can open escrows from the template
+    pub authority: Pubkey,
+    /// The mint every escrow opened from the template deposits
+    pub deposit_mint: Pubkey,
+    /// The mint every escrow opened from the template expects
+    pub expected_mint: Pubkey,
+    /// How far the amount a taker asks for may be off its deposit share, zero requires the exact share
+    pub amount_tolerance: u64,
+    /// The program derived address that has to sign every exchange, the default pubkey lets any taker exchange
+    pub settlement_authority: Pubkey,
+}
+
+impl Sealed for EscrowTemplate {}
+
+impl IsInitialized for EscrowTemplate {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for EscrowTemplate {
+    const LEN: usize =
+        1 // is_initialized: boolean
+        + 32 // authority: Pubkey
+        + 32 // deposit_mint: Pubkey
+        + 32 // expected_mint: Pubkey
+        + 8 // amount_tolerance: u64
+        + 32 // settlement_authority: Pubkey
+    ;
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, EscrowTemplate::LEN];
+
+        let (
+            is_initialized,
+            authority,
+            deposit_mint,
+            expected_mint,
+            amount_tolerance,
+            settlement_authority,
+        ) = array_refs![src, 1, 32, 32, 32, 8, 32];
+
+        Ok(EscrowTemplate {
+            is_initialized: unpack_bool(is_initialized)?,
+            authority: Pubkey::new_from_array(*authority),
+            deposit_mint: Pubkey::new_from_array(*deposit_mint),
+            expected_mint: Pubkey::new_from_array(*expected_mint),
+            amount_tolerance: u64::from_le_bytes(*amount_tolerance),
+            settlement_authority: Pubkey::new_from_array(*settlement_authority),
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, EscrowTemplate::LEN];
+
+        let (
+            is_initialized_dst,
+            authority_dst,
+            deposit_mint_dst,
+            expected_mint_dst,
+            amount_tolerance_dst,
+            settlement_authority_dst,
+        ) = mut_array_refs![dst, 1, 32, 32, 32, 8, 32];
+
+        let EscrowTemplate {
+            is_initialized,
+            authority,
+            deposit_mint,
+            expected_mint,
+            amount_tolerance,
+            settlement_authority,
+        } = self;
+
+        is_initialized_dst[0] = *is_initialized as u8;
+        authority_dst.copy_from_slice(authority.as_ref());
+        deposit_mint_dst.copy_from_slice(deposit_mint.as_ref());
+        expected_mint_dst.copy_from_slice(expected_mint.as_ref());
+        *amount_tolerance_dst = amount_tolerance.to_le_bytes();
+        settlement_authority_dst.copy_from_slice(settlement_authority.as_ref());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn decaying_escrow() -> Escrow {
+        let mut escrow = Escrow::UNINITIALIZED;
+        escrow.is_initialized = true;
+        escrow.expected_amount = 1_000;
+        escrow.decay_start_slot = 100;
+        escrow.decay_end_slot = 200;
+        escrow.decay_end_amount = 400;
+        escrow.taker_slots = 1;
+        escrow
+    }
+
+    fn full_escrow() -> Escrow {
+        Escrow {
+            is_initialized: true,
+            initializer_pubkey: Pubkey::new_unique(),
+            temp_token_account_pubkey: Pubkey::new_unique(),
+            initializer_token_to_receive_account_pubkey: Pubkey::new_unique(),
+            expected_amount: u64::MAX,
+            decay_start_slot: u64::MAX - 1,
+            decay_end_slot: u64::MAX,
+            decay_end_amount: 1,
+            nonce: u64::MAX,
+            created_at_slot: u64::MAX,
+            taker_slots: MAX_TAKER_SLOTS as u8,
+            filled_takers_count: MAX_TAKER_SLOTS as u8,
+            filled_takers: [
+                Pubkey::new_unique(),
+                Pubkey::new_unique(),
+                Pubkey::new_unique(),
+                Pubkey::new_unique(),
+            ],
+            use_slot_expiry: true,
+            expiry_timestamp: i64::MIN,
+            expiry_slot: u64::MAX,
+            is_delegated: true,
+            pda_bump: u8::MAX,
+            in_progress: true,
+            hashlock: [0xff; 32],
+            timelock_slot: u64::MAX,
+            callback_program: Pubkey::new_unique(),
+            callback_accounts_len: u8::MAX,
+            deposit_mint: Pubkey::new_unique(),
+            rent_refund_recipient: Pubkey::new_unique(),
+            cancel_delegate: Pubkey::new_unique(),
+            price_num: u64::MAX,
+            price_den: u64::MAX,
+            note_len: MAX_NOTE_LEN as u8,
+            note: [b'x'; MAX_NOTE_LEN],
+            min_amount: u64::MAX,
+            max_amount: u64::MAX,
+            is_nft: true,
+            amount_tolerance: u64::MAX,
+            settlement_authority: Pubkey::new_unique(),
+            reserved_taker: Pubkey::new_unique(),
+            reserved_until_slot: u64::MAX,
+            fills_remaining_free: u16::MAX,
+            is_native_deposit: true,
+            template: Pubkey::new_unique(),
+            namespace: [7; NAMESPACE_LEN],
+        }
+    }
+
+    #[test]
+    fn test_escrow_pack_round_trip() {
+        let mut note = [0; MAX_NOTE_LEN];
+        for (i, byte) in note.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        let escrow = Escrow {
+            note,
+            ..full_escrow()
+        };
+
+        let mut packed = [0; Escrow::LEN];
+        Escrow::pack(escrow.clone(), &mut packed).unwrap();
+        assert_eq!(Escrow::unpack(&packed).unwrap(), escrow);
+    }
+
+    fn random_pubkey(rng: &mut StdRng) -> Pubkey {
+        Pubkey::new_from_array(rng.gen())
+    }
+
+    /// An escrow with every field drawn at random, whatever the program would never write
+    fn random_escrow(rng: &mut StdRng) -> Escrow {
+        let mut note = [0; MAX_NOTE_LEN];
+        rng.fill(&mut note[..]);
+        Escrow {
+            is_initialized: rng.gen(),
+            initializer_pubkey: random_pubkey(rng),
+            temp_token_account_pubkey: random_pubkey(rng),
+            initializer_token_to_receive_account_pubkey: random_pubkey(rng),
+            expected_amount: rng.gen(),
+            decay_start_slot: rng.gen(),
+            decay_end_slot: rng.gen(),
+            decay_end_amount: rng.gen(),
+            nonce: rng.gen(),
+            created_at_slot: rng.gen(),
+            taker_slots: rng.gen(),
+            filled_takers_count: rng.gen(),
+            filled_takers: [
+                random_pubkey(rng),
+                random_pubkey(rng),
+                random_pubkey(rng),
+                random_pubkey(rng),
+            ],
+            use_slot_expiry: rng.gen(),
+            expiry_timestamp: rng.gen(),
+            expiry_slot: rng.gen(),
+            is_delegated: rng.gen(),
+            pda_bump: rng.gen(),
+            in_progress: rng.gen(),
+            hashlock: rng.gen(),
+            timelock_slot: rng.gen(),
+            callback_program: random_pubkey(rng),
+            callback_accounts_len: rng.gen(),
+            deposit_mint: random_pubkey(rng),
+            rent_refund_recipient: random_pubkey(rng),
+            cancel_delegate: random_pubkey(rng),
+            price_num: rng.gen(),
+            price_den: rng.gen(),
+            note_len: rng.gen(),
+            note,
+            min_amount: rng.gen(),
+            max_amount: rng.gen(),
+            is_nft: rng.gen(),
+            amount_tolerance: rng.gen(),
+            settlement_authority: random_pubkey(rng),
+            reserved_taker: random_pubkey(rng),
+            reserved_until_slot: rng.gen(),
+            fills_remaining_free: rng.gen(),
+            is_native_deposit: rng.gen(),
+            template: random_pubkey(rng),
+            namespace: rng.gen(),
+        }
+    }
+
+    #[test]
+    fn test_random_escrows_pack_round_trip() {
+        // A fixed seed, so a failing escrow is the same on every run
+        let mut rng = StdRng::seed_from_u64(127);
+        for _ in 0..1_000 {
+            let escrow = random_escrow(&mut rng);
+            let mut packed = [0; Escrow::LEN];
+            escrow.pack_into_slice(&mut packed);
+            let unpacked = Escrow::unpack_unchecked(&packed).unwrap();
+            assert_eq!(unpacked, escrow);
+
+            let mut repacked = [0; Escrow::LEN];
+            unpacked.pack_into_slice(&mut repacked);
+            assert_eq!(repacked, packed);
+        }
+    }
+
+    #[test]
+    fn test_uninitialized_escrow_packs_to_zeros() {
+        let mut packed = [1; Escrow::LEN];
+        Escrow::UNINITIALIZED.pack_into_slice(&mut packed);
+        assert_eq!(packed, [0; Escrow::LEN]);
+        assert_eq!(
+            Escrow::unpack_unchecked(&packed).unwrap(),
+            Escrow::UNINITIALIZED
+        );
+        assert!(!Escrow::UNINITIALIZED.is_initialized());
+    }
+
+    #[test]
+    fn test_batch_record_is_a_prefix_of_the_packed_escrow() {
+        let escrow = full_escrow();
+        let mut packed = [0; Escrow::LEN];
+        Escrow::pack(escrow.clone(), &mut packed).unwrap();
+
+        let record = &packed[..Escrow::BATCH_RECORD_LEN];
+        assert_eq!(record[0], 1);
+        assert_eq!(&record[1..33], escrow.initializer_pubkey.as_ref());
+        assert_eq!(record[Escrow::BATCH_RECORD_LEN - 2], escrow.taker_slots);
+        assert_eq!(
+            record[Escrow::BATCH_RECORD_LEN - 1],
+            escrow.filled_takers_count
+        );
+        // The filled takers follow right after the record
+        assert_eq!(
+            &packed[Escrow::BATCH_RECORD_LEN..Escrow::BATCH_RECORD_LEN + 32],
+            escrow.filled_takers[0].as_ref()
+        );
+    }
+
+    #[test]
+    fn test_escrow_unpack_rejects_invalid_bools_and_lengths() {
+        let mut packed = [0; Escrow::LEN];
+        Escrow::pack(full_escrow(), &mut packed).unwrap();
+
+        let mut invalid = packed;
+        invalid[0] = 2;
+        assert_eq!(
+            Escrow::unpack(&invalid),
+            Err(ProgramError::InvalidAccountData)
+        );
+        assert_eq!(
+            Escrow::unpack(&packed[..Escrow::LEN - 1]),
+            Err(ProgramError::InvalidAccountData)
+        );
+    }
+
+    #[test]
+    fn test_escrow_config_pack_round_trip() {
+        let mut paused_mints = [Pubkey::default(); MAX_PAUSED_MINTS];
+        for paused_mint in paused_mints.iter_mut() {
+            *paused_mint = Pubkey::new_unique();
+        }
+        let config = EscrowConfig {
+            is_initialized: true,
+            admin: Pubkey::new_unique(),
+            paused: true,
+            treasury: Pubkey::new_unique(),
+            cancel_fee: u64::MAX,
+            init_fee: u64::MAX - 1,
+            paused_mints_count: MAX_PAUSED_MINTS as u8,
+            paused_mints,
+            extension_fee: 1,
+            exchange_fee_bps: MAX_EXCHANGE_FEE_BPS,
+            free_fills: u16::MAX,
+            namespaces_count: MAX_NAMESPACES as u8,
+            namespaces: [[5; NAMESPACE_LEN]; MAX_NAMESPACES],
+        };
+
+        let mut packed = [0; EscrowConfig::LEN];
+        EscrowConfig::pack_into_slice(&config, &mut packed);
+        assert_eq!(EscrowConfig::unpack(&packed).unwrap(), config);
+    }
+
+    #[test]
+    fn test_pair_registry_pack_round_trip() {
+        let mut registry = PairRegistry::unpack_unchecked(&[0; PairRegistry::LEN]).unwrap();
+        registry.is_initialized = true;
+        for _ in 0..MAX_ALLOWED_PAIRS {
+            registry
+                .allow(&Pubkey::new_unique(), &Pubkey::new_unique())
+                .unwrap();
+        }
+
+        let mut packed = [0; PairRegistry::LEN];
+        PairRegistry::pack_into_slice(&registry, &mut packed);
+        assert_eq!(PairRegistry::unpack(&packed).unwrap(), registry);
+    }
+
+    #[test]
+    fn test_escrow_index_pack_round_trip() {
+        let mut index = EscrowIndex::unpack_unchecked(&[0; EscrowIndex::LEN]).unwrap();
+        index.is_initialized = true;
+        for _ in 0..MAX_INDEXED_ESCROWS {
+            index.add(&Pubkey::new_unique()).unwrap();
+        }
+
+        let mut packed = [0; EscrowIndex::LEN];
+        EscrowIndex::pack_into_slice(&index, &mut packed);
+        assert_eq!(EscrowIndex::unpack(&packed).unwrap(), index);
+    }
+
+    #[test]
+    fn test_escrow_template_pack_round_trip() {
+        let template = EscrowTemplate {
+            is_initialized: true,
+            authority: Pubkey::new_unique(),
+            deposit_mint: Pubkey::new_unique(),
+            expected_mint: Pubkey::new_unique(),
+            amount_tolerance: u64::MAX,
+            settlement_authority: Pubkey::new_unique(),
+        };
+
+        let mut packed = [0; EscrowTemplate::LEN];
+        EscrowTemplate::pack_into_slice(&template, &mut packed);
+        assert_eq!(EscrowTemplate::unpack(&packed).unwrap(), template);
+    }
+
+    #[test]
+    fn test_required_amount_without_decay_schedule() {
+        let mut escrow = decaying_escrow();
+        escrow.decay_end_slot = 0;
+        for slot in [0, 100, 150, 200, u64::MAX] {
+            assert_eq!(escrow.required_amount(slot), Ok(1_000));
+        }
+    }
+
+    #[test]
+    fn test_required_amount_at_decay_start() {
+        let escrow = decaying_escrow();
+        assert_eq!(escrow.required_amount(0), Ok(1_000));
+        assert_eq!(escrow.required_amount(100), Ok(1_000));
+        assert_eq!(escrow.required_amount(101), Ok(994));
+    }
+
+    #[test]
+    fn test_required_amount_mid_decay() {
+        let escrow = decaying_escrow();
+        assert_eq!(escrow.required_amount(150), Ok(700));
+        assert_eq!(escrow.required_amount(175), Ok(550));
+        // the decayed amount is rounded down, so the required amount rounds in the initializer's favor
+        let mut escrow = decaying_escrow();
+        escrow.decay_end_slot = 103;
+        assert_eq!(escrow.required_amount(101), Ok(800));
+        assert_eq!(escrow.required_amount(102), Ok(600));
+    }
+
+    #[test]
+    fn test_required_amount_at_decay_end() {
+        let escrow = decaying_escrow();
+        assert_eq!(escrow.required_amount(199), Ok(406));
+        assert_eq!(escrow.required_amount(200), Ok(400));
+        assert_eq!(escrow.required_amount(u64::MAX), Ok(400));
+    }
+
+    #[test]
+    fn test_required_amount_never_overflows() {
+        let mut escrow = decaying_escrow();
+        escrow.expected_amount = u64::MAX;
+        escrow.decay_end_amount = 0;
+        escrow.decay_start_slot = 0;
+        escrow.decay_end_slot = u64::MAX;
+        assert_eq!(escrow.required_amount(u64::MAX / 2), Ok(u64::MAX / 2 + 1));
+        assert_eq!(escrow.required_amount(u64::MAX - 1), Ok(1));
+    }
+
+    /// Returns the payments of every taker filling the escrow in turn at the given slot
+    fn split_payments(mut escrow: Escrow, slot: u64) -> Vec<u64> {
+        (0..escrow.taker_slots)
+            .map(|_| {
+                let payment = escrow.fill_payment_amount(0, slot).unwrap();
+                escrow.filled_takers_count += 1;
+                payment
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_fill_payment_amount_splits_the_required_amount() {
+        let mut escrow = decaying_escrow();
+        escrow.decay_end_slot = 0;
+        escrow.taker_slots = 3;
+        assert_eq!(split_payments(escrow.clone(), 0), vec![333, 333, 334]);
+
+        escrow.taker_slots = 4;
+        escrow.expected_amount = 1;
+        assert_eq!(split_payments(escrow.clone(), 0), vec![0, 0, 0, 1]);
+
+        escrow.taker_slots = 1;
+        escrow.expected_amount = u64::MAX;
+        assert_eq!(split_payments(escrow, 0), vec![u64::MAX]);
+    }
+
+    #[test]
+    fn test_fill_payment_amount_follows_the_decay_across_slots() {
+        let mut escrow = decaying_escrow();
+        escrow.taker_slots = 3;
+        assert_eq!(split_payments(escrow.clone(), 100), vec![333, 333, 334]);
+        assert_eq!(split_payments(escrow.clone(), 150), vec![233, 233, 234]);
+        assert_eq!(split_payments(escrow.clone(), 200), vec![133, 133, 134]);
+
+        // Each taker pays the share of the required amount at its own slot
+        let first = escrow.fill_payment_amount(0, 100).unwrap();
+        escrow.filled_takers_count = 1;
+        let second = escrow.fill_payment_amount(0, 150).unwrap();
+        escrow.filled_takers_count = 2;
+        let third = escrow.fill_payment_amount(0, 200).unwrap();
+        assert_eq!((first, second, third), (333, 233, 134));
+    }
+
+    #[test]
+    fn test_fill_payment_amount_with_a_price_ratio_across_decimals() {
+        let mut escrow = decaying_escrow();
+        escrow.decay_end_slot = 0;
+        // 1.5 expected tokens per deposited token, both with the same decimals
+        escrow.price_num = 3;
+        escrow.price_den = 2;
+        assert_eq!(escrow.fill_payment_amount(333, 0), Ok(499));
+        assert_eq!(escrow.fill_payment_amount(1, 0), Ok(1));
+
+        // 1 expected token with 9 decimals per deposited token with 6 decimals
+        escrow.price_num = 1_000;
+        escrow.price_den = 1;
+        assert_eq!(escrow.fill_payment_amount(2_500_000, 0), Ok(2_500_000_000));
+
+        // 1 expected token with 6 decimals per deposited token with 9 decimals, rounded down
+        escrow.price_num = 1;
+        escrow.price_den = 1_000;
+        assert_eq!(escrow.fill_payment_amount(2_500_999, 0), Ok(2_500));
+        assert_eq!(escrow.fill_payment_amount(u64::MAX, 0), Ok(u64::MAX / 1_000));
+
+        // The ratio ignores the slot
+        assert_eq!(escrow.fill_payment_amount(2_500_999, 150), Ok(2_500));
+
+        // A fill too small to cost anything is rejected rather than given away
+        assert_eq!(escrow.fill_payment_amount(999, 0), Err(EscrowError::InvalidAmount));
+        assert_eq!(escrow.fill_payment_amount(1_000, 0), Ok(1));
+    }
+    #[test]
+    fn test_fill_amounts_at_u64_max() {
+        let mut escrow = decaying_escrow();
+        escrow.decay_end_slot = 0;
+        // Splitting the largest expected amount never overflows and the payments still add up to it
+        escrow.expected_amount = u64::MAX;
+        escrow.taker_slots = 7;
+        let payments = split_payments(escrow.clone(), 0);
+        assert!(payments.iter().all(|payment| *payment - u64::MAX / 7 <= 1));
+        assert_eq!(payments.iter().map(|payment| *payment as u128).sum::<u128>(), u64::MAX as u128);
+        assert_eq!(escrow.fill_deposit_amount(u64::MAX), Ok(u64::MAX / 7));
+
+        // A filled escrow has no share left to split the deposit into
+        escrow.filled_takers_count = 7;
+        assert_eq!(escrow.fill_deposit_amount(u64::MAX), Err(EscrowError::AmountOverflow));
+
+        // A price ratio pricing the fill above u64::MAX fails rather than wrapping around
+        escrow.filled_takers_count = 0;
+        escrow.taker_slots = 1;
+        escrow.price_num = 2;
+        escrow.price_den = 1;
+        assert_eq!(escrow.fill_payment_amount(u64::MAX, 0), Err(EscrowError::AmountOverflow));
+        assert_eq!(escrow.fill_payment_amount(u64::MAX / 2, 0), Ok(u64::MAX - 1));
+
+        // Only the payment has to fit into a u64, not the product of the fill and the ratio
+        escrow.price_num = u64::MAX;
+        escrow.price_den = u64::MAX;
+        assert_eq!(escrow.fill_payment_amount(u64::MAX, 0), Ok(u64::MAX));
+    }
+
+    #[test]
+    fn test_status_of_open_and_partially_filled_escrows() {
+        let mut escrow = decaying_escrow();
+        escrow.taker_slots = 2;
+        assert_eq!(escrow.status(&Clock::default()), Status::Open);
+
+        escrow.filled_takers_count = 1;
+        assert_eq!(escrow.status(&Clock::default()), Status::PartiallyFilled);
+    }
+
+    #[test]
+    fn test_status_of_expired_escrows() {
+        let mut escrow = decaying_escrow();
+        escrow.expiry_timestamp = 1_000;
+        let clock = |unix_timestamp| Clock {
+            unix_timestamp,
+            ..Clock::default()
+        };
+        assert_eq!(escrow.status(&clock(999)), Status::Open);
+        assert_eq!(escrow.status(&clock(1_000)), Status::Expired);
+
+        // Expiry wins over a partial fill, the remaining shares can't be taken anymore
+        escrow.taker_slots = 2;
+        escrow.filled_takers_count = 1;
+        assert_eq!(escrow.status(&clock(1_000)), Status::Expired);
     }
 }
\ No newline at end of file
@@ -0,0 +1,262 @@
+use crate::{error::EscrowError, state::Escrow};
+
+/// Returns how much the taker has to send for filling `fill_amount` of the deposited tokens at the given slot,
+/// out of the `pda_balance` the PDA can move. Both `processor_exchange` and off-chain clients compute the payment
+/// with it, so they always agree. Fails unless `fill_amount` is the next taker's share, give or take the escrow's
+/// `amount_tolerance`, the payment is then computed on the share itself. Asking for more than the share fails with
+/// `TransferFeeMismatch`, the PDA holds less than the taker expects, as when a transfer fee took a cut of the deposit,
+/// asking for less fails with `ExpectedAmountMismatch`.
+/// An escrow with a payment band takes the taker's `payment` instead, failing with `ExpectedAmountMismatch` outside the band
+pub fn compute_taker_payment(
+    escrow: &Escrow,
+    pda_balance: u64,
+    fill_amount: u64,
+    payment: u64,
+    slot: u64,
+) -> Result<u64, EscrowError> {
+    let share = escrow.fill_deposit_amount(pda_balance)?;
+    if fill_amount > share {
+        if fill_amount - share > escrow.amount_tolerance {
+            return Err(EscrowError::TransferFeeMismatch);
+        }
+    } else if share - fill_amount > escrow.amount_tolerance {
+        return Err(EscrowError::ExpectedAmountMismatch);
+    }
+
+    if escrow.max_amount != 0 {
+        if payment < escrow.min_amount || payment > escrow.max_amount {
+            return Err(EscrowError::ExpectedAmountMismatch);
+        }
+        return Ok(payment);
+    }
+
+    escrow.fill_payment_amount(share, slot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn escrow(taker_slots: u8) -> Escrow {
+        let mut escrow = Escrow::UNINITIALIZED;
+        escrow.is_initialized = true;
+        escrow.expected_amount = 500;
+        escrow.taker_slots = taker_slots;
+        escrow
+    }
+
+    fn ratio_escrow(taker_slots: u8, price_num: u64, price_den: u64) -> Escrow {
+        let mut escrow = escrow(taker_slots);
+        escrow.price_num = price_num;
+        escrow.price_den = price_den;
+        escrow
+    }
+
+    #[test]
+    fn test_ratio_payment_of_a_single_taker() {
+        let escrow = ratio_escrow(1, 3, 2);
+        assert_eq!(
+            compute_taker_payment(&escrow, 1_000, 1_000, 0, 0),
+            Ok(1_500)
+        );
+        assert_eq!(compute_taker_payment(&escrow, 333, 333, 0, 0), Ok(499));
+
+        // The taker's payment argument is only read with a payment band
+        assert_eq!(
+            compute_taker_payment(&escrow, 1_000, 1_000, 7, 0),
+            Ok(1_500)
+        );
+    }
+
+    #[test]
+    fn test_ratio_payment_of_split_escrow() {
+        let mut escrow = ratio_escrow(2, 3, 2);
+        assert_eq!(compute_taker_payment(&escrow, 1_001, 500, 0, 0), Ok(750));
+
+        // The last taker receives and pays for everything that is left
+        escrow.filled_takers_count = 1;
+        assert_eq!(compute_taker_payment(&escrow, 501, 501, 0, 0), Ok(751));
+    }
+
+    #[test]
+    fn test_ratio_payment_requires_the_share() {
+        let escrow = ratio_escrow(2, 3, 2);
+        assert_eq!(
+            compute_taker_payment(&escrow, 1_000, 499, 0, 0),
+            Err(EscrowError::ExpectedAmountMismatch)
+        );
+        assert_eq!(
+            compute_taker_payment(&escrow, 1_000, 1_000, 0, 0),
+            Err(EscrowError::TransferFeeMismatch)
+        );
+    }
+
+    #[test]
+    fn test_ratio_payment_rounding_to_zero_is_rejected() {
+        let escrow = ratio_escrow(1, 1, 4);
+        assert_eq!(compute_taker_payment(&escrow, 4, 4, 0, 0), Ok(1));
+        assert_eq!(
+            compute_taker_payment(&escrow, 3, 3, 0, 0),
+            Err(EscrowError::InvalidAmount)
+        );
+    }
+
+    #[test]
+    fn test_ratio_payment_overflow() {
+        let escrow = ratio_escrow(1, u64::MAX, 1);
+        assert_eq!(compute_taker_payment(&escrow, 1, 1, 0, 0), Ok(u64::MAX));
+        assert_eq!(
+            compute_taker_payment(&escrow, 2, 2, 0, 0),
+            Err(EscrowError::AmountOverflow)
+        );
+    }
+
+    fn tolerant_escrow(taker_slots: u8, amount_tolerance: u64) -> Escrow {
+        let mut escrow = escrow(taker_slots);
+        escrow.amount_tolerance = amount_tolerance;
+        escrow
+    }
+
+    #[test]
+    fn test_tolerance_accepts_amounts_on_both_sides_of_the_share() {
+        let escrow = tolerant_escrow(1, 5);
+        for fill_amount in [995, 999, 1_000, 1_001, 1_005] {
+            assert_eq!(
+                compute_taker_payment(&escrow, 1_000, fill_amount, 0, 0),
+                Ok(500)
+            );
+        }
+    }
+
+    #[test]
+    fn test_tolerance_rejects_amounts_past_it() {
+        let escrow = tolerant_escrow(1, 5);
+        for fill_amount in [0, 994] {
+            assert_eq!(
+                compute_taker_payment(&escrow, 1_000, fill_amount, 0, 0),
+                Err(EscrowError::ExpectedAmountMismatch)
+            );
+        }
+        for fill_amount in [1_006, u64::MAX] {
+            assert_eq!(
+                compute_taker_payment(&escrow, 1_000, fill_amount, 0, 0),
+                Err(EscrowError::TransferFeeMismatch)
+            );
+        }
+
+        // Without a tolerance only the exact share is accepted
+        let escrow = tolerant_escrow(1, 0);
+        assert_eq!(compute_taker_payment(&escrow, 1_000, 1_000, 0, 0), Ok(500));
+        assert_eq!(
+            compute_taker_payment(&escrow, 1_000, 999, 0, 0),
+            Err(EscrowError::ExpectedAmountMismatch)
+        );
+        assert_eq!(
+            compute_taker_payment(&escrow, 1_000, 1_001, 0, 0),
+            Err(EscrowError::TransferFeeMismatch)
+        );
+    }
+
+    #[test]
+    fn test_tolerance_prices_the_share_of_a_split_escrow() {
+        // The payment is computed on the 500 tokens share, not the amount the taker asked for
+        let mut escrow = ratio_escrow(2, 3, 2);
+        escrow.amount_tolerance = 10;
+        for fill_amount in [490, 510] {
+            assert_eq!(
+                compute_taker_payment(&escrow, 1_000, fill_amount, 0, 0),
+                Ok(750)
+            );
+        }
+        assert_eq!(
+            compute_taker_payment(&escrow, 1_000, 489, 0, 0),
+            Err(EscrowError::ExpectedAmountMismatch)
+        );
+    }
+
+    #[test]
+    fn test_tolerance_as_large_as_the_amounts() {
+        let escrow = tolerant_escrow(1, u64::MAX);
+        for fill_amount in [0, u64::MAX] {
+            assert_eq!(
+                compute_taker_payment(&escrow, u64::MAX, fill_amount, 0, 0),
+                Ok(500)
+            );
+        }
+    }
+
+    fn band_escrow(min_amount: u64, max_amount: u64) -> Escrow {
+        let mut escrow = escrow(1);
+        escrow.min_amount = min_amount;
+        escrow.max_amount = max_amount;
+        escrow
+    }
+
+    #[test]
+    fn test_deposit_short_of_the_asked_amount_is_a_transfer_fee_mismatch() {
+        // A 1% transfer fee left 990 of the 1_000 deposited tokens with the PDA, a taker expecting the whole deposit
+        // learns the PDA holds less, asking for the balance the PDA actually got still fills the escrow
+        let escrow = escrow(1);
+        assert_eq!(
+            compute_taker_payment(&escrow, 990, 1_000, 0, 0),
+            Err(EscrowError::TransferFeeMismatch)
+        );
+        assert_eq!(compute_taker_payment(&escrow, 990, 990, 0, 0), Ok(500));
+
+        // A tolerance as large as the fee accepts the amount asked for before the fee
+        let escrow = tolerant_escrow(1, 10);
+        assert_eq!(compute_taker_payment(&escrow, 990, 1_000, 0, 0), Ok(500));
+    }
+
+    #[test]
+    fn test_band_payment_below_the_band() {
+        let escrow = band_escrow(400, 600);
+        for payment in [0, 1, 399] {
+            assert_eq!(
+                compute_taker_payment(&escrow, 1_000, 1_000, payment, 0),
+                Err(EscrowError::ExpectedAmountMismatch)
+            );
+        }
+    }
+
+    #[test]
+    fn test_band_payment_inside_the_band() {
+        let escrow = band_escrow(400, 600);
+        for payment in [400, 500, 600] {
+            assert_eq!(
+                compute_taker_payment(&escrow, 1_000, 1_000, payment, 0),
+                Ok(payment)
+            );
+        }
+
+        // The band replaces the expected amount and its decay
+        let mut escrow = band_escrow(0, u64::MAX);
+        escrow.decay_end_slot = 10;
+        escrow.decay_end_amount = 1;
+        assert_eq!(compute_taker_payment(&escrow, 1_000, 1_000, 0, 5), Ok(0));
+        assert_eq!(
+            compute_taker_payment(&escrow, 1_000, 1_000, u64::MAX, 5),
+            Ok(u64::MAX)
+        );
+    }
+
+    #[test]
+    fn test_band_payment_above_the_band() {
+        let escrow = band_escrow(400, 600);
+        for payment in [601, u64::MAX] {
+            assert_eq!(
+                compute_taker_payment(&escrow, 1_000, 1_000, payment, 0),
+                Err(EscrowError::ExpectedAmountMismatch)
+            );
+        }
+    }
+
+    #[test]
+    fn test_band_payment_still_requires_the_share() {
+        let escrow = band_escrow(400, 600);
+        assert_eq!(
+            compute_taker_payment(&escrow, 1_000, 999, 500, 0),
+            Err(EscrowError::ExpectedAmountMismatch)
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use solana_program::{
+    account_info::AccountInfo, program_error::ProgramError, program_option::COption, pubkey::Pubkey,
+};
+
+use spl_token::state::Account as TokenAccount;
+
+use crate::{constants::TOKEN_PROGRAM_ID, error::EscrowError};
+
+/// Fails with `WrongOwner` unless the account is owned by the token program, checked before its data is unpacked
+pub fn require_owned_by_token_program(account_info: &AccountInfo) -> Result<(), ProgramError> {
+    if *account_info.owner != TOKEN_PROGRAM_ID {
+        return Err(EscrowError::WrongOwner.into());
+    }
+    Ok(())
+}
+
+/// Validation helpers shared by the processor for the token accounts it is given
+pub trait TokenAccountExt {
+    /// Fails with `WrongMint` unless the token account holds tokens of the given mint
+    fn require_mint(&self, mint: &Pubkey) -> Result<(), ProgramError>;
+
+    /// Fails with `WrongAuthority` unless the token account is owned by the given authority
+    fn require_authority(&self, authority: &Pubkey) -> Result<(), ProgramError>;
+
+    /// Returns the amount the given delegate can still transfer out of the token account, zero when it isn't the delegate
+    fn delegated_amount_to(&self, delegate: &Pubkey) -> u64;
+
+    /// Fails with `InvalidAmount` when the token account holds wrapped SOL but its token balance
+    /// doesn't match the lamports it holds above the rent exempt reserve, does nothing for other mints
+    fn require_synced_native(&self, account_info: &AccountInfo) -> Result<(), ProgramError>;
+}
+
+impl TokenAccountExt for TokenAccount {
+    fn require_mint(&self, mint: &Pubkey) -> Result<(), ProgramError> {
+        if self.mint != *mint {
+            return Err(EscrowError::WrongMint.into());
+        }
+        Ok(())
+    }
+
+    fn require_authority(&self, authority: &Pubkey) -> Result<(), ProgramError> {
+        if self.owner != *authority {
+            return Err(EscrowError::WrongAuthority.into());
+        }
+        Ok(())
+    }
+
+    fn delegated_amount_to(&self, delegate: &Pubkey) -> u64 {
+        if self.delegate != COption::Some(*delegate) {
+            return 0;
+        }
+        self.delegated_amount
+    }
+
+    fn require_synced_native(&self, account_info: &AccountInfo) -> Result<(), ProgramError> {
+        if self.mint != spl_token::native_mint::id() {
+            return Ok(());
+        }
+
+        let rent_exempt_reserve = match self.is_native {
+            COption::Some(rent_exempt_reserve) => rent_exempt_reserve,
+            COption::None => return Err(EscrowError::InvalidAmount.into()),
+        };
+        let wrapped_lamports = account_info
+            .lamports()
+            .checked_sub(rent_exempt_reserve)
+            .ok_or(EscrowError::InvalidAmount)?;
+        if wrapped_lamports != self.amount {
+            return Err(EscrowError::InvalidAmount.into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spl_token::state::AccountState;
+
+    fn mint() -> Pubkey {
+        Pubkey::new_from_array([1; 32])
+    }
+
+    fn owner() -> Pubkey {
+        Pubkey::new_from_array([2; 32])
+    }
+
+    fn token_account() -> TokenAccount {
+        TokenAccount {
+            mint: mint(),
+            owner: owner(),
+            amount: 1_000,
+            state: AccountState::Initialized,
+            ..TokenAccount::default()
+        }
+    }
+
+    /// Runs `f` with an account of the given owner holding the given lamports
+    fn with_account<F: FnOnce(&AccountInfo)>(owner: Pubkey, mut lamports: u64, f: F) {
+        let key = Pubkey::new_from_array([3; 32]);
+        let mut data = vec![];
+        let account_info = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &owner,
+            false,
+            0,
+        );
+        f(&account_info);
+    }
+
+    #[test]
+    fn test_require_owned_by_token_program() {
+        with_account(TOKEN_PROGRAM_ID, 0, |account_info| {
+            assert_eq!(require_owned_by_token_program(account_info), Ok(()));
+        });
+        with_account(owner(), 0, |account_info| {
+            assert_eq!(
+                require_owned_by_token_program(account_info),
+                Err(EscrowError::WrongOwner.into())
+            );
+        });
+    }
+
+    #[test]
+    fn test_require_mint() {
+        assert_eq!(token_account().require_mint(&mint()), Ok(()));
+        assert_eq!(
+            token_account().require_mint(&owner()),
+            Err(EscrowError::WrongMint.into())
+        );
+    }
+
+    #[test]
+    fn test_require_authority() {
+        assert_eq!(token_account().require_authority(&owner()), Ok(()));
+        assert_eq!(
+            token_account().require_authority(&mint()),
+            Err(EscrowError::WrongAuthority.into())
+        );
+    }
+
+    #[test]
+    fn test_delegated_amount_to() {
+        let delegate = Pubkey::new_from_array([4; 32]);
+        let delegated = TokenAccount {
+            delegate: COption::Some(delegate),
+            delegated_amount: 400,
+            ..token_account()
+        };
+        assert_eq!(delegated.delegated_amount_to(&delegate), 400);
+        assert_eq!(delegated.delegated_amount_to(&owner()), 0);
+        assert_eq!(token_account().delegated_amount_to(&delegate), 0);
+    }
+
+    #[test]
+    fn test_require_synced_native() {
+        let rent_exempt_reserve = 2_039_280;
+        let native = TokenAccount {
+            mint: spl_token::native_mint::id(),
+            is_native: COption::Some(rent_exempt_reserve),
+            ..token_account()
+        };
+
+        with_account(
+            TOKEN_PROGRAM_ID,
+            rent_exempt_reserve + 1_000,
+            |account_info| {
+                assert_eq!(native.require_synced_native(account_info), Ok(()));
+                // Other mints don't wrap lamports, their balance is never compared to them
+                assert_eq!(token_account().require_synced_native(account_info), Ok(()));
+            },
+        );
+
+        // Lamports sent to the account without a `SyncNative` afterwards
+        with_account(
+            TOKEN_PROGRAM_ID,
+            rent_exempt_reserve + 2_000,
+            |account_info| {
+                assert_eq!(
+                    native.require_synced_native(account_info),
+                    Err(EscrowError::InvalidAmount.into())
+                );
+            },
+        );
+
+        // Less than the rent exempt reserve
+        with_account(TOKEN_PROGRAM_ID, rent_exempt_reserve - 1, |account_info| {
+            assert_eq!(
+                native.require_synced_native(account_info),
+                Err(EscrowError::InvalidAmount.into())
+            );
+        });
+
+        // An account of the native mint that isn't native
+        let not_native = TokenAccount {
+            is_native: COption::None,
+            ..native
+        };
+        with_account(
+            TOKEN_PROGRAM_ID,
+            rent_exempt_reserve + 1_000,
+            |account_info| {
+                assert_eq!(
+                    not_native.require_synced_native(account_info),
+                    Err(EscrowError::InvalidAmount.into())
+                );
+            },
+        );
+    }
+}
@@ -0,0 +1,392 @@
+mod common;
+
+use common::*;
+use solana_escrow::{
+    error::EscrowError,
+    instruction::{self, InitEscrowArgs},
+    pda::{find_config_address, find_pair_registry_address, find_stats_address, NAMESPACE_LEN},
+    state::{
+        EscrowConfig, EscrowStats, PairRegistry, MAX_ALLOWED_PAIRS, MAX_NAMESPACES,
+        MAX_PAUSED_MINTS,
+    },
+};
+use solana_program::{
+    instruction::{AccountMeta, InstructionError},
+    program_pack::Pack,
+    pubkey::Pubkey,
+};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::{
+    account::Account,
+    signature::{Keypair, Signer},
+};
+
+/// Starts the test validator with the config `InitConfig` creates for `admin`, preloaded since the test validator
+/// can't run a program creating an account. The settings are then changed through the admin instructions
+async fn start_with_config(admin: &Keypair) -> ProgramTestContext {
+    start_with_config_and_accounts(admin, vec![]).await
+}
+
+/// Starts the test validator with the config of `admin` and the preloaded accounts
+async fn start_with_config_and_accounts(
+    admin: &Keypair,
+    mut accounts: Vec<(Pubkey, Account)>,
+) -> ProgramTestContext {
+    let config = EscrowConfig {
+        is_initialized: true,
+        admin: admin.pubkey(),
+        paused: false,
+        treasury: Pubkey::default(),
+        cancel_fee: 0,
+        init_fee: 0,
+        paused_mints_count: 0,
+        paused_mints: [Pubkey::default(); MAX_PAUSED_MINTS],
+        extension_fee: 0,
+        exchange_fee_bps: 0,
+        free_fills: 0,
+        namespaces_count: 0,
+        namespaces: [[0; NAMESPACE_LEN]; MAX_NAMESPACES],
+    };
+    accounts.push((
+        find_config_address(&program_id()).0,
+        program_account(config),
+    ));
+    start_with_accounts(accounts).await
+}
+
+/// Has the admin send the fees to a new, funded treasury
+async fn set_treasury(ctx: &mut ProgramTestContext, admin: &Keypair) -> Pubkey {
+    let treasury = Pubkey::new_unique();
+    let rent = minimum_balance(ctx, 0).await;
+    fund(ctx, &treasury, rent).await;
+    let ix = instruction::set_treasury(&program_id(), &admin.pubkey(), &treasury).unwrap();
+    process(ctx, &[ix], &[admin]).await.unwrap();
+    treasury
+}
+
+async fn set_paused(
+    ctx: &mut ProgramTestContext,
+    admin: &Keypair,
+    paused: bool,
+) -> Result<(), solana_sdk::transport::TransportError> {
+    let ix = instruction::set_paused(&program_id(), &admin.pubkey(), paused).unwrap();
+    process(ctx, &[ix], &[admin]).await
+}
+
+/// Starts the test validator with the stats account `InitStats` creates, preloaded since the test validator
+/// can't run a program creating an account
+async fn start_with_stats() -> ProgramTestContext {
+    let stats = EscrowStats {
+        is_initialized: true,
+        total_created: 0,
+        total_filled: 0,
+        total_volume: 0,
+    };
+    start_with_accounts(vec![(
+        find_stats_address(&program_id()).0,
+        program_account(stats),
+    )])
+    .await
+}
+
+async fn stats(ctx: &mut ProgramTestContext) -> EscrowStats {
+    let account = get_account(ctx, &find_stats_address(&program_id()).0)
+        .await
+        .unwrap();
+    EscrowStats::unpack(&account.data).unwrap()
+}
+
+#[tokio::test]
+async fn test_stats_count_the_escrows_created_and_filled() {
+    let mut ctx = start_with_stats().await;
+
+    let mut escrows = vec![];
+    for _ in 0..3 {
+        let escrow = setup_escrow(&mut ctx, EscrowParams::default()).await;
+        let mut ix = escrow.init_instruction(&escrow.temp_token_account);
+        ix.accounts
+            .push(AccountMeta::new(find_stats_address(&program_id()).0, false));
+        process(&mut ctx, &[ix], &[&escrow.initializer])
+            .await
+            .unwrap();
+        escrows.push(escrow);
+    }
+    // An escrow opened without the stats account isn't counted
+    let unlisted = open_escrow(&mut ctx, EscrowParams::default()).await;
+    assert_eq!(stats(&mut ctx).await.total_created, 3);
+
+    // Without a template, callback or settlement authority the stats account comes last
+    for escrow in &escrows[..2] {
+        let taker = create_taker(&mut ctx, escrow, 500).await;
+        let mut ix = escrow.exchange_instruction(&taker, 1_000, 0);
+        ix.accounts
+            .push(AccountMeta::new(find_stats_address(&program_id()).0, false));
+        process(&mut ctx, &[ix], &[&taker.keypair]).await.unwrap();
+    }
+    let taker = create_taker(&mut ctx, &unlisted, 500).await;
+    let ix = unlisted.exchange_instruction(&taker, 1_000, 0);
+    process(&mut ctx, &[ix], &[&taker.keypair]).await.unwrap();
+
+    let stats = stats(&mut ctx).await;
+    assert_eq!(stats.total_created, 3);
+    assert_eq!(stats.total_filled, 2);
+    assert_eq!(stats.total_volume, 2_000);
+    assert!(
+        escrow_state(&mut ctx, &escrows[2].escrow)
+            .await
+            .is_initialized
+    );
+}
+
+#[tokio::test]
+async fn test_pausing_blocks_every_exchange_until_unpaused() {
+    let admin = Keypair::new();
+    let mut ctx = start_with_config(&admin).await;
+    let escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+
+    set_paused(&mut ctx, &admin, true).await.unwrap();
+    let ix = escrow.exchange_instruction(&taker, 1_000, 0);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::ProgramPaused as u32)
+    );
+
+    // Only the admin lifts the pause
+    let error = instruction_error(set_paused(&mut ctx, &Keypair::new(), false).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::WrongAuthority as u32)
+    );
+    let ix = escrow.exchange_instruction(&taker, 1_000, 0);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::ProgramPaused as u32)
+    );
+
+    set_paused(&mut ctx, &admin, false).await.unwrap();
+    let ix = escrow.exchange_instruction(&taker, 1_000, 0);
+    process(&mut ctx, &[ix], &[&taker.keypair]).await.unwrap();
+    assert_eq!(token_balance(&mut ctx, &taker.receive_account).await, 1_000);
+    assert_eq!(token_balance(&mut ctx, &escrow.receive_account).await, 500);
+}
+
+#[tokio::test]
+async fn test_pair_registry_only_lets_allowed_pairs_open_escrows() {
+    let admin = Keypair::new();
+    let registry = PairRegistry {
+        is_initialized: true,
+        pairs_count: 0,
+        pairs: [[0; 32]; MAX_ALLOWED_PAIRS],
+    };
+    let mut ctx = start_with_config_and_accounts(
+        &admin,
+        vec![(
+            find_pair_registry_address(&program_id()).0,
+            program_account(registry),
+        )],
+    )
+    .await;
+    let allowed = setup_escrow(&mut ctx, EscrowParams::default()).await;
+    let disallowed = setup_escrow(&mut ctx, EscrowParams::default()).await;
+
+    // Only the admin allows pairs
+    let stranger = Keypair::new();
+    let ix = instruction::set_pair_allowed(
+        &program_id(),
+        &stranger.pubkey(),
+        &allowed.deposit_mint,
+        &allowed.expected_mint,
+        true,
+    )
+    .unwrap();
+    let error = instruction_error(process(&mut ctx, &[ix], &[&stranger]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::WrongAuthority as u32)
+    );
+
+    let ix = instruction::set_pair_allowed(
+        &program_id(),
+        &admin.pubkey(),
+        &allowed.deposit_mint,
+        &allowed.expected_mint,
+        true,
+    )
+    .unwrap();
+    process(&mut ctx, &[ix], &[&admin]).await.unwrap();
+
+    let ix = allowed.init_instruction(&allowed.temp_token_account);
+    process(&mut ctx, &[ix], &[&allowed.initializer])
+        .await
+        .unwrap();
+    assert!(escrow_state(&mut ctx, &allowed.escrow).await.is_initialized);
+
+    let ix = disallowed.init_instruction(&disallowed.temp_token_account);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&disallowed.initializer]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::PairNotAllowed as u32)
+    );
+    let account = get_account(&mut ctx, &disallowed.escrow).await.unwrap();
+    assert!(account.data.iter().all(|byte| *byte == 0));
+}
+
+#[tokio::test]
+async fn test_cancel_fee_goes_to_the_treasury_out_of_the_rent_refund() {
+    let admin = Keypair::new();
+    let mut ctx = start_with_config(&admin).await;
+    let treasury = set_treasury(&mut ctx, &admin).await;
+    let ix = instruction::set_cancel_fee(&program_id(), &admin.pubkey(), 10_000).unwrap();
+    process(&mut ctx, &[ix], &[&admin]).await.unwrap();
+    let escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+
+    let initializer = escrow.initializer.pubkey();
+    let cancel_instruction = |treasury: &Pubkey| {
+        instruction::cancel(
+            &program_id(),
+            &initializer,
+            &escrow.temp_token_account,
+            &escrow.initializer_deposit_account,
+            &escrow.escrow,
+            treasury,
+            &initializer,
+        )
+        .unwrap()
+    };
+
+    // The fee only goes to the config's treasury
+    let ix = cancel_instruction(&Pubkey::new_unique());
+    let error = instruction_error(process(&mut ctx, &[ix], &[&escrow.initializer]).await);
+    assert_eq!(error, InstructionError::InvalidAccountData);
+
+    let escrow_rent = lamports(&mut ctx, &escrow.escrow).await;
+    let temp_token_account_rent = lamports(&mut ctx, &escrow.temp_token_account).await;
+    let treasury_before = lamports(&mut ctx, &treasury).await;
+    let initializer_before = lamports(&mut ctx, &initializer).await;
+    let ix = cancel_instruction(&treasury);
+    process(&mut ctx, &[ix], &[&escrow.initializer])
+        .await
+        .unwrap();
+
+    assert_eq!(
+        lamports(&mut ctx, &treasury).await,
+        treasury_before + 10_000
+    );
+    assert_eq!(
+        lamports(&mut ctx, &initializer).await,
+        initializer_before + escrow_rent + temp_token_account_rent - 10_000
+    );
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.initializer_deposit_account).await,
+        1_000
+    );
+}
+
+#[tokio::test]
+async fn test_init_fee_is_paid_to_the_treasury() {
+    let admin = Keypair::new();
+    let mut ctx = start_with_config(&admin).await;
+    let treasury = set_treasury(&mut ctx, &admin).await;
+    let ix = instruction::set_init_fee(&program_id(), &admin.pubkey(), 5_000).unwrap();
+    process(&mut ctx, &[ix], &[&admin]).await.unwrap();
+    let escrow = setup_escrow(&mut ctx, EscrowParams::default()).await;
+
+    let initializer = escrow.initializer.pubkey();
+    let init_instruction = |treasury: &Pubkey| {
+        instruction::init_escrow(
+            &program_id(),
+            &initializer,
+            &escrow.temp_token_account,
+            &escrow.receive_account,
+            treasury,
+            escrow.init_args(),
+            false,
+        )
+        .unwrap()
+    };
+
+    // The fee only goes to the config's treasury
+    let ix = init_instruction(&Pubkey::new_unique());
+    let error = instruction_error(process(&mut ctx, &[ix], &[&escrow.initializer]).await);
+    assert_eq!(error, InstructionError::InvalidAccountData);
+
+    let treasury_before = lamports(&mut ctx, &treasury).await;
+    let initializer_before = lamports(&mut ctx, &initializer).await;
+    let ix = init_instruction(&treasury);
+    process(&mut ctx, &[ix], &[&escrow.initializer])
+        .await
+        .unwrap();
+
+    assert_eq!(lamports(&mut ctx, &treasury).await, treasury_before + 5_000);
+    assert_eq!(
+        lamports(&mut ctx, &initializer).await,
+        initializer_before - 5_000
+    );
+    // The escrow account still holds its rent exempt balance
+    let escrow_account = get_account(&mut ctx, &escrow.escrow).await.unwrap();
+    assert_eq!(
+        escrow_account.lamports,
+        minimum_balance(&mut ctx, escrow_account.data.len()).await
+    );
+    assert!(escrow_state(&mut ctx, &escrow.escrow).await.is_initialized);
+}
+
+#[tokio::test]
+async fn test_extension_fee_is_paid_to_the_treasury() {
+    let admin = Keypair::new();
+    let mut ctx = start_with_config(&admin).await;
+    let treasury = set_treasury(&mut ctx, &admin).await;
+    let ix = instruction::set_extension_fee(&program_id(), &admin.pubkey(), 2_000).unwrap();
+    process(&mut ctx, &[ix], &[&admin]).await.unwrap();
+
+    // An expiry far enough out for the test validator's clock
+    let expiry = 4_000_000_000;
+    let escrow = setup_escrow(&mut ctx, EscrowParams::default()).await;
+    let args = InitEscrowArgs {
+        expiry_timestamp: expiry,
+        ..escrow.init_args()
+    };
+    let ix = escrow.init_instruction_with(&escrow.temp_token_account, args);
+    process(&mut ctx, &[ix], &[&escrow.initializer])
+        .await
+        .unwrap();
+
+    let initializer = escrow.initializer.pubkey();
+    let extend_instruction = |treasury: &Pubkey| {
+        instruction::extend_expiry(
+            &program_id(),
+            &initializer,
+            &escrow.escrow,
+            treasury,
+            expiry as u64 + 1_000,
+        )
+        .unwrap()
+    };
+
+    // The fee only goes to the config's treasury
+    let ix = extend_instruction(&Pubkey::new_unique());
+    let error = instruction_error(process(&mut ctx, &[ix], &[&escrow.initializer]).await);
+    assert_eq!(error, InstructionError::InvalidAccountData);
+
+    let treasury_before = lamports(&mut ctx, &treasury).await;
+    let initializer_before = lamports(&mut ctx, &initializer).await;
+    let ix = extend_instruction(&treasury);
+    process(&mut ctx, &[ix], &[&escrow.initializer])
+        .await
+        .unwrap();
+
+    assert_eq!(lamports(&mut ctx, &treasury).await, treasury_before + 2_000);
+    assert_eq!(
+        lamports(&mut ctx, &initializer).await,
+        initializer_before - 2_000
+    );
+    assert_eq!(
+        escrow_state(&mut ctx, &escrow.escrow)
+            .await
+            .expiry_timestamp,
+        expiry + 1_000
+    );
+}
@@ -0,0 +1,138 @@
+mod common;
+
+use common::*;
+use solana_escrow::instruction::InitEscrowArgs;
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, InstructionError},
+    pubkey::Pubkey,
+    rent::Rent,
+};
+use solana_program_test::{processor, ProgramTestContext};
+use solana_sdk::account::Account;
+use std::convert::TryInto;
+
+fn recorder_id() -> Pubkey {
+    Pubkey::new_from_array([10; 32])
+}
+
+/// The account the recorder adds up the filled amounts it is called with into
+fn record() -> Pubkey {
+    Pubkey::new_from_array([11; 32])
+}
+
+/// A callback program adding the filled amount it is called with to the record account.
+/// Accounts: the record account
+fn process_record_fill(
+    _program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    data: &[u8],
+) -> ProgramResult {
+    let record = &accounts[0];
+    let mut amount = [0; 8];
+    amount.copy_from_slice(data);
+    let mut record_data = record.try_borrow_mut_data()?;
+    let recorded = u64::from_le_bytes(record_data[..8].try_into().unwrap());
+    record_data.copy_from_slice(&(recorded + u64::from_le_bytes(amount)).to_le_bytes());
+    Ok(())
+}
+
+async fn start_with_recorder() -> ProgramTestContext {
+    start_with_programs(
+        vec![("recorder", recorder_id(), processor!(process_record_fill))],
+        vec![(
+            record(),
+            Account {
+                lamports: Rent::default().minimum_balance(8),
+                data: vec![0; 8],
+                owner: recorder_id(),
+                executable: false,
+                rent_epoch: 0,
+            },
+        )],
+    )
+    .await
+}
+
+/// Opens an escrow calling the recorder after every fill
+async fn open_escrow_with_callback(ctx: &mut ProgramTestContext, taker_slots: u8) -> TestEscrow {
+    let escrow = setup_escrow(
+        ctx,
+        EscrowParams {
+            taker_slots,
+            ..EscrowParams::default()
+        },
+    )
+    .await;
+    let args = InitEscrowArgs {
+        callback_program: recorder_id(),
+        callback_accounts_len: 1,
+        ..escrow.init_args()
+    };
+    let ix = escrow.init_instruction_with(&escrow.temp_token_account, args);
+    process(ctx, &[ix], &[&escrow.initializer]).await.unwrap();
+    escrow
+}
+
+async fn recorded_amount(ctx: &mut ProgramTestContext) -> u64 {
+    let data = get_account(ctx, &record()).await.unwrap().data;
+    u64::from_le_bytes(data[..8].try_into().unwrap())
+}
+
+#[tokio::test]
+async fn test_exchange_calls_the_callback_with_each_filled_amount() {
+    let mut ctx = start_with_recorder().await;
+    let escrow = open_escrow_with_callback(&mut ctx, 2).await;
+
+    // Without a template, settlement authority or stats the callback accounts come last
+    let first_taker = create_taker(&mut ctx, &escrow, 250).await;
+    let mut ix = escrow.exchange_instruction(&first_taker, 500, 0);
+    ix.accounts.extend_from_slice(&[
+        AccountMeta::new_readonly(recorder_id(), false),
+        AccountMeta::new(record(), false),
+    ]);
+    process(&mut ctx, &[ix], &[&first_taker.keypair])
+        .await
+        .unwrap();
+    assert_eq!(recorded_amount(&mut ctx).await, 500);
+
+    let second_taker = create_taker(&mut ctx, &escrow, 250).await;
+    let mut ix = escrow.exchange_instruction(&second_taker, 500, 0);
+    ix.accounts.extend_from_slice(&[
+        AccountMeta::new_readonly(recorder_id(), false),
+        AccountMeta::new(record(), false),
+    ]);
+    process(&mut ctx, &[ix], &[&second_taker.keypair])
+        .await
+        .unwrap();
+    assert_eq!(recorded_amount(&mut ctx).await, 1_000);
+    assert!(get_account(&mut ctx, &escrow.escrow).await.is_none());
+}
+
+#[tokio::test]
+async fn test_exchange_rejects_another_callback_program() {
+    let mut ctx = start_with_recorder().await;
+    let escrow = open_escrow_with_callback(&mut ctx, 1).await;
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+
+    // A taker can't swap in a program the maker didn't pick
+    let mut ix = escrow.exchange_instruction(&taker, 1_000, 0);
+    ix.accounts.extend_from_slice(&[
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new(record(), false),
+    ]);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await);
+    assert_eq!(error, InstructionError::IncorrectProgramId);
+
+    // Nor leave the callback out
+    let ix = escrow.exchange_instruction(&taker, 1_000, 0);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await);
+    assert_eq!(error, InstructionError::NotEnoughAccountKeys);
+
+    assert_eq!(recorded_amount(&mut ctx).await, 0);
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.temp_token_account).await,
+        1_000
+    );
+}
@@ -0,0 +1,96 @@
+mod common;
+
+use common::*;
+use solana_escrow::{
+    error::EscrowError,
+    instruction::{self, InitEscrowArgs},
+};
+use solana_program::{instruction::Instruction, instruction::InstructionError, pubkey::Pubkey};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::signature::{Keypair, Signer};
+
+/// Opens an escrow `delegate` may cancel on the initializer's behalf
+async fn open_escrow_with_delegate(ctx: &mut ProgramTestContext, delegate: &Keypair) -> TestEscrow {
+    let escrow = setup_escrow(ctx, EscrowParams::default()).await;
+    let args = InitEscrowArgs {
+        cancel_delegate: delegate.pubkey(),
+        ..escrow.init_args()
+    };
+    let ix = escrow.init_instruction_with(&escrow.temp_token_account, args);
+    process(ctx, &[ix], &[&escrow.initializer]).await.unwrap();
+    escrow
+}
+
+fn cancel_by_delegate_instruction(
+    escrow: &TestEscrow,
+    delegate: &Pubkey,
+    initializer_token_account: &Pubkey,
+) -> Instruction {
+    let initializer = escrow.initializer.pubkey();
+    instruction::cancel_by_delegate(
+        &program_id(),
+        delegate,
+        &initializer,
+        &escrow.temp_token_account,
+        initializer_token_account,
+        &escrow.escrow,
+        &initializer,
+        &initializer,
+    )
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_cancel_delegate_cancels_the_escrow() {
+    let mut ctx = start().await;
+    let delegate = Keypair::new();
+    let escrow = open_escrow_with_delegate(&mut ctx, &delegate).await;
+
+    let ix = cancel_by_delegate_instruction(
+        &escrow,
+        &delegate.pubkey(),
+        &escrow.initializer_deposit_account,
+    );
+    process(&mut ctx, &[ix], &[&delegate]).await.unwrap();
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.initializer_deposit_account).await,
+        1_000
+    );
+    assert!(get_account(&mut ctx, &escrow.escrow).await.is_none());
+}
+
+#[tokio::test]
+async fn test_cancel_by_delegate_rejects_a_stranger_and_another_destination() {
+    let mut ctx = start().await;
+    let delegate = Keypair::new();
+    let escrow = open_escrow_with_delegate(&mut ctx, &delegate).await;
+
+    // Only the delegate the initializer picked cancels for them
+    let stranger = Keypair::new();
+    let ix = cancel_by_delegate_instruction(
+        &escrow,
+        &stranger.pubkey(),
+        &escrow.initializer_deposit_account,
+    );
+    let error = instruction_error(process(&mut ctx, &[ix], &[&stranger]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::WrongAuthority as u32)
+    );
+
+    // The delegate can't have the deposit sent to an account of its own
+    let delegate_account =
+        create_token_account(&mut ctx, &escrow.deposit_mint, &delegate.pubkey()).await;
+    let ix = cancel_by_delegate_instruction(&escrow, &delegate.pubkey(), &delegate_account);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&delegate]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::WrongAuthority as u32)
+    );
+
+    assert_eq!(token_balance(&mut ctx, &delegate_account).await, 0);
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.temp_token_account).await,
+        1_000
+    );
+}
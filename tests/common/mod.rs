@@ -0,0 +1,539 @@
+//! Shared setup of the integration tests: the program runs natively inside `solana-program-test`,
+//! next to a natively built token program, with every escrow opened by its own fresh initializer
+#![allow(dead_code)]
+
+use solana_escrow::{
+    instruction::{self, InitEscrowArgs},
+    pda::{find_escrow_address, find_fee_vault_address},
+    processor::Processor,
+    state::Escrow,
+};
+use solana_program::{
+    instruction::{Instruction, InstructionError},
+    program_option::COption,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction,
+};
+use solana_program_test::{processor, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::Account,
+    process_instruction::ProcessInstructionWithContext,
+    signature::{keypair_from_seed, Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+    transport::TransportError,
+};
+use spl_token::state::{Account as TokenAccount, Mint};
+
+/// The address the escrow program is deployed at in the tests
+pub fn program_id() -> Pubkey {
+    Pubkey::new_from_array([7; 32])
+}
+
+/// Starts a test validator running the escrow program and the token program
+pub async fn start() -> ProgramTestContext {
+    start_with_accounts(vec![]).await
+}
+
+/// How many escrows a test can set up, each with an initializer of its own, enough to fill the escrow index and more
+const INITIALIZERS: u8 = 40;
+
+/// The nonces every initializer can open an escrow with
+const NONCES: u64 = 2;
+
+/// The initializer of the `index`-th escrow set up in a test, a fixed keypair so its escrow accounts are known upfront
+pub fn initializer(index: u8) -> Keypair {
+    keypair_from_seed(&[index + 1; 32]).unwrap()
+}
+
+/// Starts a test validator like `start` with the accounts already in place, native programs can't create
+/// the program's PDA accounts with `InitConfig` and the like, so they are written upfront instead
+pub async fn start_with_accounts(accounts: Vec<(Pubkey, Account)>) -> ProgramTestContext {
+    start_with_programs(vec![], accounts).await
+}
+
+/// A program loaded next to the escrow program by `start_with_programs`, its name, address and `processor!`
+pub type MockProgram = (&'static str, Pubkey, Option<ProcessInstructionWithContext>);
+
+/// Starts a test validator like `start_with_accounts` running mock programs as well, e.g. the callback
+/// program of an escrow
+pub async fn start_with_programs(
+    programs: Vec<MockProgram>,
+    accounts: Vec<(Pubkey, Account)>,
+) -> ProgramTestContext {
+    let mut program_test = ProgramTest::new(
+        "solana_escrow",
+        program_id(),
+        processor!(Processor::processor),
+    );
+    program_test.add_program(
+        "spl_token",
+        spl_token::id(),
+        processor!(spl_token::processor::Processor::process),
+    );
+    for (name, address, processor) in programs {
+        program_test.add_program(name, address, processor);
+    }
+    // The escrow accounts `InitEscrow` would create are PDAs as well, they are written uninitialized
+    for index in 0..INITIALIZERS {
+        for nonce in 0..NONCES {
+            let (escrow, _) =
+                find_escrow_address(&initializer(index).pubkey(), nonce, &program_id());
+            program_test.add_account(escrow, program_account(Escrow::UNINITIALIZED));
+        }
+    }
+    // The test validator doesn't come with the native mint, wrapped SOL accounts are initialized against it
+    let mut native_mint = vec![0; Mint::LEN];
+    Mint::pack(
+        Mint {
+            mint_authority: COption::None,
+            supply: 0,
+            decimals: spl_token::native_mint::DECIMALS,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        },
+        &mut native_mint,
+    )
+    .unwrap();
+    program_test.add_account(
+        spl_token::native_mint::id(),
+        Account {
+            lamports: Rent::default().minimum_balance(Mint::LEN),
+            data: native_mint,
+            owner: spl_token::id(),
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+    for (address, account) in accounts {
+        program_test.add_account(address, account);
+    }
+    program_test.start_with_context().await
+}
+
+/// Returns a rent exempt account of the escrow program holding the packed state
+pub fn program_account<T: Pack>(state: T) -> Account {
+    let mut data = vec![0; T::LEN];
+    T::pack(state, &mut data).unwrap();
+    Account {
+        lamports: Rent::default().minimum_balance(T::LEN),
+        data,
+        owner: program_id(),
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+/// Sends the instructions in one transaction paid by the context's payer, always under a fresh blockhash
+/// so sending the same instructions twice runs them twice
+pub async fn process(
+    ctx: &mut ProgramTestContext,
+    instructions: &[Instruction],
+    signers: &[&Keypair],
+) -> Result<(), TransportError> {
+    let blockhash = loop {
+        let blockhash = ctx.banks_client.get_recent_blockhash().await?;
+        if blockhash != ctx.last_blockhash {
+            break blockhash;
+        }
+    };
+    ctx.last_blockhash = blockhash;
+
+    let mut all_signers = vec![&ctx.payer];
+    all_signers.extend_from_slice(signers);
+    let transaction = Transaction::new_signed_with_payer(
+        instructions,
+        Some(&ctx.payer.pubkey()),
+        &all_signers,
+        blockhash,
+    );
+    ctx.banks_client.process_transaction(transaction).await
+}
+
+/// Returns the error the first failing instruction of the transaction returned
+pub fn instruction_error(result: Result<(), TransportError>) -> InstructionError {
+    match result {
+        Err(TransportError::TransactionError(TransactionError::InstructionError(_, error))) => {
+            error
+        }
+        other => panic!("expected an instruction error, got {:?}", other),
+    }
+}
+
+pub async fn get_account(ctx: &mut ProgramTestContext, address: &Pubkey) -> Option<Account> {
+    ctx.banks_client.get_account(*address).await.unwrap()
+}
+
+pub async fn lamports(ctx: &mut ProgramTestContext, address: &Pubkey) -> u64 {
+    get_account(ctx, address)
+        .await
+        .map_or(0, |account| account.lamports)
+}
+
+pub async fn token_account(ctx: &mut ProgramTestContext, address: &Pubkey) -> TokenAccount {
+    let account = get_account(ctx, address).await.expect("token account");
+    TokenAccount::unpack(&account.data).unwrap()
+}
+
+pub async fn token_balance(ctx: &mut ProgramTestContext, address: &Pubkey) -> u64 {
+    token_account(ctx, address).await.amount
+}
+
+pub async fn escrow_state(ctx: &mut ProgramTestContext, address: &Pubkey) -> Escrow {
+    let account = get_account(ctx, address).await.expect("escrow account");
+    Escrow::unpack(&account.data).unwrap()
+}
+
+pub async fn minimum_balance(ctx: &mut ProgramTestContext, space: usize) -> u64 {
+    ctx.banks_client
+        .get_rent()
+        .await
+        .unwrap()
+        .minimum_balance(space)
+}
+
+/// Sends lamports from the payer, e.g. so a fresh keypair can pay for the accounts it creates
+pub async fn fund(ctx: &mut ProgramTestContext, address: &Pubkey, lamports: u64) {
+    let ix = system_instruction::transfer(&ctx.payer.pubkey(), address, lamports);
+    process(ctx, &[ix], &[]).await.unwrap();
+}
+
+/// Creates a mint whose mint authority is the payer
+pub async fn create_mint(ctx: &mut ProgramTestContext, decimals: u8) -> Pubkey {
+    create_mint_with_freeze_authority(ctx, decimals, None).await
+}
+
+/// Creates a mint whose mint authority is the payer and that `freeze_authority` can freeze token accounts of
+pub async fn create_mint_with_freeze_authority(
+    ctx: &mut ProgramTestContext,
+    decimals: u8,
+    freeze_authority: Option<&Pubkey>,
+) -> Pubkey {
+    let mint = Keypair::new();
+    let payer = ctx.payer.pubkey();
+    let ixs = [
+        system_instruction::create_account(
+            &payer,
+            &mint.pubkey(),
+            minimum_balance(ctx, Mint::LEN).await,
+            Mint::LEN as u64,
+            &spl_token::id(),
+        ),
+        spl_token::instruction::initialize_mint(
+            &spl_token::id(),
+            &mint.pubkey(),
+            &payer,
+            freeze_authority,
+            decimals,
+        )
+        .unwrap(),
+    ];
+    process(ctx, &ixs, &[&mint]).await.unwrap();
+    mint.pubkey()
+}
+
+pub async fn create_token_account(
+    ctx: &mut ProgramTestContext,
+    mint: &Pubkey,
+    owner: &Pubkey,
+) -> Pubkey {
+    let account = Keypair::new();
+    let payer = ctx.payer.pubkey();
+    let ixs = [
+        system_instruction::create_account(
+            &payer,
+            &account.pubkey(),
+            minimum_balance(ctx, TokenAccount::LEN).await,
+            TokenAccount::LEN as u64,
+            &spl_token::id(),
+        ),
+        spl_token::instruction::initialize_account(
+            &spl_token::id(),
+            &account.pubkey(),
+            mint,
+            owner,
+        )
+        .unwrap(),
+    ];
+    process(ctx, &ixs, &[&account]).await.unwrap();
+    account.pubkey()
+}
+
+pub async fn mint_to(ctx: &mut ProgramTestContext, mint: &Pubkey, account: &Pubkey, amount: u64) {
+    let ix = spl_token::instruction::mint_to(
+        &spl_token::id(),
+        mint,
+        account,
+        &ctx.payer.pubkey(),
+        &[],
+        amount,
+    )
+    .unwrap();
+    process(ctx, &[ix], &[]).await.unwrap();
+}
+
+/// The parameters of an escrow opened by `open_escrow`, the escrow has no optional feature unless set
+#[derive(Clone)]
+pub struct EscrowParams {
+    /// The deposited amount of the deposit mint
+    pub deposit: u64,
+    /// The expected amount of the expected mint
+    pub amount: u64,
+    pub deposit_decimals: u8,
+    pub expected_decimals: u8,
+    pub taker_slots: u8,
+    pub price_num: u64,
+    pub price_den: u64,
+    pub min_amount: u64,
+    pub max_amount: u64,
+    pub note: Vec<u8>,
+    pub list_in_index: bool,
+}
+
+impl Default for EscrowParams {
+    fn default() -> Self {
+        Self {
+            deposit: 1_000,
+            amount: 500,
+            deposit_decimals: 6,
+            expected_decimals: 6,
+            taker_slots: 1,
+            price_num: 0,
+            price_den: 0,
+            min_amount: 0,
+            max_amount: 0,
+            note: vec![],
+            list_in_index: false,
+        }
+    }
+}
+
+/// An escrow and the accounts of its initializer
+pub struct TestEscrow {
+    pub initializer: Keypair,
+    pub deposit_mint: Pubkey,
+    pub expected_mint: Pubkey,
+    /// The initializer's token account of the deposit mint, empty, where cancelled deposits go back to
+    pub initializer_deposit_account: Pubkey,
+    /// The temp token account handed over to the PDA
+    pub temp_token_account: Pubkey,
+    /// The initializer's token account of the expected mint
+    pub receive_account: Pubkey,
+    /// A token account of the expected mint owned by its fee vault, collecting the exchange fee
+    pub fee_account: Pubkey,
+    pub escrow: Pubkey,
+    pub nonce: u64,
+    pub params: EscrowParams,
+}
+
+impl TestEscrow {
+    /// The `InitEscrow` parameters of the escrow, every optional feature left out
+    pub fn init_args(&self) -> InitEscrowArgs {
+        InitEscrowArgs {
+            amount: self.params.amount,
+            nonce: self.nonce,
+            taker_slots: self.params.taker_slots,
+            price_num: self.params.price_num,
+            price_den: self.params.price_den,
+            min_amount: self.params.min_amount,
+            max_amount: self.params.max_amount,
+            note: self.params.note.clone(),
+            list_in_index: self.params.list_in_index,
+            ..InitEscrowArgs::default()
+        }
+    }
+
+    pub fn init_instruction(&self, temp_token_account: &Pubkey) -> Instruction {
+        self.init_instruction_with(temp_token_account, self.init_args())
+    }
+
+    /// An `InitEscrow` of the escrow with parameters other than `init_args`, e.g. the optional features
+    pub fn init_instruction_with(
+        &self,
+        temp_token_account: &Pubkey,
+        args: InitEscrowArgs,
+    ) -> Instruction {
+        let initializer = self.initializer.pubkey();
+        instruction::init_escrow(
+            &program_id(),
+            &initializer,
+            temp_token_account,
+            &self.receive_account,
+            &initializer,
+            args,
+            false,
+        )
+        .unwrap()
+    }
+
+    pub fn cancel_instruction(&self) -> Instruction {
+        let initializer = self.initializer.pubkey();
+        instruction::cancel(
+            &program_id(),
+            &initializer,
+            &self.temp_token_account,
+            &self.initializer_deposit_account,
+            &self.escrow,
+            &initializer,
+            &initializer,
+        )
+        .unwrap()
+    }
+
+    pub fn exchange_instruction(
+        &self,
+        taker: &TestTaker,
+        amount: u64,
+        payment: u64,
+    ) -> Instruction {
+        self.exchange_instruction_with(taker, amount, payment, 0, None)
+    }
+
+    /// An `Exchange` with the taker's deadline and the hashlock's preimage
+    pub fn exchange_instruction_with(
+        &self,
+        taker: &TestTaker,
+        amount: u64,
+        payment: u64,
+        taker_deadline_slot: u64,
+        preimage: Option<[u8; 32]>,
+    ) -> Instruction {
+        instruction::exchange(
+            &program_id(),
+            &taker.keypair.pubkey(),
+            &taker.send_account,
+            &taker.receive_account,
+            &self.temp_token_account,
+            &self.initializer.pubkey(),
+            &self.receive_account,
+            &self.escrow,
+            &self.deposit_mint,
+            &self.expected_mint,
+            &self.initializer.pubkey(),
+            &self.fee_account,
+            false,
+            amount,
+            payment,
+            taker_deadline_slot,
+            preimage,
+            None,
+            &[],
+            None,
+            false,
+        )
+        .unwrap()
+    }
+}
+
+/// Creates the mints and the initializer's accounts of an escrow, whose escrow account `start` loaded uninitialized
+pub async fn setup_escrow(ctx: &mut ProgramTestContext, params: EscrowParams) -> TestEscrow {
+    let deposit_mint = create_mint(ctx, params.deposit_decimals).await;
+    let expected_mint = create_mint(ctx, params.expected_decimals).await;
+    setup_escrow_with_mints(ctx, params, &deposit_mint, &expected_mint).await
+}
+
+pub async fn setup_escrow_with_mints(
+    ctx: &mut ProgramTestContext,
+    params: EscrowParams,
+    deposit_mint: &Pubkey,
+    expected_mint: &Pubkey,
+) -> TestEscrow {
+    let escrow = setup_unfunded_escrow(ctx, params, deposit_mint, expected_mint).await;
+    mint_to(
+        ctx,
+        deposit_mint,
+        &escrow.temp_token_account,
+        escrow.params.deposit,
+    )
+    .await;
+    escrow
+}
+
+/// Sets up an escrow of native SOL, whose temp token account of the native mint `InitEscrowWithSol` funds
+pub async fn setup_sol_escrow(ctx: &mut ProgramTestContext, params: EscrowParams) -> TestEscrow {
+    let expected_mint = create_mint(ctx, params.expected_decimals).await;
+    setup_unfunded_escrow(ctx, params, &spl_token::native_mint::id(), &expected_mint).await
+}
+
+/// Creates the initializer's accounts of an escrow, its temp token account left empty
+async fn setup_unfunded_escrow(
+    ctx: &mut ProgramTestContext,
+    params: EscrowParams,
+    deposit_mint: &Pubkey,
+    expected_mint: &Pubkey,
+) -> TestEscrow {
+    let initializer = next_initializer(ctx).await;
+    fund(ctx, &initializer.pubkey(), 1_000_000_000).await;
+
+    let initializer_deposit_account =
+        create_token_account(ctx, deposit_mint, &initializer.pubkey()).await;
+    let temp_token_account = create_token_account(ctx, deposit_mint, &initializer.pubkey()).await;
+    let receive_account = create_token_account(ctx, expected_mint, &initializer.pubkey()).await;
+    let fee_account = create_token_account(
+        ctx,
+        expected_mint,
+        &find_fee_vault_address(expected_mint, &program_id()).0,
+    )
+    .await;
+
+    let nonce = 0;
+    let (escrow, _) = find_escrow_address(&initializer.pubkey(), nonce, &program_id());
+
+    TestEscrow {
+        initializer,
+        deposit_mint: *deposit_mint,
+        expected_mint: *expected_mint,
+        initializer_deposit_account,
+        temp_token_account,
+        receive_account,
+        fee_account,
+        escrow,
+        nonce,
+        params,
+    }
+}
+
+/// The first initializer that hasn't been funded yet, so every escrow of a test has its own
+async fn next_initializer(ctx: &mut ProgramTestContext) -> Keypair {
+    for index in 0..INITIALIZERS {
+        let initializer = initializer(index);
+        if get_account(ctx, &initializer.pubkey()).await.is_none() {
+            return initializer;
+        }
+    }
+    panic!("Every initializer has an escrow already, raise INITIALIZERS");
+}
+
+/// Sets up an escrow and initializes it
+pub async fn open_escrow(ctx: &mut ProgramTestContext, params: EscrowParams) -> TestEscrow {
+    let escrow = setup_escrow(ctx, params).await;
+    let ix = escrow.init_instruction(&escrow.temp_token_account);
+    process(ctx, &[ix], &[&escrow.initializer]).await.unwrap();
+    escrow
+}
+
+/// A taker and its token accounts for the two mints of an escrow
+pub struct TestTaker {
+    pub keypair: Keypair,
+    /// The taker's token account of the expected mint, holding what the taker pays with
+    pub send_account: Pubkey,
+    /// The taker's token account of the deposit mint
+    pub receive_account: Pubkey,
+}
+
+pub async fn create_taker(
+    ctx: &mut ProgramTestContext,
+    escrow: &TestEscrow,
+    funds: u64,
+) -> TestTaker {
+    let keypair = Keypair::new();
+    let send_account = create_token_account(ctx, &escrow.expected_mint, &keypair.pubkey()).await;
+    mint_to(ctx, &escrow.expected_mint, &send_account, funds).await;
+    let receive_account = create_token_account(ctx, &escrow.deposit_mint, &keypair.pubkey()).await;
+    TestTaker {
+        keypair,
+        send_account,
+        receive_account,
+    }
+}
@@ -0,0 +1,119 @@
+mod common;
+
+use common::*;
+use solana_escrow::{error::EscrowError, instruction::InitEscrowArgs, pda::PdaInfo, state::Escrow};
+use solana_program::{instruction::InstructionError, program_option::COption, program_pack::Pack};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::signature::Signer;
+
+/// Opens an escrow whose initializer keeps the deposit, the PDA only being approved as its delegate
+async fn open_delegated_escrow(ctx: &mut ProgramTestContext) -> TestEscrow {
+    let escrow = setup_escrow(ctx, EscrowParams::default()).await;
+    let pda = PdaInfo::find(&program_id(), &escrow.escrow);
+    let initializer = escrow.initializer.pubkey();
+    let ix_approve = spl_token::instruction::approve(
+        &spl_token::id(),
+        &escrow.temp_token_account,
+        &pda.key,
+        &initializer,
+        &[&initializer],
+        1_000,
+    )
+    .unwrap();
+    let args = InitEscrowArgs {
+        use_delegation: true,
+        ..escrow.init_args()
+    };
+    let ix = escrow.init_instruction_with(&escrow.temp_token_account, args);
+    process(ctx, &[ix_approve, ix], &[&escrow.initializer])
+        .await
+        .unwrap();
+    escrow
+}
+
+#[tokio::test]
+async fn test_delegated_escrow_pays_the_taker_out_of_the_initializers_account() {
+    let mut ctx = start().await;
+    let escrow = open_delegated_escrow(&mut ctx).await;
+    let pda = PdaInfo::find(&program_id(), &escrow.escrow);
+
+    // The initializer keeps custody of the deposit
+    assert!(escrow_state(&mut ctx, &escrow.escrow).await.is_delegated);
+    let deposit_account = token_account(&mut ctx, &escrow.temp_token_account).await;
+    assert_eq!(deposit_account.owner, escrow.initializer.pubkey());
+    assert_eq!(deposit_account.delegate, COption::Some(pda.key));
+    assert_eq!(deposit_account.delegated_amount, 1_000);
+
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+    let ix = escrow.exchange_instruction(&taker, 1_000, 0);
+    process(&mut ctx, &[ix], &[&taker.keypair]).await.unwrap();
+    assert_eq!(token_balance(&mut ctx, &taker.receive_account).await, 1_000);
+    assert_eq!(token_balance(&mut ctx, &escrow.receive_account).await, 500);
+
+    // The emptied token account is the initializer's, it isn't closed with the escrow
+    let deposit_account = token_account(&mut ctx, &escrow.temp_token_account).await;
+    assert_eq!(deposit_account.owner, escrow.initializer.pubkey());
+    assert_eq!(deposit_account.amount, 0);
+    assert!(get_account(&mut ctx, &escrow.escrow).await.is_none());
+}
+
+#[tokio::test]
+async fn test_delegated_escrow_rejects_an_exchange_once_the_delegation_is_revoked() {
+    let mut ctx = start().await;
+    let escrow = open_delegated_escrow(&mut ctx).await;
+    let initializer = escrow.initializer.pubkey();
+    let ix_revoke = spl_token::instruction::revoke(
+        &spl_token::id(),
+        &escrow.temp_token_account,
+        &initializer,
+        &[&initializer],
+    )
+    .unwrap();
+    process(&mut ctx, &[ix_revoke], &[&escrow.initializer])
+        .await
+        .unwrap();
+
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+    let ix = escrow.exchange_instruction(&taker, 1_000, 0);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::DelegationRevoked as u32)
+    );
+    assert_eq!(token_balance(&mut ctx, &taker.send_account).await, 500);
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.temp_token_account).await,
+        1_000
+    );
+
+    // The initializer can still close the escrow, the tokens never left their account
+    let ix = escrow.cancel_instruction();
+    process(&mut ctx, &[ix], &[&escrow.initializer])
+        .await
+        .unwrap();
+    assert!(get_account(&mut ctx, &escrow.escrow).await.is_none());
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.temp_token_account).await,
+        1_000
+    );
+}
+
+#[tokio::test]
+async fn test_delegated_init_requires_the_pda_as_delegate() {
+    let mut ctx = start().await;
+    let escrow = setup_escrow(&mut ctx, EscrowParams::default()).await;
+    let args = InitEscrowArgs {
+        use_delegation: true,
+        ..escrow.init_args()
+    };
+    let ix = escrow.init_instruction_with(&escrow.temp_token_account, args);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&escrow.initializer]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::WrongAuthority as u32)
+    );
+    assert_eq!(
+        get_account(&mut ctx, &escrow.escrow).await.unwrap().data,
+        vec![0; Escrow::LEN]
+    );
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use common::*;
+use solana_escrow::{
+    error::EscrowError,
+    pda::find_escrow_index_address,
+    state::{EscrowIndex, MAX_INDEXED_ESCROWS},
+};
+use solana_program::{instruction::InstructionError, program_pack::Pack, pubkey::Pubkey};
+use solana_program_test::ProgramTestContext;
+
+/// Starts the test validator with an empty escrow index
+async fn start_with_escrow_index() -> ProgramTestContext {
+    let escrow_index = EscrowIndex {
+        is_initialized: true,
+        escrows_count: 0,
+        escrows: [Pubkey::default(); MAX_INDEXED_ESCROWS],
+    };
+    start_with_accounts(vec![(
+        find_escrow_index_address(&program_id()).0,
+        program_account(escrow_index),
+    )])
+    .await
+}
+
+async fn listed_escrows(ctx: &mut ProgramTestContext) -> Vec<Pubkey> {
+    let account = get_account(ctx, &find_escrow_index_address(&program_id()).0)
+        .await
+        .expect("escrow index account");
+    EscrowIndex::unpack(&account.data)
+        .unwrap()
+        .listed()
+        .to_vec()
+}
+
+#[tokio::test]
+async fn test_only_escrows_opting_in_are_listed() {
+    let mut ctx = start_with_escrow_index().await;
+
+    let unlisted = open_escrow(&mut ctx, EscrowParams::default()).await;
+    let listed = open_escrow(
+        &mut ctx,
+        EscrowParams {
+            list_in_index: true,
+            ..EscrowParams::default()
+        },
+    )
+    .await;
+
+    assert_eq!(listed_escrows(&mut ctx).await, vec![listed.escrow]);
+    assert!(
+        escrow_state(&mut ctx, &unlisted.escrow)
+            .await
+            .is_initialized
+    );
+}
+
+#[tokio::test]
+async fn test_a_full_index_only_blocks_escrows_opting_in() {
+    let mut ctx = start_with_escrow_index().await;
+    let deposit_mint = create_mint(&mut ctx, 6).await;
+    let expected_mint = create_mint(&mut ctx, 6).await;
+    let listed_params = EscrowParams {
+        list_in_index: true,
+        ..EscrowParams::default()
+    };
+
+    for _ in 0..MAX_INDEXED_ESCROWS {
+        let escrow = setup_escrow_with_mints(
+            &mut ctx,
+            listed_params.clone(),
+            &deposit_mint,
+            &expected_mint,
+        )
+        .await;
+        let ix = escrow.init_instruction(&escrow.temp_token_account);
+        process(&mut ctx, &[ix], &[&escrow.initializer])
+            .await
+            .unwrap();
+    }
+    assert_eq!(listed_escrows(&mut ctx).await.len(), MAX_INDEXED_ESCROWS);
+
+    let escrow =
+        setup_escrow_with_mints(&mut ctx, listed_params, &deposit_mint, &expected_mint).await;
+    let ix = escrow.init_instruction(&escrow.temp_token_account);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&escrow.initializer]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::IndexFull as u32)
+    );
+
+    let escrow = setup_escrow_with_mints(
+        &mut ctx,
+        EscrowParams::default(),
+        &deposit_mint,
+        &expected_mint,
+    )
+    .await;
+    let ix = escrow.init_instruction(&escrow.temp_token_account);
+    process(&mut ctx, &[ix], &[&escrow.initializer])
+        .await
+        .unwrap();
+    assert!(escrow_state(&mut ctx, &escrow.escrow).await.is_initialized);
+    assert!(!listed_escrows(&mut ctx).await.contains(&escrow.escrow));
+}
@@ -0,0 +1,715 @@
+mod common;
+
+use common::*;
+use solana_escrow::{
+    error::EscrowError,
+    instruction::{self, EscrowInstruction, InitEscrowArgs},
+    pda::find_stats_address,
+    state::{Escrow, EscrowStats},
+};
+use solana_program::{
+    instruction::{AccountMeta, InstructionError},
+    program_pack::Pack,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_program,
+};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::{account::Account, signature::Signer};
+use spl_token::state::Account as TokenAccount;
+
+#[tokio::test]
+async fn test_exchange_refunds_both_rents_to_the_initializer() {
+    let mut ctx = start().await;
+    let escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+
+    // Without an explicit recipient the rent goes back to the initializer
+    let initializer = escrow.initializer.pubkey();
+    assert_eq!(
+        escrow_state(&mut ctx, &escrow.escrow)
+            .await
+            .rent_refund_recipient,
+        initializer
+    );
+
+    let escrow_rent = minimum_balance(&mut ctx, Escrow::LEN).await;
+    let pda_token_account_rent = minimum_balance(&mut ctx, TokenAccount::LEN).await;
+    assert_eq!(lamports(&mut ctx, &escrow.escrow).await, escrow_rent);
+    assert_eq!(
+        lamports(&mut ctx, &escrow.temp_token_account).await,
+        pda_token_account_rent
+    );
+    let pre = lamports(&mut ctx, &initializer).await;
+
+    let ix = escrow.exchange_instruction(&taker, 1_000, 0);
+    process(&mut ctx, &[ix], &[&taker.keypair]).await.unwrap();
+
+    let post = lamports(&mut ctx, &initializer).await;
+    assert_eq!(post, pre + escrow_rent + pda_token_account_rent);
+    assert!(get_account(&mut ctx, &escrow.escrow).await.is_none());
+    assert!(get_account(&mut ctx, &escrow.temp_token_account)
+        .await
+        .is_none());
+    assert_eq!(token_balance(&mut ctx, &taker.receive_account).await, 1_000);
+    assert_eq!(token_balance(&mut ctx, &escrow.receive_account).await, 500);
+}
+
+#[tokio::test]
+async fn test_exchange_rejects_an_unrelated_pda_token_account() {
+    let mut ctx = start().await;
+    let escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+
+    // A token account of the deposit mint that isn't the escrow's, holding tokens of its own
+    let payer = ctx.payer.pubkey();
+    let stranger = create_token_account(&mut ctx, &escrow.deposit_mint, &payer).await;
+    mint_to(&mut ctx, &escrow.deposit_mint, &stranger, 1_000).await;
+
+    let mut ix = escrow.exchange_instruction(&taker, 1_000, 0);
+    // 3. The PDA's temp token account
+    ix.accounts[3].pubkey = stranger;
+    let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await);
+    assert_eq!(error, InstructionError::InvalidAccountData);
+
+    assert_eq!(token_balance(&mut ctx, &stranger).await, 1_000);
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.temp_token_account).await,
+        1_000
+    );
+    assert_eq!(token_balance(&mut ctx, &taker.send_account).await, 500);
+    assert_eq!(token_balance(&mut ctx, &taker.receive_account).await, 0);
+    assert_eq!(token_balance(&mut ctx, &escrow.receive_account).await, 0);
+    assert!(escrow_state(&mut ctx, &escrow.escrow).await.is_initialized);
+}
+
+#[tokio::test]
+async fn test_exchange_rejects_another_initializer_receive_account() {
+    let mut ctx = start().await;
+    let escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+
+    // A valid token account of the expected mint, owned by the taker instead of the initializer
+    let taker_pubkey = taker.keypair.pubkey();
+    let stranger = create_token_account(&mut ctx, &escrow.expected_mint, &taker_pubkey).await;
+
+    let mut ix = escrow.exchange_instruction(&taker, 1_000, 0);
+    // 5. The initializer's token account to receive
+    ix.accounts[5].pubkey = stranger;
+    let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await);
+    assert_eq!(error, InstructionError::InvalidAccountData);
+
+    assert_eq!(token_balance(&mut ctx, &stranger).await, 0);
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.temp_token_account).await,
+        1_000
+    );
+    assert_eq!(token_balance(&mut ctx, &taker.send_account).await, 500);
+    assert_eq!(token_balance(&mut ctx, &taker.receive_account).await, 0);
+    assert_eq!(token_balance(&mut ctx, &escrow.receive_account).await, 0);
+    assert!(escrow_state(&mut ctx, &escrow.escrow).await.is_initialized);
+}
+
+#[tokio::test]
+async fn test_exchange_cannot_be_replayed() {
+    let mut ctx = start().await;
+    let escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+    let taker = create_taker(&mut ctx, &escrow, 1_000).await;
+
+    let ix = escrow.exchange_instruction(&taker, 1_000, 0);
+    process(&mut ctx, std::slice::from_ref(&ix), &[&taker.keypair])
+        .await
+        .unwrap();
+
+    // The same instruction again, signed under a new blockhash so it isn't deduplicated as the same transaction
+    let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::NotInitialized as u32)
+    );
+    assert_eq!(token_balance(&mut ctx, &taker.send_account).await, 500);
+    assert_eq!(token_balance(&mut ctx, &taker.receive_account).await, 1_000);
+    assert_eq!(token_balance(&mut ctx, &escrow.receive_account).await, 500);
+}
+
+#[tokio::test]
+async fn test_exchange_rejects_a_passed_taker_deadline() {
+    let mut ctx = start().await;
+    let escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+    ctx.warp_to_slot(100).unwrap();
+
+    let ix = escrow.exchange_instruction_with(&taker, 1_000, 0, 99, None);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::DeadlineExceeded as u32)
+    );
+    assert_eq!(token_balance(&mut ctx, &taker.send_account).await, 500);
+
+    // A deadline still ahead lets the same taker exchange
+    let ix = escrow.exchange_instruction_with(&taker, 1_000, 0, 200, None);
+    process(&mut ctx, &[ix], &[&taker.keypair]).await.unwrap();
+    assert_eq!(token_balance(&mut ctx, &taker.receive_account).await, 1_000);
+}
+
+#[tokio::test]
+async fn test_exchange_rejects_more_than_the_pda_received() {
+    let mut ctx = start().await;
+    // The token program of the tests charges no transfer fee, the temp token account is funded with what a 1% fee
+    // would have left out of a 1_000 tokens deposit instead
+    let escrow = open_escrow(
+        &mut ctx,
+        EscrowParams {
+            deposit: 990,
+            ..EscrowParams::default()
+        },
+    )
+    .await;
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+
+    let ix = escrow.exchange_instruction(&taker, 1_000, 0);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::TransferFeeMismatch as u32)
+    );
+    assert_eq!(token_balance(&mut ctx, &taker.send_account).await, 500);
+
+    // Asking for the balance the PDA holds exchanges the escrow
+    let ix = escrow.exchange_instruction(&taker, 990, 0);
+    process(&mut ctx, &[ix], &[&taker.keypair]).await.unwrap();
+    assert_eq!(token_balance(&mut ctx, &taker.receive_account).await, 990);
+}
+
+#[tokio::test]
+async fn test_exchange_pays_out_tokens_topped_up_after_init() {
+    let mut ctx = start().await;
+    let escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+    // The escrow state keeps no copy of the deposit, the temp token account is read again on every exchange
+    mint_to(
+        &mut ctx,
+        &escrow.deposit_mint,
+        &escrow.temp_token_account,
+        500,
+    )
+    .await;
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+
+    // The deposit the escrow was opened with is no longer what the PDA holds
+    let ix = escrow.exchange_instruction(&taker, 1_000, 0);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::ExpectedAmountMismatch as u32)
+    );
+
+    let ix = escrow.exchange_instruction(&taker, 1_500, 0);
+    process(&mut ctx, &[ix], &[&taker.keypair]).await.unwrap();
+    assert_eq!(token_balance(&mut ctx, &taker.receive_account).await, 1_500);
+    assert_eq!(token_balance(&mut ctx, &escrow.receive_account).await, 500);
+}
+
+#[tokio::test]
+async fn test_exchange_rejects_another_token_program() {
+    let mut ctx = start().await;
+    let escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+
+    let mut ix = escrow.exchange_instruction(&taker, 1_000, 500);
+    // 7. The token program
+    ix.accounts[7].pubkey = Pubkey::new_unique();
+    let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await);
+    assert_eq!(error, InstructionError::IncorrectProgramId);
+
+    assert_eq!(token_balance(&mut ctx, &taker.send_account).await, 500);
+    assert!(escrow_state(&mut ctx, &escrow.escrow).await.is_initialized);
+}
+
+#[tokio::test]
+async fn test_exchange_rejects_another_pda_account() {
+    let mut ctx = start().await;
+    let escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+
+    let mut ix = escrow.exchange_instruction(&taker, 1_000, 500);
+    // 8. The PDA account
+    ix.accounts[8].pubkey = Pubkey::new_unique();
+    let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::InvalidPda as u32)
+    );
+
+    assert_eq!(token_balance(&mut ctx, &taker.send_account).await, 500);
+    assert!(escrow_state(&mut ctx, &escrow.escrow).await.is_initialized);
+}
+
+#[tokio::test]
+async fn test_validate_exchange_accounts_fails_like_exchange() {
+    let mut ctx = start().await;
+    let escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+
+    // Each bad account set by the position it replaces: the token program, the PDA, the initializer's
+    // receive account and the rent refund recipient
+    let expected_errors = [
+        (7, InstructionError::IncorrectProgramId),
+        (8, InstructionError::Custom(EscrowError::InvalidPda as u32)),
+        (5, InstructionError::InvalidAccountData),
+        (13, InstructionError::InvalidAccountData),
+    ];
+    for (index, expected_error) in expected_errors.iter() {
+        let mut exchange_ix = escrow.exchange_instruction(&taker, 1_000, 500);
+        exchange_ix.accounts[*index].pubkey = Pubkey::new_unique();
+        let mut validate_ix = exchange_ix.clone();
+        validate_ix.data = EscrowInstruction::ValidateExchangeAccounts.pack();
+
+        let validate_error =
+            instruction_error(process(&mut ctx, &[validate_ix], &[&taker.keypair]).await);
+        let exchange_error =
+            instruction_error(process(&mut ctx, &[exchange_ix], &[&taker.keypair]).await);
+        assert_eq!(validate_error, *expected_error);
+        assert_eq!(exchange_error, validate_error);
+    }
+
+    // The untouched account set passes both
+    let exchange_ix = escrow.exchange_instruction(&taker, 1_000, 500);
+    let mut validate_ix = exchange_ix.clone();
+    validate_ix.data = EscrowInstruction::ValidateExchangeAccounts.pack();
+    process(&mut ctx, &[validate_ix], &[&taker.keypair])
+        .await
+        .unwrap();
+    process(&mut ctx, &[exchange_ix], &[&taker.keypair])
+        .await
+        .unwrap();
+    assert_eq!(token_balance(&mut ctx, &taker.receive_account).await, 1_000);
+}
+
+#[tokio::test]
+async fn test_exchange_rejects_an_underfunded_taker() {
+    let mut ctx = start().await;
+    let escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+    let taker = create_taker(&mut ctx, &escrow, 499).await;
+
+    let ix = escrow.exchange_instruction(&taker, 1_000, 0);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::InsufficientTakerFunds as u32)
+    );
+    assert_eq!(token_balance(&mut ctx, &taker.send_account).await, 499);
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.temp_token_account).await,
+        1_000
+    );
+}
+
+#[tokio::test]
+async fn test_exchange_rejects_another_rent_destination() {
+    let mut ctx = start().await;
+    let escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+    let taker_pubkey = taker.keypair.pubkey();
+    let pre = lamports(&mut ctx, &taker_pubkey).await;
+
+    // Neither rent can be redirected to the taker, as the initializer or as the rent refund recipient
+    // 4. The initializer, 13. The rent refund recipient
+    for index in [4, 13] {
+        let mut ix = escrow.exchange_instruction(&taker, 1_000, 0);
+        ix.accounts[index].pubkey = taker_pubkey;
+        let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await);
+        assert_eq!(error, InstructionError::InvalidAccountData);
+    }
+
+    assert_eq!(lamports(&mut ctx, &taker_pubkey).await, pre);
+    assert!(get_account(&mut ctx, &escrow.temp_token_account)
+        .await
+        .is_some());
+    assert!(escrow_state(&mut ctx, &escrow.escrow).await.is_initialized);
+}
+
+#[tokio::test]
+async fn test_exchange_rejects_another_associated_token_program() {
+    let mut ctx = start().await;
+    let escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+
+    // The taker has no token account of the deposit mint yet, the exchange creates its associated token account
+    let taker_pubkey = taker.keypair.pubkey();
+    let associated_account = spl_associated_token_account::get_associated_token_address(
+        &taker_pubkey,
+        &escrow.deposit_mint,
+    );
+    let initializer = escrow.initializer.pubkey();
+    let mut ix = instruction::exchange(
+        &program_id(),
+        &taker_pubkey,
+        &taker.send_account,
+        &associated_account,
+        &escrow.temp_token_account,
+        &initializer,
+        &escrow.receive_account,
+        &escrow.escrow,
+        &escrow.deposit_mint,
+        &escrow.expected_mint,
+        &initializer,
+        &escrow.fee_account,
+        true,
+        1_000,
+        0,
+        0,
+        None,
+        None,
+        &[],
+        None,
+        false,
+    )
+    .unwrap();
+    // 15. The associated token program, right after the fixed accounts
+    ix.accounts[15].pubkey = spl_token::id();
+    let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await);
+    assert_eq!(error, InstructionError::IncorrectProgramId);
+
+    assert!(get_account(&mut ctx, &associated_account).await.is_none());
+    assert_eq!(token_balance(&mut ctx, &taker.send_account).await, 500);
+}
+
+#[tokio::test]
+async fn test_exchange_rejects_a_taker_receive_account_that_isnt_a_deposit_token_account() {
+    // A system account with room for a token account, which the token program would never credit
+    let system_account = Pubkey::new_unique();
+    let mut ctx = start_with_accounts(vec![(
+        system_account,
+        Account {
+            lamports: Rent::default().minimum_balance(TokenAccount::LEN),
+            data: vec![0; TokenAccount::LEN],
+            owner: system_program::id(),
+            executable: false,
+            rent_epoch: 0,
+        },
+    )])
+    .await;
+    let escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+
+    let mut ix = escrow.exchange_instruction(&taker, 1_000, 0);
+    // 2. The taker's token account to receive
+    ix.accounts[2].pubkey = system_account;
+    let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::WrongOwner as u32)
+    );
+
+    // A token account of the taker, but of the expected mint
+    let taker_pubkey = taker.keypair.pubkey();
+    let other_mint_account =
+        create_token_account(&mut ctx, &escrow.expected_mint, &taker_pubkey).await;
+    let mut ix = escrow.exchange_instruction(&taker, 1_000, 0);
+    ix.accounts[2].pubkey = other_mint_account;
+    let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::WrongMint as u32)
+    );
+
+    assert_eq!(token_balance(&mut ctx, &taker.send_account).await, 500);
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.temp_token_account).await,
+        1_000
+    );
+}
+
+#[tokio::test]
+async fn test_exchange_rejects_one_token_account_in_two_roles() {
+    let mut ctx = start().await;
+    let escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+
+    // 1. The taker's token account to send, 2. the taker's token account to receive
+    let duplicates = [
+        (1, taker.receive_account),
+        (1, escrow.receive_account),
+        (1, escrow.temp_token_account),
+        (2, escrow.temp_token_account),
+        (2, escrow.receive_account),
+    ];
+    for (index, duplicate) in duplicates {
+        let mut ix = escrow.exchange_instruction(&taker, 1_000, 0);
+        ix.accounts[index].pubkey = duplicate;
+        let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await);
+        assert_eq!(error, InstructionError::InvalidArgument);
+    }
+
+    assert_eq!(token_balance(&mut ctx, &taker.send_account).await, 500);
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.temp_token_account).await,
+        1_000
+    );
+    assert_eq!(token_balance(&mut ctx, &escrow.receive_account).await, 0);
+}
+
+#[tokio::test]
+async fn test_exchange_rejects_a_read_only_initializer() {
+    let mut ctx = start().await;
+    // The escrow rent goes elsewhere, so the initializer isn't passed writable as the rent refund recipient too
+    let rent_refund_recipient = Pubkey::new_unique();
+    let escrow = setup_escrow(&mut ctx, EscrowParams::default()).await;
+    let args = InitEscrowArgs {
+        rent_refund_recipient,
+        ..escrow.init_args()
+    };
+    let ix = escrow.init_instruction_with(&escrow.temp_token_account, args);
+    process(&mut ctx, &[ix], &[&escrow.initializer])
+        .await
+        .unwrap();
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+
+    let mut ix = escrow.exchange_instruction(&taker, 1_000, 0);
+    // 4. The initializer, credited the temp token account rent
+    ix.accounts[4].is_writable = false;
+    // 13. The rent refund recipient
+    ix.accounts[13].pubkey = rent_refund_recipient;
+    let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await);
+    assert_eq!(error, InstructionError::InvalidArgument);
+
+    assert_eq!(token_balance(&mut ctx, &taker.send_account).await, 500);
+    assert!(escrow_state(&mut ctx, &escrow.escrow).await.is_initialized);
+}
+
+#[tokio::test]
+async fn test_exchange_failing_its_last_check_moves_no_tokens() {
+    // The stats account is the last one Exchange checks, a volume about to overflow fails it
+    let stats = EscrowStats {
+        is_initialized: true,
+        total_created: 1,
+        total_filled: 0,
+        total_volume: u64::MAX - 999,
+    };
+    let mut ctx = start_with_accounts(vec![(
+        find_stats_address(&program_id()).0,
+        program_account(stats),
+    )])
+    .await;
+    let escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+    let opened = escrow_state(&mut ctx, &escrow.escrow).await;
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+
+    // Without a template, callback or settlement authority the stats account comes last
+    let mut ix = escrow.exchange_instruction(&taker, 1_000, 0);
+    ix.accounts
+        .push(AccountMeta::new(find_stats_address(&program_id()).0, false));
+    let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::AmountOverflow as u32)
+    );
+
+    assert_eq!(token_balance(&mut ctx, &taker.send_account).await, 500);
+    assert_eq!(token_balance(&mut ctx, &taker.receive_account).await, 0);
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.temp_token_account).await,
+        1_000
+    );
+    assert_eq!(token_balance(&mut ctx, &escrow.receive_account).await, 0);
+    assert_eq!(escrow_state(&mut ctx, &escrow.escrow).await, opened);
+
+    // Without the stats account the same exchange goes through
+    let ix = escrow.exchange_instruction(&taker, 1_000, 0);
+    process(&mut ctx, &[ix], &[&taker.keypair]).await.unwrap();
+    assert_eq!(token_balance(&mut ctx, &taker.receive_account).await, 1_000);
+}
+
+#[tokio::test]
+async fn test_exchange_refunds_the_escrow_rent_to_a_relayer() {
+    let mut ctx = start().await;
+    let escrow = setup_escrow(&mut ctx, EscrowParams::default()).await;
+    // The relayer paid the escrow account's rent for the initializer and gets it back on settlement
+    let relayer = Pubkey::new_unique();
+    let args = InitEscrowArgs {
+        rent_refund_recipient: relayer,
+        ..escrow.init_args()
+    };
+    let ix = escrow.init_instruction_with(&escrow.temp_token_account, args);
+    process(&mut ctx, &[ix], &[&escrow.initializer])
+        .await
+        .unwrap();
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+
+    // The initializer can't take the relayer's refund
+    let ix = escrow.exchange_instruction(&taker, 1_000, 0);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await);
+    assert_eq!(error, InstructionError::InvalidAccountData);
+
+    let escrow_rent = minimum_balance(&mut ctx, Escrow::LEN).await;
+    let pda_token_account_rent = minimum_balance(&mut ctx, TokenAccount::LEN).await;
+    let initializer = escrow.initializer.pubkey();
+    let pre = lamports(&mut ctx, &initializer).await;
+
+    // 13. The rent refund recipient
+    let mut ix = escrow.exchange_instruction(&taker, 1_000, 0);
+    ix.accounts[13].pubkey = relayer;
+    process(&mut ctx, &[ix], &[&taker.keypair]).await.unwrap();
+
+    // The temp token account's rent still goes to the initializer, who paid for it
+    assert_eq!(lamports(&mut ctx, &relayer).await, escrow_rent);
+    assert_eq!(
+        lamports(&mut ctx, &initializer).await,
+        pre + pda_token_account_rent
+    );
+    assert!(get_account(&mut ctx, &escrow.escrow).await.is_none());
+    assert_eq!(token_balance(&mut ctx, &taker.receive_account).await, 1_000);
+}
+
+/// Opens an escrow of a 990 tokens deposit accepting fill amounts within `amount_tolerance` of it
+async fn open_escrow_with_tolerance(
+    ctx: &mut ProgramTestContext,
+    amount_tolerance: u64,
+) -> TestEscrow {
+    let escrow = setup_escrow(
+        ctx,
+        EscrowParams {
+            deposit: 990,
+            ..EscrowParams::default()
+        },
+    )
+    .await;
+    let args = InitEscrowArgs {
+        amount_tolerance,
+        ..escrow.init_args()
+    };
+    let ix = escrow.init_instruction_with(&escrow.temp_token_account, args);
+    process(ctx, &[ix], &[&escrow.initializer]).await.unwrap();
+    escrow
+}
+
+#[tokio::test]
+async fn test_exchange_accepts_a_fill_amount_at_the_tolerance() {
+    let mut ctx = start().await;
+
+    // Exactly the tolerance off the 990 tokens share, on either side, the taker gets the share itself
+    for fill_amount in [1_000, 980] {
+        let escrow = open_escrow_with_tolerance(&mut ctx, 10).await;
+        let taker = create_taker(&mut ctx, &escrow, 500).await;
+        let ix = escrow.exchange_instruction(&taker, fill_amount, 0);
+        process(&mut ctx, &[ix], &[&taker.keypair]).await.unwrap();
+        assert_eq!(token_balance(&mut ctx, &taker.receive_account).await, 990);
+        assert_eq!(token_balance(&mut ctx, &escrow.receive_account).await, 500);
+    }
+}
+
+#[tokio::test]
+async fn test_exchange_rejects_a_fill_amount_beyond_the_tolerance() {
+    let mut ctx = start().await;
+    let escrow = open_escrow_with_tolerance(&mut ctx, 10).await;
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+
+    for (fill_amount, error) in [
+        (1_001, EscrowError::TransferFeeMismatch),
+        (979, EscrowError::ExpectedAmountMismatch),
+    ] {
+        let ix = escrow.exchange_instruction(&taker, fill_amount, 0);
+        assert_eq!(
+            instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await),
+            InstructionError::Custom(error as u32)
+        );
+    }
+    assert_eq!(token_balance(&mut ctx, &taker.send_account).await, 500);
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.temp_token_account).await,
+        990
+    );
+}
+
+#[tokio::test]
+async fn test_exchange_pays_a_receive_account_owned_by_another_programs_pda() {
+    let mut ctx = start().await;
+    let escrow = setup_escrow(&mut ctx, EscrowParams::default()).await;
+
+    // A program trading through the escrow receives into a token account of its own PDA, which never signs
+    let owner_program = Pubkey::new_unique();
+    let (owner_pda, _) = Pubkey::find_program_address(&[b"vault"], &owner_program);
+    let receive_account = create_token_account(&mut ctx, &escrow.expected_mint, &owner_pda).await;
+    let escrow = TestEscrow {
+        receive_account,
+        ..escrow
+    };
+    let ix = escrow.init_instruction(&escrow.temp_token_account);
+    process(&mut ctx, &[ix], &[&escrow.initializer])
+        .await
+        .unwrap();
+    assert_eq!(
+        escrow_state(&mut ctx, &escrow.escrow)
+            .await
+            .initializer_token_to_receive_account_pubkey,
+        receive_account
+    );
+
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+    let ix = escrow.exchange_instruction(&taker, 1_000, 0);
+    process(&mut ctx, &[ix], &[&taker.keypair]).await.unwrap();
+    let receive_account_state = token_account(&mut ctx, &receive_account).await;
+    assert_eq!(receive_account_state.owner, owner_pda);
+    assert_eq!(receive_account_state.amount, 500);
+    assert_eq!(token_balance(&mut ctx, &taker.receive_account).await, 1_000);
+}
+
+#[tokio::test]
+async fn test_exchange_rejects_an_escrow_closed_earlier_in_the_transaction() {
+    let mut ctx = start().await;
+    let escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+    let first_taker = create_taker(&mut ctx, &escrow, 500).await;
+    let second_taker = create_taker(&mut ctx, &escrow, 500).await;
+
+    // The first exchange closes the escrow account, the second one sees it without lamports
+    let ixs = [
+        escrow.exchange_instruction(&first_taker, 1_000, 0),
+        escrow.exchange_instruction(&second_taker, 1_000, 0),
+    ];
+    let error = instruction_error(
+        process(
+            &mut ctx,
+            &ixs,
+            &[&first_taker.keypair, &second_taker.keypair],
+        )
+        .await,
+    );
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::NotInitialized as u32)
+    );
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.temp_token_account).await,
+        1_000
+    );
+    assert_eq!(
+        token_balance(&mut ctx, &first_taker.send_account).await,
+        500
+    );
+    assert_eq!(
+        token_balance(&mut ctx, &second_taker.send_account).await,
+        500
+    );
+}
+
+#[tokio::test]
+async fn test_exchange_rejects_a_cancelled_escrow() {
+    let mut ctx = start().await;
+    let escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+    let ix = escrow.cancel_instruction();
+    process(&mut ctx, &[ix], &[&escrow.initializer])
+        .await
+        .unwrap();
+    assert!(get_account(&mut ctx, &escrow.escrow).await.is_none());
+
+    let ix = escrow.exchange_instruction(&taker, 1_000, 0);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::NotInitialized as u32)
+    );
+    assert_eq!(token_balance(&mut ctx, &taker.send_account).await, 500);
+    assert_eq!(token_balance(&mut ctx, &taker.receive_account).await, 0);
+}
@@ -0,0 +1,139 @@
+mod common;
+
+use common::*;
+use solana_escrow::{error::EscrowError, instruction::InitEscrowArgs};
+use solana_program::{
+    instruction::{AccountMeta, InstructionError},
+    sysvar,
+};
+use solana_program_test::ProgramTestContext;
+
+/// The slot the slot based escrows expire at
+const EXPIRY_SLOT: u64 = 1_000;
+
+/// Opens an escrow of two taker slots expiring at `EXPIRY_SLOT`
+async fn open_slot_expiring_escrow(ctx: &mut ProgramTestContext) -> TestEscrow {
+    let escrow = setup_escrow(
+        ctx,
+        EscrowParams {
+            taker_slots: 2,
+            ..EscrowParams::default()
+        },
+    )
+    .await;
+    let args = InitEscrowArgs {
+        use_slot_expiry: true,
+        expiry_slot: EXPIRY_SLOT,
+        ..escrow.init_args()
+    };
+    let ix = escrow.init_instruction_with(&escrow.temp_token_account, args);
+    process(ctx, &[ix], &[&escrow.initializer]).await.unwrap();
+    escrow
+}
+
+#[tokio::test]
+async fn test_slot_expiry_stops_exchanges_at_the_expiry_slot() {
+    let mut ctx = start().await;
+    let escrow = open_slot_expiring_escrow(&mut ctx).await;
+
+    // Shortly before the expiry slot the escrow is still open
+    ctx.warp_to_slot(EXPIRY_SLOT - 10).unwrap();
+    let first_taker = create_taker(&mut ctx, &escrow, 250).await;
+    let ix = escrow.exchange_instruction(&first_taker, 500, 0);
+    process(&mut ctx, &[ix], &[&first_taker.keypair])
+        .await
+        .unwrap();
+    assert_eq!(
+        token_balance(&mut ctx, &first_taker.receive_account).await,
+        500
+    );
+
+    ctx.warp_to_slot(EXPIRY_SLOT).unwrap();
+    let second_taker = create_taker(&mut ctx, &escrow, 250).await;
+    let ix = escrow.exchange_instruction(&second_taker, 500, 0);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&second_taker.keypair]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::EscrowExpired as u32)
+    );
+    assert_eq!(
+        token_balance(&mut ctx, &second_taker.send_account).await,
+        250
+    );
+
+    // The initializer still gets back what is left
+    let ix = escrow.cancel_instruction();
+    process(&mut ctx, &[ix], &[&escrow.initializer])
+        .await
+        .unwrap();
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.initializer_deposit_account).await,
+        500
+    );
+    assert!(get_account(&mut ctx, &escrow.escrow).await.is_none());
+}
+
+#[tokio::test]
+async fn test_timestamp_expiry_ignores_the_expiry_slot() {
+    let mut ctx = start().await;
+    let escrow = setup_escrow(&mut ctx, EscrowParams::default()).await;
+    // The slot is only read with `use_slot_expiry`, the timestamp is far enough out for the test validator's clock
+    let args = InitEscrowArgs {
+        expiry_timestamp: 4_000_000_000,
+        expiry_slot: EXPIRY_SLOT,
+        ..escrow.init_args()
+    };
+    let ix = escrow.init_instruction_with(&escrow.temp_token_account, args);
+    process(&mut ctx, &[ix], &[&escrow.initializer])
+        .await
+        .unwrap();
+
+    ctx.warp_to_slot(EXPIRY_SLOT + 1).unwrap();
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+    let ix = escrow.exchange_instruction(&taker, 1_000, 0);
+    process(&mut ctx, &[ix], &[&taker.keypair]).await.unwrap();
+    assert_eq!(token_balance(&mut ctx, &taker.receive_account).await, 1_000);
+}
+
+#[tokio::test]
+async fn test_clock_is_the_same_with_a_clock_sysvar_account_passed() {
+    let mut ctx = start().await;
+    let escrow = setup_escrow(&mut ctx, EscrowParams::default()).await;
+    let args = InitEscrowArgs {
+        timelock_slot: EXPIRY_SLOT,
+        ..escrow.init_args()
+    };
+    let ix = escrow.init_instruction_with(&escrow.temp_token_account, args);
+    process(&mut ctx, &[ix], &[&escrow.initializer])
+        .await
+        .unwrap();
+
+    // The test validator has the sysvar call, so the passed clock account is only read by older runtimes.
+    // Cancel reads no account after its own, the clock account is passed last
+    let cancel_with_clock_account = || {
+        let mut ix = escrow.cancel_instruction();
+        ix.accounts
+            .push(AccountMeta::new_readonly(sysvar::clock::id(), false));
+        ix
+    };
+    for ix in [escrow.cancel_instruction(), cancel_with_clock_account()] {
+        let error = instruction_error(process(&mut ctx, &[ix], &[&escrow.initializer]).await);
+        assert_eq!(
+            error,
+            InstructionError::Custom(EscrowError::EscrowTimelocked as u32)
+        );
+    }
+
+    ctx.warp_to_slot(EXPIRY_SLOT).unwrap();
+    process(
+        &mut ctx,
+        &[cancel_with_clock_account()],
+        &[&escrow.initializer],
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.initializer_deposit_account).await,
+        1_000
+    );
+}
@@ -0,0 +1,118 @@
+mod common;
+
+use common::*;
+use solana_escrow::{error::EscrowError, instruction, state::Escrow};
+use solana_program::{instruction::InstructionError, pubkey::Pubkey};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::signature::{Keypair, Signer};
+
+/// The unix timestamp the preloaded escrows expire at, far enough out for the test validator's clock
+const EXPIRY: u64 = 4_000_000_000;
+
+/// Starts the test validator with an escrow of `initializer` expiring at `expiry_timestamp`
+async fn start_with_escrow(
+    initializer: &Keypair,
+    escrow: &Pubkey,
+    expiry_timestamp: i64,
+    in_progress: bool,
+) -> ProgramTestContext {
+    let escrow_info = Escrow {
+        is_initialized: true,
+        initializer_pubkey: initializer.pubkey(),
+        taker_slots: 1,
+        expiry_timestamp,
+        in_progress,
+        ..Escrow::UNINITIALIZED
+    };
+    let mut ctx = start_with_accounts(vec![(*escrow, program_account(escrow_info))]).await;
+    fund(&mut ctx, &initializer.pubkey(), 1_000_000_000).await;
+    ctx
+}
+
+async fn extend_expiry(
+    ctx: &mut ProgramTestContext,
+    initializer: &Keypair,
+    escrow: &Pubkey,
+    expiry: u64,
+) -> Result<(), InstructionError> {
+    let ix = instruction::extend_expiry(
+        &program_id(),
+        &initializer.pubkey(),
+        escrow,
+        &Pubkey::new_unique(),
+        expiry,
+    )
+    .unwrap();
+    match process(ctx, &[ix], &[initializer]).await {
+        Ok(()) => Ok(()),
+        result => Err(instruction_error(result)),
+    }
+}
+
+#[tokio::test]
+async fn test_extend_expiry_moves_the_expiry_later() {
+    let initializer = Keypair::new();
+    let escrow = Pubkey::new_unique();
+    let mut ctx = start_with_escrow(&initializer, &escrow, EXPIRY as i64, false).await;
+    let opened = escrow_state(&mut ctx, &escrow).await;
+
+    extend_expiry(&mut ctx, &initializer, &escrow, EXPIRY + 1)
+        .await
+        .unwrap();
+    assert_eq!(
+        escrow_state(&mut ctx, &escrow).await,
+        Escrow {
+            expiry_timestamp: EXPIRY as i64 + 1,
+            ..opened
+        }
+    );
+}
+
+#[tokio::test]
+async fn test_extend_expiry_rejects_zero_and_earlier_expiries() {
+    let initializer = Keypair::new();
+    let escrow = Pubkey::new_unique();
+    let mut ctx = start_with_escrow(&initializer, &escrow, EXPIRY as i64, false).await;
+
+    for expiry in [0, 1, EXPIRY - 1, EXPIRY] {
+        assert_eq!(
+            extend_expiry(&mut ctx, &initializer, &escrow, expiry).await,
+            Err(InstructionError::InvalidArgument)
+        );
+    }
+    assert_eq!(
+        escrow_state(&mut ctx, &escrow).await.expiry_timestamp,
+        EXPIRY as i64
+    );
+}
+
+#[tokio::test]
+async fn test_extend_expiry_rejects_an_escrow_that_never_expires() {
+    let initializer = Keypair::new();
+    let escrow = Pubkey::new_unique();
+    let mut ctx = start_with_escrow(&initializer, &escrow, 0, false).await;
+
+    assert_eq!(
+        extend_expiry(&mut ctx, &initializer, &escrow, EXPIRY).await,
+        Err(InstructionError::InvalidArgument)
+    );
+    assert_eq!(escrow_state(&mut ctx, &escrow).await.expiry_timestamp, 0);
+}
+
+#[tokio::test]
+async fn test_extend_expiry_rejects_an_escrow_in_progress() {
+    let initializer = Keypair::new();
+    let escrow = Pubkey::new_unique();
+    let mut ctx = start_with_escrow(&initializer, &escrow, EXPIRY as i64, true).await;
+
+    assert_eq!(
+        extend_expiry(&mut ctx, &initializer, &escrow, EXPIRY + 1).await,
+        Err(InstructionError::Custom(
+            EscrowError::ReentrancyDetected as u32
+        ))
+    );
+    assert_eq!(
+        escrow_state(&mut ctx, &escrow).await.expiry_timestamp,
+        EXPIRY as i64
+    );
+}
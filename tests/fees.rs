@@ -0,0 +1,217 @@
+mod common;
+
+use common::*;
+use solana_escrow::{
+    error::EscrowError,
+    instruction,
+    pda::{find_config_address, NAMESPACE_LEN},
+    state::{EscrowConfig, MAX_NAMESPACES, MAX_PAUSED_MINTS},
+};
+use solana_program::{instruction::InstructionError, pubkey::Pubkey};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::signature::{Keypair, Signer};
+
+/// Starts the test validator with a config administered by `admin`, charging 1% of each payment
+/// after the first `free_fills` fills of each escrow
+async fn start_with_exchange_fee(admin: &Keypair, free_fills: u16) -> ProgramTestContext {
+    let config = EscrowConfig {
+        is_initialized: true,
+        admin: admin.pubkey(),
+        paused: false,
+        treasury: Pubkey::new_unique(),
+        cancel_fee: 0,
+        init_fee: 0,
+        paused_mints_count: 0,
+        paused_mints: [Pubkey::default(); MAX_PAUSED_MINTS],
+        extension_fee: 0,
+        exchange_fee_bps: 100,
+        free_fills,
+        namespaces_count: 0,
+        namespaces: [[0; NAMESPACE_LEN]; MAX_NAMESPACES],
+    };
+    start_with_accounts(vec![(
+        find_config_address(&program_id()).0,
+        program_account(config),
+    )])
+    .await
+}
+
+#[tokio::test]
+async fn test_exchange_fees_accumulate_in_the_vault_and_the_admin_withdraws_them() {
+    let admin = Keypair::new();
+    let mut ctx = start_with_exchange_fee(&admin, 0).await;
+
+    let deposit_mint = create_mint(&mut ctx, 6).await;
+    let expected_mint = create_mint(&mut ctx, 6).await;
+    let first = setup_escrow_with_mints(
+        &mut ctx,
+        EscrowParams::default(),
+        &deposit_mint,
+        &expected_mint,
+    )
+    .await;
+    // Both escrows expect the same mint, so their fees go to the same vault
+    let mut second = setup_escrow_with_mints(
+        &mut ctx,
+        EscrowParams::default(),
+        &deposit_mint,
+        &expected_mint,
+    )
+    .await;
+    second.fee_account = first.fee_account;
+
+    for escrow in [&first, &second] {
+        let ix = escrow.init_instruction(&escrow.temp_token_account);
+        process(&mut ctx, &[ix], &[&escrow.initializer])
+            .await
+            .unwrap();
+        let taker = create_taker(&mut ctx, escrow, 500).await;
+        let ix = escrow.exchange_instruction(&taker, 1_000, 0);
+        process(&mut ctx, &[ix], &[&taker.keypair]).await.unwrap();
+
+        // The taker pays the expected amount, 1% of which is kept as the fee
+        assert_eq!(token_balance(&mut ctx, &taker.send_account).await, 0);
+        assert_eq!(token_balance(&mut ctx, &escrow.receive_account).await, 495);
+    }
+    assert_eq!(token_balance(&mut ctx, &first.fee_account).await, 10);
+
+    // Only the admin can withdraw the fees
+    let destination = create_token_account(&mut ctx, &expected_mint, &admin.pubkey()).await;
+    let stranger = Keypair::new();
+    let ix = instruction::withdraw_fees(
+        &program_id(),
+        &stranger.pubkey(),
+        &first.fee_account,
+        &destination,
+        &expected_mint,
+    )
+    .unwrap();
+    let error = instruction_error(process(&mut ctx, &[ix], &[&stranger]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::WrongAuthority as u32)
+    );
+
+    let ix = instruction::withdraw_fees(
+        &program_id(),
+        &admin.pubkey(),
+        &first.fee_account,
+        &destination,
+        &expected_mint,
+    )
+    .unwrap();
+    process(&mut ctx, &[ix], &[&admin]).await.unwrap();
+    assert_eq!(token_balance(&mut ctx, &first.fee_account).await, 0);
+    assert_eq!(token_balance(&mut ctx, &destination).await, 10);
+}
+
+#[tokio::test]
+async fn test_exchange_rejects_a_fee_account_outside_the_vault() {
+    let admin = Keypair::new();
+    let mut ctx = start_with_exchange_fee(&admin, 0).await;
+
+    let mut escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+    // A token account of the expected mint the taker owns would let them keep the fee
+    escrow.fee_account =
+        create_token_account(&mut ctx, &escrow.expected_mint, &taker.keypair.pubkey()).await;
+
+    let ix = escrow.exchange_instruction(&taker, 1_000, 0);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::WrongAuthority as u32)
+    );
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.temp_token_account).await,
+        1_000
+    );
+}
+
+#[tokio::test]
+async fn test_set_exchange_fee_is_admin_only_and_bounded() {
+    let admin = Keypair::new();
+    let mut ctx = start_with_exchange_fee(&admin, 0).await;
+
+    let stranger = Keypair::new();
+    let ix = instruction::set_exchange_fee(&program_id(), &stranger.pubkey(), 0).unwrap();
+    let error = instruction_error(process(&mut ctx, &[ix], &[&stranger]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::WrongAuthority as u32)
+    );
+
+    // More than the whole payment can't be charged
+    let ix = instruction::set_exchange_fee(&program_id(), &admin.pubkey(), 10_001).unwrap();
+    let error = instruction_error(process(&mut ctx, &[ix], &[&admin]).await);
+    assert_eq!(error, InstructionError::InvalidArgument);
+
+    // Without a fee the fee account isn't checked, the initializer receives the whole payment
+    let ix = instruction::set_exchange_fee(&program_id(), &admin.pubkey(), 0).unwrap();
+    process(&mut ctx, &[ix], &[&admin]).await.unwrap();
+    let mut escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+    escrow.fee_account = Pubkey::new_unique();
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+    let ix = escrow.exchange_instruction(&taker, 1_000, 0);
+    process(&mut ctx, &[ix], &[&taker.keypair]).await.unwrap();
+    assert_eq!(token_balance(&mut ctx, &escrow.receive_account).await, 500);
+}
+
+#[tokio::test]
+async fn test_first_fills_of_an_escrow_are_free_of_the_exchange_fee() {
+    let admin = Keypair::new();
+    let mut ctx = start_with_exchange_fee(&admin, 2).await;
+
+    // Each of the four takers pays 250, 2 of which is the fee once the two free fills are used up
+    let escrow = open_escrow(
+        &mut ctx,
+        EscrowParams {
+            amount: 1_000,
+            taker_slots: 4,
+            ..EscrowParams::default()
+        },
+    )
+    .await;
+    assert_eq!(
+        escrow_state(&mut ctx, &escrow.escrow)
+            .await
+            .fills_remaining_free,
+        2
+    );
+
+    let mut received = 0;
+    for (fills_remaining_free, fee) in [(1, 0), (0, 0), (0, 2)] {
+        let taker = create_taker(&mut ctx, &escrow, 250).await;
+        let ix = escrow.exchange_instruction(&taker, 250, 0);
+        process(&mut ctx, &[ix], &[&taker.keypair]).await.unwrap();
+
+        received += 250 - fee;
+        assert_eq!(
+            token_balance(&mut ctx, &escrow.receive_account).await,
+            received
+        );
+        assert_eq!(
+            escrow_state(&mut ctx, &escrow.escrow)
+                .await
+                .fills_remaining_free,
+            fills_remaining_free
+        );
+    }
+    assert_eq!(token_balance(&mut ctx, &escrow.fee_account).await, 2);
+
+    // Free fills set later only apply to new escrows
+    let ix = instruction::set_free_fills(&program_id(), &admin.pubkey(), 5).unwrap();
+    process(&mut ctx, &[ix], &[&admin]).await.unwrap();
+    let taker = create_taker(&mut ctx, &escrow, 250).await;
+    let ix = escrow.exchange_instruction(&taker, 250, 0);
+    process(&mut ctx, &[ix], &[&taker.keypair]).await.unwrap();
+    assert_eq!(token_balance(&mut ctx, &escrow.fee_account).await, 4);
+
+    let new_escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+    assert_eq!(
+        escrow_state(&mut ctx, &new_escrow.escrow)
+            .await
+            .fills_remaining_free,
+        5
+    );
+}
@@ -0,0 +1,137 @@
+mod common;
+
+use common::*;
+use solana_escrow::{instruction, state::Escrow};
+use solana_program::{
+    instruction::{Instruction, InstructionError},
+    program_pack::Pack,
+    pubkey::Pubkey,
+    rent::Rent,
+};
+use solana_sdk::{
+    account::Account,
+    signature::{Keypair, Signer},
+};
+use spl_token::state::{Account as TokenAccount, AccountState};
+
+fn force_reclaim_instruction(
+    initializer: &Pubkey,
+    pda_token_account: &Pubkey,
+    escrow: &Pubkey,
+) -> Instruction {
+    instruction::force_reclaim(
+        &program_id(),
+        initializer,
+        pda_token_account,
+        escrow,
+        initializer,
+        initializer,
+    )
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_force_reclaim_hands_the_token_account_back() {
+    let mut ctx = start().await;
+    let escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+    let initializer = escrow.initializer.pubkey();
+    let escrow_rent = minimum_balance(&mut ctx, Escrow::LEN).await;
+    let pre = lamports(&mut ctx, &initializer).await;
+
+    let ix = force_reclaim_instruction(&initializer, &escrow.temp_token_account, &escrow.escrow);
+    process(&mut ctx, &[ix], &[&escrow.initializer])
+        .await
+        .unwrap();
+
+    // The tokens stay in the temp token account, which is the initializer's again
+    let temp_token_account = token_account(&mut ctx, &escrow.temp_token_account).await;
+    assert_eq!(temp_token_account.owner, initializer);
+    assert_eq!(temp_token_account.amount, 1_000);
+    assert!(get_account(&mut ctx, &escrow.escrow).await.is_none());
+    assert_eq!(lamports(&mut ctx, &initializer).await, pre + escrow_rent);
+}
+
+#[tokio::test]
+async fn test_force_reclaim_closes_an_escrow_whose_token_account_is_gone() {
+    let initializer = Keypair::new();
+    let escrow = Pubkey::new_unique();
+    let pda_token_account = Pubkey::new_unique();
+    let escrow_info = Escrow {
+        is_initialized: true,
+        initializer_pubkey: initializer.pubkey(),
+        temp_token_account_pubkey: pda_token_account,
+        taker_slots: 1,
+        rent_refund_recipient: initializer.pubkey(),
+        ..Escrow::UNINITIALIZED
+    };
+    let mut ctx = start_with_accounts(vec![(escrow, program_account(escrow_info))]).await;
+    fund(&mut ctx, &initializer.pubkey(), 1_000_000_000).await;
+    let escrow_rent = minimum_balance(&mut ctx, Escrow::LEN).await;
+
+    // Cancel can't get past the missing token account, ForceReclaim skips it and closes the escrow
+    let ix = force_reclaim_instruction(&initializer.pubkey(), &pda_token_account, &escrow);
+    process(&mut ctx, &[ix], &[&initializer]).await.unwrap();
+    assert!(get_account(&mut ctx, &escrow).await.is_none());
+    assert_eq!(
+        lamports(&mut ctx, &initializer.pubkey()).await,
+        1_000_000_000 + escrow_rent
+    );
+}
+
+#[tokio::test]
+async fn test_force_reclaim_leaves_a_token_account_the_pda_no_longer_owns() {
+    let initializer = Keypair::new();
+    let escrow = Pubkey::new_unique();
+    let pda_token_account = Pubkey::new_unique();
+    let stranger = Pubkey::new_unique();
+    let escrow_info = Escrow {
+        is_initialized: true,
+        initializer_pubkey: initializer.pubkey(),
+        temp_token_account_pubkey: pda_token_account,
+        taker_slots: 1,
+        rent_refund_recipient: initializer.pubkey(),
+        ..Escrow::UNINITIALIZED
+    };
+    // A token account whose owner was changed away from the PDA, the PDA can't sign for it anymore
+    let mut token_account_data = vec![0; TokenAccount::LEN];
+    TokenAccount::pack(
+        TokenAccount {
+            mint: Pubkey::new_unique(),
+            owner: stranger,
+            amount: 1_000,
+            state: AccountState::Initialized,
+            ..TokenAccount::default()
+        },
+        &mut token_account_data,
+    )
+    .unwrap();
+    let mut ctx = start_with_accounts(vec![
+        (escrow, program_account(escrow_info)),
+        (
+            pda_token_account,
+            Account {
+                lamports: Rent::default().minimum_balance(TokenAccount::LEN),
+                data: token_account_data,
+                owner: spl_token::id(),
+                executable: false,
+                rent_epoch: 0,
+            },
+        ),
+    ])
+    .await;
+    fund(&mut ctx, &initializer.pubkey(), 1_000_000_000).await;
+
+    // A stranger can't close the initializer's escrow
+    let impostor = Keypair::new();
+    fund(&mut ctx, &impostor.pubkey(), 1_000_000_000).await;
+    let ix = force_reclaim_instruction(&impostor.pubkey(), &pda_token_account, &escrow);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&impostor]).await);
+    assert_eq!(error, InstructionError::InvalidAccountData);
+
+    let ix = force_reclaim_instruction(&initializer.pubkey(), &pda_token_account, &escrow);
+    process(&mut ctx, &[ix], &[&initializer]).await.unwrap();
+    assert!(get_account(&mut ctx, &escrow).await.is_none());
+    let token_account_state = token_account(&mut ctx, &pda_token_account).await;
+    assert_eq!(token_account_state.owner, stranger);
+    assert_eq!(token_account_state.amount, 1_000);
+}
@@ -0,0 +1,82 @@
+mod common;
+
+use common::*;
+use solana_escrow::{error::EscrowError, instruction};
+use solana_program::instruction::InstructionError;
+use solana_program_test::ProgramTestContext;
+use solana_sdk::signature::Signer;
+
+/// Opens an escrow of a mint the payer can freeze, then freezes the PDA's temp token account
+async fn open_frozen_escrow(ctx: &mut ProgramTestContext) -> TestEscrow {
+    let payer = ctx.payer.pubkey();
+    let deposit_mint = create_mint_with_freeze_authority(ctx, 6, Some(&payer)).await;
+    let expected_mint = create_mint(ctx, 6).await;
+    let escrow =
+        setup_escrow_with_mints(ctx, EscrowParams::default(), &deposit_mint, &expected_mint).await;
+    let ix = escrow.init_instruction(&escrow.temp_token_account);
+    process(ctx, &[ix], &[&escrow.initializer]).await.unwrap();
+
+    let ix = spl_token::instruction::freeze_account(
+        &spl_token::id(),
+        &escrow.temp_token_account,
+        &deposit_mint,
+        &payer,
+        &[],
+    )
+    .unwrap();
+    process(ctx, &[ix], &[]).await.unwrap();
+    escrow
+}
+
+#[tokio::test]
+async fn test_exchange_and_cancel_reject_a_frozen_temp_token_account() {
+    let mut ctx = start().await;
+    let escrow = open_frozen_escrow(&mut ctx).await;
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+
+    let ix = escrow.exchange_instruction(&taker, 1_000, 0);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::AccountFrozen as u32)
+    );
+
+    let ix = escrow.cancel_instruction();
+    let error = instruction_error(process(&mut ctx, &[ix], &[&escrow.initializer]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::AccountFrozen as u32)
+    );
+
+    assert_eq!(token_balance(&mut ctx, &taker.send_account).await, 500);
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.temp_token_account).await,
+        1_000
+    );
+    assert!(escrow_state(&mut ctx, &escrow.escrow).await.is_initialized);
+}
+
+#[tokio::test]
+async fn test_force_reclaim_closes_an_escrow_of_a_frozen_temp_token_account() {
+    let mut ctx = start().await;
+    let escrow = open_frozen_escrow(&mut ctx).await;
+
+    // The frozen account is left as it is, the initializer still gets the escrow account's rent back
+    let initializer = escrow.initializer.pubkey();
+    let ix = instruction::force_reclaim(
+        &program_id(),
+        &initializer,
+        &escrow.temp_token_account,
+        &escrow.escrow,
+        &initializer,
+        &initializer,
+    )
+    .unwrap();
+    process(&mut ctx, &[ix], &[&escrow.initializer])
+        .await
+        .unwrap();
+    assert!(get_account(&mut ctx, &escrow.escrow).await.is_none());
+    let temp_token_account = token_account(&mut ctx, &escrow.temp_token_account).await;
+    assert!(temp_token_account.is_frozen());
+    assert_eq!(temp_token_account.amount, 1_000);
+}
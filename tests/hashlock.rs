@@ -0,0 +1,109 @@
+mod common;
+
+use common::*;
+use solana_escrow::{error::EscrowError, instruction::InitEscrowArgs};
+use solana_program::{hash::hash, instruction::InstructionError};
+use solana_program_test::ProgramTestContext;
+
+const PREIMAGE: [u8; 32] = [42; 32];
+
+/// The slot the hashlock's window closes at in the timelock tests
+const TIMELOCK_SLOT: u64 = 1_000;
+
+/// Opens an escrow locked behind the hash of `PREIMAGE`
+async fn open_hashlocked_escrow(ctx: &mut ProgramTestContext) -> TestEscrow {
+    open_timelocked_escrow(ctx, 0).await
+}
+
+/// Opens an escrow locked behind the hash of `PREIMAGE` until `timelock_slot`
+async fn open_timelocked_escrow(ctx: &mut ProgramTestContext, timelock_slot: u64) -> TestEscrow {
+    let escrow = setup_escrow(ctx, EscrowParams::default()).await;
+    let args = InitEscrowArgs {
+        hashlock: hash(&PREIMAGE).to_bytes(),
+        timelock_slot,
+        ..escrow.init_args()
+    };
+    let ix = escrow.init_instruction_with(&escrow.temp_token_account, args);
+    process(ctx, &[ix], &[&escrow.initializer]).await.unwrap();
+    escrow
+}
+
+#[tokio::test]
+async fn test_exchange_with_the_preimage_of_the_hashlock() {
+    let mut ctx = start().await;
+    let escrow = open_hashlocked_escrow(&mut ctx).await;
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+
+    let ix = escrow.exchange_instruction_with(&taker, 1_000, 0, 0, Some(PREIMAGE));
+    process(&mut ctx, &[ix], &[&taker.keypair]).await.unwrap();
+    assert_eq!(token_balance(&mut ctx, &taker.receive_account).await, 1_000);
+    assert_eq!(token_balance(&mut ctx, &escrow.receive_account).await, 500);
+}
+
+#[tokio::test]
+async fn test_exchange_rejects_a_wrong_or_missing_preimage() {
+    let mut ctx = start().await;
+    let escrow = open_hashlocked_escrow(&mut ctx).await;
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+
+    for preimage in [Some([43; 32]), None] {
+        let ix = escrow.exchange_instruction_with(&taker, 1_000, 0, 0, preimage);
+        let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await);
+        assert_eq!(
+            error,
+            InstructionError::Custom(EscrowError::InvalidPreimage as u32)
+        );
+    }
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.temp_token_account).await,
+        1_000
+    );
+    assert_eq!(token_balance(&mut ctx, &taker.send_account).await, 500);
+}
+
+#[tokio::test]
+async fn test_taker_reveals_the_preimage_before_the_timelock() {
+    let mut ctx = start().await;
+    let escrow = open_timelocked_escrow(&mut ctx, TIMELOCK_SLOT).await;
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+
+    // The initializer can't take the deposit back while the taker may still reveal the preimage
+    let ix = escrow.cancel_instruction();
+    let error = instruction_error(process(&mut ctx, &[ix], &[&escrow.initializer]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::EscrowTimelocked as u32)
+    );
+
+    let ix = escrow.exchange_instruction_with(&taker, 1_000, 0, 0, Some(PREIMAGE));
+    process(&mut ctx, &[ix], &[&taker.keypair]).await.unwrap();
+    assert_eq!(token_balance(&mut ctx, &taker.receive_account).await, 1_000);
+    assert_eq!(token_balance(&mut ctx, &escrow.receive_account).await, 500);
+}
+
+#[tokio::test]
+async fn test_initializer_cancels_once_the_timelock_is_over() {
+    let mut ctx = start().await;
+    let escrow = open_timelocked_escrow(&mut ctx, TIMELOCK_SLOT).await;
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+    ctx.warp_to_slot(TIMELOCK_SLOT).unwrap();
+
+    // From the timelock slot on the preimage doesn't settle the escrow anymore
+    let ix = escrow.exchange_instruction_with(&taker, 1_000, 0, 0, Some(PREIMAGE));
+    let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::EscrowExpired as u32)
+    );
+    assert_eq!(token_balance(&mut ctx, &taker.send_account).await, 500);
+
+    let ix = escrow.cancel_instruction();
+    process(&mut ctx, &[ix], &[&escrow.initializer])
+        .await
+        .unwrap();
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.initializer_deposit_account).await,
+        1_000
+    );
+    assert!(get_account(&mut ctx, &escrow.escrow).await.is_none());
+}
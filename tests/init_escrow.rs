@@ -0,0 +1,249 @@
+mod common;
+
+use common::*;
+use solana_escrow::{
+    error::EscrowError,
+    pda::{find_escrow_address, PdaInfo},
+    state::{Escrow, MIN_EXPECTED_AMOUNT},
+};
+use solana_program::{
+    clock::Clock, instruction::InstructionError, program_pack::Pack, pubkey::Pubkey,
+};
+use solana_sdk::signature::Signer;
+
+#[tokio::test]
+async fn test_init_hands_the_temp_token_account_to_the_pda() {
+    let mut ctx = start().await;
+    let escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+
+    let pda = PdaInfo::find(&program_id(), &escrow.escrow);
+    let temp_token_account = token_account(&mut ctx, &escrow.temp_token_account).await;
+    assert_eq!(temp_token_account.owner, pda.key);
+    assert_eq!(temp_token_account.amount, 1_000);
+
+    let state = escrow_state(&mut ctx, &escrow.escrow).await;
+    assert!(state.created_at_slot > 0);
+    // Every field the parameters leave out keeps its uninitialized value
+    assert_eq!(
+        state,
+        Escrow {
+            is_initialized: true,
+            initializer_pubkey: escrow.initializer.pubkey(),
+            temp_token_account_pubkey: escrow.temp_token_account,
+            initializer_token_to_receive_account_pubkey: escrow.receive_account,
+            expected_amount: 500,
+            nonce: escrow.nonce,
+            created_at_slot: state.created_at_slot,
+            taker_slots: 1,
+            pda_bump: pda.bump,
+            deposit_mint: escrow.deposit_mint,
+            rent_refund_recipient: escrow.initializer.pubkey(),
+            ..Escrow::UNINITIALIZED
+        }
+    );
+}
+
+#[tokio::test]
+async fn test_init_records_the_slot_the_escrow_is_created_at() {
+    let mut ctx = start().await;
+    ctx.warp_to_slot(500).unwrap();
+    let escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+
+    let clock = ctx.banks_client.get_sysvar::<Clock>().await.unwrap();
+    assert!(clock.slot >= 500);
+    assert_eq!(
+        escrow_state(&mut ctx, &escrow.escrow).await.created_at_slot,
+        clock.slot
+    );
+}
+
+#[tokio::test]
+async fn test_init_rejects_an_initialized_escrow() {
+    let mut ctx = start().await;
+    let escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+    let state = escrow_state(&mut ctx, &escrow.escrow).await;
+
+    // The first temp token account belongs to the PDA now, a fresh one gets past the authority check
+    let initializer = escrow.initializer.pubkey();
+    let fresh_temp = create_token_account(&mut ctx, &escrow.deposit_mint, &initializer).await;
+    mint_to(&mut ctx, &escrow.deposit_mint, &fresh_temp, 1_000).await;
+
+    let ix = escrow.init_instruction(&fresh_temp);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&escrow.initializer]).await);
+    assert_eq!(error, InstructionError::AccountAlreadyInitialized);
+
+    assert_eq!(escrow_state(&mut ctx, &escrow.escrow).await, state);
+    assert_eq!(
+        token_account(&mut ctx, &fresh_temp).await.owner,
+        initializer
+    );
+    assert_eq!(token_balance(&mut ctx, &fresh_temp).await, 1_000);
+}
+
+#[tokio::test]
+async fn test_identical_escrows_with_different_nonces_coexist() {
+    let mut ctx = start().await;
+    let first = open_escrow(&mut ctx, EscrowParams::default()).await;
+    let first_escrow = first.escrow;
+    let initializer = first.initializer.pubkey();
+
+    // The same trade again, apart from its nonce and the tokens it holds
+    let second_temp = create_token_account(&mut ctx, &first.deposit_mint, &initializer).await;
+    mint_to(&mut ctx, &first.deposit_mint, &second_temp, 1_000).await;
+    let (second_escrow, _) = find_escrow_address(&initializer, 1, &program_id());
+    assert_ne!(second_escrow, first_escrow);
+    let second = TestEscrow {
+        temp_token_account: second_temp,
+        escrow: second_escrow,
+        nonce: 1,
+        ..first
+    };
+    let ix = second.init_instruction(&second_temp);
+    process(&mut ctx, &[ix], &[&second.initializer])
+        .await
+        .unwrap();
+
+    let first_state = escrow_state(&mut ctx, &first_escrow).await;
+    let second_state = escrow_state(&mut ctx, &second_escrow).await;
+    assert!(first_state.is_initialized && second_state.is_initialized);
+    assert_eq!((first_state.nonce, second_state.nonce), (0, 1));
+    assert_eq!(first_state.expected_amount, second_state.expected_amount);
+    assert_ne!(
+        first_state.temp_token_account_pubkey,
+        second_state.temp_token_account_pubkey
+    );
+}
+
+#[tokio::test]
+async fn test_init_rejects_another_token_program() {
+    let mut ctx = start().await;
+    let escrow = setup_escrow(&mut ctx, EscrowParams::default()).await;
+
+    let mut ix = escrow.init_instruction(&escrow.temp_token_account);
+    // 5. The token program
+    ix.accounts[5].pubkey = Pubkey::new_unique();
+    let error = instruction_error(process(&mut ctx, &[ix], &[&escrow.initializer]).await);
+    assert_eq!(error, InstructionError::IncorrectProgramId);
+
+    assert_eq!(
+        get_account(&mut ctx, &escrow.escrow).await.unwrap().data,
+        vec![0; Escrow::LEN]
+    );
+    assert_eq!(
+        token_account(&mut ctx, &escrow.temp_token_account)
+            .await
+            .owner,
+        escrow.initializer.pubkey()
+    );
+}
+
+#[tokio::test]
+async fn test_escrow_expecting_u64_max_is_exchanged() {
+    let mut ctx = start().await;
+    // The whole range of expected amounts is accepted, the payment is never added to or scaled up
+    let escrow = open_escrow(
+        &mut ctx,
+        EscrowParams {
+            amount: u64::MAX,
+            ..EscrowParams::default()
+        },
+    )
+    .await;
+    assert_eq!(
+        escrow_state(&mut ctx, &escrow.escrow).await.expected_amount,
+        u64::MAX
+    );
+
+    let taker = create_taker(&mut ctx, &escrow, u64::MAX).await;
+    let ix = escrow.exchange_instruction(&taker, 1_000, 0);
+    process(&mut ctx, &[ix], &[&taker.keypair]).await.unwrap();
+    assert_eq!(token_balance(&mut ctx, &taker.send_account).await, 0);
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.receive_account).await,
+        u64::MAX
+    );
+    assert_eq!(token_balance(&mut ctx, &taker.receive_account).await, 1_000);
+}
+
+#[tokio::test]
+async fn test_init_rejects_the_temp_token_account_as_the_receive_account() {
+    let mut ctx = start().await;
+    let escrow = setup_escrow(&mut ctx, EscrowParams::default()).await;
+
+    let mut ix = escrow.init_instruction(&escrow.temp_token_account);
+    // 2. The initializer's token account to receive
+    ix.accounts[2].pubkey = escrow.temp_token_account;
+    let error = instruction_error(process(&mut ctx, &[ix], &[&escrow.initializer]).await);
+    assert_eq!(error, InstructionError::InvalidArgument);
+
+    assert_eq!(
+        get_account(&mut ctx, &escrow.escrow).await.unwrap().data,
+        vec![0; Escrow::LEN]
+    );
+    assert_eq!(
+        token_account(&mut ctx, &escrow.temp_token_account)
+            .await
+            .owner,
+        escrow.initializer.pubkey()
+    );
+}
+
+#[tokio::test]
+async fn test_init_rejects_an_empty_temp_token_account() {
+    let mut ctx = start().await;
+    let escrow = setup_escrow(
+        &mut ctx,
+        EscrowParams {
+            deposit: 0,
+            ..EscrowParams::default()
+        },
+    )
+    .await;
+    assert_eq!(token_balance(&mut ctx, &escrow.temp_token_account).await, 0);
+
+    let ix = escrow.init_instruction(&escrow.temp_token_account);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&escrow.initializer]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::InvalidAmount as u32)
+    );
+
+    assert_eq!(
+        get_account(&mut ctx, &escrow.escrow).await.unwrap().data,
+        vec![0; Escrow::LEN]
+    );
+}
+
+#[tokio::test]
+async fn test_init_requires_the_minimum_expected_amount() {
+    let mut ctx = start().await;
+    let below = setup_escrow(
+        &mut ctx,
+        EscrowParams {
+            amount: MIN_EXPECTED_AMOUNT - 1,
+            ..EscrowParams::default()
+        },
+    )
+    .await;
+    let ix = below.init_instruction(&below.temp_token_account);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&below.initializer]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::InvalidAmount as u32)
+    );
+
+    let at_minimum = open_escrow(
+        &mut ctx,
+        EscrowParams {
+            amount: MIN_EXPECTED_AMOUNT,
+            ..EscrowParams::default()
+        },
+    )
+    .await;
+    assert_eq!(
+        escrow_state(&mut ctx, &at_minimum.escrow)
+            .await
+            .expected_amount,
+        MIN_EXPECTED_AMOUNT
+    );
+}
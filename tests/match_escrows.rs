@@ -0,0 +1,109 @@
+mod common;
+
+use common::*;
+use solana_escrow::{error::EscrowError, instruction, state::Escrow};
+use solana_program::{
+    instruction::{Instruction, InstructionError},
+    program_pack::Pack,
+};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::signature::Signer;
+use spl_token::state::Account as TokenAccount;
+
+fn match_instruction(a: &TestEscrow, b: &TestEscrow) -> Instruction {
+    let initializer_a = a.initializer.pubkey();
+    let initializer_b = b.initializer.pubkey();
+    instruction::match_escrows(
+        &program_id(),
+        &a.escrow,
+        &a.temp_token_account,
+        &initializer_a,
+        &a.receive_account,
+        &initializer_a,
+        &a.deposit_mint,
+        &b.escrow,
+        &b.temp_token_account,
+        &initializer_b,
+        &b.receive_account,
+        &initializer_b,
+        &b.deposit_mint,
+    )
+    .unwrap()
+}
+
+/// Opens an escrow offering 1000 X for 500 Y, and a mirrored one offering `mirror_deposit` Y for 1000 X
+async fn open_mirrored_escrows(
+    ctx: &mut ProgramTestContext,
+    mirror_deposit: u64,
+) -> (TestEscrow, TestEscrow) {
+    let x = create_mint(ctx, 6).await;
+    let y = create_mint(ctx, 6).await;
+    let a = setup_escrow_with_mints(ctx, EscrowParams::default(), &x, &y).await;
+    let b = setup_escrow_with_mints(
+        ctx,
+        EscrowParams {
+            deposit: mirror_deposit,
+            amount: 1_000,
+            ..EscrowParams::default()
+        },
+        &y,
+        &x,
+    )
+    .await;
+    for escrow in [&a, &b] {
+        let ix = escrow.init_instruction(&escrow.temp_token_account);
+        process(ctx, &[ix], &[&escrow.initializer]).await.unwrap();
+    }
+    (a, b)
+}
+
+#[tokio::test]
+async fn test_match_escrows_settles_both_escrows() {
+    let mut ctx = start().await;
+    let (a, b) = open_mirrored_escrows(&mut ctx, 500).await;
+
+    let escrow_rent = minimum_balance(&mut ctx, Escrow::LEN).await;
+    let pda_token_account_rent = minimum_balance(&mut ctx, TokenAccount::LEN).await;
+    let pre_a = lamports(&mut ctx, &a.initializer.pubkey()).await;
+    let pre_b = lamports(&mut ctx, &b.initializer.pubkey()).await;
+
+    let ix = match_instruction(&a, &b);
+    process(&mut ctx, &[ix], &[]).await.unwrap();
+
+    assert_eq!(token_balance(&mut ctx, &a.receive_account).await, 500);
+    assert_eq!(token_balance(&mut ctx, &b.receive_account).await, 1_000);
+    for escrow in [&a, &b] {
+        assert!(get_account(&mut ctx, &escrow.escrow).await.is_none());
+        assert!(get_account(&mut ctx, &escrow.temp_token_account)
+            .await
+            .is_none());
+    }
+    assert_eq!(
+        lamports(&mut ctx, &a.initializer.pubkey()).await,
+        pre_a + escrow_rent + pda_token_account_rent
+    );
+    assert_eq!(
+        lamports(&mut ctx, &b.initializer.pubkey()).await,
+        pre_b + escrow_rent + pda_token_account_rent
+    );
+}
+
+#[tokio::test]
+async fn test_match_escrows_rejects_a_deposit_short_of_the_expected_amount() {
+    let mut ctx = start().await;
+    let (a, b) = open_mirrored_escrows(&mut ctx, 499).await;
+
+    let ix = match_instruction(&a, &b);
+    let error = instruction_error(process(&mut ctx, &[ix], &[]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::ExpectedAmountMismatch as u32)
+    );
+
+    assert_eq!(token_balance(&mut ctx, &a.temp_token_account).await, 1_000);
+    assert_eq!(token_balance(&mut ctx, &b.temp_token_account).await, 499);
+    assert_eq!(token_balance(&mut ctx, &a.receive_account).await, 0);
+    assert_eq!(token_balance(&mut ctx, &b.receive_account).await, 0);
+    assert!(escrow_state(&mut ctx, &a.escrow).await.is_initialized);
+    assert!(escrow_state(&mut ctx, &b.escrow).await.is_initialized);
+}
@@ -0,0 +1,79 @@
+mod common;
+
+use common::*;
+use solana_escrow::{
+    error::EscrowError,
+    instruction,
+    pda::{find_config_address, NAMESPACE_LEN},
+    state::{EscrowConfig, MAX_NAMESPACES, MAX_PAUSED_MINTS},
+};
+use solana_program::{instruction::InstructionError, pubkey::Pubkey};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::signature::{Keypair, Signer};
+
+/// Starts the test validator with a config administered by `admin`, with no fee and nothing paused
+async fn start_with_config(admin: &Keypair) -> ProgramTestContext {
+    let config = EscrowConfig {
+        is_initialized: true,
+        admin: admin.pubkey(),
+        paused: false,
+        treasury: Pubkey::new_unique(),
+        cancel_fee: 0,
+        init_fee: 0,
+        paused_mints_count: 0,
+        paused_mints: [Pubkey::default(); MAX_PAUSED_MINTS],
+        extension_fee: 0,
+        exchange_fee_bps: 0,
+        free_fills: 0,
+        namespaces_count: 0,
+        namespaces: [[0; NAMESPACE_LEN]; MAX_NAMESPACES],
+    };
+    start_with_accounts(vec![(
+        find_config_address(&program_id()).0,
+        program_account(config),
+    )])
+    .await
+}
+
+#[tokio::test]
+async fn test_pausing_a_mint_only_blocks_the_escrows_involving_it() {
+    let admin = Keypair::new();
+    let mut ctx = start_with_config(&admin).await;
+
+    let paused_mint = create_mint(&mut ctx, 6).await;
+    let other_mint = create_mint(&mut ctx, 6).await;
+    let depositing_paused =
+        setup_escrow_with_mints(&mut ctx, EscrowParams::default(), &paused_mint, &other_mint).await;
+    let expecting_paused =
+        setup_escrow_with_mints(&mut ctx, EscrowParams::default(), &other_mint, &paused_mint).await;
+    for escrow in [&depositing_paused, &expecting_paused] {
+        let ix = escrow.init_instruction(&escrow.temp_token_account);
+        process(&mut ctx, &[ix], &[&escrow.initializer])
+            .await
+            .unwrap();
+    }
+    let unrelated = open_escrow(&mut ctx, EscrowParams::default()).await;
+
+    let ix =
+        instruction::set_mint_paused(&program_id(), &admin.pubkey(), &paused_mint, true).unwrap();
+    process(&mut ctx, &[ix], &[&admin]).await.unwrap();
+
+    for escrow in [&depositing_paused, &expecting_paused] {
+        let taker = create_taker(&mut ctx, escrow, 1_000).await;
+        let ix = escrow.exchange_instruction(&taker, 1_000, 0);
+        let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await);
+        assert_eq!(
+            error,
+            InstructionError::Custom(EscrowError::MintPaused as u32)
+        );
+        assert_eq!(
+            token_balance(&mut ctx, &escrow.temp_token_account).await,
+            1_000
+        );
+    }
+
+    let taker = create_taker(&mut ctx, &unrelated, 1_000).await;
+    let ix = unrelated.exchange_instruction(&taker, 1_000, 0);
+    process(&mut ctx, &[ix], &[&taker.keypair]).await.unwrap();
+    assert_eq!(token_balance(&mut ctx, &taker.receive_account).await, 1_000);
+}
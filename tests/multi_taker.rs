@@ -0,0 +1,106 @@
+mod common;
+
+use common::*;
+use solana_escrow::{error::EscrowError, state::Escrow};
+use solana_program::instruction::InstructionError;
+use solana_sdk::signature::Signer;
+
+#[tokio::test]
+async fn test_two_takers_complete_a_split_escrow() {
+    let mut ctx = start().await;
+    let escrow = open_escrow(
+        &mut ctx,
+        EscrowParams {
+            taker_slots: 2,
+            ..EscrowParams::default()
+        },
+    )
+    .await;
+    let first_taker = create_taker(&mut ctx, &escrow, 1_000).await;
+    let second_taker = create_taker(&mut ctx, &escrow, 1_000).await;
+    let opened = escrow_state(&mut ctx, &escrow.escrow).await;
+
+    let ix = escrow.exchange_instruction(&first_taker, 500, 0);
+    process(&mut ctx, &[ix], &[&first_taker.keypair])
+        .await
+        .unwrap();
+
+    // Recording the first taker is the only change to the escrow
+    let mut filled_takers = opened.filled_takers;
+    filled_takers[0] = first_taker.keypair.pubkey();
+    assert_eq!(
+        escrow_state(&mut ctx, &escrow.escrow).await,
+        Escrow {
+            filled_takers_count: 1,
+            filled_takers,
+            ..opened
+        }
+    );
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.temp_token_account).await,
+        500
+    );
+    assert_eq!(token_balance(&mut ctx, &escrow.receive_account).await, 250);
+
+    let ix = escrow.exchange_instruction(&second_taker, 500, 0);
+    process(&mut ctx, &[ix], &[&second_taker.keypair])
+        .await
+        .unwrap();
+
+    assert_eq!(
+        token_balance(&mut ctx, &first_taker.receive_account).await,
+        500
+    );
+    assert_eq!(
+        token_balance(&mut ctx, &second_taker.receive_account).await,
+        500
+    );
+    assert_eq!(
+        token_balance(&mut ctx, &first_taker.send_account).await,
+        750
+    );
+    assert_eq!(
+        token_balance(&mut ctx, &second_taker.send_account).await,
+        750
+    );
+    assert_eq!(token_balance(&mut ctx, &escrow.receive_account).await, 500);
+    assert!(get_account(&mut ctx, &escrow.temp_token_account)
+        .await
+        .is_none());
+    assert!(get_account(&mut ctx, &escrow.escrow).await.is_none());
+}
+
+#[tokio::test]
+async fn test_a_taker_can_fill_only_one_share() {
+    let mut ctx = start().await;
+    let escrow = open_escrow(
+        &mut ctx,
+        EscrowParams {
+            taker_slots: 2,
+            ..EscrowParams::default()
+        },
+    )
+    .await;
+    let taker = create_taker(&mut ctx, &escrow, 1_000).await;
+
+    let ix = escrow.exchange_instruction(&taker, 500, 0);
+    process(&mut ctx, &[ix], &[&taker.keypair]).await.unwrap();
+
+    let ix = escrow.exchange_instruction(&taker, 500, 0);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::TakerNotAllowed as u32)
+    );
+    assert_eq!(token_balance(&mut ctx, &taker.receive_account).await, 500);
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.temp_token_account).await,
+        500
+    );
+    assert_eq!(
+        escrow_state(&mut ctx, &escrow.escrow)
+            .await
+            .filled_takers_count,
+        1
+    );
+}
@@ -0,0 +1,188 @@
+mod common;
+
+use common::*;
+use solana_escrow::{
+    error::EscrowError,
+    instruction,
+    pda::{find_config_address, PdaInfo, NAMESPACE_LEN},
+    state::{EscrowConfig, MAX_NAMESPACES, MAX_PAUSED_MINTS},
+};
+use solana_program::{instruction::Instruction, instruction::InstructionError, pubkey::Pubkey};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::signature::{Keypair, Signer};
+
+const NAMESPACE_A: [u8; NAMESPACE_LEN] = *b"namespace-a\0\0\0\0\0";
+const NAMESPACE_B: [u8; NAMESPACE_LEN] = *b"namespace-b\0\0\0\0\0";
+
+/// Starts the test validator with a config administered by `admin` allowing the two namespaces
+async fn start_with_namespaces(admin: &Keypair) -> ProgramTestContext {
+    let mut namespaces = [[0; NAMESPACE_LEN]; MAX_NAMESPACES];
+    namespaces[0] = NAMESPACE_A;
+    namespaces[1] = NAMESPACE_B;
+    let config = EscrowConfig {
+        is_initialized: true,
+        admin: admin.pubkey(),
+        paused: false,
+        treasury: Pubkey::new_unique(),
+        cancel_fee: 0,
+        init_fee: 0,
+        paused_mints_count: 0,
+        paused_mints: [Pubkey::default(); MAX_PAUSED_MINTS],
+        extension_fee: 0,
+        exchange_fee_bps: 0,
+        free_fills: 0,
+        namespaces_count: 2,
+        namespaces,
+    };
+    start_with_accounts(vec![(
+        find_config_address(&program_id()).0,
+        program_account(config),
+    )])
+    .await
+}
+
+fn init_in_namespace_instruction(
+    escrow: &TestEscrow,
+    namespace: &[u8; NAMESPACE_LEN],
+) -> Instruction {
+    let initializer = escrow.initializer.pubkey();
+    instruction::init_escrow_in_namespace(
+        &program_id(),
+        &initializer,
+        &escrow.temp_token_account,
+        &escrow.receive_account,
+        &initializer,
+        namespace,
+        escrow.init_args(),
+        false,
+    )
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_escrows_of_different_namespaces_have_different_authorities() {
+    let admin = Keypair::new();
+    let mut ctx = start_with_namespaces(&admin).await;
+
+    let first = setup_escrow(&mut ctx, EscrowParams::default()).await;
+    let second = setup_escrow(&mut ctx, EscrowParams::default()).await;
+    for (escrow, namespace) in [(&first, &NAMESPACE_A), (&second, &NAMESPACE_B)] {
+        let ix = init_in_namespace_instruction(escrow, namespace);
+        process(&mut ctx, &[ix], &[&escrow.initializer])
+            .await
+            .unwrap();
+
+        // The deposit is handed to the authority of the namespace, not the one of the default namespace
+        let authority = PdaInfo::find_in_namespace(&program_id(), namespace, &escrow.escrow).key;
+        assert_ne!(authority, PdaInfo::find(&program_id(), &escrow.escrow).key);
+        assert_eq!(
+            token_account(&mut ctx, &escrow.temp_token_account)
+                .await
+                .owner,
+            authority
+        );
+        assert_eq!(
+            escrow_state(&mut ctx, &escrow.escrow).await.namespace,
+            *namespace
+        );
+    }
+    // The same escrow account derives a different authority in each namespace
+    assert_ne!(
+        PdaInfo::find_in_namespace(&program_id(), &NAMESPACE_A, &first.escrow).key,
+        PdaInfo::find_in_namespace(&program_id(), &NAMESPACE_B, &first.escrow).key
+    );
+
+    // Each escrow is only settled through the authority of its own namespace
+    let taker = create_taker(&mut ctx, &first, 500).await;
+    let ix = first.exchange_instruction(&taker, 1_000, 0);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::InvalidPda as u32)
+    );
+    let ix = instruction::with_namespace(
+        first.exchange_instruction(&taker, 1_000, 0),
+        &first.escrow,
+        &NAMESPACE_B,
+    );
+    let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::InvalidPda as u32)
+    );
+
+    let ix = instruction::with_namespace(
+        first.exchange_instruction(&taker, 1_000, 0),
+        &first.escrow,
+        &NAMESPACE_A,
+    );
+    process(&mut ctx, &[ix], &[&taker.keypair]).await.unwrap();
+    assert_eq!(token_balance(&mut ctx, &taker.receive_account).await, 1_000);
+    assert_eq!(token_balance(&mut ctx, &first.receive_account).await, 500);
+
+    let ix = instruction::with_namespace(second.cancel_instruction(), &second.escrow, &NAMESPACE_B);
+    process(&mut ctx, &[ix], &[&second.initializer])
+        .await
+        .unwrap();
+    assert_eq!(
+        token_balance(&mut ctx, &second.initializer_deposit_account).await,
+        1_000
+    );
+    assert!(get_account(&mut ctx, &second.escrow).await.is_none());
+}
+
+#[tokio::test]
+async fn test_init_escrow_in_namespace_requires_a_whitelisted_namespace() {
+    let admin = Keypair::new();
+    let mut ctx = start_with_namespaces(&admin).await;
+    let escrow = setup_escrow(&mut ctx, EscrowParams::default()).await;
+
+    let namespace_c = *b"namespace-c\0\0\0\0\0";
+    let ix = init_in_namespace_instruction(&escrow, &namespace_c);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&escrow.initializer]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::NamespaceNotAllowed as u32)
+    );
+
+    // The default namespace isn't opened into, `InitEscrow` already does
+    let ix = init_in_namespace_instruction(&escrow, &[0; NAMESPACE_LEN]);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&escrow.initializer]).await);
+    assert_eq!(error, InstructionError::InvalidArgument);
+
+    // Only the admin whitelists namespaces
+    let stranger = Keypair::new();
+    let ix =
+        instruction::set_namespace_allowed(&program_id(), &stranger.pubkey(), &namespace_c, true)
+            .unwrap();
+    let error = instruction_error(process(&mut ctx, &[ix], &[&stranger]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::WrongAuthority as u32)
+    );
+
+    let ix = instruction::set_namespace_allowed(&program_id(), &admin.pubkey(), &namespace_c, true)
+        .unwrap();
+    process(&mut ctx, &[ix], &[&admin]).await.unwrap();
+    let ix = init_in_namespace_instruction(&escrow, &namespace_c);
+    process(&mut ctx, &[ix], &[&escrow.initializer])
+        .await
+        .unwrap();
+    assert_eq!(
+        escrow_state(&mut ctx, &escrow.escrow).await.namespace,
+        namespace_c
+    );
+
+    // Disallowing a namespace stops new escrows being opened in it
+    let ix =
+        instruction::set_namespace_allowed(&program_id(), &admin.pubkey(), &NAMESPACE_A, false)
+            .unwrap();
+    process(&mut ctx, &[ix], &[&admin]).await.unwrap();
+    let other_escrow = setup_escrow(&mut ctx, EscrowParams::default()).await;
+    let ix = init_in_namespace_instruction(&other_escrow, &NAMESPACE_A);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&other_escrow.initializer]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::NamespaceNotAllowed as u32)
+    );
+}
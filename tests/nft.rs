@@ -0,0 +1,119 @@
+mod common;
+
+use common::*;
+use solana_escrow::{error::EscrowError, instruction};
+use solana_program::instruction::{Instruction, InstructionError};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::signature::Signer;
+
+fn init_nft_instruction(escrow: &TestEscrow) -> Instruction {
+    let initializer = escrow.initializer.pubkey();
+    instruction::init_escrow_nft(
+        &program_id(),
+        &escrow.deposit_mint,
+        &initializer,
+        &escrow.temp_token_account,
+        &escrow.receive_account,
+        &initializer,
+        escrow.init_args(),
+        false,
+    )
+    .unwrap()
+}
+
+/// The parameters of an escrow depositing a single unit of a mint without decimals
+fn nft_params() -> EscrowParams {
+    EscrowParams {
+        deposit: 1,
+        deposit_decimals: 0,
+        ..EscrowParams::default()
+    }
+}
+
+async fn open_nft_escrow(ctx: &mut ProgramTestContext, params: EscrowParams) -> TestEscrow {
+    let escrow = setup_escrow(ctx, params).await;
+    let ix = init_nft_instruction(&escrow);
+    process(ctx, &[ix], &[&escrow.initializer]).await.unwrap();
+    escrow
+}
+
+#[tokio::test]
+async fn test_nft_escrow_hands_the_nft_to_the_taker() {
+    let mut ctx = start().await;
+    let escrow = open_nft_escrow(&mut ctx, nft_params()).await;
+    assert!(escrow_state(&mut ctx, &escrow.escrow).await.is_nft);
+
+    let taker = create_taker(&mut ctx, &escrow, 1_000).await;
+    let ix = escrow.exchange_instruction(&taker, 1, 0);
+    process(&mut ctx, &[ix], &[&taker.keypair]).await.unwrap();
+
+    assert_eq!(token_balance(&mut ctx, &taker.receive_account).await, 1);
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.receive_account).await,
+        escrow.params.amount
+    );
+}
+
+#[tokio::test]
+async fn test_nft_escrow_rejects_a_fungible_deposit() {
+    let mut ctx = start().await;
+    let escrow = setup_escrow(&mut ctx, EscrowParams::default()).await;
+
+    let ix = init_nft_instruction(&escrow);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&escrow.initializer]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::NotAnNft as u32)
+    );
+}
+
+#[tokio::test]
+async fn test_nft_escrow_rejects_split_and_per_fill_pricing() {
+    let mut ctx = start().await;
+
+    let split = EscrowParams {
+        taker_slots: 2,
+        ..nft_params()
+    };
+    let band = EscrowParams {
+        min_amount: 500,
+        max_amount: 1_000,
+        ..nft_params()
+    };
+    let ratio = EscrowParams {
+        price_num: 1,
+        price_den: 2,
+        ..nft_params()
+    };
+    for params in [split, band, ratio] {
+        let escrow = setup_escrow(&mut ctx, params).await;
+        let ix = init_nft_instruction(&escrow);
+        let error = instruction_error(process(&mut ctx, &[ix], &[&escrow.initializer]).await);
+        assert_eq!(error, InstructionError::InvalidArgument);
+        let account = get_account(&mut ctx, &escrow.escrow).await.unwrap();
+        assert!(account.data.iter().all(|byte| *byte == 0));
+    }
+}
+
+#[tokio::test]
+async fn test_nft_escrow_rejects_units_minted_after_init() {
+    let mut ctx = start().await;
+    let escrow = open_nft_escrow(&mut ctx, nft_params()).await;
+    mint_to(
+        &mut ctx,
+        &escrow.deposit_mint,
+        &escrow.temp_token_account,
+        1,
+    )
+    .await;
+
+    // Even a taker agreeing to receive both units is turned down
+    let taker = create_taker(&mut ctx, &escrow, 1_000).await;
+    let ix = escrow.exchange_instruction(&taker, 2, 0);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::NotAnNft as u32)
+    );
+    assert_eq!(token_balance(&mut ctx, &escrow.temp_token_account).await, 2);
+}
@@ -0,0 +1,178 @@
+mod common;
+
+use common::*;
+use solana_escrow::{error::EscrowError, instruction, state::Escrow};
+use solana_program::{
+    instruction::InstructionError, program_pack::Pack, pubkey::Pubkey, system_instruction,
+};
+use solana_sdk::signature::{Keypair, Signer};
+
+fn partial_withdraw_instruction(
+    escrow: &TestEscrow,
+    amount: u64,
+) -> solana_program::instruction::Instruction {
+    instruction::partial_withdraw(
+        &program_id(),
+        &escrow.initializer.pubkey(),
+        &escrow.temp_token_account,
+        &escrow.initializer_deposit_account,
+        &escrow.escrow,
+        amount,
+    )
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_partial_withdraw_leaves_the_escrow_open() {
+    let mut ctx = start().await;
+    let escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+
+    let ix = partial_withdraw_instruction(&escrow, 400);
+    process(&mut ctx, &[ix], &[&escrow.initializer])
+        .await
+        .unwrap();
+
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.temp_token_account).await,
+        600
+    );
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.initializer_deposit_account).await,
+        400
+    );
+    assert!(escrow_state(&mut ctx, &escrow.escrow).await.is_initialized);
+}
+
+#[tokio::test]
+async fn test_partial_withdraw_rejects_withdrawing_everything_or_more() {
+    let mut ctx = start().await;
+    let escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+
+    for amount in [0, 1_000, 1_001] {
+        let ix = partial_withdraw_instruction(&escrow, amount);
+        let error = instruction_error(process(&mut ctx, &[ix], &[&escrow.initializer]).await);
+        assert_eq!(
+            error,
+            InstructionError::Custom(EscrowError::InvalidAmount as u32)
+        );
+    }
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.temp_token_account).await,
+        1_000
+    );
+}
+
+#[tokio::test]
+async fn test_partial_withdraw_rejects_an_escrow_account_of_another_program() {
+    let mut ctx = start().await;
+    let escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+
+    // An account the size of an escrow, but owned by another program
+    let forged_escrow = Keypair::new();
+    let payer = ctx.payer.pubkey();
+    let ix = system_instruction::create_account(
+        &payer,
+        &forged_escrow.pubkey(),
+        minimum_balance(&mut ctx, Escrow::LEN).await,
+        Escrow::LEN as u64,
+        &Pubkey::new_unique(),
+    );
+    process(&mut ctx, &[ix], &[&forged_escrow]).await.unwrap();
+
+    let mut ix = partial_withdraw_instruction(&escrow, 400);
+    // 3. The escrow account
+    ix.accounts[3].pubkey = forged_escrow.pubkey();
+    let error = instruction_error(process(&mut ctx, &[ix], &[&escrow.initializer]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::WrongOwner as u32)
+    );
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.temp_token_account).await,
+        1_000
+    );
+}
+
+#[tokio::test]
+async fn test_partial_withdraw_rejects_another_token_program_or_pda() {
+    let mut ctx = start().await;
+    let escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+
+    // 4. The token program, 5. the PDA account
+    let expected_errors = [
+        (4, InstructionError::IncorrectProgramId),
+        (5, InstructionError::Custom(EscrowError::InvalidPda as u32)),
+    ];
+    for (index, expected_error) in expected_errors.iter() {
+        let mut ix = partial_withdraw_instruction(&escrow, 400);
+        ix.accounts[*index].pubkey = Pubkey::new_unique();
+        let error = instruction_error(process(&mut ctx, &[ix], &[&escrow.initializer]).await);
+        assert_eq!(error, *expected_error);
+    }
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.temp_token_account).await,
+        1_000
+    );
+}
+
+#[tokio::test]
+async fn test_cancel_after_partial_withdraw_returns_the_rest() {
+    let mut ctx = start().await;
+    let escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+
+    let ix = partial_withdraw_instruction(&escrow, 250);
+    process(&mut ctx, &[ix], &[&escrow.initializer])
+        .await
+        .unwrap();
+    let ix = escrow.cancel_instruction();
+    process(&mut ctx, &[ix], &[&escrow.initializer])
+        .await
+        .unwrap();
+
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.initializer_deposit_account).await,
+        1_000
+    );
+    assert!(get_account(&mut ctx, &escrow.temp_token_account)
+        .await
+        .is_none());
+    assert!(get_account(&mut ctx, &escrow.escrow).await.is_none());
+}
+
+#[tokio::test]
+async fn test_cancel_requires_the_initializer() {
+    let mut ctx = start().await;
+    let escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+
+    let mut ix = escrow.cancel_instruction();
+    ix.accounts[0].is_signer = false;
+    let error = instruction_error(process(&mut ctx, &[ix], &[]).await);
+    assert_eq!(error, InstructionError::MissingRequiredSignature);
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.temp_token_account).await,
+        1_000
+    );
+}
+
+#[tokio::test]
+async fn test_cancel_rejects_another_token_program_or_pda() {
+    let mut ctx = start().await;
+    let escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+
+    // 4. The token program, 5. the PDA account
+    let expected_errors = [
+        (4, InstructionError::IncorrectProgramId),
+        (5, InstructionError::Custom(EscrowError::InvalidPda as u32)),
+    ];
+    for (index, expected_error) in expected_errors.iter() {
+        let mut ix = escrow.cancel_instruction();
+        ix.accounts[*index].pubkey = Pubkey::new_unique();
+        let error = instruction_error(process(&mut ctx, &[ix], &[&escrow.initializer]).await);
+        assert_eq!(error, *expected_error);
+    }
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.temp_token_account).await,
+        1_000
+    );
+    assert!(escrow_state(&mut ctx, &escrow.escrow).await.is_initialized);
+}
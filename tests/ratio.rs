@@ -0,0 +1,62 @@
+mod common;
+
+use common::*;
+use solana_escrow::error::EscrowError;
+use solana_program::instruction::InstructionError;
+
+fn ratio_params(taker_slots: u8, price_num: u64, price_den: u64) -> EscrowParams {
+    EscrowParams {
+        taker_slots,
+        price_num,
+        price_den,
+        ..EscrowParams::default()
+    }
+}
+
+#[tokio::test]
+async fn test_each_fill_is_priced_at_the_ratio() {
+    let mut ctx = start().await;
+    // 1000 tokens in three shares of 333, 333 and 334, each paid at 3/2 rounded down
+    let escrow = open_escrow(&mut ctx, ratio_params(3, 3, 2)).await;
+
+    for (fill, payment) in [(333, 499), (333, 499), (334, 501)] {
+        let taker = create_taker(&mut ctx, &escrow, payment).await;
+        let ix = escrow.exchange_instruction(&taker, fill, 0);
+        process(&mut ctx, &[ix], &[&taker.keypair]).await.unwrap();
+        assert_eq!(token_balance(&mut ctx, &taker.receive_account).await, fill);
+        assert_eq!(token_balance(&mut ctx, &taker.send_account).await, 0);
+    }
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.receive_account).await,
+        1_499
+    );
+    assert!(get_account(&mut ctx, &escrow.escrow).await.is_none());
+}
+
+#[tokio::test]
+async fn test_ratio_rejects_a_fill_rounding_down_to_no_payment() {
+    let mut ctx = start().await;
+    // Each share of 500 tokens is worth half a base unit
+    let escrow = open_escrow(&mut ctx, ratio_params(2, 1, 1_000)).await;
+    let taker = create_taker(&mut ctx, &escrow, 1).await;
+
+    let ix = escrow.exchange_instruction(&taker, 500, 0);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::InvalidAmount as u32)
+    );
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.temp_token_account).await,
+        1_000
+    );
+
+    // Nor is a ratio without a numerator accepted at init
+    let escrow = setup_escrow(&mut ctx, ratio_params(1, 0, 2)).await;
+    let ix = escrow.init_instruction(&escrow.temp_token_account);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&escrow.initializer]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::InvalidAmount as u32)
+    );
+}
@@ -0,0 +1,142 @@
+mod common;
+
+use common::*;
+use solana_escrow::{
+    error::EscrowError,
+    instruction::{EscrowInstruction, InitEscrowArgs},
+    processor::Processor,
+};
+use solana_program::{
+    account_info::AccountInfo, entrypoint::ProgramResult, instruction::AccountMeta,
+    program_error::ProgramError, pubkey::Pubkey, rent::Rent,
+};
+use solana_program_test::{processor, ProgramTestContext};
+use solana_sdk::account::Account;
+
+fn nested_caller_id() -> Pubkey {
+    Pubkey::new_from_array([8; 32])
+}
+
+/// The account the nested caller records the error code of its nested call into
+fn record() -> Pubkey {
+    Pubkey::new_from_array([9; 32])
+}
+
+/// A callback program entering the escrow program again while it exchanges the escrow. The runtime already rejects
+/// a CPI back into the escrow program, so it calls the processor directly, as a program the escrow program invoked
+/// could once the runtime allows it, and records the error of reserving the escrow.
+/// Accounts: the record account, the escrow account
+fn process_nested_call(
+    _program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    _data: &[u8],
+) -> ProgramResult {
+    let record = &accounts[0];
+    let escrow = &accounts[1];
+    // The record account stands in for the taker, a signer as far as the processor can tell
+    let mut taker = record.clone();
+    taker.is_signer = true;
+
+    let result = Processor::processor(
+        &program_id(),
+        &[taker, escrow.clone()],
+        &EscrowInstruction::Reserve {
+            reserved_until_slot: 1,
+        }
+        .pack(),
+    );
+    let code = match result {
+        Err(ProgramError::Custom(code)) => code,
+        _ => u32::MAX,
+    };
+    record
+        .try_borrow_mut_data()?
+        .copy_from_slice(&code.to_le_bytes());
+    Ok(())
+}
+
+async fn start_with_nested_caller() -> ProgramTestContext {
+    start_with_programs(
+        vec![(
+            "nested_caller",
+            nested_caller_id(),
+            processor!(process_nested_call),
+        )],
+        vec![(
+            record(),
+            Account {
+                lamports: Rent::default().minimum_balance(4),
+                data: vec![0; 4],
+                owner: nested_caller_id(),
+                executable: false,
+                rent_epoch: 0,
+            },
+        )],
+    )
+    .await
+}
+
+#[tokio::test]
+async fn test_a_nested_call_during_the_exchange_is_rejected() {
+    let mut ctx = start_with_nested_caller().await;
+    let escrow = setup_escrow(&mut ctx, EscrowParams::default()).await;
+    let args = InitEscrowArgs {
+        callback_program: nested_caller_id(),
+        callback_accounts_len: 2,
+        ..escrow.init_args()
+    };
+    let ix = escrow.init_instruction_with(&escrow.temp_token_account, args);
+    process(&mut ctx, &[ix], &[&escrow.initializer])
+        .await
+        .unwrap();
+
+    // Without a template, settlement authority or stats the callback accounts come last
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+    let mut ix = escrow.exchange_instruction(&taker, 1_000, 0);
+    ix.accounts.extend_from_slice(&[
+        AccountMeta::new_readonly(nested_caller_id(), false),
+        AccountMeta::new(record(), false),
+        AccountMeta::new_readonly(escrow.escrow, false),
+    ]);
+    process(&mut ctx, &[ix], &[&taker.keypair]).await.unwrap();
+
+    // The nested call saw the escrow in progress, the exchange itself went through
+    let record_data = get_account(&mut ctx, &record()).await.unwrap().data;
+    assert_eq!(
+        record_data,
+        (EscrowError::ReentrancyDetected as u32).to_le_bytes()
+    );
+    assert_eq!(token_balance(&mut ctx, &taker.receive_account).await, 1_000);
+    assert!(get_account(&mut ctx, &escrow.escrow).await.is_none());
+}
+
+#[tokio::test]
+async fn test_exchange_clears_the_in_progress_flag_of_a_split_escrow() {
+    let mut ctx = start().await;
+    let escrow = open_escrow(
+        &mut ctx,
+        EscrowParams {
+            taker_slots: 2,
+            ..EscrowParams::default()
+        },
+    )
+    .await;
+
+    let first_taker = create_taker(&mut ctx, &escrow, 250).await;
+    let ix = escrow.exchange_instruction(&first_taker, 500, 0);
+    process(&mut ctx, &[ix], &[&first_taker.keypair])
+        .await
+        .unwrap();
+    assert!(!escrow_state(&mut ctx, &escrow.escrow).await.in_progress);
+
+    // The escrow left open takes the next taker
+    let second_taker = create_taker(&mut ctx, &escrow, 250).await;
+    let ix = escrow.exchange_instruction(&second_taker, 500, 0);
+    process(&mut ctx, &[ix], &[&second_taker.keypair])
+        .await
+        .unwrap();
+    assert_eq!(
+        token_balance(&mut ctx, &second_taker.receive_account).await,
+        500
+    );
+}
@@ -0,0 +1,94 @@
+mod common;
+
+use common::*;
+use solana_escrow::{
+    instruction::{self, InitEscrowArgs},
+    pda::find_escrow_address,
+    state::Escrow,
+};
+use solana_program::program_pack::Pack;
+use solana_sdk::signature::{Keypair, Signer};
+
+#[tokio::test]
+async fn test_replace_closes_the_old_escrow_and_opens_the_new_one() {
+    let mut ctx = start().await;
+    // The natively run program can't credit the initializer the old escrow's rent and then pass it read-only
+    // to the token program for the new escrow, so the old escrow refunds its rent to someone else
+    let escrow = setup_escrow(&mut ctx, EscrowParams::default()).await;
+    let initializer = escrow.initializer.pubkey();
+    let rent_refund_recipient = Keypair::new().pubkey();
+    let ix = instruction::init_escrow(
+        &program_id(),
+        &initializer,
+        &escrow.temp_token_account,
+        &escrow.receive_account,
+        &initializer,
+        InitEscrowArgs {
+            rent_refund_recipient,
+            ..escrow.init_args()
+        },
+        false,
+    )
+    .unwrap();
+    process(&mut ctx, &[ix], &[&escrow.initializer])
+        .await
+        .unwrap();
+
+    // The new deposit sits in a temp token account of its own, the new escrow account at the next nonce
+    let new_temp_token_account =
+        create_token_account(&mut ctx, &escrow.deposit_mint, &initializer).await;
+    mint_to(
+        &mut ctx,
+        &escrow.deposit_mint,
+        &new_temp_token_account,
+        2_000,
+    )
+    .await;
+    let new_nonce = escrow.nonce + 1;
+    let (new_escrow, _) = find_escrow_address(&initializer, new_nonce, &program_id());
+
+    let ix = instruction::replace(
+        &program_id(),
+        &initializer,
+        &escrow.temp_token_account,
+        &escrow.initializer_deposit_account,
+        &escrow.escrow,
+        &initializer,
+        &rent_refund_recipient,
+        &new_temp_token_account,
+        &escrow.receive_account,
+        InitEscrowArgs {
+            amount: 900,
+            nonce: new_nonce,
+            ..escrow.init_args()
+        },
+        false,
+    )
+    .unwrap();
+    process(&mut ctx, &[ix], &[&escrow.initializer])
+        .await
+        .unwrap();
+
+    // The old deposit is back with the initializer and its escrow account is closed
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.initializer_deposit_account).await,
+        escrow.params.deposit
+    );
+    assert!(get_account(&mut ctx, &escrow.temp_token_account)
+        .await
+        .is_none());
+    assert!(get_account(&mut ctx, &escrow.escrow).await.is_none());
+    assert_eq!(
+        lamports(&mut ctx, &rent_refund_recipient).await,
+        minimum_balance(&mut ctx, Escrow::LEN).await
+    );
+
+    let new_escrow_info = escrow_state(&mut ctx, &new_escrow).await;
+    assert_eq!(new_escrow_info.initializer_pubkey, initializer);
+    assert_eq!(
+        new_escrow_info.temp_token_account_pubkey,
+        new_temp_token_account
+    );
+    assert_eq!(new_escrow_info.expected_amount, 900);
+    assert_eq!(new_escrow_info.nonce, new_nonce);
+}
@@ -0,0 +1,124 @@
+mod common;
+
+use common::*;
+use solana_escrow::{
+    error::EscrowError,
+    instruction,
+    state::{Escrow, MAX_RESERVATION_SLOTS},
+};
+use solana_program::instruction::InstructionError;
+use solana_program_test::ProgramTestContext;
+use solana_sdk::signature::{Keypair, Signer};
+
+async fn reserve(
+    ctx: &mut ProgramTestContext,
+    escrow: &TestEscrow,
+    taker: &Keypair,
+    reserved_until_slot: u64,
+) -> Result<(), solana_sdk::transport::TransportError> {
+    let ix = instruction::reserve(
+        &program_id(),
+        &taker.pubkey(),
+        &escrow.escrow,
+        reserved_until_slot,
+    )
+    .unwrap();
+    process(ctx, &[ix], &[taker]).await
+}
+
+#[tokio::test]
+async fn test_cancel_is_rejected_while_the_escrow_is_reserved() {
+    let mut ctx = start().await;
+    let escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+    ctx.warp_to_slot(100).unwrap();
+    let opened = escrow_state(&mut ctx, &escrow.escrow).await;
+
+    reserve(&mut ctx, &escrow, &taker.keypair, 200)
+        .await
+        .unwrap();
+    assert_eq!(
+        escrow_state(&mut ctx, &escrow.escrow).await,
+        Escrow {
+            reserved_taker: taker.keypair.pubkey(),
+            reserved_until_slot: 200,
+            ..opened
+        }
+    );
+
+    let ix = escrow.cancel_instruction();
+    let error = instruction_error(process(&mut ctx, &[ix], &[&escrow.initializer]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::EscrowReserved as u32)
+    );
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.temp_token_account).await,
+        1_000
+    );
+
+    // Once the reservation is over the initializer gets the deposit back
+    ctx.warp_to_slot(200).unwrap();
+    let ix = escrow.cancel_instruction();
+    process(&mut ctx, &[ix], &[&escrow.initializer])
+        .await
+        .unwrap();
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.initializer_deposit_account).await,
+        1_000
+    );
+    assert!(get_account(&mut ctx, &escrow.escrow).await.is_none());
+}
+
+#[tokio::test]
+async fn test_only_the_reserving_taker_exchanges_during_the_reservation() {
+    let mut ctx = start().await;
+    let escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+    let other_taker = create_taker(&mut ctx, &escrow, 500).await;
+    ctx.warp_to_slot(100).unwrap();
+
+    reserve(&mut ctx, &escrow, &taker.keypair, 200)
+        .await
+        .unwrap();
+
+    // Another taker can neither exchange nor take the reservation over
+    let ix = escrow.exchange_instruction(&other_taker, 1_000, 0);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&other_taker.keypair]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::EscrowReserved as u32)
+    );
+    let error = instruction_error(reserve(&mut ctx, &escrow, &other_taker.keypair, 200).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::EscrowReserved as u32)
+    );
+
+    let ix = escrow.exchange_instruction(&taker, 1_000, 0);
+    process(&mut ctx, &[ix], &[&taker.keypair]).await.unwrap();
+    assert_eq!(token_balance(&mut ctx, &taker.receive_account).await, 1_000);
+}
+
+#[tokio::test]
+async fn test_reservation_has_to_end_within_the_window() {
+    let mut ctx = start().await;
+    let escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+    let taker = Keypair::new();
+    ctx.warp_to_slot(100).unwrap();
+    let slot = ctx.banks_client.get_clock().await.unwrap().slot;
+
+    for reserved_until_slot in [0, slot, slot + MAX_RESERVATION_SLOTS + 1] {
+        let error =
+            instruction_error(reserve(&mut ctx, &escrow, &taker, reserved_until_slot).await);
+        assert_eq!(error, InstructionError::InvalidArgument);
+    }
+
+    reserve(&mut ctx, &escrow, &taker, slot + MAX_RESERVATION_SLOTS)
+        .await
+        .unwrap();
+    // The reserving taker can renew their own reservation
+    reserve(&mut ctx, &escrow, &taker, slot + MAX_RESERVATION_SLOTS)
+        .await
+        .unwrap();
+}
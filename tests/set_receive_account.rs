@@ -0,0 +1,92 @@
+mod common;
+
+use common::*;
+use solana_escrow::{error::EscrowError, instruction, state::Escrow};
+use solana_program::{
+    instruction::{Instruction, InstructionError},
+    pubkey::Pubkey,
+};
+use solana_sdk::signature::{Keypair, Signer};
+
+fn set_receive_account_instruction(
+    escrow: &TestEscrow,
+    initializer: &Pubkey,
+    new_receive_account: &Pubkey,
+) -> Instruction {
+    instruction::set_receive_account(
+        &program_id(),
+        initializer,
+        &escrow.escrow,
+        &escrow.receive_account,
+        new_receive_account,
+    )
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_set_receive_account_has_the_exchange_pay_the_new_account() {
+    let mut ctx = start().await;
+    let escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+    let opened = escrow_state(&mut ctx, &escrow.escrow).await;
+    let new_receive_account = create_token_account(
+        &mut ctx,
+        &escrow.expected_mint,
+        &escrow.initializer.pubkey(),
+    )
+    .await;
+
+    let ix = set_receive_account_instruction(
+        &escrow,
+        &escrow.initializer.pubkey(),
+        &new_receive_account,
+    );
+    process(&mut ctx, &[ix], &[&escrow.initializer])
+        .await
+        .unwrap();
+    assert_eq!(
+        escrow_state(&mut ctx, &escrow.escrow).await,
+        Escrow {
+            initializer_token_to_receive_account_pubkey: new_receive_account,
+            ..opened
+        }
+    );
+
+    // The initializer's receive account is the sixth account of the exchange
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+    let mut ix = escrow.exchange_instruction(&taker, 1_000, 0);
+    ix.accounts[5].pubkey = new_receive_account;
+    process(&mut ctx, &[ix], &[&taker.keypair]).await.unwrap();
+    assert_eq!(token_balance(&mut ctx, &new_receive_account).await, 500);
+    assert_eq!(token_balance(&mut ctx, &escrow.receive_account).await, 0);
+    assert_eq!(token_balance(&mut ctx, &taker.receive_account).await, 1_000);
+}
+
+#[tokio::test]
+async fn test_set_receive_account_rejects_a_stranger_and_another_mint() {
+    let mut ctx = start().await;
+    let escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+    let opened = escrow_state(&mut ctx, &escrow.escrow).await;
+
+    let stranger = Keypair::new();
+    let stranger_account =
+        create_token_account(&mut ctx, &escrow.expected_mint, &stranger.pubkey()).await;
+    let ix = set_receive_account_instruction(&escrow, &stranger.pubkey(), &stranger_account);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&stranger]).await);
+    assert_eq!(error, InstructionError::InvalidAccountData);
+
+    // The new account must hold the mint the escrow expects
+    let deposit_mint_account =
+        create_token_account(&mut ctx, &escrow.deposit_mint, &escrow.initializer.pubkey()).await;
+    let ix = set_receive_account_instruction(
+        &escrow,
+        &escrow.initializer.pubkey(),
+        &deposit_mint_account,
+    );
+    let error = instruction_error(process(&mut ctx, &[ix], &[&escrow.initializer]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::WrongMint as u32)
+    );
+
+    assert_eq!(escrow_state(&mut ctx, &escrow.escrow).await, opened);
+}
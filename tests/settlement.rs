@@ -0,0 +1,124 @@
+mod common;
+
+use common::*;
+use solana_escrow::{error::EscrowError, instruction::InitEscrowArgs};
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction, InstructionError},
+    program::invoke_signed,
+    pubkey::Pubkey,
+};
+use solana_program_test::{processor, ProgramTestContext};
+use solana_sdk::signature::{Keypair, Signer};
+
+fn settler_id() -> Pubkey {
+    Pubkey::new_from_array([12; 32])
+}
+
+/// The settlement authority, a PDA of the settler
+fn settlement_authority() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"settlement"], &settler_id())
+}
+
+/// An off-chain matching engine's program settling an exchange it matched, signing it as the settlement authority.
+/// Accounts: the escrow program, then the accounts of the `Exchange`, whose data it passes on as is
+fn process_settle(_program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let (escrow_program, exchange_accounts) = accounts.split_first().unwrap();
+    let (authority, bump) = settlement_authority();
+    let ix = Instruction {
+        program_id: *escrow_program.key,
+        accounts: exchange_accounts
+            .iter()
+            .map(|account| AccountMeta {
+                pubkey: *account.key,
+                is_signer: account.is_signer || *account.key == authority,
+                is_writable: account.is_writable,
+            })
+            .collect(),
+        data: data.to_vec(),
+    };
+    invoke_signed(&ix, accounts, &[&[b"settlement", &[bump]]])
+}
+
+/// Opens an escrow only `settlement_authority` can take
+async fn open_settled_escrow(
+    ctx: &mut ProgramTestContext,
+    settlement_authority: &Pubkey,
+) -> TestEscrow {
+    let escrow = setup_escrow(ctx, EscrowParams::default()).await;
+    let args = InitEscrowArgs {
+        settlement_authority: *settlement_authority,
+        ..escrow.init_args()
+    };
+    let ix = escrow.init_instruction_with(&escrow.temp_token_account, args);
+    process(ctx, &[ix], &[&escrow.initializer]).await.unwrap();
+    escrow
+}
+
+/// An `Exchange` of the escrow by the taker, passing `authority` as the settlement authority
+fn exchange_instruction(
+    escrow: &TestEscrow,
+    taker: &TestTaker,
+    authority: &Pubkey,
+    is_signer: bool,
+) -> Instruction {
+    // Without a template, callback or stats the settlement authority comes last
+    let mut ix = escrow.exchange_instruction(taker, 1_000, 0);
+    ix.accounts
+        .push(AccountMeta::new_readonly(*authority, is_signer));
+    ix
+}
+
+#[tokio::test]
+async fn test_settlement_program_signs_the_exchange() {
+    let mut ctx = start_with_programs(
+        vec![("settler", settler_id(), processor!(process_settle))],
+        vec![],
+    )
+    .await;
+    let authority = settlement_authority().0;
+    let escrow = open_settled_escrow(&mut ctx, &authority).await;
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+
+    let exchange = exchange_instruction(&escrow, &taker, &authority, false);
+    let mut accounts = vec![AccountMeta::new_readonly(program_id(), false)];
+    accounts.extend(exchange.accounts);
+    let ix = Instruction {
+        program_id: settler_id(),
+        accounts,
+        data: exchange.data,
+    };
+    process(&mut ctx, &[ix], &[&taker.keypair]).await.unwrap();
+    assert_eq!(token_balance(&mut ctx, &taker.receive_account).await, 1_000);
+    assert_eq!(token_balance(&mut ctx, &escrow.receive_account).await, 500);
+    assert!(get_account(&mut ctx, &escrow.escrow).await.is_none());
+}
+
+#[tokio::test]
+async fn test_exchange_without_the_settlement_authority_is_rejected() {
+    let mut ctx = start().await;
+    let authority = settlement_authority().0;
+    let escrow = open_settled_escrow(&mut ctx, &authority).await;
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+
+    // The taker can name the settlement authority but not sign for it
+    let ix = exchange_instruction(&escrow, &taker, &authority, false);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await);
+    assert_eq!(error, InstructionError::MissingRequiredSignature);
+
+    // Nor have a key of its own stand in for it
+    let impostor = Keypair::new();
+    let ix = exchange_instruction(&escrow, &taker, &impostor.pubkey(), true);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair, &impostor]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::WrongAuthority as u32)
+    );
+
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.temp_token_account).await,
+        1_000
+    );
+    assert_eq!(token_balance(&mut ctx, &taker.send_account).await, 500);
+}
@@ -0,0 +1,242 @@
+mod common;
+
+use common::*;
+use solana_escrow::{
+    error::EscrowError,
+    instruction::{self, InitEscrowArgs},
+    pda::PdaInfo,
+    state::Escrow,
+};
+use solana_program::{instruction::InstructionError, program_pack::Pack, pubkey::Pubkey};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::signature::{Keypair, Signer};
+use spl_token::state::Account as TokenAccount;
+
+const DEPOSIT: u64 = 100_000_000;
+
+async fn init_sol_escrow(
+    ctx: &mut ProgramTestContext,
+    escrow: &TestEscrow,
+    args: InitEscrowArgs,
+) -> Result<(), solana_sdk::transport::TransportError> {
+    let initializer = escrow.initializer.pubkey();
+    let ix = instruction::init_escrow_with_sol(
+        &program_id(),
+        &initializer,
+        &escrow.temp_token_account,
+        &escrow.receive_account,
+        &initializer,
+        DEPOSIT,
+        args,
+        false,
+    )
+    .unwrap();
+    process(ctx, &[ix], &[&escrow.initializer]).await
+}
+
+#[tokio::test]
+async fn test_sol_deposit_is_paid_to_the_taker_in_lamports() {
+    let mut ctx = start().await;
+    let escrow = setup_sol_escrow(&mut ctx, EscrowParams::default()).await;
+    init_sol_escrow(&mut ctx, &escrow, escrow.init_args())
+        .await
+        .unwrap();
+
+    // The lamports are wrapped into the temp token account, now the PDA's
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.temp_token_account).await,
+        DEPOSIT
+    );
+    assert!(
+        escrow_state(&mut ctx, &escrow.escrow)
+            .await
+            .is_native_deposit
+    );
+
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+    // Any account receives the lamports, here one that doesn't exist yet
+    let taker_lamports_account = Pubkey::new_unique();
+    let initializer_lamports = lamports(&mut ctx, &escrow.initializer.pubkey()).await;
+    let token_account_rent = minimum_balance(&mut ctx, TokenAccount::LEN).await;
+    let escrow_rent = minimum_balance(&mut ctx, Escrow::LEN).await;
+
+    let ix = instruction::exchange_sol_deposit(
+        &program_id(),
+        &taker.keypair.pubkey(),
+        &taker.send_account,
+        &taker_lamports_account,
+        &escrow.temp_token_account,
+        &escrow.initializer.pubkey(),
+        &escrow.receive_account,
+        &escrow.escrow,
+        &escrow.expected_mint,
+        &escrow.initializer.pubkey(),
+        &escrow.fee_account,
+        DEPOSIT,
+        0,
+        0,
+        None,
+        None,
+        &[],
+        None,
+        false,
+    )
+    .unwrap();
+    process(&mut ctx, &[ix], &[&taker.keypair]).await.unwrap();
+
+    assert_eq!(lamports(&mut ctx, &taker_lamports_account).await, DEPOSIT);
+    assert_eq!(token_balance(&mut ctx, &escrow.receive_account).await, 500);
+    // The initializer gets the rent of both the temp token account and the escrow account back, the PDA keeps nothing
+    assert_eq!(
+        lamports(&mut ctx, &escrow.initializer.pubkey()).await,
+        initializer_lamports + token_account_rent + escrow_rent
+    );
+    let pda = PdaInfo::find(&program_id(), &escrow.escrow).key;
+    assert_eq!(lamports(&mut ctx, &pda).await, 0);
+    assert!(get_account(&mut ctx, &escrow.temp_token_account)
+        .await
+        .is_none());
+    assert!(get_account(&mut ctx, &escrow.escrow).await.is_none());
+}
+
+#[tokio::test]
+async fn test_cancel_unwraps_a_sol_deposit() {
+    let mut ctx = start().await;
+    let escrow = setup_sol_escrow(&mut ctx, EscrowParams::default()).await;
+    init_sol_escrow(&mut ctx, &escrow, escrow.init_args())
+        .await
+        .unwrap();
+    let initializer_lamports = lamports(&mut ctx, &escrow.initializer.pubkey()).await;
+    let token_account_rent = minimum_balance(&mut ctx, TokenAccount::LEN).await;
+    let escrow_rent = minimum_balance(&mut ctx, Escrow::LEN).await;
+
+    let ix = escrow.cancel_instruction();
+    process(&mut ctx, &[ix], &[&escrow.initializer])
+        .await
+        .unwrap();
+
+    // The deposit comes back as lamports, the initializer's wrapped SOL account stays empty
+    assert_eq!(
+        lamports(&mut ctx, &escrow.initializer.pubkey()).await,
+        initializer_lamports + DEPOSIT + token_account_rent + escrow_rent
+    );
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.initializer_deposit_account).await,
+        0
+    );
+    assert!(get_account(&mut ctx, &escrow.temp_token_account)
+        .await
+        .is_none());
+    assert!(get_account(&mut ctx, &escrow.escrow).await.is_none());
+}
+
+#[tokio::test]
+async fn test_init_escrow_with_sol_rejects_split_escrows_and_other_mints() {
+    let mut ctx = start().await;
+    let escrow = setup_sol_escrow(&mut ctx, EscrowParams::default()).await;
+
+    // A share of the deposit can't be unwrapped for each taker
+    let error = instruction_error(
+        init_sol_escrow(
+            &mut ctx,
+            &escrow,
+            InitEscrowArgs {
+                taker_slots: 2,
+                ..escrow.init_args()
+            },
+        )
+        .await,
+    );
+    assert_eq!(error, InstructionError::InvalidArgument);
+
+    // The lamports can only be wrapped into a token account of the native mint
+    let other_escrow = setup_escrow(&mut ctx, EscrowParams::default()).await;
+    let error =
+        instruction_error(init_sol_escrow(&mut ctx, &other_escrow, other_escrow.init_args()).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::WrongMint as u32)
+    );
+    // Neither attempt wrapped any lamports
+    assert_eq!(token_balance(&mut ctx, &escrow.temp_token_account).await, 0);
+}
+
+#[tokio::test]
+async fn test_init_escrow_rejects_an_unsynced_wrapped_sol_deposit() {
+    let mut ctx = start().await;
+    let escrow = setup_sol_escrow(&mut ctx, EscrowParams::default()).await;
+
+    // Wrapped SOL, then more lamports sent to the account without syncing its token balance
+    fund(&mut ctx, &escrow.temp_token_account, DEPOSIT).await;
+    let ix =
+        spl_token::instruction::sync_native(&spl_token::id(), &escrow.temp_token_account).unwrap();
+    process(&mut ctx, &[ix], &[]).await.unwrap();
+    fund(&mut ctx, &escrow.temp_token_account, DEPOSIT).await;
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.temp_token_account).await,
+        DEPOSIT
+    );
+
+    let ix = escrow.init_instruction(&escrow.temp_token_account);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&escrow.initializer]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::InvalidAmount as u32)
+    );
+    let account = get_account(&mut ctx, &escrow.escrow).await.unwrap();
+    assert!(account.data.iter().all(|byte| *byte == 0));
+}
+
+#[tokio::test]
+async fn test_exchange_for_wrapped_sol_rejects_an_unsynced_or_unwrapped_payment() {
+    let mut ctx = start().await;
+    let deposit_mint = create_mint(&mut ctx, 6).await;
+    let escrow = setup_escrow_with_mints(
+        &mut ctx,
+        EscrowParams::default(),
+        &deposit_mint,
+        &spl_token::native_mint::id(),
+    )
+    .await;
+    let ix = escrow.init_instruction(&escrow.temp_token_account);
+    process(&mut ctx, &[ix], &[&escrow.initializer])
+        .await
+        .unwrap();
+
+    // The taker sent the lamports to its wrapped SOL account but never synced its token balance
+    let keypair = Keypair::new();
+    let send_account =
+        create_token_account(&mut ctx, &spl_token::native_mint::id(), &keypair.pubkey()).await;
+    fund(&mut ctx, &send_account, 500).await;
+    let receive_account = create_token_account(&mut ctx, &deposit_mint, &keypair.pubkey()).await;
+    let taker = TestTaker {
+        keypair,
+        send_account,
+        receive_account,
+    };
+    let ix = escrow.exchange_instruction(&taker, 1_000, 0);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::InvalidAmount as u32)
+    );
+
+    // Nor can it pay out of a token account of another mint
+    let other_mint = create_mint(&mut ctx, 9).await;
+    let other_account = create_token_account(&mut ctx, &other_mint, &taker.keypair.pubkey()).await;
+    mint_to(&mut ctx, &other_mint, &other_account, 500).await;
+    let mut ix = escrow.exchange_instruction(&taker, 1_000, 0);
+    // 1. The taker's token account to send
+    ix.accounts[1].pubkey = other_account;
+    let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::WrongMint as u32)
+    );
+
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.temp_token_account).await,
+        1_000
+    );
+    assert_eq!(token_balance(&mut ctx, &escrow.receive_account).await, 0);
+}
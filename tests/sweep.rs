@@ -0,0 +1,123 @@
+mod common;
+
+use common::*;
+use solana_escrow::{
+    error::EscrowError,
+    instruction,
+    pda::{find_config_address, PdaInfo, NAMESPACE_LEN},
+    state::{EscrowConfig, MAX_NAMESPACES, MAX_PAUSED_MINTS},
+};
+use solana_program::{instruction::InstructionError, pubkey::Pubkey};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::signature::{Keypair, Signer};
+
+/// Starts the test validator with a config administered by `admin`
+async fn start_with_config(admin: &Keypair) -> ProgramTestContext {
+    let config = EscrowConfig {
+        is_initialized: true,
+        admin: admin.pubkey(),
+        paused: false,
+        treasury: Pubkey::new_unique(),
+        cancel_fee: 0,
+        init_fee: 0,
+        paused_mints_count: 0,
+        paused_mints: [Pubkey::default(); MAX_PAUSED_MINTS],
+        extension_fee: 0,
+        exchange_fee_bps: 0,
+        free_fills: 0,
+        namespaces_count: 0,
+        namespaces: [[0; NAMESPACE_LEN]; MAX_NAMESPACES],
+    };
+    start_with_accounts(vec![(
+        find_config_address(&program_id()).0,
+        program_account(config),
+    )])
+    .await
+}
+
+#[tokio::test]
+async fn test_admin_sweeps_tokens_sent_to_the_pda() {
+    let admin = Keypair::new();
+    let mut ctx = start_with_config(&admin).await;
+    let escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+
+    // Tokens sent to the PDA in an account the escrow doesn't track
+    let pda = PdaInfo::find(&program_id(), &escrow.escrow).key;
+    let stray_token_account = create_token_account(&mut ctx, &escrow.deposit_mint, &pda).await;
+    mint_to(&mut ctx, &escrow.deposit_mint, &stray_token_account, 300).await;
+    let destination = create_token_account(&mut ctx, &escrow.deposit_mint, &admin.pubkey()).await;
+
+    // Only the admin sweeps
+    let stranger = Keypair::new();
+    let ix = instruction::sweep_stray_tokens(
+        &program_id(),
+        &stranger.pubkey(),
+        &stray_token_account,
+        &destination,
+        &escrow.escrow,
+    )
+    .unwrap();
+    let error = instruction_error(process(&mut ctx, &[ix], &[&stranger]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::WrongAuthority as u32)
+    );
+
+    let ix = instruction::sweep_stray_tokens(
+        &program_id(),
+        &admin.pubkey(),
+        &stray_token_account,
+        &destination,
+        &escrow.escrow,
+    )
+    .unwrap();
+    process(&mut ctx, &[ix], &[&admin]).await.unwrap();
+    assert_eq!(token_balance(&mut ctx, &stray_token_account).await, 0);
+    assert_eq!(token_balance(&mut ctx, &destination).await, 300);
+    // The deposit itself stays in the escrow
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.temp_token_account).await,
+        1_000
+    );
+}
+
+#[tokio::test]
+async fn test_sweep_rejects_the_temp_token_account_and_another_token_program() {
+    let admin = Keypair::new();
+    let mut ctx = start_with_config(&admin).await;
+    let escrow = open_escrow(&mut ctx, EscrowParams::default()).await;
+    let destination = create_token_account(&mut ctx, &escrow.deposit_mint, &admin.pubkey()).await;
+
+    // The temp token account is owned by the PDA too, but holds the deposit takers pay for
+    let ix = instruction::sweep_stray_tokens(
+        &program_id(),
+        &admin.pubkey(),
+        &escrow.temp_token_account,
+        &destination,
+        &escrow.escrow,
+    )
+    .unwrap();
+    let error = instruction_error(process(&mut ctx, &[ix], &[&admin]).await);
+    assert_eq!(error, InstructionError::InvalidArgument);
+    assert_eq!(
+        token_balance(&mut ctx, &escrow.temp_token_account).await,
+        1_000
+    );
+
+    // Another program can't be handed the PDA's signature
+    let pda = PdaInfo::find(&program_id(), &escrow.escrow).key;
+    let stray_token_account = create_token_account(&mut ctx, &escrow.deposit_mint, &pda).await;
+    mint_to(&mut ctx, &escrow.deposit_mint, &stray_token_account, 300).await;
+    let mut ix = instruction::sweep_stray_tokens(
+        &program_id(),
+        &admin.pubkey(),
+        &stray_token_account,
+        &destination,
+        &escrow.escrow,
+    )
+    .unwrap();
+    ix.accounts[5].pubkey = Pubkey::new_unique();
+    let error = instruction_error(process(&mut ctx, &[ix], &[&admin]).await);
+    assert_eq!(error, InstructionError::IncorrectProgramId);
+    assert_eq!(token_balance(&mut ctx, &stray_token_account).await, 300);
+}
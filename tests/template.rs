@@ -0,0 +1,176 @@
+mod common;
+
+use common::*;
+use solana_escrow::{
+    error::EscrowError,
+    instruction::{self, InitEscrowArgs},
+    pda::find_template_address,
+    state::EscrowTemplate,
+};
+use solana_program::{
+    instruction::{Instruction, InstructionError},
+    pubkey::Pubkey,
+};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::signature::Signer;
+
+/// Starts the test validator with the template account of the first initializer's template 0 allocated,
+/// native programs can't create it
+async fn start_with_template() -> (ProgramTestContext, Pubkey) {
+    let template = find_template_address(&initializer(0).pubkey(), 0, &program_id()).0;
+    let ctx = start_with_accounts(vec![(
+        template,
+        program_account(EscrowTemplate {
+            is_initialized: false,
+            authority: Pubkey::default(),
+            deposit_mint: Pubkey::default(),
+            expected_mint: Pubkey::default(),
+            amount_tolerance: 0,
+            settlement_authority: Pubkey::default(),
+        }),
+    )])
+    .await;
+    (ctx, template)
+}
+
+async fn init_template(ctx: &mut ProgramTestContext, escrow: &TestEscrow, amount_tolerance: u64) {
+    let ix = instruction::init_template(
+        &program_id(),
+        &escrow.initializer.pubkey(),
+        &escrow.deposit_mint,
+        &escrow.expected_mint,
+        0,
+        amount_tolerance,
+        &Pubkey::default(),
+    )
+    .unwrap();
+    process(ctx, &[ix], &[&escrow.initializer]).await.unwrap();
+}
+
+fn init_from_template_instruction(
+    escrow: &TestEscrow,
+    template: &Pubkey,
+    args: InitEscrowArgs,
+) -> Instruction {
+    let initializer = escrow.initializer.pubkey();
+    instruction::init_escrow_from_template(
+        &program_id(),
+        template,
+        &initializer,
+        &escrow.temp_token_account,
+        &escrow.receive_account,
+        &initializer,
+        args,
+        false,
+    )
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_exchange_resolves_the_template_of_the_escrow() {
+    let (mut ctx, template) = start_with_template().await;
+    let escrow = setup_escrow(&mut ctx, EscrowParams::default()).await;
+    init_template(&mut ctx, &escrow, 10).await;
+
+    let ix = init_from_template_instruction(&escrow, &template, escrow.init_args());
+    process(&mut ctx, &[ix], &[&escrow.initializer])
+        .await
+        .unwrap();
+    // The escrow only refers to the template, the tolerance is stored once in the template
+    let escrow_info = escrow_state(&mut ctx, &escrow.escrow).await;
+    assert_eq!(escrow_info.template, template);
+    assert_eq!(escrow_info.amount_tolerance, 0);
+
+    // The exchange can't do without the template
+    let taker = create_taker(&mut ctx, &escrow, 500).await;
+    let ix = escrow.exchange_instruction(&taker, 990, 0);
+    let error = instruction_error(process(&mut ctx, &[ix], &[&taker.keypair]).await);
+    assert_eq!(error, InstructionError::NotEnoughAccountKeys);
+
+    // Within the template's tolerance the taker receives the whole deposit
+    let ix = instruction::exchange(
+        &program_id(),
+        &taker.keypair.pubkey(),
+        &taker.send_account,
+        &taker.receive_account,
+        &escrow.temp_token_account,
+        &escrow.initializer.pubkey(),
+        &escrow.receive_account,
+        &escrow.escrow,
+        &escrow.deposit_mint,
+        &escrow.expected_mint,
+        &escrow.initializer.pubkey(),
+        &escrow.fee_account,
+        false,
+        990,
+        0,
+        0,
+        None,
+        Some(&template),
+        &[],
+        None,
+        false,
+    )
+    .unwrap();
+    process(&mut ctx, &[ix], &[&taker.keypair]).await.unwrap();
+    assert_eq!(token_balance(&mut ctx, &taker.receive_account).await, 1_000);
+    assert_eq!(token_balance(&mut ctx, &escrow.receive_account).await, 500);
+    assert!(get_account(&mut ctx, &escrow.escrow).await.is_none());
+}
+
+#[tokio::test]
+async fn test_init_from_template_rejects_other_makers_mints_and_overrides() {
+    let (mut ctx, template) = start_with_template().await;
+    let escrow = setup_escrow(&mut ctx, EscrowParams::default()).await;
+    init_template(&mut ctx, &escrow, 10).await;
+
+    // The template's parameters can't be overridden
+    let ix = init_from_template_instruction(
+        &escrow,
+        &template,
+        InitEscrowArgs {
+            amount_tolerance: 1,
+            ..escrow.init_args()
+        },
+    );
+    let error = instruction_error(process(&mut ctx, &[ix], &[&escrow.initializer]).await);
+    assert_eq!(error, InstructionError::InvalidArgument);
+
+    // Only the maker who initialized the template opens escrows from it
+    let other_escrow = setup_escrow_with_mints(
+        &mut ctx,
+        EscrowParams::default(),
+        &escrow.deposit_mint,
+        &escrow.expected_mint,
+    )
+    .await;
+    let ix = init_from_template_instruction(&other_escrow, &template, other_escrow.init_args());
+    let error = instruction_error(process(&mut ctx, &[ix], &[&other_escrow.initializer]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::WrongAuthority as u32)
+    );
+
+    // The deposit has to be of the template's deposit mint
+    let other_mint = create_mint(&mut ctx, 6).await;
+    let temp_token_account =
+        create_token_account(&mut ctx, &other_mint, &escrow.initializer.pubkey()).await;
+    mint_to(&mut ctx, &other_mint, &temp_token_account, 1_000).await;
+    let initializer = escrow.initializer.pubkey();
+    let ix = instruction::init_escrow_from_template(
+        &program_id(),
+        &template,
+        &initializer,
+        &temp_token_account,
+        &escrow.receive_account,
+        &initializer,
+        escrow.init_args(),
+        false,
+    )
+    .unwrap();
+    let error = instruction_error(process(&mut ctx, &[ix], &[&escrow.initializer]).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::WrongMint as u32)
+    );
+}
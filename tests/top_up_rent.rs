@@ -0,0 +1,70 @@
+mod common;
+
+use common::*;
+use solana_escrow::{error::EscrowError, instruction, state::Escrow};
+use solana_program::{
+    instruction::InstructionError, program_pack::Pack, pubkey::Pubkey, rent::Rent,
+};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::signature::Signer;
+
+/// How many lamports the preloaded escrow is short of being rent exempt
+const SHORTFALL: u64 = 1_000;
+
+/// Starts the test validator with an initialized escrow holding slightly less than its rent exempt balance
+async fn start_with_underfunded_escrow(escrow: &Pubkey) -> ProgramTestContext {
+    let mut escrow_account = program_account(Escrow {
+        is_initialized: true,
+        initializer_pubkey: Pubkey::new_unique(),
+        taker_slots: 1,
+        ..Escrow::UNINITIALIZED
+    });
+    escrow_account.lamports -= SHORTFALL;
+    start_with_accounts(vec![(*escrow, escrow_account)]).await
+}
+
+async fn top_up_rent(
+    ctx: &mut ProgramTestContext,
+    escrow: &Pubkey,
+    lamports: u64,
+) -> Result<(), solana_sdk::transport::TransportError> {
+    let ix =
+        instruction::top_up_rent(&program_id(), &ctx.payer.pubkey(), escrow, lamports).unwrap();
+    process(ctx, &[ix], &[]).await
+}
+
+#[tokio::test]
+async fn test_top_up_rent_brings_an_escrow_to_its_rent_exempt_balance() {
+    let escrow = Pubkey::new_unique();
+    let mut ctx = start_with_underfunded_escrow(&escrow).await;
+    let minimum_balance = Rent::default().minimum_balance(Escrow::LEN);
+    assert_eq!(
+        lamports(&mut ctx, &escrow).await,
+        minimum_balance - SHORTFALL
+    );
+
+    // A top up leaving the escrow short is reverted
+    let error = instruction_error(top_up_rent(&mut ctx, &escrow, SHORTFALL - 1).await);
+    assert_eq!(
+        error,
+        InstructionError::Custom(EscrowError::NotRentExempt as u32)
+    );
+    assert_eq!(
+        lamports(&mut ctx, &escrow).await,
+        minimum_balance - SHORTFALL
+    );
+
+    top_up_rent(&mut ctx, &escrow, SHORTFALL).await.unwrap();
+    assert_eq!(lamports(&mut ctx, &escrow).await, minimum_balance);
+    // The escrow itself is left as it was
+    assert!(escrow_state(&mut ctx, &escrow).await.is_initialized);
+}
+
+#[tokio::test]
+async fn test_top_up_rent_rejects_an_uninitialized_escrow() {
+    let mut ctx = start().await;
+    let escrow = setup_escrow(&mut ctx, EscrowParams::default()).await;
+
+    let error = instruction_error(top_up_rent(&mut ctx, &escrow.escrow, SHORTFALL).await);
+    assert_eq!(error, InstructionError::UninitializedAccount);
+}